@@ -183,6 +183,15 @@ impl ConfigV2 {
                 oss_cfg.access_key_id = String::new();
                 oss_cfg.access_key_secret = String::new();
             }
+            if let Some(azure_cfg) = backend_cfg.azure.as_mut() {
+                azure_cfg.account_key = String::new();
+                azure_cfg.sas_token = String::new();
+            }
+            if let Some(gcs_cfg) = backend_cfg.gcs.as_mut() {
+                gcs_cfg.access_key_id = String::new();
+                gcs_cfg.access_key_secret = String::new();
+                gcs_cfg.access_token = String::new();
+            }
             if let Some(registry_cfg) = backend_cfg.registry.as_mut() {
                 registry_cfg.auth = None;
                 registry_cfg.registry_token = None;
@@ -275,6 +284,10 @@ pub struct BackendConfigV2 {
     pub oss: Option<OssConfig>,
     /// Configuration for S3 backend.
     pub s3: Option<S3Config>,
+    /// Configuration for Azure Blob Storage backend.
+    pub azure: Option<AzureConfig>,
+    /// Configuration for Google Cloud Storage backend.
+    pub gcs: Option<GcsConfig>,
     /// Configuration for container registry backend.
     pub registry: Option<RegistryConfig>,
     /// Configuration for local http proxy.
@@ -318,6 +331,22 @@ impl BackendConfigV2 {
                 }
                 None => return false,
             },
+            "azure" => match self.azure.as_ref() {
+                Some(v) => {
+                    if v.account_name.is_empty() || v.container_name.is_empty() {
+                        return false;
+                    }
+                }
+                None => return false,
+            },
+            "gcs" => match self.gcs.as_ref() {
+                Some(v) => {
+                    if v.bucket_name.is_empty() {
+                        return false;
+                    }
+                }
+                None => return false,
+            },
             "registry" => match self.registry.as_ref() {
                 Some(v) => {
                     if v.host.is_empty() || v.repo.is_empty() {
@@ -422,6 +451,40 @@ impl BackendConfigV2 {
         }
     }
 
+    /// Get configuration information for Azure Blob Storage
+    pub fn get_azure_config(&self) -> Result<&AzureConfig> {
+        if &self.backend_type != "azure" {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "backend type is not 'azure'",
+            ))
+        } else {
+            self.azure.as_ref().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "no configuration information for azure",
+                )
+            })
+        }
+    }
+
+    /// Get configuration information for Google Cloud Storage
+    pub fn get_gcs_config(&self) -> Result<&GcsConfig> {
+        if &self.backend_type != "gcs" {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "backend type is not 'gcs'",
+            ))
+        } else {
+            self.gcs.as_ref().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "no configuration information for gcs",
+                )
+            })
+        }
+    }
+
     /// Get configuration information for Registry
     pub fn get_registry_config(&self) -> Result<&RegistryConfig> {
         if &self.backend_type != "registry" {
@@ -545,6 +608,126 @@ pub struct S3Config {
     /// S3 secret
     #[serde(default)]
     pub access_key_secret: String,
+    /// S3 session token of temporary credentials.
+    #[serde(default)]
+    pub session_token: String,
+    /// Path of the web identity token file to get temporary credentials by STS
+    /// `AssumeRoleWithWebIdentity`.
+    #[serde(default)]
+    pub web_identity_token_file: String,
+    /// ARN of the role to assume with the web identity token.
+    #[serde(default)]
+    pub role_arn: String,
+    /// Session name to assume the role, "nydus" by default.
+    #[serde(default)]
+    pub role_session_name: String,
+    /// STS endpoint URL, "https://sts.amazonaws.com" by default.
+    #[serde(default)]
+    pub sts_endpoint: String,
+    /// Get temporary credentials from the EC2 instance metadata service.
+    #[serde(default)]
+    pub use_instance_metadata: bool,
+    /// Instance metadata service endpoint URL, "http://169.254.169.254" by default.
+    #[serde(default)]
+    pub imds_endpoint: String,
+    /// Split reads larger than the size into range GET requests sent concurrently, 0 to disable.
+    #[serde(default)]
+    pub part_size: u64,
+    /// Skip SSL certificate validation for HTTPS scheme.
+    #[serde(default)]
+    pub skip_verify: bool,
+    /// Drop the read request once http request timeout, in seconds.
+    #[serde(default = "default_http_timeout")]
+    pub timeout: u32,
+    /// Drop the read request once http connection timeout, in seconds.
+    #[serde(default = "default_http_timeout")]
+    pub connect_timeout: u32,
+    /// Retry count when read request failed.
+    #[serde(default)]
+    pub retry_limit: u8,
+    /// Enable HTTP proxy for the read request.
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+}
+
+/// Azure Blob Storage configuration information to access blobs.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AzureConfig {
+    /// Azure http scheme, either 'http' or 'https'
+    #[serde(default = "default_http_scheme")]
+    pub scheme: String,
+    /// Azure blob service endpoint, "<account_name>.blob.core.windows.net" by default.
+    #[serde(default)]
+    pub endpoint: String,
+    /// Azure storage account name
+    pub account_name: String,
+    /// Azure storage account key encoded in base64, to authorize requests with shared key.
+    #[serde(default)]
+    pub account_key: String,
+    /// Shared access signature token, to authorize requests with SAS if `account_key` is empty.
+    #[serde(default)]
+    pub sas_token: String,
+    /// Azure blob container name
+    pub container_name: String,
+    /// Prefix object_prefix to Azure blob name, for example the simulation of subdirectory:
+    /// - object_key: sha256:xxx
+    /// - object_prefix: nydus/
+    /// - object_key with object_prefix: nydus/sha256:xxx
+    #[serde(default)]
+    pub object_prefix: String,
+    /// Skip SSL certificate validation for HTTPS scheme.
+    #[serde(default)]
+    pub skip_verify: bool,
+    /// Drop the read request once http request timeout, in seconds.
+    #[serde(default = "default_http_timeout")]
+    pub timeout: u32,
+    /// Drop the read request once http connection timeout, in seconds.
+    #[serde(default = "default_http_timeout")]
+    pub connect_timeout: u32,
+    /// Retry count when read request failed.
+    #[serde(default)]
+    pub retry_limit: u8,
+    /// Enable HTTP proxy for the read request.
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+}
+
+/// Google Cloud Storage configuration information to access blobs.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GcsConfig {
+    /// GCS http scheme, either 'http' or 'https'
+    #[serde(default = "default_http_scheme")]
+    pub scheme: String,
+    /// GCS XML API endpoint, "storage.googleapis.com" by default.
+    #[serde(default)]
+    pub endpoint: String,
+    /// GCS bucket name
+    pub bucket_name: String,
+    /// Prefix object_prefix to GCS object name, for example the simulation of subdirectory:
+    /// - object_key: sha256:xxx
+    /// - object_prefix: nydus/
+    /// - object_key with object_prefix: nydus/sha256:xxx
+    #[serde(default)]
+    pub object_prefix: String,
+    /// HMAC access key id, to authorize requests with HMAC keys.
+    #[serde(default)]
+    pub access_key_id: String,
+    /// HMAC secret
+    #[serde(default)]
+    pub access_key_secret: String,
+    /// OAuth 2.0 access token, to authorize requests with bearer token if no HMAC key is given.
+    ///
+    /// The token is used as is and never refreshed, so requests fail once it expires. Enable
+    /// `use_metadata_server` for long running instances instead.
+    #[serde(default)]
+    pub access_token: String,
+    /// Fetch OAuth 2.0 access tokens of the attached service account from the GCE metadata
+    /// server, and refresh them before they expire, instead of using `access_token`.
+    #[serde(default)]
+    pub use_metadata_server: bool,
+    /// GCE metadata server endpoint, "http://metadata.google.internal" by default.
+    #[serde(default)]
+    pub metadata_endpoint: String,
     /// Skip SSL certificate validation for HTTPS scheme.
     #[serde(default)]
     pub skip_verify: bool,
@@ -601,6 +784,11 @@ pub struct RegistryConfig {
     /// Base64_encoded(username:password), the field should be sent to registry auth server to get a bearer token.
     #[serde(default)]
     pub auth: Option<String>,
+    /// Path to docker `config.json` to resolve registry credentials from, including `credHelpers`
+    /// and `credsStore`. An empty string means `$DOCKER_CONFIG/config.json` or
+    /// `~/.docker/config.json`. Credentials are resolved again when rejected by the registry.
+    #[serde(default)]
+    pub docker_config: Option<String>,
     /// Skip SSL certificate validation for HTTPS scheme.
     #[serde(default)]
     pub skip_verify: bool,
@@ -626,6 +814,9 @@ pub struct RegistryConfig {
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
+    /// Registry mirrors to try in order before falling back to the registry itself.
+    #[serde(default)]
+    pub mirrors: Vec<MirrorConfig>,
 }
 
 /// Configuration information for blob cache manager.
@@ -649,6 +840,13 @@ pub struct CacheConfigV2 {
     #[serde(rename = "fscache")]
     /// Configuration information for fscache
     pub fs_cache: Option<FsCacheConfig>,
+    /// Size in bytes of the in-memory cache for decompressed chunk data, zero means disabled.
+    ///
+    /// The memory cache sits in front of the file cache and is shared by all blobs of a blob
+    /// cache manager. Chunks are keyed by their digests, so chunks shared by multiple blobs are
+    /// cached once. Only the "blobcache"/"filecache" type supports the memory cache.
+    #[serde(default)]
+    pub mem_cache_size: u64,
 }
 
 impl CacheConfigV2 {
@@ -752,9 +950,30 @@ pub struct FileCacheConfig {
     /// Key for data encryption, a heximal representation of [u8; 32].
     #[serde(default)]
     pub encryption_key: String,
+    /// Maximum disk space in bytes used by cached blobs in the working directory, zero means
+    /// no limit.
+    #[serde(default)]
+    pub capacity: u64,
+    /// Evict cached blobs which have not been used for the specified seconds, zero means never.
+    #[serde(default)]
+    pub max_idle_time: u64,
+    /// Interval in seconds to check and reclaim disk space used by cached blobs.
+    #[serde(default = "default_cache_gc_interval")]
+    pub gc_interval: u64,
+    /// Interval in seconds to scrub cached data in background, zero means disabled.
+    ///
+    /// The scrubber verifies data of ready chunks against their digests and marks corrupted
+    /// chunks as not ready, so they will be fetched from the storage backend again.
+    #[serde(default)]
+    pub scrub_interval: u64,
 }
 
 impl FileCacheConfig {
+    /// Check whether cached blobs should be evicted from the working directory.
+    pub fn eviction_enabled(&self) -> bool {
+        self.capacity > 0 || self.max_idle_time > 0
+    }
+
     /// Get the working directory.
     pub fn get_work_dir(&self) -> Result<&str> {
         let path = fs::metadata(&self.work_dir)
@@ -839,6 +1058,21 @@ pub struct RafsConfigV2 {
     /// Filesystem prefetching configuration.
     #[serde(default)]
     pub prefetch: PrefetchConfigV2,
+    /// Refuse to mount bootstraps without a valid signature from `signature_keys`.
+    #[serde(default)]
+    pub require_signature: bool,
+    /// Paths of PEM encoded ed25519 or ECDSA public keys to verify bootstrap signatures.
+    #[serde(default)]
+    pub signature_keys: Vec<String>,
+    /// Verify file data against fs-verity digests generated by `nydus-image create --verity-files`.
+    #[serde(default)]
+    pub enforce_verity: bool,
+    /// Map uids of files in the RAFS filesystem to uids presented by the mount.
+    #[serde(default)]
+    pub uid_map: Vec<IdMapRange>,
+    /// Map gids of files in the RAFS filesystem to gids presented by the mount.
+    #[serde(default)]
+    pub gid_map: Vec<IdMapRange>,
 }
 
 impl RafsConfigV2 {
@@ -858,6 +1092,66 @@ impl RafsConfigV2 {
                 return false;
             }
         }
+        if self.require_signature && self.signature_keys.is_empty() {
+            return false;
+        }
+        if !IdMapRange::validate_map(&self.uid_map) || !IdMapRange::validate_map(&self.gid_map) {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// A range of user or group ids to map, in the same way as `/proc/[pid]/uid_map`.
+///
+/// Ids in range `[inside, inside + count)` of the RAFS filesystem are presented as ids in range
+/// `[outside, outside + count)` by the mount.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct IdMapRange {
+    /// First id of the range in the RAFS filesystem.
+    pub inside: u32,
+    /// First id of the range presented by the mount.
+    pub outside: u32,
+    /// Number of ids in the range.
+    pub count: u32,
+}
+
+impl IdMapRange {
+    /// Id presented for ids which are not covered by an id map, same as the kernel overflow id.
+    pub const OVERFLOW_ID: u32 = 65534;
+
+    /// Map `id` in the RAFS filesystem by `map`, an empty map means identity mapping.
+    pub fn map_id(map: &[IdMapRange], id: u32) -> u32 {
+        if map.is_empty() {
+            return id;
+        }
+        map.iter()
+            .find(|r| id >= r.inside && id - r.inside < r.count)
+            .map(|r| r.outside + (id - r.inside))
+            .unwrap_or(Self::OVERFLOW_ID)
+    }
+
+    /// Validate an id map, ranges must not be empty, overflow or overlap with each other.
+    pub fn validate_map(map: &[IdMapRange]) -> bool {
+        let overlap = |a: u32, b: u32, count_a: u32, count_b: u32| {
+            (a as u64) < b as u64 + count_b as u64 && (b as u64) < a as u64 + count_a as u64
+        };
+        for (idx, r) in map.iter().enumerate() {
+            if r.count == 0
+                || r.inside.checked_add(r.count - 1).is_none()
+                || r.outside.checked_add(r.count - 1).is_none()
+            {
+                return false;
+            }
+            for o in &map[idx + 1..] {
+                if overlap(r.inside, o.inside, r.count, o.count)
+                    || overlap(r.outside, o.outside, r.count, o.count)
+                {
+                    return false;
+                }
+            }
+        }
 
         true
     }
@@ -918,6 +1212,41 @@ impl Default for ProxyConfig {
     }
 }
 
+/// Configuration information for a registry mirror.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MirrorConfig {
+    /// Mirror server URL, like `http://127.0.0.1:65001` or `https://mirror.example.com`.
+    pub host: String,
+    /// Mirror health checking endpoint, defaults to `<host>/v2`.
+    #[serde(default)]
+    pub ping_url: String,
+    /// Extra HTTP headers to send to the mirror server.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Send authentication requests to the mirror server too, instead of the registry only.
+    #[serde(default)]
+    pub auth_through: bool,
+    /// Interval for health checking of an unhealthy mirror, in seconds.
+    #[serde(default = "default_check_interval")]
+    pub health_check_interval: u64,
+    /// Number of consecutive failures before marking the mirror as unhealthy.
+    #[serde(default = "default_mirror_failure_limit")]
+    pub failure_limit: u8,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            ping_url: String::new(),
+            headers: HashMap::new(),
+            auth_through: false,
+            health_check_interval: 5,
+            failure_limit: 5,
+        }
+    }
+}
+
 /// Configuration information for a cached blob`.
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct BlobCacheEntryConfigV2 {
@@ -938,6 +1267,12 @@ pub struct BlobCacheEntryConfigV2 {
     /// Optional file path for metadata blob.
     #[serde(default)]
     pub metadata_path: Option<String>,
+    /// Refuse to load bootstraps without a valid signature from `signature_keys`.
+    #[serde(default)]
+    pub require_signature: bool,
+    /// Paths of PEM encoded ed25519 or ECDSA public keys to verify bootstrap signatures.
+    #[serde(default)]
+    pub signature_keys: Vec<String>,
 }
 
 impl BlobCacheEntryConfigV2 {
@@ -991,7 +1326,7 @@ impl FromStr for BlobCacheEntryConfigV2 {
 
 impl From<&BlobCacheEntryConfigV2> for ConfigV2 {
     fn from(c: &BlobCacheEntryConfigV2) -> Self {
-        ConfigV2 {
+        let mut config = ConfigV2 {
             version: c.version,
             id: c.id.clone(),
             backend: Some(c.backend.clone()),
@@ -1000,7 +1335,17 @@ impl From<&BlobCacheEntryConfigV2> for ConfigV2 {
             rafs: None,
             overlay: None,
             internal: ConfigV2Internal::default(),
+        };
+        // Carry the signature policy to verify bootstraps loaded for the blob cache entry.
+        if c.require_signature || !c.signature_keys.is_empty() {
+            config.rafs = Some(RafsConfigV2 {
+                mode: default_rafs_mode(),
+                require_signature: c.require_signature,
+                signature_keys: c.signature_keys.clone(),
+                ..Default::default()
+            });
         }
+        config
     }
 }
 
@@ -1165,6 +1510,14 @@ fn default_check_pause_elapsed() -> u64 {
     300
 }
 
+fn default_mirror_failure_limit() -> u8 {
+    5
+}
+
+fn default_cache_gc_interval() -> u64 {
+    60
+}
+
 fn default_work_dir() -> String {
     ".".to_string()
 }
@@ -1215,6 +1568,8 @@ impl TryFrom<&BackendConfig> for BackendConfigV2 {
             localfs: None,
             oss: None,
             s3: None,
+            azure: None,
+            gcs: None,
             registry: None,
             http_proxy: None,
         };
@@ -1232,6 +1587,12 @@ impl TryFrom<&BackendConfig> for BackendConfigV2 {
             "s3" => {
                 config.s3 = Some(serde_json::from_value(value.backend_config.clone())?);
             }
+            "azure" => {
+                config.azure = Some(serde_json::from_value(value.backend_config.clone())?);
+            }
+            "gcs" => {
+                config.gcs = Some(serde_json::from_value(value.backend_config.clone())?);
+            }
             "registry" => {
                 config.registry = Some(serde_json::from_value(value.backend_config.clone())?);
             }
@@ -1291,6 +1652,7 @@ impl TryFrom<&CacheConfig> for CacheConfigV2 {
             prefetch: (&v.prefetch_config).into(),
             file_cache: None,
             fs_cache: None,
+            mem_cache_size: 0,
         };
 
         match v.cache_type.as_str() {
@@ -1375,6 +1737,11 @@ impl TryFrom<RafsConfig> for ConfigV2 {
             access_pattern: v.access_pattern,
             latest_read_files: v.latest_read_files,
             prefetch: v.fs_prefetch.into(),
+            require_signature: false,
+            signature_keys: Vec::new(),
+            enforce_verity: false,
+            uid_map: Vec::new(),
+            gid_map: Vec::new(),
         };
         if !cache.prefetch.enable && rafs.prefetch.enable {
             cache.prefetch = rafs.prefetch.clone();
@@ -1516,6 +1883,8 @@ impl TryFrom<&BlobCacheEntryConfig> for BlobCacheEntryConfigV2 {
             external_backends: v.external_backends.clone(),
             cache: (&cache_config).try_into()?,
             metadata_path: v.metadata_path.clone(),
+            require_signature: false,
+            signature_keys: Vec::new(),
         })
     }
 }
@@ -1580,6 +1949,44 @@ mod tests {
         assert!(config.get_work_dir().is_err());
     }
 
+    #[test]
+    fn test_file_cache_eviction_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.capacity, 0);
+        assert_eq!(config.max_idle_time, 0);
+        assert_eq!(config.gc_interval, 60);
+        assert!(!config.eviction_enabled());
+
+        let config: FileCacheConfig =
+            serde_json::from_str("{\"capacity\":1048576,\"gc_interval\":10}").unwrap();
+        assert_eq!(config.capacity, 0x100000);
+        assert_eq!(config.gc_interval, 10);
+        assert!(config.eviction_enabled());
+
+        let config: FileCacheConfig = serde_json::from_str("{\"max_idle_time\":3600}").unwrap();
+        assert_eq!(config.max_idle_time, 3600);
+        assert!(config.eviction_enabled());
+    }
+
+    #[test]
+    fn test_cache_mem_cache_config() {
+        let config: CacheConfigV2 = serde_json::from_str("{\"type\":\"filecache\"}").unwrap();
+        assert_eq!(config.mem_cache_size, 0);
+
+        let config: CacheConfigV2 =
+            serde_json::from_str("{\"type\":\"filecache\",\"mem_cache_size\":67108864}").unwrap();
+        assert_eq!(config.mem_cache_size, 0x400_0000);
+    }
+
+    #[test]
+    fn test_file_cache_scrub_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.scrub_interval, 0);
+
+        let config: FileCacheConfig = serde_json::from_str("{\"scrub_interval\":3600}").unwrap();
+        assert_eq!(config.scrub_interval, 3600);
+    }
+
     #[test]
     fn test_fs_cache_config() {
         let config: FsCacheConfig = serde_json::from_str("{}").unwrap();
@@ -1711,6 +2118,23 @@ mod tests {
         assert!(!config.skip_verify);
         assert_eq!(config.timeout, 5);
         assert_eq!(config.connect_timeout, 5);
+
+        let content = r#"{
+            "endpoint": "",
+            "region": "us-east-1",
+            "bucket_name": "antsys-nydus",
+            "web_identity_token_file": "/var/run/secrets/token",
+            "role_arn": "arn:aws:iam::123456789012:role/nydus",
+            "use_instance_metadata": true,
+            "part_size": 8388608
+        }"#;
+        let config: S3Config = serde_json::from_str(content).unwrap();
+        assert_eq!(config.web_identity_token_file, "/var/run/secrets/token");
+        assert_eq!(config.role_arn, "arn:aws:iam::123456789012:role/nydus");
+        assert!(config.session_token.is_empty());
+        assert!(config.sts_endpoint.is_empty());
+        assert!(config.use_instance_metadata);
+        assert_eq!(config.part_size, 8388608);
     }
 
     #[test]
@@ -1727,6 +2151,55 @@ mod tests {
         let config: RegistryConfig = serde_json::from_str(content).unwrap();
         assert_eq!(config.scheme, "http");
         assert!(config.skip_verify);
+        assert!(config.mirrors.is_empty());
+        assert!(config.docker_config.is_none());
+
+        let content = r#"{
+            "host": "my-registry:5000",
+            "repo": "test/repo",
+            "docker_config": ""
+        }"#;
+        let config: RegistryConfig = serde_json::from_str(content).unwrap();
+        assert_eq!(config.docker_config, Some(String::new()));
+    }
+
+    #[test]
+    fn test_registry_mirror_config() {
+        let content = r#"{
+            "host": "my-registry:5000",
+            "repo": "test/repo",
+            "mirrors": [
+                {
+                    "host": "http://127.0.0.1:65001",
+                    "headers": {"X-Dragonfly-Registry": "https://my-registry:5000"},
+                    "auth_through": true
+                },
+                {
+                    "host": "https://mirror.example.com",
+                    "ping_url": "https://mirror.example.com/healthz",
+                    "health_check_interval": 10,
+                    "failure_limit": 1
+                }
+            ]
+        }"#;
+        let config: RegistryConfig = serde_json::from_str(content).unwrap();
+        assert_eq!(config.mirrors.len(), 2);
+        assert_eq!(config.mirrors[0].host, "http://127.0.0.1:65001");
+        assert_eq!(
+            config.mirrors[0].headers["X-Dragonfly-Registry"],
+            "https://my-registry:5000"
+        );
+        assert!(config.mirrors[0].auth_through);
+        assert_eq!(config.mirrors[0].ping_url, "");
+        assert_eq!(config.mirrors[0].health_check_interval, 5);
+        assert_eq!(config.mirrors[0].failure_limit, 5);
+        assert!(!config.mirrors[1].auth_through);
+        assert_eq!(
+            config.mirrors[1].ping_url,
+            "https://mirror.example.com/healthz"
+        );
+        assert_eq!(config.mirrors[1].health_check_interval, 10);
+        assert_eq!(config.mirrors[1].failure_limit, 1);
     }
 
     #[test]
@@ -1863,6 +2336,62 @@ mod tests {
         assert!(oss.proxy.use_http);
     }
 
+    #[test]
+    fn test_v2_backend_azure() {
+        let content = r#"version=2
+        [backend]
+        type = "azure"
+        [backend.azure]
+        account_name = "my_account"
+        account_key = "bXlfYWNjb3VudF9rZXk="
+        container_name = "my_container"
+        object_prefix = "my_object_prefix"
+        retry_limit = 5
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let backend = config.backend.as_ref().unwrap();
+        assert_eq!(&backend.backend_type, "azure");
+        assert!(backend.validate());
+
+        let azure = backend.get_azure_config().unwrap();
+        assert_eq!(&azure.account_name, "my_account");
+        assert_eq!(&azure.account_key, "bXlfYWNjb3VudF9rZXk=");
+        assert_eq!(&azure.container_name, "my_container");
+        assert_eq!(&azure.object_prefix, "my_object_prefix");
+        assert_eq!(&azure.scheme, "https");
+        assert!(azure.endpoint.is_empty());
+        assert!(azure.sas_token.is_empty());
+        assert_eq!(azure.timeout, 5);
+        assert_eq!(azure.retry_limit, 5);
+    }
+
+    #[test]
+    fn test_v2_backend_gcs() {
+        let content = r#"version=2
+        [backend]
+        type = "gcs"
+        [backend.gcs]
+        bucket_name = "my_bucket_name"
+        object_prefix = "my_object_prefix"
+        access_token = "my_access_token"
+        scheme = "http"
+        endpoint = "localhost:4443"
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let backend = config.backend.as_ref().unwrap();
+        assert_eq!(&backend.backend_type, "gcs");
+        assert!(backend.validate());
+
+        let gcs = backend.get_gcs_config().unwrap();
+        assert_eq!(&gcs.bucket_name, "my_bucket_name");
+        assert_eq!(&gcs.object_prefix, "my_object_prefix");
+        assert_eq!(&gcs.access_token, "my_access_token");
+        assert_eq!(&gcs.scheme, "http");
+        assert_eq!(&gcs.endpoint, "localhost:4443");
+        assert!(gcs.access_key_id.is_empty());
+        assert_eq!(gcs.connect_timeout, 5);
+    }
+
     #[test]
     fn test_v2_backend_registry() {
         let content = r#"version=2
@@ -1966,6 +2495,10 @@ mod tests {
         iostats_files = true
         access_pattern = true
         latest_read_files = true
+        require_signature = true
+        signature_keys = ["/etc/nydus/ed25519.pub"]
+        enforce_verity = true
+        uid_map = [{ inside = 0, outside = 100000, count = 65536 }]
         [rafs.prefetch]
         enable = true
         threads = 4
@@ -1987,6 +2520,25 @@ mod tests {
         assert!(rafs.iostats_files);
         assert!(rafs.access_pattern);
         assert!(rafs.latest_read_files);
+        assert!(rafs.require_signature);
+        assert_eq!(
+            rafs.signature_keys,
+            vec!["/etc/nydus/ed25519.pub".to_string()]
+        );
+        assert!(rafs.enforce_verity);
+        assert_eq!(
+            rafs.uid_map,
+            vec![IdMapRange {
+                inside: 0,
+                outside: 100000,
+                count: 65536
+            }]
+        );
+        assert!(rafs.gid_map.is_empty());
+        assert!(rafs.validate());
+        let mut unsigned = rafs.clone();
+        unsigned.signature_keys.clear();
+        assert!(!unsigned.validate());
         assert!(rafs.prefetch.enable);
         assert_eq!(rafs.prefetch.threads_count, 4);
         assert_eq!(rafs.prefetch.batch_size, 1000000);
@@ -1994,6 +2546,35 @@ mod tests {
         assert!(rafs.prefetch.prefetch_all)
     }
 
+    #[test]
+    fn test_id_map_range() {
+        let range = |inside, outside, count| IdMapRange {
+            inside,
+            outside,
+            count,
+        };
+        let map = vec![range(0, 100000, 1000), range(1000, 200000, 10)];
+        assert!(IdMapRange::validate_map(&map));
+        assert_eq!(IdMapRange::map_id(&map, 0), 100000);
+        assert_eq!(IdMapRange::map_id(&map, 999), 100999);
+        assert_eq!(IdMapRange::map_id(&map, 1005), 200005);
+        assert_eq!(IdMapRange::map_id(&map, 1010), IdMapRange::OVERFLOW_ID);
+        assert_eq!(IdMapRange::map_id(&[], 1010), 1010);
+
+        assert!(IdMapRange::validate_map(&[]));
+        assert!(IdMapRange::validate_map(&[range(1, u32::MAX, 1)]));
+        assert!(!IdMapRange::validate_map(&[range(0, 0, 0)]));
+        assert!(!IdMapRange::validate_map(&[range(1, u32::MAX, 2)]));
+        assert!(!IdMapRange::validate_map(&[
+            range(0, 0, 10),
+            range(9, 100, 10)
+        ]));
+        assert!(!IdMapRange::validate_map(&[
+            range(0, 100, 10),
+            range(10, 105, 10)
+        ]));
+    }
+
     #[test]
     fn test_v2_blob_cache_entry() {
         let content = r#"version=2
@@ -2016,6 +2597,9 @@ mod tests {
         assert_eq!(config.version, 2);
         assert_eq!(&config.id, "my_id");
         assert_eq!(config.metadata_path.as_ref().unwrap(), "meta_path");
+        assert!(!config.require_signature);
+        let v2: ConfigV2 = (&config).into();
+        assert!(v2.rafs.is_none());
 
         let backend = &config.backend;
         assert_eq!(&backend.backend_type, "localfs");
@@ -2025,6 +2609,17 @@ mod tests {
         assert_eq!(&localfs.blob_file, "/tmp/nydus.blob.data");
         assert_eq!(&localfs.dir, "/tmp");
         assert_eq!(&localfs.alt_dirs[0], "/var/nydus/cache");
+
+        let mut config = config;
+        config.require_signature = true;
+        config.signature_keys = vec!["/etc/nydus/ed25519.pub".to_string()];
+        assert!(config.validate());
+        let v2: ConfigV2 = (&config).into();
+        let rafs = v2.rafs.as_ref().unwrap();
+        assert!(rafs.require_signature);
+        assert_eq!(rafs.signature_keys.len(), 1);
+        config.signature_keys.clear();
+        assert!(!config.validate());
     }
 
     #[test]
@@ -2236,6 +2831,18 @@ mod tests {
         };
         assert!(!cfg.validate());
 
+        let cfg = BackendConfigV2 {
+            backend_type: "azure".to_string(),
+            ..Default::default()
+        };
+        assert!(!cfg.validate());
+
+        let cfg = BackendConfigV2 {
+            backend_type: "gcs".to_string(),
+            ..Default::default()
+        };
+        assert!(!cfg.validate());
+
         let cfg = BackendConfigV2 {
             backend_type: "register".to_string(),
             ..Default::default()
@@ -2269,6 +2876,8 @@ mod tests {
         get_config("localfs");
         get_config("oss");
         get_config("s3");
+        get_config("azure");
+        get_config("gcs");
         get_config("register");
         get_config("http-proxy");
     }
@@ -2559,6 +3168,18 @@ mod tests {
         };
         assert!(BackendConfigV2::try_from(&config).is_ok());
 
+        let config = BackendConfig {
+            backend_type: "azure".to_string(),
+            backend_config: serde_json::to_value(AzureConfig::default()).unwrap(),
+        };
+        assert!(BackendConfigV2::try_from(&config).is_ok());
+
+        let config = BackendConfig {
+            backend_type: "gcs".to_string(),
+            backend_config: serde_json::to_value(GcsConfig::default()).unwrap(),
+        };
+        assert!(BackendConfigV2::try_from(&config).is_ok());
+
         let config = BackendConfig {
             backend_type: "registry".to_string(),
             backend_config: serde_json::to_value(RegistryConfig::default()).unwrap(),
@@ -5,13 +5,14 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::io;
+use std::str::FromStr;
 use std::sync::mpsc::{RecvError, SendError};
 
 use serde::Deserialize;
 use serde_json::Error as SerdeError;
 use thiserror::Error;
 
-use crate::BlobCacheEntry;
+use crate::{BackendConfigV2, BlobCacheEntry, ConfigV2, IdMapRange};
 
 /// Errors related to Metrics.
 #[derive(Error, Debug)]
@@ -35,6 +36,47 @@ pub struct ApiMountCmd {
     /// List of files to prefetch.
     #[serde(default)]
     pub prefetch_files: Option<Vec<String>>,
+    /// Map uids of files in the filesystem to uids presented by the mount, overriding `uid_map`
+    /// of the RAFS configuration.
+    #[serde(default)]
+    pub uid_map: Option<Vec<IdMapRange>>,
+    /// Map gids of files in the filesystem to gids presented by the mount, overriding `gid_map`
+    /// of the RAFS configuration.
+    #[serde(default)]
+    pub gid_map: Option<Vec<IdMapRange>>,
+}
+
+impl ApiMountCmd {
+    /// Get configuration for the filesystem, with uid/gid maps of the mount request applied.
+    pub fn get_config(&self) -> io::Result<String> {
+        if self.uid_map.is_none() && self.gid_map.is_none() {
+            return Ok(self.config.clone());
+        }
+
+        let mut config = ConfigV2::from_str(&self.config)?;
+        let rafs = config
+            .rafs
+            .as_mut()
+            .ok_or_else(|| einval!("uid/gid maps are only supported by RAFS filesystems"))?;
+        if let Some(map) = self.uid_map.as_ref() {
+            rafs.uid_map = map.clone();
+        }
+        if let Some(map) = self.gid_map.as_ref() {
+            rafs.gid_map = map.clone();
+        }
+        if !rafs.validate() {
+            return Err(einval!("invalid uid/gid maps"));
+        }
+
+        serde_json::to_string(&config).map_err(|e| einval!(e))
+    }
+}
+
+/// Prefetch files or directories of a mounted filesystem.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ApiPrefetchFilesCmd {
+    /// Absolute paths of files or directories to prefetch.
+    pub files: Vec<String>,
 }
 
 /// Umount a mounted filesystem.
@@ -89,17 +131,27 @@ pub enum ApiRequest {
     Remount(String, ApiMountCmd),
     /// Unmount a filesystem.
     Umount(String),
+    /// Prefetch files or directories of a mounted filesystem in background.
+    PrefetchFiles(String, ApiPrefetchFilesCmd),
+    /// Get progress of a background prefetch job.
+    GetPrefetchProgress(String, u64),
+    /// Update storage backend configuration of a mounted filesystem in place.
+    UpdateFsBackend(String, Box<BackendConfigV2>),
 
     /// Get storage backend metrics.
     ExportBackendMetrics(Option<String>),
     /// Get blob cache metrics.
     ExportBlobcacheMetrics(Option<String>),
+    /// Get all metrics in the Prometheus text exposition format.
+    ExportPrometheusMetrics,
 
     // Nydus API v1 requests
     /// Get filesystem global metrics.
     ExportFsGlobalMetrics(Option<String>),
     /// Get filesystem access pattern log.
     ExportFsAccessPatterns(Option<String>),
+    /// Get files read by the filesystem as a prefetch file list, ordered by first access time.
+    ExportFsPrefetchList(Option<String>),
     /// Get filesystem backend information.
     ExportFsBackendInfo(String),
     /// Get filesystem file metrics.
@@ -118,6 +170,10 @@ pub enum ApiRequest {
     DeleteBlobObject(BlobCacheObjectId),
     /// Delete a blob cache file
     DeleteBlobFile(String),
+    /// Get statistics information about the chunk deduplication database
+    GetCasStats,
+    /// Garbage collect stale records from the chunk deduplication database
+    CasGc,
 }
 
 /// Kinds for daemon related error messages.
@@ -157,6 +213,8 @@ pub enum ApiError {
     Metrics(MetricsErrorKind),
     #[error("failed to mount filesystem: {0:?}")]
     MountFilesystem(DaemonErrorKind),
+    #[error("failed to prefetch files: {0:?}")]
+    Prefetch(DaemonErrorKind),
     #[error("failed to send request to the API service: {0:?}")]
     RequestSend(#[from] SendError<Option<ApiRequest>>),
     #[error("failed to parse response payload type")]
@@ -176,6 +234,8 @@ pub enum ApiResponsePayload {
     BackendMetrics(String),
     /// Blobcache metrics.
     BlobcacheMetrics(String),
+    /// All metrics in the Prometheus text exposition format.
+    PrometheusMetrics(String),
     /// Daemon version, configuration and status information in json.
     DaemonInfo(String),
     /// No data is sent on the channel.
@@ -193,9 +253,13 @@ pub enum ApiResponsePayload {
     FsBackendInfo(String),
     // Filesystem Inflight Requests, v1.
     FsInflightMetrics(String),
+    /// Filesystem prefetch job id or progress, v1.
+    FsPrefetchJob(String),
 
     /// List of blob objects, v2
     BlobObjectList(String),
+    /// Chunk deduplication database statistics or garbage collection result, v2
+    CasStats(String),
 }
 
 /// Specialized version of [`std::result::Result`] for value returned by backend services.
@@ -233,10 +297,14 @@ pub enum HttpError {
     BackendMetrics(ApiError),
     /// Failed to get blobcache metrics.
     BlobcacheMetrics(ApiError),
+    /// Failed to get metrics in the Prometheus format.
+    PrometheusMetrics(ApiError),
 
     // Filesystem related errors (v1)
     /// Failed to get filesystem backend information
     FsBackendInfo(ApiError),
+    /// Failed to update filesystem storage backend configuration.
+    FsBackendUpdate(ApiError),
     /// Failed to get filesystem per-file metrics.
     FsFilesMetrics(ApiError),
     /// Failed to get global metrics.
@@ -245,6 +313,8 @@ pub enum HttpError {
     InflightMetrics(ApiError),
     /// Failed to get filesystem file access trace.
     Pattern(ApiError),
+    /// Failed to prefetch files or get prefetch progress.
+    Prefetch(ApiError),
 
     // Blob cache management related errors (v2)
     /// Failed to create blob object
@@ -255,6 +325,10 @@ pub enum HttpError {
     DeleteBlobFile(ApiError),
     /// Failed to list existing blob objects
     GetBlobObjects(ApiError),
+    /// Failed to get chunk deduplication database statistics
+    CasStats(ApiError),
+    /// Failed to garbage collect chunk deduplication database
+    CasGc(ApiError),
 }
 
 #[derive(Serialize, Debug)]
@@ -269,3 +343,52 @@ impl From<ErrorMessage> for Vec<u8> {
         serde_json::to_vec(&msg).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_cmd_id_map() {
+        let config = r#"{
+            "version": 2,
+            "id": "my_id",
+            "backend": { "type": "localfs", "localfs": { "dir": "/tmp" } },
+            "rafs": { "mode": "direct" }
+        }"#;
+        let mut cmd = ApiMountCmd {
+            source: "/tmp/bootstrap".to_string(),
+            fs_type: "rafs".to_string(),
+            config: config.to_string(),
+            prefetch_files: None,
+            uid_map: None,
+            gid_map: None,
+        };
+        assert_eq!(cmd.get_config().unwrap(), config);
+
+        cmd.uid_map = Some(vec![IdMapRange {
+            inside: 0,
+            outside: 100000,
+            count: 65536,
+        }]);
+        let cfg = ConfigV2::from_str(&cmd.get_config().unwrap()).unwrap();
+        let rafs = cfg.rafs.unwrap();
+        assert_eq!(rafs.uid_map.len(), 1);
+        assert_eq!(rafs.uid_map[0].outside, 100000);
+        assert!(rafs.gid_map.is_empty());
+
+        cmd.gid_map = Some(vec![
+            IdMapRange {
+                inside: 0,
+                outside: 100000,
+                count: 10,
+            },
+            IdMapRange {
+                inside: 5,
+                outside: 200000,
+                count: 10,
+            },
+        ]);
+        assert!(cmd.get_config().is_err());
+    }
+}
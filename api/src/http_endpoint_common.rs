@@ -3,7 +3,7 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use dbs_uhttp::{Method, Request, Response};
+use dbs_uhttp::{MediaType, Method, Request, Response};
 
 use crate::http::{ApiError, ApiRequest, ApiResponse, ApiResponsePayload, HttpError};
 use crate::http_handler::{
@@ -129,6 +129,28 @@ impl EndpointHandler for MetricsBlobcacheHandler {
     }
 }
 
+/// Get all metrics in the Prometheus text exposition format.
+pub struct MetricsPrometheusHandler {}
+impl EndpointHandler for MetricsPrometheusHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => match kicker(ApiRequest::ExportPrometheusMetrics) {
+                Ok(ApiResponsePayload::PrometheusMetrics(d)) => {
+                    let mut r = success_response(Some(d));
+                    r.set_content_type(MediaType::PlainText);
+                    Ok(r)
+                }
+                r => Ok(convert_to_response(r, HttpError::PrometheusMetrics)),
+            },
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
 /// Mount a filesystem.
 pub struct MountHandler {}
 impl EndpointHandler for MountHandler {
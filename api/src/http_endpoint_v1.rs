@@ -34,6 +34,7 @@ fn convert_to_response<O: FnOnce(ApiError) -> HttpError>(api_resp: ApiResponse,
                 FsFilesPatterns(d) => success_response(Some(d)),
                 FsBackendInfo(d) => success_response(Some(d)),
                 FsInflightMetrics(d) => success_response(Some(d)),
+                FsPrefetchJob(d) => success_response(Some(d)),
                 _ => panic!("Unexpected response message from API service"),
             }
         }
@@ -67,7 +68,7 @@ impl EndpointHandler for InfoHandler {
     }
 }
 
-/// Get filesystem backend information.
+/// Get filesystem backend information and update storage backend configuration.
 pub struct FsBackendInfo {}
 impl EndpointHandler for FsBackendInfo {
     fn handle_request(
@@ -75,16 +76,52 @@ impl EndpointHandler for FsBackendInfo {
         req: &Request,
         kicker: &dyn Fn(ApiRequest) -> ApiResponse,
     ) -> HttpResult {
+        let mountpoint = extract_query_part(req, "mountpoint").ok_or_else(|| {
+            HttpError::QueryString("'mountpoint' should be specified in query string".to_string())
+        })?;
         match (req.method(), req.body.as_ref()) {
             (Method::Get, None) => {
-                let mountpoint = extract_query_part(req, "mountpoint").ok_or_else(|| {
-                    HttpError::QueryString(
-                        "'mountpoint' should be specified in query string".to_string(),
-                    )
-                })?;
                 let r = kicker(ApiRequest::ExportFsBackendInfo(mountpoint));
                 Ok(convert_to_response(r, HttpError::FsBackendInfo))
             }
+            (Method::Put, Some(body)) => {
+                let config = parse_body(body)?;
+                let r = kicker(ApiRequest::UpdateFsBackend(mountpoint, Box::new(config)));
+                Ok(convert_to_response(r, HttpError::FsBackendUpdate))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+/// Prefetch files of a mounted filesystem and get progress of prefetch jobs.
+pub struct MountPrefetchHandler {}
+impl EndpointHandler for MountPrefetchHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        let mountpoint = extract_query_part(req, "mountpoint").ok_or_else(|| {
+            HttpError::QueryString("'mountpoint' should be specified in query string".to_string())
+        })?;
+        match (req.method(), req.body.as_ref()) {
+            (Method::Post, Some(body)) => {
+                let cmd = parse_body(body)?;
+                let r = kicker(ApiRequest::PrefetchFiles(mountpoint, cmd));
+                Ok(convert_to_response(r, HttpError::Prefetch))
+            }
+            (Method::Get, None) => {
+                let job_id = extract_query_part(req, "job_id")
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .ok_or_else(|| {
+                        HttpError::QueryString(
+                            "'job_id' should be specified in query string".to_string(),
+                        )
+                    })?;
+                let r = kicker(ApiRequest::GetPrefetchProgress(mountpoint, job_id));
+                Ok(convert_to_response(r, HttpError::Prefetch))
+            }
             _ => Err(HttpError::BadRequest),
         }
     }
@@ -120,7 +157,11 @@ impl EndpointHandler for MetricsFsAccessPatternHandler {
         match (req.method(), req.body.as_ref()) {
             (Method::Get, None) => {
                 let id = extract_query_part(req, "id");
-                let r = kicker(ApiRequest::ExportFsAccessPatterns(id));
+                let r = match extract_query_part(req, "format").as_deref() {
+                    None | Some("pattern") => kicker(ApiRequest::ExportFsAccessPatterns(id)),
+                    Some("prefetch") => kicker(ApiRequest::ExportFsPrefetchList(id)),
+                    Some(_) => return Err(HttpError::BadRequest),
+                };
                 Ok(convert_to_response(r, HttpError::Pattern))
             }
             _ => Err(HttpError::BadRequest),
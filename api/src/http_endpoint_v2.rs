@@ -34,6 +34,7 @@ fn convert_to_response<O: FnOnce(ApiError) -> HttpError>(api_resp: ApiResponse,
                 Empty => success_response(None),
                 DaemonInfo(d) => success_response(Some(d)),
                 BlobObjectList(d) => success_response(Some(d)),
+                CasStats(d) => success_response(Some(d)),
                 _ => panic!("Unexpected response message from API service"),
             }
         }
@@ -110,3 +111,39 @@ impl EndpointHandler for BlobObjectListHandlerV2 {
         }
     }
 }
+
+/// Get statistics information about the chunk deduplication database.
+pub struct CasHandlerV2 {}
+impl EndpointHandler for CasHandlerV2 {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::GetCasStats);
+                Ok(convert_to_response(r, HttpError::CasStats))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+/// Garbage collect stale records from the chunk deduplication database.
+pub struct CasGcHandlerV2 {}
+impl EndpointHandler for CasGcHandlerV2 {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Put, None) => {
+                let r = kicker(ApiRequest::CasGc);
+                Ok(convert_to_response(r, HttpError::CasGc))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
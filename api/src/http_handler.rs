@@ -7,7 +7,7 @@ use std::sync::Arc;
 use std::time::SystemTime;
 use std::{fs, thread};
 
-use dbs_uhttp::{Body, HttpServer, MediaType, Request, Response, ServerError, StatusCode, Version};
+use dbs_uhttp::{Body, HttpServer, Request, Response, ServerError, StatusCode, Version};
 
 use http::uri::Uri;
 use mio::unix::SourceFd;
@@ -20,14 +20,16 @@ use crate::http::{
     MetricsErrorKind,
 };
 use crate::http_endpoint_common::{
-    EventsHandler, ExitHandler, MetricsBackendHandler, MetricsBlobcacheHandler, MountHandler,
-    SendFuseFdHandler, StartHandler, TakeoverFuseFdHandler,
+    EventsHandler, ExitHandler, MetricsBackendHandler, MetricsBlobcacheHandler,
+    MetricsPrometheusHandler, MountHandler, SendFuseFdHandler, StartHandler, TakeoverFuseFdHandler,
 };
 use crate::http_endpoint_v1::{
     FsBackendInfo, InfoHandler, MetricsFsAccessPatternHandler, MetricsFsFilesHandler,
-    MetricsFsGlobalHandler, MetricsFsInflightHandler, HTTP_ROOT_V1,
+    MetricsFsGlobalHandler, MetricsFsInflightHandler, MountPrefetchHandler, HTTP_ROOT_V1,
+};
+use crate::http_endpoint_v2::{
+    BlobObjectListHandlerV2, CasGcHandlerV2, CasHandlerV2, InfoV2Handler, HTTP_ROOT_V2,
 };
-use crate::http_endpoint_v2::{BlobObjectListHandlerV2, InfoV2Handler, HTTP_ROOT_V2};
 
 const EXIT_TOKEN: Token = Token(usize::MAX);
 const REQUEST_TOKEN: Token = Token(1);
@@ -65,7 +67,9 @@ pub(crate) fn parse_body<'a, F: Deserialize<'a>>(b: &'a Body) -> std::result::Re
 /// Translate ApiError message to HTTP status code.
 pub(crate) fn translate_status_code(e: &ApiError) -> StatusCode {
     match e {
-        ApiError::DaemonAbnormal(kind) | ApiError::MountFilesystem(kind) => match kind {
+        ApiError::DaemonAbnormal(kind)
+        | ApiError::MountFilesystem(kind)
+        | ApiError::Prefetch(kind) => match kind {
             DaemonErrorKind::NotReady => StatusCode::ServiceUnavailable,
             DaemonErrorKind::Unsupported => StatusCode::NotImplemented,
             DaemonErrorKind::UnexpectedEvent(_) => StatusCode::BadRequest,
@@ -148,6 +152,7 @@ lazy_static! {
         r.routes.insert(endpoint_v1!("/mount"), Box::new(MountHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/backend"), Box::new(MetricsBackendHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/blobcache"), Box::new(MetricsBlobcacheHandler{}));
+        r.routes.insert(endpoint_v1!("/metrics/prometheus"), Box::new(MetricsPrometheusHandler{}));
 
         // Nydus API, v1
         r.routes.insert(endpoint_v1!("/daemon"), Box::new(InfoHandler{}));
@@ -156,10 +161,13 @@ lazy_static! {
         r.routes.insert(endpoint_v1!("/metrics/files"), Box::new(MetricsFsFilesHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/inflight"), Box::new(MetricsFsInflightHandler{}));
         r.routes.insert(endpoint_v1!("/metrics/pattern"), Box::new(MetricsFsAccessPatternHandler{}));
+        r.routes.insert(endpoint_v1!("/mount/prefetch"), Box::new(MountPrefetchHandler{}));
 
         // Nydus API, v2
         r.routes.insert(endpoint_v2!("/daemon"), Box::new(InfoV2Handler{}));
         r.routes.insert(endpoint_v2!("/blobs"), Box::new(BlobObjectListHandlerV2{}));
+        r.routes.insert(endpoint_v2!("/cas"), Box::new(CasHandlerV2{}));
+        r.routes.insert(endpoint_v2!("/cas/gc"), Box::new(CasGcHandlerV2{}));
 
         r
     };
@@ -222,7 +230,6 @@ fn handle_http_request(
         }
     };
     response.set_server("Nydus API");
-    response.set_content_type(MediaType::ApplicationJson);
 
     trace_api_end(&response, request.method(), begin_time);
 
@@ -337,18 +344,24 @@ mod tests {
             .routes
             .contains_key("/api/v1/daemon/fuse/takeover"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v1/mount"));
+        assert!(HTTP_ROUTES.routes.contains_key("/api/v1/mount/prefetch"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v1/metrics"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v1/metrics/files"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v1/metrics/pattern"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v1/metrics/backend"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v1/metrics/blobcache"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v1/metrics/inflight"));
+        assert!(HTTP_ROUTES
+            .routes
+            .contains_key("/api/v1/metrics/prometheus"));
     }
 
     #[test]
     fn test_http_api_routes_v2() {
         assert!(HTTP_ROUTES.routes.contains_key("/api/v2/daemon"));
         assert!(HTTP_ROUTES.routes.contains_key("/api/v2/blobs"));
+        assert!(HTTP_ROUTES.routes.contains_key("/api/v2/cas"));
+        assert!(HTTP_ROUTES.routes.contains_key("/api/v2/cas/gc"));
     }
 
     #[test]
@@ -3,6 +3,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
 use std::slice;
 
 use anyhow::{bail, Context, Result};
@@ -19,6 +21,10 @@ use crate::core::context::Artifact;
 use crate::{BlobContext, BlobManager, BuildContext, ConversionType, Feature};
 
 const VALID_BLOB_ID_LENGTH: usize = 64;
+/// Maximum size of the compression dictionary trained from file data.
+const COMPRESSION_DICT_MAX_SIZE: usize = 112 << 10;
+/// Maximum size of file data sampled to train the compression dictionary.
+const COMPRESSION_DICT_SAMPLE_SIZE: usize = COMPRESSION_DICT_MAX_SIZE * 100;
 
 /// Generator for RAFS data blob.
 pub(crate) struct Blob {}
@@ -55,6 +61,8 @@ impl Blob {
             ConversionType::TarToTarfs
             | ConversionType::TarToRef
             | ConversionType::TargzToRef
+            | ConversionType::TarZstdToRef
+            | ConversionType::TarXzToRef
             | ConversionType::EStargzToRef => {
                 // Use `sha256(tarball)` as `blob_id` for ref-type conversions.
                 if let Some((_, blob_ctx)) = blob_mgr.get_current_blob() {
@@ -163,8 +171,42 @@ impl Blob {
         Ok(())
     }
 
+    /// Train a compression dictionary by sampling the first chunk of files in blob layout order.
+    pub(crate) fn train_compression_dict(
+        ctx: &BuildContext,
+    ) -> Result<Option<compress::CompressionDict>> {
+        let (inodes, _) = BlobLayout::layout_blob_simple(&ctx.prefetch)?;
+        let mut samples = Vec::new();
+        let mut sample_size = 0;
+        for node in inodes.iter() {
+            let node = node.borrow();
+            if !node.is_reg() || node.inode.size() == 0 {
+                continue;
+            }
+            let size = std::cmp::min(node.inode.size(), ctx.chunk_size as u64) as usize;
+            let mut buf = vec![0u8; size];
+            File::open(node.path())
+                .and_then(|mut f| f.read_exact(&mut buf))
+                .with_context(|| format!("failed to read node file {:?}", node.path()))?;
+            samples.push(buf);
+            sample_size += size;
+            if sample_size >= COMPRESSION_DICT_SAMPLE_SIZE {
+                break;
+            }
+        }
+        if samples.is_empty() {
+            return Ok(None);
+        }
+
+        let dict = compress::CompressionDict::train(&samples, COMPRESSION_DICT_MAX_SIZE)
+            .context("failed to train compression dictionary")?;
+        Ok(Some(dict))
+    }
+
     fn get_compression_algorithm_for_meta(ctx: &BuildContext) -> compress::Algorithm {
-        if ctx.conversion_type.is_to_ref() {
+        // The compression dictionary is stored in the blob meta, so it can't be used to compress
+        // the blob meta itself.
+        if ctx.conversion_type.is_to_ref() || ctx.compressor.need_dict() {
             compress::Algorithm::Zstd
         } else {
             ctx.compressor
@@ -186,6 +228,7 @@ impl Blob {
         let cipher_obj = &blob_ctx.cipher_object;
         let cipher_ctx = &blob_ctx.cipher_ctx;
         let blob_meta_info = &blob_ctx.blob_meta_info;
+        let mut dict_buf = Vec::new();
         let mut ci_data = blob_meta_info.as_byte_slice();
         let mut inflate_buf = Vec::new();
         let mut header = blob_ctx.blob_meta_header;
@@ -209,6 +252,13 @@ impl Blob {
         } else if ctx.blob_tar_reader.is_some() {
             header.set_separate_blob(true);
         };
+        // Append the compression dictionary to the chunk compression context table.
+        if let Some(dict) = blob_ctx.blob_compression_dict.as_ref() {
+            header.set_ci_dict_offset(ci_data.len() as u64);
+            header.set_ci_dict_size(dict.as_bytes().len() as u32);
+            dict_buf = [ci_data, dict.as_bytes()].concat();
+            ci_data = &dict_buf;
+        }
         let mut compressor = Self::get_compression_algorithm_for_meta(ctx);
         let (compressed_data, compressed) = compress::compress(ci_data, compressor)
             .with_context(|| "failed to compress blob chunk info array".to_string())?;
@@ -266,7 +316,9 @@ impl Blob {
         // Generate ToC entry for `blob.meta` and write chunk digest array.
         if ctx.features.is_enabled(Feature::BlobToc) {
             let mut hasher = RafsDigest::hasher(digest::Algorithm::Sha256);
-            let ci_data = if ctx.blob_features.contains(BlobFeatures::BATCH)
+            let ci_data = if !dict_buf.is_empty() {
+                dict_buf.as_slice()
+            } else if ctx.blob_features.contains(BlobFeatures::BATCH)
                 || ctx.blob_features.contains(BlobFeatures::ZRAN)
             {
                 inflate_buf.as_slice()
@@ -360,5 +412,14 @@ mod tests {
         };
         let compressor = Blob::get_compression_algorithm_for_meta(&ctx);
         assert_eq!(compressor, compress::Algorithm::Zstd);
+
+        //DirectoryToRafs with compression dictionary
+        ctx = BuildContext {
+            conversion_type: ConversionType::DirectoryToRafs,
+            compressor: compress::Algorithm::ZstdDict,
+            ..ctx
+        };
+        let compressor = Blob::get_compression_algorithm_for_meta(&ctx);
+        assert_eq!(compressor, compress::Algorithm::Zstd);
     }
 }
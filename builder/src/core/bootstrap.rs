@@ -5,10 +5,14 @@
 
 use anyhow::{Context, Error, Result};
 use nydus_utils::digest::{self, RafsDigest};
+use std::ffi::OsStr;
 use std::ops::Deref;
 
-use nydus_rafs::metadata::layout::{RafsBlobTable, RAFS_V5_ROOT_INODE};
+use nydus_rafs::metadata::layout::{
+    RafsBlobTable, RAFS_V5_ROOT_INODE, RAFS_XATTR_VERITY, RAFS_XATTR_VERITY_TREE,
+};
 use nydus_rafs::metadata::{RafsSuper, RafsSuperConfig, RafsSuperFlags};
+use nydus_utils::verity::FileVerityTree;
 
 use crate::{ArtifactStorage, BlobManager, BootstrapContext, BootstrapManager, BuildContext, Tree};
 
@@ -157,6 +161,22 @@ impl Bootstrap {
                     .insert(key, vec![child.node.clone()]);
             }
 
+            // Reserve space for fs-verity digests of files whose data is dumped after building
+            // the bootstrap, and drop stale digests copied from the source files.
+            if child_node.is_reg() && !child_node.overlay.is_lower_layer() {
+                let key = OsStr::new(RAFS_XATTR_VERITY);
+                let tree_key = OsStr::new(RAFS_XATTR_VERITY_TREE);
+                let has_digest = child_node.info.xattrs.get(key).is_some();
+                let has_tree = child_node.info.xattrs.get(tree_key).is_some();
+                if ctx.verity_files && !(has_digest && has_tree) {
+                    let tree = FileVerityTree::with_file_size(child_node.inode.size());
+                    child_node.set_verity(&RafsDigest::default(), &tree)?;
+                } else if !ctx.verity_files && (has_digest || has_tree) {
+                    child_node.remove_xattr(key);
+                    child_node.remove_xattr(tree_key);
+                }
+            }
+
             // update bootstrap_ctx.offset for rafs v6 non-dir nodes.
             if !child_node.is_dir() && ctx.fs_version.is_v6() {
                 child_node.v6_set_offset(bootstrap_ctx, v6_hardlink_offset, block_size)?;
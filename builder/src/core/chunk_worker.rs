@@ -0,0 +1,223 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Worker threads to hash and compress file chunks for the data blob.
+//!
+//! Chunks of a file are read in order by the builder thread, handed over to the shared worker
+//! threads through a bounded channel, and results are sent back through a bounded channel owned
+//! by the builder thread, which then dumps chunks into the data blob in order.
+
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Context, Result};
+use nydus_utils::digest::{self, RafsDigest};
+use nydus_utils::{compress, crc32};
+
+// Worker threads shared by all builds in the process, created on first use.
+static CHUNK_WORKERS: Mutex<Option<Arc<ChunkWorkers>>> = Mutex::new(None);
+
+/// Chunk data compressed ahead by worker threads.
+pub(crate) struct CompressedChunk {
+    pub data: Vec<u8>,
+    pub is_compressed: bool,
+    pub algorithm: compress::Algorithm,
+}
+
+/// Operations to apply to chunk data.
+pub(crate) enum ChunkTask {
+    /// Compute chunk digest and optional crc32 checksum.
+    Digest(digest::Algorithm, crc32::Algorithm),
+    /// Compress chunk data with optional compression dictionary.
+    Compress(compress::Algorithm, Option<Arc<compress::CompressionDict>>),
+}
+
+/// Result of a `ChunkTask`, which carries back the chunk data.
+pub(crate) enum ChunkOutput {
+    Digested {
+        index: u32,
+        data: Vec<u8>,
+        id: RafsDigest,
+        crc: Option<u32>,
+    },
+    Compressed {
+        index: u32,
+        data: Vec<u8>,
+        result: Result<CompressedChunk>,
+    },
+}
+
+/// Request to apply a `ChunkTask` to the `index`-th chunk of a file.
+pub(crate) struct ChunkJob {
+    pub index: u32,
+    pub data: Vec<u8>,
+    pub task: ChunkTask,
+    pub result: SyncSender<ChunkOutput>,
+}
+
+impl ChunkJob {
+    fn run(self) {
+        let ChunkJob {
+            index,
+            data,
+            task,
+            result,
+        } = self;
+        let output = match task {
+            ChunkTask::Digest(digester, crc32_algorithm) => {
+                let crc = if crc32_algorithm != crc32::Algorithm::None {
+                    Some(crc32::Crc32::new(crc32_algorithm).from_buf(&data))
+                } else {
+                    None
+                };
+                let id = RafsDigest::from_buf(&data, digester);
+                ChunkOutput::Digested {
+                    index,
+                    data,
+                    id,
+                    crc,
+                }
+            }
+            ChunkTask::Compress(algorithm, dict) => {
+                let result = compress::compress_with_dict(&data, algorithm, dict.as_deref())
+                    .map(|(compressed, is_compressed)| CompressedChunk {
+                        data: compressed.into_owned(),
+                        is_compressed,
+                        algorithm,
+                    })
+                    .with_context(|| "failed to compress node file".to_string());
+                ChunkOutput::Compressed {
+                    index,
+                    data,
+                    result,
+                }
+            }
+        };
+        // The builder thread may have bailed out on errors, so just drop the result.
+        let _ = result.send(output);
+    }
+}
+
+/// Pool of worker threads to hash and compress file chunks.
+pub(crate) struct ChunkWorkers {
+    threads: usize,
+    sender: Option<SyncSender<ChunkJob>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl ChunkWorkers {
+    fn new(threads: usize) -> Result<Self> {
+        let (sender, receiver) = sync_channel::<ChunkJob>(threads * 2);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut handles = Vec::with_capacity(threads);
+        for idx in 0..threads {
+            let receiver = receiver.clone();
+            let handle = thread::Builder::new()
+                .name(format!("chunk_worker_{}", idx))
+                .spawn(move || Self::run(receiver))
+                .context("failed to create chunk worker thread")?;
+            handles.push(handle);
+        }
+
+        Ok(ChunkWorkers {
+            threads,
+            sender: Some(sender),
+            handles,
+        })
+    }
+
+    /// Get the shared pool with `threads` worker threads, and create it if needed.
+    pub fn get(threads: usize) -> Result<Arc<ChunkWorkers>> {
+        let mut guard = CHUNK_WORKERS.lock().unwrap();
+        match guard.as_ref() {
+            Some(workers) if workers.threads == threads => Ok(workers.clone()),
+            _ => {
+                let workers = Arc::new(ChunkWorkers::new(threads)?);
+                *guard = Some(workers.clone());
+                Ok(workers)
+            }
+        }
+    }
+
+    /// Queue a job for worker threads, blocking if there are too many pending jobs.
+    pub fn submit(&self, job: ChunkJob) -> Result<()> {
+        self.sender
+            .as_ref()
+            .ok_or_else(|| anyhow!("chunk workers have been stopped"))?
+            .send(job)
+            .map_err(|_| anyhow!("chunk worker threads exited unexpectedly"))
+    }
+
+    fn run(receiver: Arc<Mutex<Receiver<ChunkJob>>>) {
+        loop {
+            let job = receiver.lock().unwrap().recv();
+            match job {
+                Ok(job) => job.run(),
+                // All senders have been dropped, the pool is being destroyed.
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl Drop for ChunkWorkers {
+    fn drop(&mut self) {
+        self.sender.take();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_workers() {
+        let workers = ChunkWorkers::get(2).unwrap();
+
+        let (tx, rx) = sync_channel(4);
+        for index in 0..2u32 {
+            let task = if index == 0 {
+                ChunkTask::Digest(digest::Algorithm::Sha256, crc32::Algorithm::Crc32Iscsi)
+            } else {
+                ChunkTask::Compress(compress::Algorithm::Zstd, None)
+            };
+            let job = ChunkJob {
+                index,
+                data: vec![0x5a; 0x1000],
+                task,
+                result: tx.clone(),
+            };
+            workers.submit(job).unwrap();
+        }
+
+        for _ in 0..2 {
+            match rx.recv().unwrap() {
+                ChunkOutput::Digested {
+                    index,
+                    data,
+                    id,
+                    crc,
+                } => {
+                    assert_eq!(index, 0);
+                    assert_eq!(id, RafsDigest::from_buf(&data, digest::Algorithm::Sha256));
+                    assert!(crc.is_some());
+                }
+                ChunkOutput::Compressed {
+                    index,
+                    data,
+                    result,
+                } => {
+                    assert_eq!(index, 1);
+                    let compressed = result.unwrap();
+                    assert!(compressed.is_compressed);
+                    assert!(compressed.data.len() < data.len());
+                }
+            }
+        }
+    }
+}
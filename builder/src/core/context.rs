@@ -35,6 +35,7 @@ use nydus_rafs::metadata::layout::RafsBlobTable;
 use nydus_rafs::metadata::{Inode, RAFS_DEFAULT_CHUNK_SIZE};
 use nydus_rafs::metadata::{RafsSuperFlags, RafsVersion};
 use nydus_rafs::RafsIoWrite;
+use nydus_storage::backend::{BlobUploader, BlobWriter};
 use nydus_storage::device::{BlobFeatures, BlobInfo};
 use nydus_storage::factory::BlobFactory;
 use nydus_storage::meta::toc::{TocEntryList, TocLocation};
@@ -69,6 +70,8 @@ pub enum ConversionType {
     TarToRafs,
     TarToRef,
     TarToTarfs,
+    TarZstdToRef,
+    TarXzToRef,
 }
 
 impl Default for ConversionType {
@@ -93,6 +96,8 @@ impl FromStr for ConversionType {
             "tar-rafs" => Ok(Self::TarToRafs),
             "tar-stargz" => Ok(Self::TarToStargz),
             "tar-tarfs" => Ok(Self::TarToTarfs),
+            "tarzstd-ref" => Ok(Self::TarZstdToRef),
+            "tarxz-ref" => Ok(Self::TarXzToRef),
             // kept for backward compatibility
             "directory" => Ok(Self::DirectoryToRafs),
             "stargz_index" => Ok(Self::EStargzIndexToRef),
@@ -117,6 +122,8 @@ impl fmt::Display for ConversionType {
             ConversionType::TarToRef => write!(f, "tar-ref"),
             ConversionType::TarToStargz => write!(f, "tar-stargz"),
             ConversionType::TarToTarfs => write!(f, "tar-tarfs"),
+            ConversionType::TarZstdToRef => write!(f, "tarzstd-ref"),
+            ConversionType::TarXzToRef => write!(f, "tarxz-ref"),
         }
     }
 }
@@ -131,6 +138,8 @@ impl ConversionType {
                 | ConversionType::TargzToRef
                 | ConversionType::TarToRef
                 | ConversionType::TarToTarfs
+                | ConversionType::TarZstdToRef
+                | ConversionType::TarXzToRef
         )
     }
 }
@@ -408,6 +417,53 @@ impl Artifact for ArtifactWriter {
     }
 }
 
+/// ArtifactUploadWriter streams blob data to remote storage like container image registry.
+pub struct ArtifactUploadWriter {
+    pos: u64,
+    writer: Box<dyn BlobWriter>,
+}
+
+impl ArtifactUploadWriter {
+    /// Create a new instance of [ArtifactUploadWriter] to upload a blob by `uploader`.
+    pub fn new(uploader: &dyn BlobUploader) -> Result<Self> {
+        let writer = uploader
+            .create_writer()
+            .map_err(|e| anyhow!("failed to start uploading blob, {}", e))?;
+        Ok(Self { pos: 0, writer })
+    }
+}
+
+impl Write for ArtifactUploadWriter {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let n = self.writer.write(bytes)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Artifact for ArtifactUploadWriter {
+    fn pos(&self) -> Result<u64> {
+        Ok(self.pos)
+    }
+
+    /// Commit the uploaded blob with digest `name`, or cancel the upload if `name` is None.
+    fn finalize(&mut self, name: Option<String>) -> Result<()> {
+        if let Some(n) = name {
+            self.writer
+                .commit(&n)
+                .map_err(|e| anyhow!("failed to upload blob {}, {}", n, e))
+        } else {
+            self.writer
+                .abort()
+                .map_err(|e| anyhow!("failed to cancel uploading blob, {}", e))
+        }
+    }
+}
+
 pub struct BlobCacheGenerator {
     blob_data: Mutex<ArtifactFileWriter>,
     blob_meta: Mutex<ArtifactFileWriter>,
@@ -528,6 +584,8 @@ pub struct BlobContext {
 
     /// Whether the blob is from external storage backend.
     pub external: bool,
+    /// Compression dictionary for chunk data, stored in the blob meta.
+    pub blob_compression_dict: Option<Arc<compress::CompressionDict>>,
 }
 
 impl BlobContext {
@@ -581,6 +639,7 @@ impl BlobContext {
             cipher_ctx,
 
             external,
+            blob_compression_dict: None,
         };
 
         blob_ctx
@@ -958,9 +1017,11 @@ impl BlobManager {
         };
         let mut blob_features = ctx.blob_features;
         let mut compressor = ctx.compressor;
+        let mut compression_dict = ctx.blob_compression_dict.clone();
         if self.external {
             blob_features.insert(BlobFeatures::EXTERNAL);
             compressor = compress::Algorithm::None;
+            compression_dict = None;
         }
         let mut blob_ctx = BlobContext::new(
             ctx.blob_id.clone(),
@@ -974,6 +1035,7 @@ impl BlobManager {
             self.external,
         );
         blob_ctx.set_chunk_size(ctx.chunk_size);
+        blob_ctx.blob_compression_dict = compression_dict;
         blob_ctx.set_meta_info_enabled(
             ctx.fs_version == RafsVersion::V6 && ctx.conversion_type != ConversionType::TarToTarfs,
         );
@@ -1378,10 +1440,14 @@ pub struct BuildContext {
 
     /// Storage writing blob to single file or a directory.
     pub blob_storage: Option<ArtifactStorage>,
+    /// Upload the generated data blob to remote storage instead of `blob_storage`.
+    pub blob_uploader: Option<Arc<dyn BlobUploader>>,
     pub external_blob_storage: Option<ArtifactStorage>,
     pub blob_zran_generator: Option<Mutex<ZranContextGenerator<File>>>,
     pub blob_batch_generator: Option<Mutex<BatchContextGenerator>>,
     pub blob_tar_reader: Option<BufReaderInfo<File>>,
+    /// Compression dictionary trained from file data, for the `zstd_dict` compressor.
+    pub blob_compression_dict: Option<Arc<compress::CompressionDict>>,
     pub blob_features: BlobFeatures,
     pub blob_inline_meta: bool,
 
@@ -1390,6 +1456,13 @@ pub struct BuildContext {
     /// Generate the blob cache and blob meta
     pub blob_cache_generator: Option<BlobCacheGenerator>,
 
+    /// Number of worker threads to hash and compress file data.
+    pub threads: usize,
+    /// Cache file to reuse chunks of unchanged files from the parent image.
+    pub incremental_cache: Option<PathBuf>,
+    /// Generate fs-verity digests for regular files.
+    pub verity_files: bool,
+
     /// Whether is chunkdict.
     pub is_chunkdict_generated: bool,
     /// Nydus attributes for different build behavior.
@@ -1457,9 +1530,11 @@ impl BuildContext {
 
             prefetch,
             blob_storage,
+            blob_uploader: None,
             external_blob_storage,
             blob_zran_generator: None,
             blob_batch_generator: None,
+            blob_compression_dict: None,
             blob_tar_reader: None,
             blob_features,
             blob_inline_meta,
@@ -1469,6 +1544,9 @@ impl BuildContext {
             configuration: Arc::new(ConfigV2::default()),
             blob_cache_generator: None,
             is_chunkdict_generated: false,
+            threads: 1,
+            incremental_cache: None,
+            verity_files: false,
 
             attributes,
         }
@@ -1490,6 +1568,17 @@ impl BuildContext {
         self.configuration = config;
     }
 
+    /// Create a writer to store the generated data blob.
+    pub fn create_blob_writer(&self) -> Result<Box<dyn Artifact>> {
+        if let Some(uploader) = self.blob_uploader.as_ref() {
+            Ok(Box::new(ArtifactUploadWriter::new(uploader.as_ref())?))
+        } else if let Some(blob_stor) = self.blob_storage.clone() {
+            Ok(Box::new(ArtifactWriter::new(blob_stor)?))
+        } else {
+            Ok(Box::<NoopArtifactWriter>::default())
+        }
+    }
+
     pub fn set_is_chunkdict(&mut self, is_chunkdict: bool) {
         self.is_chunkdict_generated = is_chunkdict;
     }
@@ -1517,9 +1606,11 @@ impl Default for BuildContext {
 
             prefetch: Prefetch::default(),
             blob_storage: None,
+            blob_uploader: None,
             external_blob_storage: None,
             blob_zran_generator: None,
             blob_batch_generator: None,
+            blob_compression_dict: None,
             blob_tar_reader: None,
             blob_features: BlobFeatures::empty(),
             has_xattr: true,
@@ -1528,6 +1619,9 @@ impl Default for BuildContext {
             configuration: Arc::new(ConfigV2::default()),
             blob_cache_generator: None,
             is_chunkdict_generated: false,
+            threads: 1,
+            incremental_cache: None,
+            verity_files: false,
 
             attributes: Attributes::default(),
         }
@@ -1547,6 +1641,8 @@ pub struct BuildOutput {
     pub bootstrap_path: Option<String>,
     /// File path for the external metadata blob.
     pub external_bootstrap_path: Option<String>,
+    /// Digest of the metadata blob pushed to registry.
+    pub bootstrap_digest: Option<String>,
 }
 
 impl fmt::Display for BuildOutput {
@@ -1556,6 +1652,9 @@ impl fmt::Display for BuildOutput {
             "meta blob path: {}",
             self.bootstrap_path.as_deref().unwrap_or("<none>")
         )?;
+        if let Some(digest) = self.bootstrap_digest.as_ref() {
+            writeln!(f, "meta blob digest: sha256:{}", digest)?;
+        }
         writeln!(
             f,
             "data blob size: 0x{:x}",
@@ -1602,6 +1701,7 @@ impl BuildOutput {
             blob_size,
             bootstrap_path,
             external_bootstrap_path,
+            bootstrap_digest: None,
         })
     }
 }
@@ -1651,6 +1751,8 @@ mod tests {
                 }),
                 oss: None,
                 s3: None,
+                azure: None,
+                gcs: None,
                 registry: None,
                 http_proxy: None,
             }),
@@ -0,0 +1,543 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Incremental build to reuse chunks of unchanged files from the parent image.
+//!
+//! Rebuilding an image from a directory needs to read, digest and compress all files, even if
+//! only a few of them have been changed since the parent image was built. The incremental cache
+//! records file attributes of the source directory and digests of chunk lists for all regular
+//! files of the last build. When building a new layer over the parent image, a file is treated
+//! as unchanged if its attributes match the cache and the parent image has the same chunk list
+//! for it, so chunks are reused from the parent image instead of reading the file again.
+//!
+//! Chunks are only reused if the RAFS version, chunk size, compressor and digester of the current
+//! build match both the last build recorded by the cache and the parent image.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nydus_rafs::metadata::layout::{RAFS_XATTR_VERITY, RAFS_XATTR_VERITY_TREE};
+use nydus_rafs::metadata::{RafsSuper, RafsVersion};
+use nydus_utils::compress;
+use nydus_utils::digest::{self, DigestHasher, RafsDigest};
+use nydus_utils::verity::{parse_fsverity_measurement, FileVerityTree};
+use serde::{Deserialize, Serialize};
+
+use super::chunk_dict::HashChunkDict;
+use super::context::BuildContext;
+use super::node::{ChunkSource, NodeChunk};
+use super::tree::{Tree, TreeNode};
+
+/// Attributes of a source file to detect file changes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStat {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    ctime: i64,
+    ctime_nsec: i64,
+    ino: u64,
+    dev: u64,
+}
+
+impl FileStat {
+    fn new(path: &Path) -> Result<Self> {
+        let md = fs::symlink_metadata(path)
+            .with_context(|| format!("failed to get metadata of {:?}", path))?;
+        Ok(FileStat {
+            size: md.size(),
+            mtime: md.mtime(),
+            mtime_nsec: md.mtime_nsec(),
+            ctime: md.ctime(),
+            ctime_nsec: md.ctime_nsec(),
+            ino: md.ino(),
+            dev: md.dev(),
+        })
+    }
+}
+
+/// Build parameters which affect chunk data and layout.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct BuildParams {
+    fs_version: u32,
+    chunk_size: u32,
+    compressor: String,
+    digester: String,
+}
+
+impl BuildParams {
+    fn new(
+        fs_version: RafsVersion,
+        chunk_size: u32,
+        compressor: compress::Algorithm,
+        digester: digest::Algorithm,
+    ) -> Self {
+        BuildParams {
+            fs_version: fs_version.into(),
+            chunk_size,
+            compressor: compressor.to_string(),
+            digester: digester.to_string(),
+        }
+    }
+
+    fn from_ctx(ctx: &BuildContext) -> Self {
+        Self::new(ctx.fs_version, ctx.chunk_size, ctx.compressor, ctx.digester)
+    }
+
+    fn from_bootstrap(rs: &RafsSuper) -> Self {
+        let config = rs.meta.get_config();
+        Self::new(
+            config.version,
+            config.chunk_size,
+            config.compressor,
+            config.digester,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    stat: FileStat,
+    // Digest of the chunk digest list of the file.
+    digest: String,
+}
+
+/// Regular file of the source directory to build.
+pub(crate) struct SourceFile {
+    node: TreeNode,
+    stat: FileStat,
+}
+
+/// Cache of file attributes and chunk list digests of the last build.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct IncrementalCache {
+    // Build parameters of the last build, caches without them are never used.
+    #[serde(default)]
+    params: Option<BuildParams>,
+    files: HashMap<PathBuf, CacheEntry>,
+}
+
+impl IncrementalCache {
+    /// Load the cache from file, an empty cache is returned if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("failed to parse incremental cache {:?}", path)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(e).with_context(|| format!("failed to open incremental cache {:?}", path))
+            }
+        }
+    }
+
+    /// Save the cache into file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        let file = File::create(&tmp)
+            .with_context(|| format!("failed to create incremental cache {:?}", tmp))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        fs::rename(&tmp, path)
+            .with_context(|| format!("failed to rename incremental cache to {:?}", path))?;
+        Ok(())
+    }
+
+    /// Collect all non-empty regular files of the tree built from the source directory.
+    pub fn collect_files(tree: &Tree) -> Result<Vec<SourceFile>> {
+        let mut files = Vec::new();
+        tree.walk_dfs_pre(&mut |t| {
+            let node = t.borrow_mut_node();
+            if node.is_reg() && node.inode.size() > 0 {
+                files.push(SourceFile {
+                    node: t.node.clone(),
+                    stat: FileStat::new(node.path())?,
+                });
+            }
+            Ok(())
+        })?;
+        Ok(files)
+    }
+
+    /// Reuse chunks from the parent image for unchanged files, return the number of reused files.
+    pub fn reuse_parent_chunks(
+        &self,
+        ctx: &BuildContext,
+        parent_path: &Path,
+        files: &[SourceFile],
+    ) -> Result<usize> {
+        if self.files.is_empty() {
+            return Ok(0);
+        }
+        let params = BuildParams::from_ctx(ctx);
+        if self.params.as_ref() != Some(&params) {
+            warn!(
+                "incremental build: ignore cache of last build with {:?}, current {:?}",
+                self.params, params
+            );
+            return Ok(0);
+        }
+
+        let (rs, _) = RafsSuper::load_from_file(parent_path, ctx.configuration.clone(), false)
+            .with_context(|| format!("failed to load parent bootstrap {:?}", parent_path))?;
+        let parent_params = BuildParams::from_bootstrap(&rs);
+        if parent_params != params {
+            warn!(
+                "incremental build: ignore cache for parent image with {:?}, current {:?}",
+                parent_params, params
+            );
+            return Ok(0);
+        }
+        let mut dict = HashChunkDict::new(rs.meta.get_digester());
+        let parent = Tree::from_bootstrap(&rs, &mut dict)
+            .context("failed to build tree from parent bootstrap")?;
+        let mut parent_files = HashMap::new();
+        parent.walk_dfs_pre(&mut |t| {
+            let node = t.borrow_mut_node();
+            if node.is_reg() {
+                parent_files.insert(node.target().clone(), t.node.clone());
+            }
+            Ok(())
+        })?;
+
+        let mut reused = 0;
+        for file in files {
+            let mut node = file.node.borrow_mut();
+            let entry = match self.files.get(node.target()) {
+                Some(v) if v.stat == file.stat => v,
+                _ => continue,
+            };
+            let parent_node = match parent_files.get(node.target()) {
+                Some(v) => v.borrow(),
+                None => continue,
+            };
+            // The parent image must have the same content as the last build recorded.
+            if parent_node.inode.size() != file.stat.size
+                || Self::file_digest(&parent_node.chunks) != entry.digest
+            {
+                continue;
+            }
+            // Data of reused files is not read again, so fs-verity digests must be available.
+            let verity = if ctx.verity_files {
+                let xattrs = &parent_node.info.xattrs;
+                match (
+                    xattrs.get(OsStr::new(RAFS_XATTR_VERITY)),
+                    xattrs.get(OsStr::new(RAFS_XATTR_VERITY_TREE)),
+                ) {
+                    (Some(digest), Some(tree)) => Some((
+                        parse_fsverity_measurement(digest)?,
+                        FileVerityTree::decode(tree, file.stat.size)?,
+                    )),
+                    _ => continue,
+                }
+            } else {
+                None
+            };
+
+            node.chunks = parent_node
+                .chunks
+                .iter()
+                .map(|c| NodeChunk {
+                    source: ChunkSource::Parent,
+                    inner: c.inner.clone(),
+                })
+                .collect();
+            if node.inode.is_v5() {
+                // Inode digest of RAFS v5 is calculated from chunk digests, same as the dump path.
+                let mut hasher = RafsDigest::hasher(ctx.digester);
+                for chunk in node.chunks.iter() {
+                    hasher.digest_update(chunk.inner.id().as_ref());
+                }
+                node.inode.set_digest(hasher.digest_finalize());
+            }
+            if let Some((digest, tree)) = verity {
+                node.set_verity(&digest, &tree)?;
+            }
+            reused += 1;
+        }
+
+        Ok(reused)
+    }
+
+    /// Replace content of the cache with files of the current build.
+    pub fn update(&mut self, ctx: &BuildContext, files: &[SourceFile]) {
+        self.params = Some(BuildParams::from_ctx(ctx));
+        self.files.clear();
+        for file in files {
+            let node = file.node.borrow();
+            if node.chunks.is_empty() {
+                continue;
+            }
+            self.files.insert(
+                node.target().clone(),
+                CacheEntry {
+                    stat: file.stat.clone(),
+                    digest: Self::file_digest(&node.chunks),
+                },
+            );
+        }
+    }
+
+    fn file_digest(chunks: &[NodeChunk]) -> String {
+        let mut hasher = RafsDigest::hasher(digest::Algorithm::Blake3);
+        for chunk in chunks {
+            hasher.digest_update(chunk.inner.id().as_ref());
+        }
+        hasher.digest_finalize().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Attributes;
+    use crate::core::context::NoopArtifactWriter;
+    use crate::core::node::Node;
+    use crate::{
+        ArtifactStorage, BlobManager, BootstrapManager, Builder, ConversionType, DirectoryBuilder,
+        Features, Overlay, Prefetch, WhiteoutSpec,
+    };
+    use nydus_rafs::metadata::chunk::ChunkWrapper;
+    use nydus_storage::RAFS_DEFAULT_CHUNK_SIZE;
+    use std::sync::Arc;
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    fn build_ctx(source: &Path, blob_dir: &Path, cache: &Path) -> BuildContext {
+        let mut ctx = BuildContext::new(
+            String::new(),
+            true,
+            0,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::DirectoryToRafs,
+            source.to_path_buf(),
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir((
+                blob_dir.to_path_buf(),
+                String::new(),
+            ))),
+            None,
+            false,
+            Features::new(),
+            false,
+            Attributes::default(),
+        );
+        ctx.set_fs_version(RafsVersion::V6);
+        ctx.incremental_cache = Some(cache.to_path_buf());
+        ctx
+    }
+
+    // Build an image from the source directory over an optional parent image.
+    fn build(ctx: &mut BuildContext, bootstrap: &Path, parent: Option<&Path>) {
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::SingleFile(bootstrap.to_path_buf())),
+            parent.map(|p| p.to_string_lossy().to_string()),
+        );
+        let mut blob_mgr = BlobManager::new(ctx.digester, false);
+        DirectoryBuilder::new()
+            .build(ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+    }
+
+    // Get blob indexes and chunk digests of all regular files in the image.
+    fn file_chunks(ctx: &BuildContext, bootstrap: &Path) -> HashMap<PathBuf, Vec<(u32, String)>> {
+        let (rs, _) =
+            RafsSuper::load_from_file(bootstrap, ctx.configuration.clone(), false).unwrap();
+        let mut dict = HashChunkDict::new(rs.meta.get_digester());
+        let tree = Tree::from_bootstrap(&rs, &mut dict).unwrap();
+        let mut files = HashMap::new();
+        tree.walk_dfs_pre(&mut |t| {
+            let node = t.borrow_mut_node();
+            if node.is_reg() {
+                let chunks = node
+                    .chunks
+                    .iter()
+                    .map(|c| (c.inner.blob_index(), c.inner.id().to_string()))
+                    .collect();
+                files.insert(node.target().clone(), chunks);
+            }
+            Ok(())
+        })
+        .unwrap();
+        files
+    }
+
+    fn source_file(source: &Path, name: &str) -> Vec<SourceFile> {
+        let node = Node::from_fs_object(
+            RafsVersion::V6,
+            source.to_path_buf(),
+            source.join(name),
+            Overlay::UpperAddition,
+            RAFS_DEFAULT_CHUNK_SIZE as u32,
+            0,
+            true,
+            false,
+        )
+        .unwrap();
+        IncrementalCache::collect_files(&Tree::new(node)).unwrap()
+    }
+
+    #[test]
+    fn test_reuse_parent_chunks() {
+        let tmpdir = TempDir::new().unwrap();
+        let source = tmpdir.as_path().join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("unchanged"), vec![0x5au8; 0x1000]).unwrap();
+        let cache_path = tmpdir.as_path().join("cache.json");
+        let parent = tmpdir.as_path().join("parent.boot");
+        let mut ctx = build_ctx(&source, tmpdir.as_path(), &cache_path);
+        build(&mut ctx, &parent, None);
+        let cache = IncrementalCache::load(&cache_path).unwrap();
+        assert_eq!(cache.files.len(), 1);
+
+        let files = source_file(&source, "unchanged");
+        assert_eq!(cache.reuse_parent_chunks(&ctx, &parent, &files).unwrap(), 1);
+        {
+            let node = files[0].node.borrow();
+            assert_eq!(node.chunks.len(), 1);
+            assert!(node.chunks[0].source == ChunkSource::Parent);
+            assert_eq!(
+                IncrementalCache::file_digest(&node.chunks),
+                cache.files[node.target()].digest
+            );
+        }
+        // Data of reused files is not read again.
+        fs::remove_file(source.join("unchanged")).unwrap();
+        let mut blob_mgr = BlobManager::new(ctx.digester, false);
+        let mut writer = NoopArtifactWriter::default();
+        let mut buf = vec![0u8; RAFS_DEFAULT_CHUNK_SIZE as usize];
+        let size = files[0]
+            .node
+            .borrow_mut()
+            .dump_node_data(&ctx, &mut blob_mgr, &mut writer, &mut buf)
+            .unwrap();
+        assert_eq!(size, 0);
+        fs::write(source.join("unchanged"), vec![0x5au8; 0x1000]).unwrap();
+
+        // Changed files are not reused.
+        let files = source_file(&source, "unchanged");
+        assert_eq!(cache.reuse_parent_chunks(&ctx, &parent, &files).unwrap(), 0);
+        assert!(files[0].node.borrow().chunks.is_empty());
+
+        // Chunks are not reused if build parameters differ from the last build.
+        let mut cache = IncrementalCache::load(&cache_path).unwrap();
+        let mut stat = FileStat::new(&source.join("unchanged")).unwrap();
+        for entry in cache.files.values_mut() {
+            std::mem::swap(&mut entry.stat, &mut stat);
+        }
+        let files = source_file(&source, "unchanged");
+        ctx.compressor = compress::Algorithm::Lz4Block;
+        assert_eq!(cache.reuse_parent_chunks(&ctx, &parent, &files).unwrap(), 0);
+        // Or from the parent image.
+        cache.params = Some(BuildParams::from_ctx(&ctx));
+        assert_eq!(cache.reuse_parent_chunks(&ctx, &parent, &files).unwrap(), 0);
+        ctx.compressor = compress::Algorithm::Zstd;
+        cache.params = Some(BuildParams::from_ctx(&ctx));
+        assert_eq!(cache.reuse_parent_chunks(&ctx, &parent, &files).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_incremental_rebuild() {
+        let tmpdir = TempDir::new().unwrap();
+        let source = tmpdir.as_path().join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("unchanged"), vec![0x5au8; 0x1000]).unwrap();
+        fs::write(source.join("changed"), vec![0xa5u8; 0x1000]).unwrap();
+        let cache_path = tmpdir.as_path().join("cache.json");
+        let parent = tmpdir.as_path().join("parent.boot");
+        let mut ctx = build_ctx(&source, tmpdir.as_path(), &cache_path);
+        build(&mut ctx, &parent, None);
+        let parent_chunks = file_chunks(&ctx, &parent);
+
+        fs::write(source.join("changed"), vec![0x3cu8; 0x2000]).unwrap();
+        let bootstrap = tmpdir.as_path().join("rebuild.boot");
+        let mut ctx = build_ctx(&source, tmpdir.as_path(), &cache_path);
+        build(&mut ctx, &bootstrap, Some(&parent));
+        let chunks = file_chunks(&ctx, &bootstrap);
+
+        // Chunks of the unchanged file refer to the parent blob, others are in the new blob.
+        let unchanged = PathBuf::from("/unchanged");
+        assert_eq!(chunks[&unchanged], parent_chunks[&unchanged]);
+        assert_eq!(chunks[&unchanged][0].0, 0);
+        let changed = PathBuf::from("/changed");
+        assert_eq!(chunks[&changed].len(), 1);
+        assert_eq!(chunks[&changed][0].0, 1);
+        assert_ne!(chunks[&changed], parent_chunks[&changed]);
+        let expected = RafsDigest::from_buf(&[0x3cu8; 0x2000], digest::Algorithm::Sha256);
+        assert_eq!(chunks[&changed][0].1, expected.to_string());
+
+        // The cache records the current build.
+        let cache = IncrementalCache::load(&cache_path).unwrap();
+        assert_eq!(cache.params, Some(BuildParams::from_ctx(&ctx)));
+        assert_eq!(cache.files.len(), 2);
+        assert_eq!(
+            cache.files[&changed].stat,
+            FileStat::new(&source.join("changed")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_incremental_cache() {
+        let tmpdir = TempDir::new().unwrap();
+        let tmpfile = TempFile::new_in(tmpdir.as_path()).unwrap();
+        fs::write(tmpfile.as_path(), b"incremental").unwrap();
+        let node = Node::from_fs_object(
+            RafsVersion::V6,
+            tmpdir.as_path().to_path_buf(),
+            tmpfile.as_path().to_path_buf(),
+            Overlay::UpperAddition,
+            RAFS_DEFAULT_CHUNK_SIZE as u32,
+            0,
+            true,
+            false,
+        )
+        .unwrap();
+        let tree = Tree::new(node);
+        let files = IncrementalCache::collect_files(&tree).unwrap();
+        assert_eq!(files.len(), 1);
+
+        let mut chunk = ChunkWrapper::new(RafsVersion::V6);
+        chunk.set_id(RafsDigest::from_buf(
+            b"incremental",
+            digest::Algorithm::Sha256,
+        ));
+        files[0].node.borrow_mut().chunks.push(NodeChunk {
+            source: ChunkSource::Build,
+            inner: Arc::new(chunk),
+        });
+
+        let cache_path = tmpdir.as_path().join("cache.json");
+        let cache = IncrementalCache::load(&cache_path).unwrap();
+        assert!(cache.files.is_empty());
+        assert!(cache.params.is_none());
+        let mut cache = cache;
+        let ctx = BuildContext::default();
+        cache.update(&ctx, &files);
+        cache.save(&cache_path).unwrap();
+
+        let cache = IncrementalCache::load(&cache_path).unwrap();
+        assert_eq!(cache.params, Some(BuildParams::from_ctx(&ctx)));
+        assert_eq!(cache.files.len(), 1);
+        let target = files[0].node.borrow().target().clone();
+        let entry = cache.files.get(&target).unwrap();
+        assert_eq!(entry.stat, files[0].stat);
+        assert_eq!(
+            entry.digest,
+            IncrementalCache::file_digest(&files[0].node.borrow().chunks)
+        );
+
+        fs::write(tmpfile.as_path(), b"incremental-changed").unwrap();
+        let stat = FileStat::new(tmpfile.as_path()).unwrap();
+        assert_ne!(entry.stat, stat);
+    }
+}
@@ -5,8 +5,10 @@
 pub(crate) mod blob;
 pub(crate) mod bootstrap;
 pub(crate) mod chunk_dict;
+pub(crate) mod chunk_worker;
 pub(crate) mod context;
 pub(crate) mod feature;
+pub(crate) mod incremental;
 pub(crate) mod layout;
 pub(crate) mod node;
 pub(crate) mod overlay;
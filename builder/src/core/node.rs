@@ -3,6 +3,8 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Display, Formatter, Result as FmtResult};
 use std::fs::{self, File};
@@ -14,17 +16,19 @@ use std::os::linux::fs::MetadataExt;
 use std::os::macos::fs::MetadataExt;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::sync_channel;
 use std::sync::Arc;
 
 use anyhow::{anyhow, bail, Context, Error, Result};
 use nydus_rafs::metadata::chunk::ChunkWrapper;
 use nydus_rafs::metadata::inode::InodeWrapper;
 use nydus_rafs::metadata::layout::v6::EROFS_INODE_FLAT_PLAIN;
-use nydus_rafs::metadata::layout::RafsXAttrs;
+use nydus_rafs::metadata::layout::{RafsXAttrs, RAFS_XATTR_VERITY, RAFS_XATTR_VERITY_TREE};
 use nydus_rafs::metadata::{Inode, RafsVersion};
 use nydus_storage::device::BlobFeatures;
 use nydus_storage::meta::{BlobChunkInfoV2Ondisk, BlobMetaChunkInfo};
-use nydus_utils::digest::{DigestHasher, RafsDigest};
+use nydus_utils::digest::{DigestHasher, RafsDigest, RafsDigestHasher};
+use nydus_utils::verity::{fsverity_measurement, FileVerityHasher, FileVerityTree};
 use nydus_utils::{compress, crc32, crypt};
 use nydus_utils::{div_round_up, event_tracer, root_tracer, try_round_up_4k, ByteSize};
 use parse_size::parse_size;
@@ -32,6 +36,7 @@ use sha2::digest::Digest;
 
 use crate::{BlobContext, BlobManager, BuildContext, ChunkDict, ConversionType, Overlay};
 
+use super::chunk_worker::{ChunkJob, ChunkOutput, ChunkTask, ChunkWorkers, CompressedChunk};
 use super::context::Artifact;
 
 /// Filesystem root path for Unix OSs.
@@ -65,6 +70,13 @@ pub struct NodeChunk {
     pub inner: Arc<ChunkWrapper>,
 }
 
+/// Chunk read from a file, which is ready to be dumped into the data blob.
+struct PreparedChunk {
+    chunk: ChunkWrapper,
+    chunk_info: Option<BlobChunkInfoV2Ondisk>,
+    compressed: Option<CompressedChunk>,
+}
+
 impl Display for NodeChunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.inner,)
@@ -223,6 +235,11 @@ impl Node {
         blob_writer: &mut dyn Artifact,
         chunk_data_buf: &mut [u8],
     ) -> Result<u64> {
+        // Chunks of unchanged files have been reused from the parent image by incremental build.
+        if ctx.incremental_cache.is_some() && self.is_reg() && !self.chunks.is_empty() {
+            return Ok(0);
+        }
+
         let mut reader = if self.is_reg() {
             let file = File::open(self.path())
                 .with_context(|| format!("failed to open node file {:?}", self.path()))?;
@@ -358,79 +375,308 @@ impl Node {
             return Ok(0);
         }
 
+        let mut verity_hasher = if ctx.verity_files {
+            Some(FileVerityHasher::new())
+        } else {
+            None
+        };
+
         // `child_count` of regular file is reused as `chunk_count`.
-        for i in 0..self.inode.child_count() {
-            let chunk_size = ctx.chunk_size;
-            let file_offset = i as u64 * chunk_size as u64;
-            let uncompressed_size = if i == self.inode.child_count() - 1 {
-                (self.inode.size() - chunk_size as u64 * i as u64) as u32
-            } else {
-                chunk_size
+        let chunk_count = self.inode.child_count();
+        let window = self.chunk_window(ctx, blob_mgr.external);
+        if window > 1 {
+            blob_size += self.dump_file_chunks_parallel(
+                ctx,
+                blob_mgr,
+                blob_writer,
+                reader,
+                window,
+                &mut inode_hasher,
+                &mut verity_hasher,
+            )?;
+        } else {
+            for i in 0..chunk_count {
+                let size = self.chunk_data_size(ctx, i) as usize;
+                let (chunk, chunk_info) =
+                    self.read_file_chunk(ctx, reader, &mut data_buf[..size], blob_mgr.external)?;
+                let prepared = PreparedChunk {
+                    chunk,
+                    chunk_info,
+                    compressed: None,
+                };
+                blob_size += self.dump_prepared_chunk(
+                    ctx,
+                    blob_mgr,
+                    blob_writer,
+                    &data_buf[..size],
+                    i,
+                    prepared,
+                    &mut inode_hasher,
+                    &mut verity_hasher,
+                )?;
+            }
+        }
+
+        // Finish inode digest calculation
+        if let Some(h) = inode_hasher {
+            self.inode.set_digest(h.digest_finalize());
+        }
+        if let Some(h) = verity_hasher {
+            let (digest, blocks) = h.finalize();
+            let tree = FileVerityTree::from_blocks(self.inode.size(), &blocks);
+            self.set_verity(&digest, &tree)?;
+        }
+
+        Ok(blob_size)
+    }
+
+    /// Dump the `i`-th chunk of the file, with data in `chunk_data`, into the data blob.
+    #[allow(clippy::too_many_arguments)]
+    fn dump_prepared_chunk(
+        &mut self,
+        ctx: &BuildContext,
+        blob_mgr: &mut BlobManager,
+        blob_writer: &mut dyn Artifact,
+        chunk_data: &[u8],
+        i: u32,
+        prepared: PreparedChunk,
+        inode_hasher: &mut Option<RafsDigestHasher>,
+        verity_hasher: &mut Option<FileVerityHasher>,
+    ) -> Result<u64> {
+        let chunk_size = ctx.chunk_size;
+        let file_offset = i as u64 * chunk_size as u64;
+        let uncompressed_size = self.chunk_data_size(ctx, i);
+        let PreparedChunk {
+            mut chunk,
+            mut chunk_info,
+            compressed,
+        } = prepared;
+        if let Some(h) = inode_hasher.as_mut() {
+            h.digest_update(chunk.id().as_ref());
+        }
+        if let Some(h) = verity_hasher.as_mut() {
+            h.update(chunk_data);
+        }
+
+        // No need to perform chunk deduplication for tar-tarfs/external blob case.
+        if ctx.conversion_type != ConversionType::TarToTarfs && !blob_mgr.external {
+            chunk = match self.deduplicate_chunk(
+                ctx,
+                blob_mgr,
+                file_offset,
+                uncompressed_size,
+                chunk,
+            )? {
+                None => return Ok(0),
+                Some(c) => c,
             };
+        }
 
-            let chunk_data = &mut data_buf[0..uncompressed_size as usize];
-            let (mut chunk, mut chunk_info) =
-                self.read_file_chunk(ctx, reader, chunk_data, blob_mgr.external)?;
-            if let Some(h) = inode_hasher.as_mut() {
-                h.digest_update(chunk.id().as_ref());
+        let (blob_index, blob_ctx) = blob_mgr.get_or_create_current_blob(ctx)?;
+        let chunk_index = blob_ctx.alloc_chunk_index()?;
+        chunk.set_blob_index(blob_index);
+        chunk.set_index(chunk_index);
+        chunk.set_file_offset(file_offset);
+        let mut dumped_size = chunk.compressed_size();
+        if ctx.conversion_type == ConversionType::TarToTarfs {
+            chunk.set_uncompressed_offset(chunk.compressed_offset());
+            chunk.set_uncompressed_size(chunk.compressed_size());
+        } else {
+            let (info, d_size) = self.dump_file_chunk(
+                ctx,
+                blob_ctx,
+                blob_writer,
+                chunk_data,
+                &mut chunk,
+                compressed,
+            )?;
+            if info.is_some() {
+                chunk_info = info;
+            }
+            if let Some(d_size) = d_size {
+                dumped_size = d_size;
             }
+        }
+
+        let chunk = Arc::new(chunk);
+        if ctx.conversion_type != ConversionType::TarToTarfs {
+            blob_ctx.add_chunk_meta_info(&chunk, chunk_info)?;
+            blob_mgr
+                .layered_chunk_dict
+                .add_chunk(chunk.clone(), ctx.digester);
+        }
+        self.chunks.push(NodeChunk {
+            source: ChunkSource::Build,
+            inner: chunk,
+        });
+
+        Ok(dumped_size as u64)
+    }
+
+    /// Get size of data of the `i`-th chunk of the file.
+    fn chunk_data_size(&self, ctx: &BuildContext, i: u32) -> u32 {
+        if i == self.inode.child_count() - 1 {
+            (self.inode.size() - ctx.chunk_size as u64 * i as u64) as u32
+        } else {
+            ctx.chunk_size
+        }
+    }
+
+    /// Get maximum number of chunks in flight, which are hashed and compressed by worker threads.
+    ///
+    /// Chunks are still dumped into the data blob in order, so the generated blob is the same as
+    /// dumping chunks one by one.
+    fn chunk_window(&self, ctx: &BuildContext, external: bool) -> u32 {
+        if ctx.threads > 1
+            && self.inode.child_count() > 1
+            && !external
+            && ctx.conversion_type != ConversionType::TarToTarfs
+            && ctx.blob_zran_generator.is_none()
+            && ctx.blob_tar_reader.is_none()
+            && !ctx.blob_features.contains(BlobFeatures::SEPARATE)
+        {
+            cmp::min(ctx.threads as u32 * 2, self.inode.child_count())
+        } else {
+            1
+        }
+    }
 
-            // No need to perform chunk deduplication for tar-tarfs/external blob case.
-            if ctx.conversion_type != ConversionType::TarToTarfs && !blob_mgr.external {
-                chunk = match self.deduplicate_chunk(
+    /// Dump chunks of the file through a reader -> worker -> ordered writer pipeline.
+    ///
+    /// Chunks are read in order by the current thread, hashed and compressed by the shared chunk
+    /// workers, and then dumped into the data blob in order by the current thread. At most
+    /// `window` chunks are in flight, which bounds memory used to buffer chunk data.
+    #[allow(clippy::too_many_arguments)]
+    fn dump_file_chunks_parallel<R: Read>(
+        &mut self,
+        ctx: &BuildContext,
+        blob_mgr: &mut BlobManager,
+        blob_writer: &mut dyn Artifact,
+        reader: &mut R,
+        window: u32,
+        inode_hasher: &mut Option<RafsDigestHasher>,
+        verity_hasher: &mut Option<FileVerityHasher>,
+    ) -> Result<u64> {
+        let workers = ChunkWorkers::get(ctx.threads)?;
+        let (tx, rx) = sync_channel(window as usize);
+        let (algorithm, dict) = match blob_mgr.get_current_blob() {
+            Some((_, blob_ctx)) => (
+                blob_ctx.blob_compressor,
+                blob_ctx.blob_compression_dict.clone(),
+            ),
+            None => (ctx.compressor, ctx.blob_compression_dict.clone()),
+        };
+
+        let chunk_count = self.inode.child_count();
+        let mut buffers: Vec<Vec<u8>> = Vec::new();
+        let mut digests = HashMap::new();
+        let mut ready: BTreeMap<u32, (Vec<u8>, PreparedChunk)> = BTreeMap::new();
+        let mut found = HashSet::new();
+        let (mut next_read, mut next_dump, mut in_flight) = (0u32, 0u32, 0u32);
+        let mut blob_size = 0;
+        while next_dump < chunk_count {
+            while next_read < chunk_count && in_flight < window {
+                let mut data = buffers.pop().unwrap_or_default();
+                data.resize(self.chunk_data_size(ctx, next_read) as usize, 0);
+                reader
+                    .read_exact(&mut data)
+                    .with_context(|| format!("failed to read node file {:?}", self.path()))?;
+                workers.submit(ChunkJob {
+                    index: next_read,
+                    data,
+                    task: ChunkTask::Digest(ctx.digester, ctx.crc32_algorithm),
+                    result: tx.clone(),
+                })?;
+                next_read += 1;
+                in_flight += 1;
+            }
+
+            if let Some((data, prepared)) = ready.remove(&next_dump) {
+                blob_size += self.dump_prepared_chunk(
                     ctx,
                     blob_mgr,
-                    file_offset,
-                    uncompressed_size,
-                    chunk,
-                )? {
-                    None => continue,
-                    Some(c) => c,
-                };
+                    blob_writer,
+                    &data,
+                    next_dump,
+                    prepared,
+                    inode_hasher,
+                    verity_hasher,
+                )?;
+                buffers.push(data);
+                next_dump += 1;
+                in_flight -= 1;
+                continue;
             }
 
-            let (blob_index, blob_ctx) = blob_mgr.get_or_create_current_blob(ctx)?;
-            let chunk_index = blob_ctx.alloc_chunk_index()?;
-            chunk.set_blob_index(blob_index);
-            chunk.set_index(chunk_index);
-            chunk.set_file_offset(file_offset);
-            let mut dumped_size = chunk.compressed_size();
-            if ctx.conversion_type == ConversionType::TarToTarfs {
-                chunk.set_uncompressed_offset(chunk.compressed_offset());
-                chunk.set_uncompressed_size(chunk.compressed_size());
-            } else {
-                let (info, d_size) =
-                    self.dump_file_chunk(ctx, blob_ctx, blob_writer, chunk_data, &mut chunk)?;
-                if info.is_some() {
-                    chunk_info = info;
+            match rx
+                .recv()
+                .context("failed to receive result from chunk workers")?
+            {
+                ChunkOutput::Digested {
+                    index,
+                    data,
+                    id,
+                    crc,
+                } => {
+                    // Skip compressing chunks which are going to be deduplicated.
+                    let size = data.len() as u32;
+                    if blob_mgr.global_chunk_dict.get_chunk(&id, size).is_none()
+                        && blob_mgr.layered_chunk_dict.get_chunk(&id, size).is_none()
+                        && found.insert((id, size))
+                    {
+                        digests.insert(index, (id, crc));
+                        workers.submit(ChunkJob {
+                            index,
+                            data,
+                            task: ChunkTask::Compress(algorithm, dict.clone()),
+                            result: tx.clone(),
+                        })?;
+                    } else {
+                        let prepared = self.prepare_chunk(ctx, id, crc, None);
+                        ready.insert(index, (data, prepared));
+                    }
                 }
-                if let Some(d_size) = d_size {
-                    dumped_size = d_size;
+                ChunkOutput::Compressed {
+                    index,
+                    data,
+                    result,
+                } => {
+                    let (id, crc) = digests
+                        .remove(&index)
+                        .ok_or_else(|| anyhow!("unexpected compressed chunk {}", index))?;
+                    let prepared = self.prepare_chunk(ctx, id, crc, Some(result?));
+                    ready.insert(index, (data, prepared));
                 }
             }
-
-            let chunk = Arc::new(chunk);
-            blob_size += dumped_size as u64;
-            if ctx.conversion_type != ConversionType::TarToTarfs {
-                blob_ctx.add_chunk_meta_info(&chunk, chunk_info)?;
-                blob_mgr
-                    .layered_chunk_dict
-                    .add_chunk(chunk.clone(), ctx.digester);
-            }
-            self.chunks.push(NodeChunk {
-                source: ChunkSource::Build,
-                inner: chunk,
-            });
-        }
-
-        // Finish inode digest calculation
-        if let Some(h) = inode_hasher {
-            self.inode.set_digest(h.digest_finalize());
         }
 
         Ok(blob_size)
     }
 
+    /// Create a chunk with digest and checksum computed by chunk workers.
+    fn prepare_chunk(
+        &self,
+        ctx: &BuildContext,
+        id: RafsDigest,
+        crc: Option<u32>,
+        compressed: Option<CompressedChunk>,
+    ) -> PreparedChunk {
+        let mut chunk = self.inode.create_chunk();
+        chunk.set_id(id);
+        if let Some(crc) = crc {
+            chunk.set_has_crc32(true);
+            chunk.set_crc32(crc);
+        }
+        if ctx.cipher != crypt::Algorithm::None {
+            chunk.set_encrypted(true);
+        }
+        PreparedChunk {
+            chunk,
+            chunk_info: None,
+            compressed,
+        }
+    }
+
     fn set_external_chunk_crc32(
         &self,
         ctx: &BuildContext,
@@ -518,6 +764,7 @@ impl Node {
         blob_writer: &mut dyn Artifact,
         chunk_data: &[u8],
         chunk: &mut ChunkWrapper,
+        compressed: Option<CompressedChunk>,
     ) -> Result<(Option<BlobChunkInfoV2Ondisk>, Option<u32>)> {
         let d_size = chunk_data.len() as u32;
         let aligned_d_size = if ctx.aligned_chunk {
@@ -574,7 +821,8 @@ impl Node {
             }
         } else if !ctx.blob_features.contains(BlobFeatures::SEPARATE) {
             // For other case which needs to write chunk data to data blobs. Which means,
-            // `tar-ref`, `targz-ref`, `estargz-ref`, and `estargzindex-ref`, are excluded.
+            // `tar-ref`, `targz-ref`, `tarzstd-ref`, `tarxz-ref`, `estargz-ref`, and
+            // `estargzindex-ref`, are excluded.
 
             // Interrupt and dump buffered batch chunks.
             // TODO: cancel the interruption.
@@ -590,9 +838,18 @@ impl Node {
                 }
             }
 
-            let (pre_c_offset, c_size, is_compressed) =
-                Self::write_chunk_data(ctx, blob_ctx, blob_writer, chunk_data)
-                    .with_context(|| format!("failed to write chunk data {:?}", self.path()))?;
+            let (pre_c_offset, c_size, is_compressed) = match compressed {
+                Some(c) if c.algorithm == blob_ctx.blob_compressor => {
+                    Self::write_compressed_chunk_data(
+                        blob_ctx,
+                        blob_writer,
+                        &c.data,
+                        c.is_compressed,
+                    )
+                }
+                _ => Self::write_chunk_data(ctx, blob_ctx, blob_writer, chunk_data),
+            }
+            .with_context(|| format!("failed to write chunk data {:?}", self.path()))?;
             dumped_size = Some(dumped_size.unwrap_or(0) + c_size);
             chunk.set_compressed_offset(pre_c_offset);
             chunk.set_compressed_size(c_size);
@@ -613,10 +870,24 @@ impl Node {
         blob_writer: &mut dyn Artifact,
         chunk_data: &[u8],
     ) -> Result<(u64, u32, bool)> {
-        let (compressed, is_compressed) = compress::compress(chunk_data, blob_ctx.blob_compressor)
-            .with_context(|| "failed to compress node file".to_string())?;
+        let (compressed, is_compressed) = compress::compress_with_dict(
+            chunk_data,
+            blob_ctx.blob_compressor,
+            blob_ctx.blob_compression_dict.as_deref(),
+        )
+        .with_context(|| "failed to compress node file".to_string())?;
+        Self::write_compressed_chunk_data(blob_ctx, blob_writer, &compressed, is_compressed)
+    }
+
+    /// Encrypt and write compressed chunk data into the data blob.
+    fn write_compressed_chunk_data(
+        blob_ctx: &mut BlobContext,
+        blob_writer: &mut dyn Artifact,
+        compressed: &[u8],
+        is_compressed: bool,
+    ) -> Result<(u64, u32, bool)> {
         let encrypted = crypt::encrypt_with_context(
-            &compressed,
+            compressed,
             &blob_ctx.cipher_object,
             &blob_ctx.cipher_ctx,
             blob_ctx.blob_cipher != crypt::Algorithm::None,
@@ -1007,6 +1278,20 @@ impl Node {
         self.info = Arc::new(info);
     }
 
+    /// Store fs-verity digest and Merkle tree of the regular file as extended attributes.
+    pub fn set_verity(&mut self, digest: &RafsDigest, tree: &FileVerityTree) -> Result<()> {
+        let mut info = self.info.deref().clone();
+        info.xattrs.add(
+            OsString::from(RAFS_XATTR_VERITY),
+            fsverity_measurement(digest),
+        )?;
+        info.xattrs
+            .add(OsString::from(RAFS_XATTR_VERITY_TREE), tree.encode())?;
+        self.inode.set_has_xattr(true);
+        self.info = Arc::new(info);
+        Ok(())
+    }
+
     /// Delete an extend attribute with id `key`.
     pub fn remove_xattr(&mut self, key: &OsStr) {
         let mut info = self.info.deref().clone();
@@ -1022,6 +1307,7 @@ impl Node {
 mod tests {
     use std::{collections::HashMap, io::BufReader};
 
+    use nydus_utils::verity::parse_fsverity_measurement;
     use nydus_utils::{digest, BufReaderInfo};
     use vmm_sys_util::tempfile::TempFile;
 
@@ -1234,6 +1520,88 @@ mod tests {
         assert!(!node.inode.has_xattr());
     }
 
+    #[test]
+    fn test_node_dump_node_data_parallel() {
+        let chunk_size = 0x1000usize;
+        let mut data = Vec::new();
+        for i in 0..10u32 {
+            let seed = if i == 7 { 2 } else { i };
+            data.extend(
+                (0..chunk_size as u32)
+                    .map(|v| ((v / (seed + 1)) as u8).wrapping_mul(seed as u8 + 1)),
+            );
+        }
+        data.extend_from_slice(&[0x5au8; 0x234]);
+        let tmp_file = TempFile::new().unwrap();
+        std::fs::write(tmp_file.as_path(), &data).unwrap();
+        let path = tmp_file.as_path().to_path_buf();
+        let source = path.parent().unwrap().to_path_buf();
+
+        let mut outputs = Vec::new();
+        for threads in [1, 3, 4, 16] {
+            let mut node = Node::from_fs_object(
+                RafsVersion::V5,
+                source.clone(),
+                path.clone(),
+                Overlay::UpperAddition,
+                chunk_size as u32,
+                data.len() as u64,
+                true,
+                false,
+            )
+            .unwrap();
+            assert_eq!(node.inode.child_count(), 11);
+
+            let mut ctx = BuildContext::default();
+            ctx.set_chunk_size(chunk_size as u32);
+            ctx.compressor = compress::Algorithm::Zstd;
+            ctx.threads = threads;
+            ctx.verity_files = true;
+            let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256, false);
+            let blob_file = TempFile::new().unwrap();
+            let mut blob_writer = ArtifactWriter::new(crate::ArtifactStorage::SingleFile(
+                blob_file.as_path().to_path_buf(),
+            ))
+            .unwrap();
+            let mut chunk_data_buf = vec![0u8; chunk_size];
+            let size = node
+                .dump_node_data(&ctx, &mut blob_mgr, &mut blob_writer, &mut chunk_data_buf)
+                .unwrap();
+            blob_writer.finalize(Some("blob".to_string())).unwrap();
+
+            let chunks: Vec<String> = node.chunks.iter().map(|c| c.to_string()).collect();
+            assert_eq!(chunks.len(), 11);
+            let blob = std::fs::read(blob_file.as_path()).unwrap();
+            assert_eq!(blob.len() as u64, size);
+            let verity = node
+                .info
+                .xattrs
+                .get(OsStr::new(RAFS_XATTR_VERITY))
+                .unwrap()
+                .clone();
+            let tree = node
+                .info
+                .xattrs
+                .get(OsStr::new(RAFS_XATTR_VERITY_TREE))
+                .unwrap();
+            let file_size = data.len() as u64;
+            let tree = FileVerityTree::decode(tree, file_size).unwrap();
+            assert_eq!(
+                tree.file_digest(file_size),
+                parse_fsverity_measurement(&verity).unwrap()
+            );
+            outputs.push((chunks, blob, node.inode.digest().to_owned(), verity));
+        }
+
+        for output in &outputs[1..] {
+            assert_eq!(output, &outputs[0]);
+        }
+        // Data of deduplicated chunks is also covered by the fs-verity digest.
+        let mut hasher = FileVerityHasher::new();
+        hasher.update(&data);
+        assert_eq!(outputs[0].3, fsverity_measurement(&hasher.finalize().0));
+    }
+
     #[test]
     fn test_set_external_chunk_crc32() {
         let mut ctx = BuildContext {
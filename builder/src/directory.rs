@@ -4,6 +4,7 @@
 
 use std::fs;
 use std::fs::DirEntry;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Context, Result};
 use nydus_utils::{event_tracer, lazy_drop, root_tracer, timing_tracer};
@@ -15,6 +16,7 @@ use super::core::blob::Blob;
 use super::core::context::{
     ArtifactWriter, BlobManager, BootstrapManager, BuildContext, BuildOutput,
 };
+use super::core::incremental::IncrementalCache;
 use super::core::node::Node;
 use super::{build_bootstrap, dump_bootstrap, finalize_blob, Builder, Overlay, Tree, TreeNode};
 
@@ -167,6 +169,11 @@ impl DirectoryBuilder {
             "build_bootstrap"
         )?;
 
+        // Train compression dictionary before dumping chunk data.
+        if ctx.compressor.need_dict() && !blob_mgr.external {
+            ctx.blob_compression_dict = Blob::train_compression_dict(ctx)?.map(Arc::new);
+        }
+
         // Dump blob file
         timing_tracer!(
             { Blob::dump(ctx, blob_mgr, blob_writer.as_mut()) },
@@ -230,13 +237,34 @@ impl Builder for DirectoryBuilder {
         let (tree, external_tree) =
             timing_tracer!({ self.build_tree(ctx, layer_idx) }, "build_tree")?;
 
-        // Build for tree
-        let mut blob_writer: Box<dyn Artifact> = if let Some(blob_stor) = ctx.blob_storage.clone() {
-            Box::new(ArtifactWriter::new(blob_stor)?)
-        } else {
-            Box::<NoopArtifactWriter>::default()
+        // Reuse chunks of unchanged files from the parent image for incremental build.
+        let incremental = match ctx.incremental_cache.as_ref() {
+            Some(path) => {
+                let cache = IncrementalCache::load(path)?;
+                let files = IncrementalCache::collect_files(&tree)?;
+                if let Some(parent_path) = bootstrap_mgr.f_parent_path.as_ref() {
+                    let reused = timing_tracer!(
+                        { cache.reuse_parent_chunks(ctx, parent_path, &files) },
+                        "reuse_parent_chunks"
+                    )?;
+                    info!(
+                        "incremental build: reuse chunks of {}/{} files from parent image",
+                        reused,
+                        files.len()
+                    );
+                }
+                Some((path.clone(), cache, files))
+            }
+            None => None,
         };
+
+        // Build for tree
+        let mut blob_writer = ctx.create_blob_writer()?;
         let mut output = self.one_build(ctx, bootstrap_mgr, blob_mgr, &mut blob_writer, tree)?;
+        if let Some((path, mut cache, files)) = incremental {
+            cache.update(ctx, &files);
+            cache.save(&path)?;
+        }
 
         // Build for external tree
         ctx.prefetch = prefetch::Prefetch::new(prefetch::PrefetchPolicy::None)?;
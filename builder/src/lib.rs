@@ -36,12 +36,16 @@ pub use self::core::context::{
 };
 pub use self::core::feature::{Feature, Features};
 pub use self::core::node::{ChunkSource, NodeChunk};
-pub use self::core::overlay::{Overlay, WhiteoutSpec};
+pub use self::core::overlay::{
+    Overlay, WhiteoutSpec, WhiteoutType, OCISPEC_WHITEOUT_OPAQUE, OCISPEC_WHITEOUT_PREFIX,
+    OVERLAYFS_WHITEOUT_OPAQUE,
+};
 pub use self::core::prefetch::{Prefetch, PrefetchPolicy};
 pub use self::core::tree::{MetadataTreeBuilder, Tree, TreeNode};
 pub use self::directory::DirectoryBuilder;
 pub use self::merge::Merger;
 pub use self::optimize_prefetch::generate_prefetch_file_info;
+pub use self::optimize_prefetch::generate_prefetch_file_info_from_access_trace;
 pub use self::optimize_prefetch::update_ctx_from_bootstrap;
 pub use self::optimize_prefetch::OptimizePrefetch;
 pub use self::stargz::StargzBuilder;
@@ -62,6 +62,18 @@ struct PrefetchFileJson {
     ranges: Option<Vec<[u64; 2]>>,
 }
 
+/// File access pattern exported by nydusd through `/api/v1/metrics/pattern`.
+#[derive(Deserialize)]
+struct AccessPatternJson {
+    ino: u64,
+    #[serde(default)]
+    nr_read: u64,
+    #[serde(default)]
+    first_access_time_secs: u64,
+    #[serde(default)]
+    first_access_time_nanos: u32,
+}
+
 impl PrefetchBlobState {
     fn new(ctx: &BuildContext, blob_layer_num: u32, output_blob_dir_path: &Path) -> Result<Self> {
         let mut blob_info = BlobInfo::new(
@@ -439,6 +451,40 @@ pub fn generate_prefetch_file_info(prefetch_file: &Path) -> Result<Vec<PrefetchF
     Ok(prefetch_nodes)
 }
 
+/// Generate prefetch file information from file access patterns recorded by nydusd.
+///
+/// Files never read are skipped, others are ordered by their first access time.
+pub fn generate_prefetch_file_info_from_access_trace(
+    sb: &RafsSuper,
+    trace_file: &Path,
+) -> Result<Vec<PrefetchFileInfo>> {
+    let content = std::fs::read_to_string(trace_file).map_err(|e| {
+        anyhow!(
+            "failed to read access trace from {}: {}",
+            trace_file.display(),
+            e
+        )
+    })?;
+    let mut patterns: Vec<AccessPatternJson> = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("failed to parse access trace as JSON: {}", e))?;
+    patterns.retain(|p| p.nr_read != 0);
+    patterns.sort_by_key(|p| (p.first_access_time_secs, p.first_access_time_nanos, p.ino));
+
+    let inos: Vec<u64> = patterns.iter().map(|p| p.ino).collect();
+    let paths = sb.paths_from_inos(&inos)?;
+    let mut prefetch_nodes = Vec::with_capacity(inos.len());
+    for ino in inos {
+        match paths.get(&ino) {
+            Some(path) => prefetch_nodes.push(PrefetchFileInfo {
+                path: path.clone(),
+                ranges: None,
+            }),
+            None => warn!("inode {} in access trace is not found, skipping", ino),
+        }
+    }
+    Ok(prefetch_nodes)
+}
+
 fn range_overlap(chunk: &mut NodeChunk, range: &PrefetchFileRange) -> bool {
     if max(range.offset, chunk.inner.file_offset())
         <= min(
@@ -450,3 +496,37 @@ fn range_overlap(chunk: &mut NodeChunk, range: &PrefetchFileRange) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vmm_sys_util::tempfile::TempFile;
+
+    #[test]
+    fn test_generate_prefetch_file_info_from_access_trace() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let (sb, _) =
+            RafsSuper::load_from_file(&path, Arc::new(ConfigV2::default()), false).unwrap();
+
+        let trace = TempFile::new().unwrap();
+        std::fs::write(
+            trace.as_path(),
+            r#"[
+                {"ino": 136, "nr_read": 2, "first_access_time_secs": 10, "first_access_time_nanos": 5},
+                {"ino": 133, "nr_read": 1, "first_access_time_secs": 10, "first_access_time_nanos": 1},
+                {"ino": 1, "nr_read": 0, "first_access_time_secs": 0, "first_access_time_nanos": 0},
+                {"ino": 4294967295, "nr_read": 1, "first_access_time_secs": 11, "first_access_time_nanos": 0}
+            ]"#,
+        )
+        .unwrap();
+
+        let nodes = generate_prefetch_file_info_from_access_trace(&sb, trace.as_path()).unwrap();
+        let paths: Vec<&Path> = nodes.iter().map(|n| n.path.as_path()).collect();
+        assert_eq!(paths, vec![Path::new("/lib.rs"), Path::new("/sync_io.rs")]);
+        assert!(nodes.iter().all(|n| n.ranges.is_none()));
+
+        std::fs::write(trace.as_path(), "invalid").unwrap();
+        assert!(generate_prefetch_file_info_from_access_trace(&sb, trace.as_path()).is_err());
+    }
+}
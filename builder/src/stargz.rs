@@ -29,12 +29,8 @@ use nydus_utils::digest::{self, DigestData, RafsDigest};
 use nydus_utils::{lazy_drop, root_tracer, timing_tracer, try_round_up_4k, ByteSize};
 use serde::{Deserialize, Serialize};
 
-use crate::core::context::{Artifact, NoopArtifactWriter};
-
 use super::core::blob::Blob;
-use super::core::context::{
-    ArtifactWriter, BlobManager, BootstrapManager, BuildContext, BuildOutput,
-};
+use super::core::context::{BlobManager, BootstrapManager, BuildContext, BuildOutput};
 use super::core::node::{ChunkSource, Node, NodeChunk, NodeInfo};
 use super::{
     build_bootstrap, dump_bootstrap, finalize_blob, Bootstrap, Builder, TarBuilder, Tree, TreeNode,
@@ -838,11 +834,7 @@ impl Builder for StargzBuilder {
         } else if ctx.digester != digest::Algorithm::Sha256 {
             bail!("stargz: invalid digest algorithm {:?}", ctx.digester);
         }
-        let mut blob_writer: Box<dyn Artifact> = if let Some(blob_stor) = ctx.blob_storage.clone() {
-            Box::new(ArtifactWriter::new(blob_stor)?)
-        } else {
-            Box::<NoopArtifactWriter>::default()
-        };
+        let mut blob_writer = ctx.create_blob_writer()?;
         let mut bootstrap_ctx = bootstrap_mgr.create_ctx()?;
         let layer_idx = u16::from(bootstrap_ctx.layered);
 
@@ -4,7 +4,8 @@
 
 //! Generate RAFS filesystem from a tarball.
 //!
-//! It support generating RAFS filesystem from a tar/targz/stargz file with or without data blob.
+//! It support generating RAFS filesystem from a tar/targz/stargz/tar.zst/tar.xz file with or
+//! without data blob.
 //!
 //! The tarball data is arrange as a sequence of tar headers with associated file data interleaved.
 //! - (tar header) (tar header) (file data) (tar header) (file data) (tar header)
@@ -17,7 +18,7 @@
 //! - dump the RAFS filesystem tree into RAFS metadata blob
 use std::ffi::{OsStr, OsString};
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
@@ -31,27 +32,32 @@ use nydus_rafs::metadata::layout::v5::RafsV5Inode;
 use nydus_rafs::metadata::layout::RafsXAttrs;
 use nydus_rafs::metadata::RafsVersion;
 use nydus_storage::device::BlobFeatures;
-use nydus_storage::meta::ZranContextGenerator;
+use nydus_storage::meta::{ZranContextGenerator, ZranContextReader};
 use nydus_storage::RAFS_MAX_CHUNKS_PER_BLOB;
 use nydus_utils::compact::makedev;
-use nydus_utils::compress::zlib_random::{ZranReader, ZRAN_READER_BUF_SIZE};
-use nydus_utils::compress::ZlibDecoder;
+use nydus_utils::compress::zlib_random::ZRAN_READER_BUF_SIZE;
+use nydus_utils::compress::{self, Decoder, ZlibDecoder};
 use nydus_utils::digest::RafsDigest;
 use nydus_utils::{div_round_up, lazy_drop, root_tracer, timing_tracer, BufReaderInfo, ByteSize};
 
-use crate::core::context::{Artifact, NoopArtifactWriter};
+use crate::core::context::Artifact;
 
 use super::core::blob::Blob;
 use super::core::context::{
-    ArtifactWriter, BlobManager, BootstrapManager, BuildContext, BuildOutput, ConversionType,
+    BlobManager, BootstrapManager, BuildContext, BuildOutput, ConversionType,
 };
 use super::core::node::{Node, NodeInfo};
 use super::core::tree::Tree;
 use super::{build_bootstrap, dump_bootstrap, finalize_blob, Builder, TarBuilder};
 
+const XZ_MAGIC: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
 enum CompressionType {
     None,
     Gzip,
+    Xz,
+    Zstd,
 }
 
 enum TarReader {
@@ -61,7 +67,9 @@ enum TarReader {
     BufReaderInfoSeekable(BufReaderInfo<File>),
     TarGzFile(Box<ZlibDecoder<File>>),
     TarGzBufReader(Box<ZlibDecoder<BufReader<File>>>),
-    ZranReader(ZranReader<File>),
+    TarXzBufReader(Box<Decoder<'static, BufReader<File>>>),
+    TarZstdBufReader(Box<Decoder<'static, BufReader<File>>>),
+    ZranReader(ZranContextReader<File>),
 }
 
 impl Read for TarReader {
@@ -73,6 +81,8 @@ impl Read for TarReader {
             TarReader::BufReaderInfoSeekable(b) => b.read(buf),
             TarReader::TarGzFile(f) => f.read(buf),
             TarReader::TarGzBufReader(b) => b.read(buf),
+            TarReader::TarXzBufReader(b) => b.read(buf),
+            TarReader::TarZstdBufReader(b) => b.read(buf),
             TarReader::ZranReader(f) => f.read(buf),
         }
     }
@@ -139,12 +149,41 @@ impl<'a> TarballTreeBuilder<'a> {
         let reader = match self.ty {
             ConversionType::EStargzToRef
             | ConversionType::TargzToRef
+            | ConversionType::TarZstdToRef
+            | ConversionType::TarXzToRef
             | ConversionType::TarToRef => match Self::detect_compression_algo(file)? {
                 (CompressionType::Gzip, buf_reader) => {
                     let generator = ZranContextGenerator::from_buf_reader(buf_reader)?;
                     let reader = generator.reader();
                     self.ctx.blob_zran_generator = Some(Mutex::new(generator));
                     self.ctx.blob_features.insert(BlobFeatures::ZRAN);
+                    self.ctx.compressor = compress::Algorithm::GZip;
+                    TarReader::ZranReader(reader)
+                }
+                (CompressionType::Zstd, buf_reader) => {
+                    // Reference zstd frames in the tarball, the blob compressor tells the
+                    // runtime how to decode them.
+                    let generator = ZranContextGenerator::from_zstd_buf_reader(buf_reader)?;
+                    let reader = generator.reader();
+                    self.ctx.blob_zran_generator = Some(Mutex::new(generator));
+                    self.ctx.blob_features.insert(BlobFeatures::ZRAN);
+                    self.ctx.compressor = compress::Algorithm::Zstd;
+                    TarReader::ZranReader(reader)
+                }
+                (CompressionType::Xz, buf_reader) => {
+                    // Reference xz blocks in the tarball, which are located by the index at the
+                    // end of xz streams, so the tarball must be a regular file.
+                    if !is_file {
+                        bail!(
+                            "tarball: xz compressed tarball must be a regular file for conversion type {}",
+                            self.ty
+                        );
+                    }
+                    let generator = ZranContextGenerator::from_xz_buf_reader(buf_reader)?;
+                    let reader = generator.reader();
+                    self.ctx.blob_zran_generator = Some(Mutex::new(generator));
+                    self.ctx.blob_features.insert(BlobFeatures::ZRAN);
+                    self.ctx.compressor = compress::Algorithm::Xz;
                     TarReader::ZranReader(reader)
                 }
                 (CompressionType::None, buf_reader) => {
@@ -166,6 +205,12 @@ impl<'a> TarballTreeBuilder<'a> {
                         TarReader::TarGzBufReader(Box::new(ZlibDecoder::new(buf_reader)))
                     }
                 }
+                (CompressionType::Xz, buf_reader) => TarReader::TarXzBufReader(Box::new(
+                    Decoder::new(buf_reader, compress::Algorithm::Xz)?,
+                )),
+                (CompressionType::Zstd, buf_reader) => TarReader::TarZstdBufReader(Box::new(
+                    Decoder::new(buf_reader, compress::Algorithm::Zstd)?,
+                )),
                 (CompressionType::None, buf_reader) => {
                     if is_file {
                         let mut file = buf_reader.into_inner();
@@ -549,11 +594,14 @@ impl<'a> TarballTreeBuilder<'a> {
         let mut buf_reader = BufReader::with_capacity(ZRAN_READER_BUF_SIZE, file);
         let mut buf = [0u8; 3];
         buf_reader.read_exact(&mut buf)?;
+        buf_reader.seek_relative(-3).unwrap();
         if buf[0] == 0x1f && buf[1] == 0x8b && buf[2] == 0x08 {
-            buf_reader.seek_relative(-3).unwrap();
             Ok((CompressionType::Gzip, buf_reader))
+        } else if buf_reader.fill_buf()?.starts_with(&XZ_MAGIC) {
+            Ok((CompressionType::Xz, buf_reader))
+        } else if buf_reader.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+            Ok((CompressionType::Zstd, buf_reader))
         } else {
-            buf_reader.seek_relative(-3).unwrap();
             Ok((CompressionType::None, buf_reader))
         }
     }
@@ -587,14 +635,10 @@ impl Builder for TarballBuilder {
             | ConversionType::EStargzToRef
             | ConversionType::TargzToRafs
             | ConversionType::TargzToRef
+            | ConversionType::TarZstdToRef
+            | ConversionType::TarXzToRef
             | ConversionType::TarToRafs
-            | ConversionType::TarToTarfs => {
-                if let Some(blob_stor) = ctx.blob_storage.clone() {
-                    Box::new(ArtifactWriter::new(blob_stor)?)
-                } else {
-                    Box::<NoopArtifactWriter>::default()
-                }
-            }
+            | ConversionType::TarToTarfs => ctx.create_blob_writer()?,
             _ => {
                 return Err(anyhow!(
                     "tarball: unsupported image conversion type '{}'",
@@ -706,6 +750,171 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_build_rafs_from_xz_tarball() {
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let tmp_dir = tmp_dir.as_path().to_path_buf();
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let source_path =
+            PathBuf::from(root_dir).join("../tests/texture/tar/all-entry-type.tar.xz");
+        let prefetch = Prefetch::default();
+        let mut ctx = BuildContext::new(
+            "test".to_string(),
+            true,
+            0,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::TarToRafs,
+            source_path.clone(),
+            prefetch,
+            Some(ArtifactStorage::FileDir((tmp_dir.clone(), String::new()))),
+            None,
+            false,
+            Features::new(),
+            false,
+            Attributes::default(),
+        );
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::FileDir((tmp_dir.clone(), String::new()))),
+            None,
+        );
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256, false);
+        let mut builder = TarballBuilder::new(ConversionType::TarToRafs);
+        builder
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+        assert!(ctx.blob_zran_generator.is_none());
+
+        // Reference xz blocks in the tarball.
+        let mut ctx = BuildContext::new(
+            String::new(),
+            true,
+            0,
+            compress::Algorithm::Xz,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::TarXzToRef,
+            source_path,
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir((tmp_dir.clone(), String::new()))),
+            None,
+            false,
+            Features::new(),
+            false,
+            Attributes::default(),
+        );
+        ctx.blob_features.insert(BlobFeatures::CHUNK_INFO_V2);
+        ctx.blob_features.insert(BlobFeatures::SEPARATE);
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::FileDir((tmp_dir, String::new()))),
+            None,
+        );
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256, false);
+        let mut builder = TarballBuilder::new(ConversionType::TarXzToRef);
+        builder
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+        assert!(ctx.blob_features.contains(BlobFeatures::ZRAN));
+        assert_eq!(ctx.compressor, compress::Algorithm::Xz);
+        assert_eq!(
+            ctx.blob_zran_generator
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .len(),
+            1
+        );
+        let (_, blob_ctx) = blob_mgr.get_current_blob().unwrap();
+        assert_eq!(blob_ctx.blob_compressor, compress::Algorithm::Xz);
+        assert_eq!(blob_ctx.compressed_blob_size, 340);
+    }
+
+    #[test]
+    fn test_build_rafs_from_zstd_tarball() {
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let tmp_dir = tmp_dir.as_path().to_path_buf();
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let source_path =
+            PathBuf::from(root_dir).join("../tests/texture/tar/all-entry-type.tar.zst");
+        let mut ctx = BuildContext::new(
+            "test".to_string(),
+            true,
+            0,
+            compress::Algorithm::Lz4Block,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::TarToRafs,
+            source_path.clone(),
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir((tmp_dir.clone(), String::new()))),
+            None,
+            false,
+            Features::new(),
+            false,
+            Attributes::default(),
+        );
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::FileDir((tmp_dir.clone(), String::new()))),
+            None,
+        );
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256, false);
+        let mut builder = TarballBuilder::new(ConversionType::TarToRafs);
+        builder
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+        assert!(ctx.blob_zran_generator.is_none());
+
+        // Reference zstd frames in the tarball.
+        let mut ctx = BuildContext::new(
+            String::new(),
+            true,
+            0,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::TarZstdToRef,
+            source_path,
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir((tmp_dir.clone(), String::new()))),
+            None,
+            false,
+            Features::new(),
+            false,
+            Attributes::default(),
+        );
+        ctx.blob_features.insert(BlobFeatures::CHUNK_INFO_V2);
+        ctx.blob_features.insert(BlobFeatures::SEPARATE);
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::FileDir((tmp_dir, String::new()))),
+            None,
+        );
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256, false);
+        let mut builder = TarballBuilder::new(ConversionType::TarZstdToRef);
+        builder
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+        assert!(ctx.blob_features.contains(BlobFeatures::ZRAN));
+        assert_eq!(ctx.compressor, compress::Algorithm::Zstd);
+        assert_eq!(
+            ctx.blob_zran_generator
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .len(),
+            1
+        );
+        let (_, blob_ctx) = blob_mgr.get_current_blob().unwrap();
+        assert_eq!(blob_ctx.blob_compressor, compress::Algorithm::Zstd);
+        assert_eq!(blob_ctx.compressed_blob_size, 488);
+    }
+
     #[test]
     fn test_build_encrypted_tarfs() {
         let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
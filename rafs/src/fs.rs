@@ -16,28 +16,40 @@
 
 use std::any::Any;
 use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::ffi::{CStr, OsStr, OsString};
 use std::io::Result;
 use std::ops::Deref;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
+use arc_swap::ArcSwap;
 use fuse_backend_rs::abi::fuse_abi::Attr;
 use fuse_backend_rs::abi::fuse_abi::{stat64, statvfs64};
 use fuse_backend_rs::api::filesystem::*;
 use fuse_backend_rs::api::BackendFileSystem;
 use nix::unistd::{getegid, geteuid};
+use serde::Serialize;
 
-use nydus_api::ConfigV2;
-use nydus_storage::device::{BlobDevice, BlobIoVec, BlobPrefetchRequest};
+use nydus_api::{BackendConfigV2, ConfigV2, IdMapRange};
+use nydus_storage::device::{
+    BlobChunkInfo, BlobDevice, BlobIoDesc, BlobIoVec, BlobPrefetchRequest,
+};
 use nydus_storage::{RAFS_DEFAULT_CHUNK_SIZE, RAFS_MAX_CHUNK_SIZE};
+use nydus_utils::digest::RafsDigest;
+use nydus_utils::verity::{
+    parse_fsverity_measurement, FileVerityHasher, FileVerityTree, FSVERITY_BLOCK_SIZE,
+};
 use nydus_utils::{
     div_round_up,
     metrics::{self, FopRecorder, StatsFop::*},
+    round_up,
 };
 
+use crate::metadata::layout::{RAFS_XATTR_VERITY, RAFS_XATTR_VERITY_TREE};
 use crate::metadata::{
     Inode, RafsInode, RafsInodeWalkAction, RafsSuper, RafsSuperMeta, DOT, DOTDOT,
 };
@@ -51,6 +63,167 @@ pub const RAFS_DEFAULT_ATTR_TIMEOUT: u64 = 1 << 32;
 /// Rafs default entry timeout value.
 pub const RAFS_DEFAULT_ENTRY_TIMEOUT: u64 = RAFS_DEFAULT_ATTR_TIMEOUT;
 
+/// Maximum number of on-demand prefetch jobs to keep for progress query.
+const MAX_PREFETCH_JOBS: usize = 64;
+/// Maximum number of verified fs-verity digests to cache, about 32MB.
+const MAX_VERITY_CACHED_DIGESTS: usize = 0x100000;
+
+static PREFETCH_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Progress of an on-demand prefetch job.
+#[derive(Debug, Default, Serialize)]
+pub struct PrefetchJobProgress {
+    /// Identifier of the prefetch job.
+    pub job_id: u64,
+    /// Files and directories to prefetch.
+    pub files: Vec<String>,
+    /// Whether all chunks have been fetched into the blob cache.
+    pub completed: bool,
+    /// Number of chunks to prefetch.
+    pub total_chunks: u64,
+    /// Number of chunks still waiting to be fetched.
+    pub pending_chunks: u64,
+    /// Compressed size of chunks to prefetch.
+    pub total_bytes: u64,
+    /// Compressed size of chunks already fetched.
+    pub fetched_bytes: u64,
+}
+
+// Deduplicated chunk set of a prefetch job, indexed by (blob index, chunk index).
+type PrefetchChunks = (HashSet<(u32, u32)>, Vec<BlobIoDesc>);
+
+/// On-demand prefetch job submitted after the filesystem has been mounted.
+struct PrefetchJob {
+    id: u64,
+    files: Vec<String>,
+    // Chunks to prefetch.
+    chunks: Mutex<PrefetchChunks>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum VerityKey {
+    Tree(Inode),
+    Group(Inode, u64),
+}
+
+/// Cache of verified fs-verity Merkle trees and digests of data blocks.
+///
+/// Oldest entries are evicted first when the total number of cached digests exceeds capacity.
+struct VerityCache {
+    capacity: usize,
+    digests: usize,
+    trees: HashMap<Inode, Arc<FileVerityTree>>,
+    groups: HashMap<(Inode, u64), Arc<Vec<RafsDigest>>>,
+    order: VecDeque<VerityKey>,
+}
+
+impl VerityCache {
+    fn new(capacity: usize) -> Self {
+        VerityCache {
+            capacity,
+            digests: 0,
+            trees: HashMap::new(),
+            groups: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get_tree(&self, ino: Inode) -> Option<Arc<FileVerityTree>> {
+        self.trees.get(&ino).cloned()
+    }
+
+    fn get_group(&self, ino: Inode, group: u64) -> Option<Arc<Vec<RafsDigest>>> {
+        self.groups.get(&(ino, group)).cloned()
+    }
+
+    fn insert_tree(&mut self, ino: Inode, tree: Arc<FileVerityTree>) {
+        let count = tree.entries().len();
+        if self.trees.insert(ino, tree).is_none() {
+            self.digests += count;
+            self.order.push_back(VerityKey::Tree(ino));
+            self.evict();
+        }
+    }
+
+    fn insert_group(&mut self, ino: Inode, group: u64, blocks: Arc<Vec<RafsDigest>>) {
+        let count = blocks.len();
+        if self.groups.insert((ino, group), blocks).is_none() {
+            self.digests += count;
+            self.order.push_back(VerityKey::Group(ino, group));
+            self.evict();
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.digests > self.capacity {
+            let count = match self.order.pop_front() {
+                Some(VerityKey::Tree(ino)) => self.trees.remove(&ino).map(|v| v.entries().len()),
+                Some(VerityKey::Group(ino, group)) => {
+                    self.groups.remove(&(ino, group)).map(|v| v.len())
+                }
+                None => break,
+            };
+            self.digests -= count.unwrap_or_default();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.digests = 0;
+        self.trees.clear();
+        self.groups.clear();
+        self.order.clear();
+    }
+}
+
+impl Default for VerityCache {
+    fn default() -> Self {
+        Self::new(MAX_VERITY_CACHED_DIGESTS)
+    }
+}
+
+impl PrefetchJob {
+    fn new(files: Vec<String>) -> Self {
+        PrefetchJob {
+            id: PREFETCH_JOB_ID.fetch_add(1, Ordering::Relaxed),
+            files,
+            chunks: Mutex::new((HashSet::new(), Vec::new())),
+        }
+    }
+
+    fn add_chunks(&self, desc: &BlobIoVec) {
+        let mut guard = self.chunks.lock().unwrap();
+        let (ids, chunks) = &mut *guard;
+        for idx in 0..desc.len() {
+            if let Some(bio) = desc.blob_io_desc(idx) {
+                if ids.insert((bio.blob.blob_index(), bio.chunkinfo.id())) {
+                    chunks.push(bio.clone());
+                }
+            }
+        }
+    }
+
+    fn progress(&self, device: &BlobDevice) -> PrefetchJobProgress {
+        let guard = self.chunks.lock().unwrap();
+        let chunks = &guard.1;
+        let total_chunks = chunks.len() as u64;
+        let total_bytes = chunks
+            .iter()
+            .map(|c| c.chunkinfo.compressed_size() as u64)
+            .sum();
+        let (ready_chunks, fetched_bytes) = device.ready_chunks(chunks);
+
+        PrefetchJobProgress {
+            job_id: self.id,
+            files: self.files.clone(),
+            completed: ready_chunks == total_chunks,
+            total_chunks,
+            pending_chunks: total_chunks - ready_chunks,
+            total_bytes,
+            fetched_bytes,
+        }
+    }
+}
+
 /// Struct to glue fuse, storage backend and filesystem metadata together.
 ///
 /// The [Rafs](struct.Rafs.html) structure implements the `fuse_backend_rs::FileSystem` trait,
@@ -67,8 +240,17 @@ pub struct Rafs {
     digest_validate: bool,
     fs_prefetch: bool,
     prefetch_all: bool,
+    prefetch_jobs: Mutex<BTreeMap<u64, Arc<PrefetchJob>>>,
     xattr_enabled: bool,
     user_io_batch_size: u32,
+    enforce_verity: bool,
+    // Merkle trees and digests of data blocks which have passed fs-verity verification.
+    verity_cache: Mutex<VerityCache>,
+
+    // Map explicit uid/gid of inodes to present an idmapped view of the filesystem, may be
+    // replaced when remounting.
+    uid_map: ArcSwap<Vec<IdMapRange>>,
+    gid_map: ArcSwap<Vec<IdMapRange>>,
 
     // static inode attributes
     i_uid: u32,
@@ -104,7 +286,12 @@ impl Rafs {
             fs_prefetch: rafs_cfg.prefetch.enable,
             user_io_batch_size: rafs_cfg.user_io_batch_size as u32,
             prefetch_all: rafs_cfg.prefetch.prefetch_all,
+            prefetch_jobs: Mutex::new(BTreeMap::new()),
             xattr_enabled: rafs_cfg.enable_xattr,
+            enforce_verity: rafs_cfg.enforce_verity,
+            verity_cache: Mutex::new(VerityCache::default()),
+            uid_map: ArcSwap::from_pointee(rafs_cfg.uid_map.clone()),
+            gid_map: ArcSwap::from_pointee(rafs_cfg.gid_map.clone()),
 
             i_uid: geteuid().into(),
             i_gid: getegid().into(),
@@ -153,6 +340,8 @@ impl Rafs {
             e
         })?;
         info!("update sb is successful");
+        // Inode numbers may refer to different files after updating the super block.
+        self.verity_cache.lock().unwrap().clear();
 
         // step 2: update device (only localfs is supported)
         let blob_infos = self.sb.superblock.get_blob_infos();
@@ -161,6 +350,26 @@ impl Rafs {
             .map_err(RafsError::SwapBackend)?;
         info!("update device is successful");
 
+        // step 3: update uid/gid maps of the idmapped view.
+        if let Some(rafs_cfg) = conf.rafs.as_ref() {
+            self.uid_map.store(Arc::new(rafs_cfg.uid_map.clone()));
+            self.gid_map.store(Arc::new(rafs_cfg.gid_map.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Update storage backend configuration, such as credentials and endpoints, in place.
+    pub fn update_backend(&self, backend: &BackendConfigV2) -> RafsResult<()> {
+        if !self.initialized {
+            warn!("Rafs is not yet initialized");
+            return Err(RafsError::Uninitialized);
+        }
+        self.device
+            .update_backend(backend)
+            .map_err(RafsError::SwapBackend)?;
+        info!("update storage backend is successful");
+
         Ok(())
     }
 
@@ -211,6 +420,23 @@ impl Rafs {
         &self.sb.meta
     }
 
+    /// Export files read so far as a prefetch file list in JSON, ordered by first access time.
+    ///
+    /// The output is accepted by `nydus-image optimize --prefetch-files`, and access pattern
+    /// recording must be enabled to get a meaningful list.
+    pub fn export_prefetch_list(&self) -> Result<String> {
+        let inos = self.ios.get_accessed_inodes();
+        let paths = self.sb.paths_from_inos(&inos).map_err(|e| eother!(e))?;
+        let files = inos
+            .iter()
+            .filter_map(|ino| paths.get(ino))
+            .map(|path| serde_json::json!({ "path": path }))
+            .collect::<Vec<_>>();
+
+        serde_json::to_string(&serde_json::json!({ "version": "v1", "files": files }))
+            .map_err(|e| einval!(e))
+    }
+
     fn xattr_supported(&self) -> bool {
         self.xattr_enabled || self.sb.meta.has_xattr()
     }
@@ -277,6 +503,9 @@ impl Rafs {
         if !self.sb.meta.explicit_uidgid() {
             attr.uid = self.i_uid;
             attr.gid = self.i_gid;
+        } else {
+            attr.uid = IdMapRange::map_id(&self.uid_map.load(), attr.uid);
+            attr.gid = IdMapRange::map_id(&self.gid_map.load(), attr.gid);
         }
 
         // Older rafs image or the root inode doesn't include mtime, in such cases
@@ -306,6 +535,9 @@ impl Rafs {
         if !self.sb.meta.explicit_uidgid() {
             entry.attr.st_uid = self.i_uid;
             entry.attr.st_gid = self.i_gid;
+        } else {
+            entry.attr.st_uid = IdMapRange::map_id(&self.uid_map.load(), entry.attr.st_uid);
+            entry.attr.st_gid = IdMapRange::map_id(&self.gid_map.load(), entry.attr.st_gid);
         }
 
         // Older rafs image doesn't include mtime, in such case we use runtime timestamp.
@@ -339,6 +571,68 @@ impl Rafs {
         });
     }
 
+    /// Prefetch files and directories in background after the filesystem has been mounted.
+    ///
+    /// Chunks of the files are collected from the in-memory metadata in the caller's context,
+    /// and then handed over to the prefetch workers of the blob cache, so the amount of
+    /// concurrent background work is bounded by the prefetch worker configuration.
+    ///
+    /// Return id of the prefetch job, which may be used to query progress of the job.
+    pub fn prefetch_files_on_demand(&self, files: &[PathBuf]) -> Result<u64> {
+        if !self.initialized {
+            return Err(einval!("filesystem is not mounted yet"));
+        } else if !self.fs_prefetch {
+            return Err(einval!("prefetch is disabled for the filesystem"));
+        } else if files.is_empty() {
+            return Err(einval!("no file to prefetch"));
+        }
+        let mut inodes = Vec::with_capacity(files.len());
+        for f in files {
+            let ino = self
+                .sb
+                .ino_from_path(f)
+                .map_err(|e| enoent!(format!("failed to find {}, {}", f.display(), e)))?;
+            inodes.push(ino);
+        }
+
+        let job = Arc::new(PrefetchJob::new(
+            files.iter().map(|f| f.display().to_string()).collect(),
+        ));
+        let job_id = job.id;
+        let fetcher = |desc: &mut BlobIoVec, last: bool| {
+            if desc.size() > RAFS_MAX_CHUNK_SIZE || desc.len() > 1024 || (last && desc.size() > 0) {
+                job.add_chunks(desc);
+                self.device.prefetch(&[desc], &[]).unwrap_or_else(|e| {
+                    warn!("Prefetch error, {:?}", e);
+                });
+                desc.reset();
+            }
+        };
+        self.sb
+            .prefetch_inodes(&self.device, &inodes, &fetcher)
+            .map_err(|e| eother!(format!("failed to prefetch files, {}", e)))?;
+
+        let mut jobs = self.prefetch_jobs.lock().unwrap();
+        jobs.insert(job_id, job);
+        while jobs.len() > MAX_PREFETCH_JOBS {
+            jobs.pop_first();
+        }
+
+        Ok(job_id)
+    }
+
+    /// Get progress of an on-demand prefetch job.
+    pub fn prefetch_job_progress(&self, job_id: u64) -> Result<PrefetchJobProgress> {
+        let job = self
+            .prefetch_jobs
+            .lock()
+            .unwrap()
+            .get(&job_id)
+            .cloned()
+            .ok_or_else(|| enoent!(format!("prefetch job {} not found", job_id)))?;
+        Ok(job.progress(&self.device))
+    }
+
     /// for blobfs
     pub fn fetch_range_synchronous(&self, prefetches: &[BlobPrefetchRequest]) -> Result<()> {
         self.device.fetch_range_synchronous(prefetches)
@@ -478,6 +772,174 @@ impl Rafs {
         }
     }
 
+    // Read a range of file data into a buffer.
+    fn read_file_range(&self, inode: &dyn RafsInode, offset: u64, size: u64) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; size as usize];
+        let mut pos = 0;
+        for mut desc in inode.alloc_bio_vecs(&self.device, offset, size as usize, true)? {
+            let len = desc.size() as usize;
+            let r = self
+                .device
+                .read_to_buf(&mut buf[pos..pos + len], &mut desc)?;
+            if r != len {
+                return Err(eio!(format!(
+                    "short read of inode {}, expect {} bytes, got {}",
+                    inode.ino(),
+                    len,
+                    r
+                )));
+            }
+            pos += len;
+        }
+        Ok(buf)
+    }
+
+    // Get the verified fs-verity Merkle tree of a file with fs-verity digest.
+    //
+    // The tree stored in the image is verified against the fs-verity digest of the file. For
+    // images without stored trees, the tree is rebuilt by reading the whole file.
+    fn get_verity_tree(&self, inode: &dyn RafsInode) -> Result<Option<Arc<FileVerityTree>>> {
+        if let Some(tree) = self.verity_cache.lock().unwrap().get_tree(inode.ino()) {
+            return Ok(Some(tree));
+        }
+        if !inode.has_xattr() {
+            return Ok(None);
+        }
+        let digest = match inode.get_xattr(OsStr::new(RAFS_XATTR_VERITY))? {
+            Some(v) => parse_fsverity_measurement(&v)?,
+            None => return Ok(None),
+        };
+
+        let size = inode.size();
+        let tree = match inode.get_xattr(OsStr::new(RAFS_XATTR_VERITY_TREE))? {
+            Some(v) => FileVerityTree::decode(&v, size)?,
+            None => {
+                let mut hasher = FileVerityHasher::new();
+                let mut offset = 0;
+                while offset < size {
+                    let len = cmp::min(size - offset, RAFS_DEFAULT_CHUNK_SIZE);
+                    hasher.update(&self.read_file_range(inode, offset, len)?);
+                    offset += len;
+                }
+                FileVerityTree::from_blocks(size, &hasher.finalize().1)
+            }
+        };
+        let file_digest = tree.file_digest(size);
+        if file_digest != digest {
+            error!(
+                "fs-verity digest of inode {} mismatch, expect {}, got {}",
+                inode.ino(),
+                digest,
+                file_digest
+            );
+            return Err(eio!("fs-verity digest mismatch"));
+        }
+
+        let tree = Arc::new(tree);
+        self.verity_cache
+            .lock()
+            .unwrap()
+            .insert_tree(inode.ino(), tree.clone());
+        Ok(Some(tree))
+    }
+
+    // Get verified digests of data blocks covered by entry `group` of the Merkle tree.
+    fn get_verity_group(
+        &self,
+        inode: &dyn RafsInode,
+        tree: &FileVerityTree,
+        group: u64,
+    ) -> Result<Arc<Vec<RafsDigest>>> {
+        if let Some(blocks) = self
+            .verity_cache
+            .lock()
+            .unwrap()
+            .get_group(inode.ino(), group)
+        {
+            return Ok(blocks);
+        }
+
+        let group_size = tree.group_blocks() * FSVERITY_BLOCK_SIZE as u64;
+        let start = group * group_size;
+        let end = cmp::min(start + group_size, inode.size());
+        let mut blocks = Vec::with_capacity(tree.group_blocks() as usize);
+        let mut offset = start;
+        while offset < end {
+            let len = cmp::min(end - offset, RAFS_DEFAULT_CHUNK_SIZE);
+            let buf = self.read_file_range(inode, offset, len)?;
+            blocks.extend(
+                buf.chunks(FSVERITY_BLOCK_SIZE)
+                    .map(FileVerityHasher::block_digest),
+            );
+            offset += len;
+        }
+        if !tree.verify_group(group as usize, &blocks) {
+            error!(
+                "fs-verity digest of block group {} of inode {} mismatch",
+                group,
+                inode.ino()
+            );
+            return Err(eio!("fs-verity digest mismatch"));
+        }
+
+        let blocks = Arc::new(blocks);
+        self.verity_cache
+            .lock()
+            .unwrap()
+            .insert_group(inode.ino(), group, blocks.clone());
+        Ok(blocks)
+    }
+
+    // Read file data and verify data blocks covering the range against the fs-verity Merkle tree.
+    //
+    // Only block groups touched by the read are verified, each of which is covered by an entry of
+    // the stored Merkle tree level.
+    fn read_verified(
+        &self,
+        inode: &dyn RafsInode,
+        tree: &FileVerityTree,
+        w: &mut dyn ZeroCopyWriter,
+        offset: u64,
+        size: u64,
+    ) -> Result<usize> {
+        let block_size = FSVERITY_BLOCK_SIZE as u64;
+        let start = offset & !(block_size - 1);
+        let end = cmp::min(round_up(offset + size, block_size), inode.size());
+        let buf = self.read_file_range(inode, start, end - start)?;
+        let group_blocks = tree.group_blocks();
+        let mut group_digests: Option<(u64, Arc<Vec<RafsDigest>>)> = None;
+        for (idx, data) in buf.chunks(FSVERITY_BLOCK_SIZE).enumerate() {
+            let index = start / block_size + idx as u64;
+            let digest = FileVerityHasher::block_digest(data);
+            let valid = if tree.level() == 1 {
+                tree.entries().get(index as usize) == Some(&digest)
+            } else {
+                let group = index / group_blocks;
+                let blocks = match group_digests.as_ref() {
+                    Some((g, blocks)) if *g == group => blocks.clone(),
+                    _ => {
+                        let blocks = self.get_verity_group(inode, tree, group)?;
+                        group_digests = Some((group, blocks.clone()));
+                        blocks
+                    }
+                };
+                blocks.get((index - group * group_blocks) as usize) == Some(&digest)
+            };
+            if !valid {
+                error!(
+                    "fs-verity digest of block {} of inode {} mismatch",
+                    index,
+                    inode.ino()
+                );
+                return Err(eio!("fs-verity digest mismatch"));
+            }
+        }
+
+        let pos = (offset - start) as usize;
+        w.write_all(&buf[pos..pos + size as usize])?;
+        Ok(size as usize)
+    }
+
     fn convert_file_list(files: &[PathBuf], sb: &Arc<RafsSuper>) -> Vec<Inode> {
         let mut inodes = Vec::<Inode>::with_capacity(files.len());
 
@@ -629,6 +1091,16 @@ impl FileSystem for Rafs {
         }
 
         let real_size = cmp::min(size as u64, inode_size - offset);
+        if self.enforce_verity {
+            if let Some(tree) = self.get_verity_tree(inode.as_ref())? {
+                let start = self.ios.latency_start();
+                let r = self.read_verified(inode.as_ref(), &tree, w, offset, real_size)?;
+                self.ios.latency_end(&start, Read);
+                recorder.mark_success(r);
+                return Ok(r);
+            }
+        }
+
         let mut result = 0;
         let mut io_vecs = inode.alloc_bio_vecs(&self.device, offset, real_size as usize, true)?;
         assert!(!io_vecs.is_empty() && !io_vecs[0].is_empty());
@@ -846,6 +1318,7 @@ impl FileSystem for Rafs {
 
     fn access(&self, ctx: &Context, ino: u64, mask: u32) -> Result<()> {
         let mut rec = FopRecorder::settle(Access, ino, &self.ios);
+        // Check permission against uid/gid after applying id maps, as presented to the caller.
         let st = self.get_inode_attr(ino)?;
         let mode = mask as i32 & (libc::R_OK | libc::W_OK | libc::X_OK);
 
@@ -1032,26 +1505,39 @@ impl Layer for Rafs {
 
 #[cfg(test)]
 mod tests {
+    use nydus_utils::digest;
     use nydus_utils::metrics::FsIoStats;
+    use std::str::FromStr;
+    use vmm_sys_util::tempdir::TempDir;
 
     use super::*;
-    #[test]
-    fn test_rafs() {
-        let rafs = Rafs {
+
+    fn new_test_rafs(sb: RafsSuper, uid_map: Vec<IdMapRange>, gid_map: Vec<IdMapRange>) -> Rafs {
+        Rafs {
             id: "foo".into(),
             device: BlobDevice::default(),
             ios: FsIoStats::default().into(),
-            sb: Arc::new(RafsSuper::default()),
+            sb: Arc::new(sb),
             initialized: false,
             digest_validate: false,
             fs_prefetch: false,
             prefetch_all: false,
+            prefetch_jobs: Default::default(),
             xattr_enabled: false,
             user_io_batch_size: 0,
+            enforce_verity: false,
+            verity_cache: Default::default(),
+            uid_map: ArcSwap::from_pointee(uid_map),
+            gid_map: ArcSwap::from_pointee(gid_map),
             i_uid: 0,
             i_gid: 0,
             i_time: 0,
-        };
+        }
+    }
+
+    #[test]
+    fn test_rafs() {
+        let rafs = new_test_rafs(RafsSuper::default(), Vec::new(), Vec::new());
         assert_eq!(rafs.id(), "foo");
         assert!(!rafs.xattr_supported());
         let ent = rafs.negative_entry();
@@ -1075,4 +1561,191 @@ mod tests {
         rafs.statfs(&Context::default(), Inode::default()).unwrap();
         rafs.destroy();
     }
+
+    #[test]
+    fn test_export_prefetch_list() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let (sb, _) =
+            RafsSuper::load_from_file(&path, Arc::new(ConfigV2::default()), false).unwrap();
+        let rafs = new_test_rafs(sb, Vec::new(), Vec::new());
+        assert_eq!(
+            rafs.export_prefetch_list().unwrap(),
+            r#"{"files":[],"version":"v1"}"#
+        );
+
+        rafs.ios.toggle_access_pattern(true);
+        for ino in [133, 136, 0xffff_ffff] {
+            rafs.ios.new_file_counter(ino);
+            let mut rec = FopRecorder::settle(Read, ino, &rafs.ios);
+            rec.mark_success(0);
+        }
+        let list = rafs.export_prefetch_list().unwrap();
+        let list: serde_json::Value = serde_json::from_str(&list).unwrap();
+        let files = list["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.contains(&serde_json::json!({"path": "/lib.rs"})));
+        assert!(files.contains(&serde_json::json!({"path": "/sync_io.rs"})));
+        assert_eq!(list["version"], "v1");
+    }
+
+    #[test]
+    fn test_id_mapped_attr() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let (sb, _) =
+            RafsSuper::load_from_file(&path, Arc::new(ConfigV2::default()), false).unwrap();
+        assert!(sb.meta.explicit_uidgid());
+        let rafs = new_test_rafs(
+            sb,
+            vec![IdMapRange {
+                inside: 0,
+                outside: 100000,
+                count: 65536,
+            }],
+            vec![IdMapRange {
+                inside: 1,
+                outside: 200000,
+                count: 1,
+            }],
+        );
+
+        let name = std::ffi::CString::new("lib.rs").unwrap();
+        let entry = rafs
+            .lookup(&Context::default(), rafs.root_ino(), &name)
+            .unwrap();
+        assert_eq!(entry.attr.st_uid, 100000);
+        // Unmapped gid is presented as the overflow id.
+        assert_eq!(entry.attr.st_gid, IdMapRange::OVERFLOW_ID);
+        let (st, _) = rafs
+            .getattr(&Context::default(), entry.inode, None)
+            .unwrap();
+        assert_eq!(st.st_uid, 100000);
+        assert_eq!(st.st_gid, IdMapRange::OVERFLOW_ID);
+
+        let mut ctx = Context {
+            uid: 100000,
+            gid: 100000,
+            ..Default::default()
+        };
+        let mode = st.st_mode & 0o777;
+        assert_eq!(mode & 0o200, 0o200);
+        rafs.access(&ctx, entry.inode, libc::W_OK as u32).unwrap();
+        ctx.uid = 0;
+        ctx.gid = 0;
+        if mode & 0o002 == 0 {
+            // Root still bypasses permission checks.
+            rafs.access(&ctx, entry.inode, libc::W_OK as u32).unwrap();
+            ctx.uid = 1000;
+            ctx.gid = 1000;
+            assert!(rafs.access(&ctx, entry.inode, libc::W_OK as u32).is_err());
+        }
+
+        // Maps replaced by remounting take effect for following requests.
+        rafs.uid_map.store(Arc::new(vec![IdMapRange {
+            inside: 0,
+            outside: 300000,
+            count: 65536,
+        }]));
+        rafs.gid_map.store(Arc::new(Vec::new()));
+        let (st, _) = rafs
+            .getattr(&Context::default(), entry.inode, None)
+            .unwrap();
+        assert_eq!(st.st_uid, 300000);
+        assert_eq!(st.st_gid, 0);
+    }
+
+    #[test]
+    fn test_verity_cache() {
+        let digest = |v: u64| RafsDigest::from_buf(&v.to_le_bytes(), digest::Algorithm::Sha256);
+        let mut cache = VerityCache::new(10);
+        let tree = FileVerityTree::from_blocks(4 * 4096, &(0..4).map(digest).collect::<Vec<_>>());
+        cache.insert_tree(1, Arc::new(tree.clone()));
+        cache.insert_group(1, 0, Arc::new((0..4).map(digest).collect()));
+        assert_eq!(cache.digests, 8);
+        assert_eq!(cache.get_tree(1).unwrap().as_ref(), &tree);
+        assert_eq!(cache.get_group(1, 0).unwrap().len(), 4);
+        assert!(cache.get_group(1, 1).is_none());
+
+        // Oldest entries are evicted first.
+        cache.insert_group(2, 3, Arc::new((0..4).map(digest).collect()));
+        assert_eq!(cache.digests, 8);
+        assert!(cache.get_tree(1).is_none());
+        assert!(cache.get_group(1, 0).is_some());
+        assert!(cache.get_group(2, 3).is_some());
+
+        // Entries bigger than capacity are not cached.
+        cache.insert_group(3, 0, Arc::new((0..11).map(digest).collect()));
+        assert_eq!(cache.digests, 0);
+        assert!(cache.get_group(3, 0).is_none());
+
+        cache.insert_tree(1, Arc::new(tree));
+        cache.clear();
+        assert_eq!(cache.digests, 0);
+        assert!(cache.get_tree(1).is_none());
+    }
+
+    #[test]
+    fn test_prefetch_files_on_demand() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let blob_dir = PathBuf::from(root_dir).join("../tests/texture/blobs");
+        let work_dir = TempDir::new().unwrap();
+        let content = format!(
+            r#"
+        version = 2
+        id = "test"
+        backend.type = "localfs"
+        backend.localfs.dir = "{}"
+        cache.type = "filecache"
+        cache.filecache.work_dir = "{}"
+        cache.prefetch.enable = true
+        cache.prefetch.threads = 2
+        "#,
+            blob_dir.display(),
+            work_dir.as_path().display()
+        );
+        let config = Arc::new(ConfigV2::from_str(&content).unwrap());
+        let (sb, _) = RafsSuper::load_from_file(&path, config.clone(), false).unwrap();
+        let device = BlobDevice::new(&config, &sb.superblock.get_blob_infos()).unwrap();
+        device.start_prefetch();
+        let mut rafs = new_test_rafs(sb, Vec::new(), Vec::new());
+        rafs.device = device;
+
+        let files = vec![PathBuf::from("/lib.rs")];
+        assert!(rafs.prefetch_files_on_demand(&files).is_err());
+        rafs.initialized = true;
+        assert!(rafs.prefetch_files_on_demand(&files).is_err());
+        rafs.fs_prefetch = true;
+        assert!(rafs.prefetch_files_on_demand(&[]).is_err());
+        assert!(rafs
+            .prefetch_files_on_demand(&[PathBuf::from("/nonexist")])
+            .is_err());
+
+        let job_id = rafs.prefetch_files_on_demand(&files).unwrap();
+        let mut progress = rafs.prefetch_job_progress(job_id).unwrap();
+        assert_eq!(progress.job_id, job_id);
+        assert_eq!(progress.files, vec!["/lib.rs".to_string()]);
+        assert!(progress.total_chunks > 0);
+        assert!(progress.total_bytes > 0);
+        for _ in 0..500 {
+            if progress.completed {
+                break;
+            }
+            assert!(progress.pending_chunks > 0);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            progress = rafs.prefetch_job_progress(job_id).unwrap();
+        }
+        assert!(progress.completed);
+        assert_eq!(progress.pending_chunks, 0);
+        assert_eq!(progress.fetched_bytes, progress.total_bytes);
+        assert!(rafs.prefetch_job_progress(job_id + 1000).is_err());
+
+        for _ in 0..MAX_PREFETCH_JOBS {
+            rafs.prefetch_files_on_demand(&files).unwrap();
+        }
+        assert!(rafs.prefetch_job_progress(job_id).is_err());
+        assert_eq!(rafs.prefetch_jobs.lock().unwrap().len(), MAX_PREFETCH_JOBS);
+        rafs.device.stop_prefetch();
+    }
 }
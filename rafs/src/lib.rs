@@ -86,6 +86,8 @@ pub enum RafsError {
     IllegalMetaStruct(MetaType, String),
     #[error("Invalid image data")]
     InvalidImageData,
+    #[error("Failed to verify signature: {0}`")]
+    VerifySignature(Error),
 }
 
 #[derive(Debug)]
@@ -221,6 +221,17 @@ pub const RAFS_XATTR_PREFIXES: [&str; 5] = [
     "system.posix_acl_default",
 ];
 
+/// Extended attribute to store the fs-verity digest of a regular file.
+///
+/// The value is in format of `struct fsverity_digest`, as returned by `FS_IOC_MEASURE_VERITY`.
+pub const RAFS_XATTR_VERITY: &str = "user.nydus.verity";
+
+/// Extended attribute to store a level of the fs-verity Merkle tree of a regular file.
+///
+/// The value is encoded by `FileVerityTree::encode()`, to verify file data without reading the
+/// whole file.
+pub const RAFS_XATTR_VERITY_TREE: &str = "user.nydus.verity.tree";
+
 /// Rafs inode extended attributes.
 ///
 /// An extended attribute is a (String, String) pair associated with a inode.
@@ -275,6 +286,11 @@ impl RafsXAttrs {
         Err(einval!("invalid xattr key"))
     }
 
+    /// Get an iterator over all extended attributes.
+    pub fn iter(&self) -> impl Iterator<Item = (&OsString, &XattrValue)> {
+        self.pairs.iter()
+    }
+
     /// Remove an extended attribute
     pub fn remove(&mut self, name: &OsStr) {
         self.pairs.remove(name);
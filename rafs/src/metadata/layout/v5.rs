@@ -229,6 +229,8 @@ impl RafsV5SuperBlock {
         self.s_flags &= !RafsSuperFlags::COMPRESSION_LZ4.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_GZIP.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_ZSTD.bits();
+        self.s_flags &= !RafsSuperFlags::COMPRESSION_ZSTD_DICT.bits();
+        self.s_flags &= !RafsSuperFlags::COMPRESSION_XZ.bits();
         self.s_flags |= c.bits();
     }
 
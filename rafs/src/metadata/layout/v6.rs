@@ -426,7 +426,9 @@ impl RafsV6SuperBlockExt {
         flags &= RafsSuperFlags::COMPRESSION_NONE.bits()
             | RafsSuperFlags::COMPRESSION_LZ4.bits()
             | RafsSuperFlags::COMPRESSION_GZIP.bits()
-            | RafsSuperFlags::COMPRESSION_ZSTD.bits();
+            | RafsSuperFlags::COMPRESSION_ZSTD.bits()
+            | RafsSuperFlags::COMPRESSION_ZSTD_DICT.bits()
+            | RafsSuperFlags::COMPRESSION_XZ.bits();
         if flags.count_ones() != 1 {
             return Err(einval!(format!(
                 "invalid flags {:#x} related to compression algorithm in Rafs v6 extended superblock",
@@ -535,6 +537,8 @@ impl RafsV6SuperBlockExt {
         self.s_flags &= !RafsSuperFlags::COMPRESSION_LZ4.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_GZIP.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_ZSTD.bits();
+        self.s_flags &= !RafsSuperFlags::COMPRESSION_ZSTD_DICT.bits();
+        self.s_flags &= !RafsSuperFlags::COMPRESSION_XZ.bits();
         self.s_flags |= c.bits();
     }
 
@@ -1723,7 +1727,24 @@ impl RafsV6Blob {
         }
 
         let count = chunk_count as u64;
-        if blob_features.contains(BlobFeatures::CHUNK_INFO_V2)
+        // The compression dictionary is appended to the chunk compression context table.
+        let has_dict = compress::Algorithm::try_from(u32::from_le(self.compression_algo))
+            .map(|c| c.need_dict())
+            .unwrap_or_default();
+        if has_dict {
+            let entry_size = if blob_features.contains(BlobFeatures::CHUNK_INFO_V2) {
+                size_of::<BlobChunkInfoV2Ondisk>()
+            } else {
+                size_of::<BlobChunkInfoV1Ondisk>()
+            };
+            if ci_uncompr_size <= count * entry_size as u64 {
+                error!(
+                    "RafsV6Blob: idx {} invalid ci_d_size {}",
+                    blob_index, ci_uncompr_size
+                );
+                return false;
+            }
+        } else if blob_features.contains(BlobFeatures::CHUNK_INFO_V2)
             && (blob_features.contains(BlobFeatures::BATCH)
                 || blob_features.contains(BlobFeatures::ZRAN)
                 || blob_features.contains(BlobFeatures::ENCRYPTED))
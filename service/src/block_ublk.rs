@@ -0,0 +1,910 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export a RAFSv6 image as a block device through Linux ublk (userspace block device) driver.
+//!
+//! The [ublk](https://docs.kernel.org/block/ublk.html) driver forwards IO requests of a block
+//! device to a userspace server through io_uring passthrough commands. Compared with NBD, there's
+//! no socket and no data copy through socket buffers, and each hardware queue of the block device
+//! is served by a dedicated worker thread. The [UblkService] exposes a RAFSv6 image as a read-only
+//! ublk block device, and each [UblkQueueWorker] serves IO requests of one ublk queue by reading
+//! data from the [BlockDevice] composed from the RAFSv6 image.
+
+use std::any::Any;
+use std::fs::{self, OpenOptions};
+use std::io::{Error, Result};
+use std::os::fd::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use mio::Waker;
+use nydus_api::{BlobCacheEntry, BuildTimeInfo};
+use nydus_storage::utils::alloc_buf;
+use nydus_utils::round_up;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::mpsc;
+
+use crate::blob_cache::{generate_blob_key, BlobCacheMgr};
+use crate::block_device::BlockDevice;
+use crate::daemon::{
+    DaemonState, DaemonStateMachineContext, DaemonStateMachineInput, DaemonStateMachineSubscriber,
+    NydusDaemon,
+};
+use crate::{Error as NydusError, Result as NydusResult};
+
+const UBLK_CONTROL_PATH: &str = "/dev/ublk-control";
+const UBLK_CMD_ADD_DEV: u32 = 0x04;
+const UBLK_CMD_DEL_DEV: u32 = 0x05;
+const UBLK_CMD_START_DEV: u32 = 0x06;
+const UBLK_CMD_STOP_DEV: u32 = 0x07;
+const UBLK_CMD_SET_PARAMS: u32 = 0x08;
+const UBLK_IO_FETCH_REQ: u32 = 0x20;
+const UBLK_IO_COMMIT_AND_FETCH_REQ: u32 = 0x21;
+const UBLK_IO_OP_READ: u32 = 0;
+const UBLK_IO_RES_OK: i32 = 0;
+const UBLK_F_CMD_IOCTL_ENCODE: u64 = 1 << 6;
+const UBLK_PARAM_TYPE_BASIC: u32 = 1;
+const UBLK_ATTR_READ_ONLY: u32 = 1;
+const UBLK_MAX_QUEUE_DEPTH: usize = 4096;
+const UBLK_MAX_IO_BUF_BYTES: u32 = 0x80000;
+const UBLK_SECTOR_SHIFT: u32 = 9;
+const UBLK_DEV_ID_ANY: u32 = u32::MAX;
+
+/// Control command header, `struct ublksrv_ctrl_cmd`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct UblkCtrlCmd {
+    dev_id: u32,
+    queue_id: u16,
+    len: u16,
+    addr: u64,
+    data: u64,
+    dev_path_len: u16,
+    pad: u16,
+    reserved: u32,
+}
+
+/// Device information, `struct ublksrv_ctrl_dev_info`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct UblkCtrlDevInfo {
+    nr_hw_queues: u16,
+    queue_depth: u16,
+    state: u16,
+    pad0: u16,
+    max_io_buf_bytes: u32,
+    dev_id: u32,
+    ublksrv_pid: i32,
+    pad1: u32,
+    flags: u64,
+    ublksrv_flags: u64,
+    owner_uid: u32,
+    owner_gid: u32,
+    reserved1: u64,
+    reserved2: u64,
+}
+
+/// Basic device parameters, `struct ublk_param_basic`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct UblkParamBasic {
+    attrs: u32,
+    logical_bs_shift: u8,
+    physical_bs_shift: u8,
+    io_opt_shift: u8,
+    io_min_shift: u8,
+    max_sectors: u32,
+    chunk_sectors: u32,
+    dev_sectors: u64,
+    virt_boundary_mask: u64,
+}
+
+/// Device parameters, `struct ublk_params` with only basic parameters.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct UblkParams {
+    len: u32,
+    types: u32,
+    basic: UblkParamBasic,
+}
+
+/// IO descriptor shared by the kernel, `struct ublksrv_io_desc`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct UblkIoDesc {
+    op_flags: u32,
+    nr_sectors: u32,
+    start_sector: u64,
+    addr: u64,
+}
+
+/// IO command, `struct ublksrv_io_cmd`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct UblkIoCmd {
+    q_id: u16,
+    tag: u16,
+    result: i32,
+    addr: u64,
+}
+
+fn ublk_cmd_op(nr: u32, size: usize) -> u32 {
+    nix::request_code_readwrite!(b'u', nr, size) as u32
+}
+
+fn ublk_ctrl_cmd_op(nr: u32) -> u32 {
+    ublk_cmd_op(nr, std::mem::size_of::<UblkCtrlCmd>())
+}
+
+fn ublk_io_cmd_op(nr: u32) -> u32 {
+    ublk_cmd_op(nr, std::mem::size_of::<UblkIoCmd>())
+}
+
+fn ublk_queue_desc_size(depth: u16) -> usize {
+    round_up(
+        depth as u64 * std::mem::size_of::<UblkIoDesc>() as u64,
+        page_size(),
+    ) as usize
+}
+
+fn page_size() -> u64 {
+    // Safe because sysconf() has no side effect.
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+/// Issue a control command to the ublk control device and wait for its completion.
+fn ublk_ctrl_cmd(ctrl: RawFd, op: u32, cmd: UblkCtrlCmd) -> Result<i32> {
+    let mut ring = IoUring::<squeue::Entry128, cqueue::Entry>::generic_new(4)?;
+    let mut buf = [0u8; 80];
+    // Safe because UblkCtrlCmd is a plain old data structure smaller than the buffer.
+    unsafe {
+        std::ptr::write_unaligned(buf.as_mut_ptr() as *mut UblkCtrlCmd, cmd);
+    }
+    let entry = opcode::UringCmd80::new(types::Fd(ctrl), ublk_ctrl_cmd_op(op))
+        .cmd(buf)
+        .build()
+        .user_data(op as u64);
+    // Safe because the command and the buffers it references are valid until completion.
+    unsafe {
+        ring.submission()
+            .push(&entry)
+            .map_err(|_| eother!("block_ublk: failed to queue control command"))?;
+    }
+    ring.submit_and_wait(1)?;
+    let res = match ring.completion().next() {
+        Some(cqe) => cqe.result(),
+        None => return Err(eother!("block_ublk: no completion for control command")),
+    };
+    if res < 0 {
+        Err(Error::from_raw_os_error(-res))
+    } else {
+        Ok(res)
+    }
+}
+
+/// Userspace block device server to expose RAFSv6 images as block devices through ublk.
+pub struct UblkService {
+    blob_id: String,
+    cache_mgr: Arc<BlobCacheMgr>,
+    ctrl: fs::File,
+    cdev: Option<Arc<fs::File>>,
+    dev_id: u32,
+    queues: u16,
+    depth: u16,
+    buf_size: u32,
+}
+
+impl UblkService {
+    /// Create a new instance of [UblkService] to expose a RAFSv6 image as a block device.
+    ///
+    /// It adds a new ublk device with `queues` hardware queues of `depth` entries each, and
+    /// configures it according to information from the block device composed from a RAFSv6
+    /// image. The device id is allocated by the kernel if `dev_id` is `None`. The caller needs
+    /// to ensure that the `ublk_drv` kernel module has been loaded.
+    pub fn new(device: &BlockDevice, dev_id: Option<u32>, queues: u16, depth: u16) -> Result<Self> {
+        if queues == 0 || depth == 0 || depth as usize > UBLK_MAX_QUEUE_DEPTH {
+            return Err(einval!(format!(
+                "block_ublk: invalid queue number {} or queue depth {}",
+                queues, depth
+            )));
+        }
+
+        let ctrl = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(UBLK_CONTROL_PATH)
+            .inspect_err(|_| error!("block_ublk: failed to open {}", UBLK_CONTROL_PATH))?;
+
+        let mut info = UblkCtrlDevInfo {
+            nr_hw_queues: queues,
+            queue_depth: depth,
+            max_io_buf_bytes: UBLK_MAX_IO_BUF_BYTES,
+            dev_id: dev_id.unwrap_or(UBLK_DEV_ID_ANY),
+            flags: UBLK_F_CMD_IOCTL_ENCODE,
+            ..Default::default()
+        };
+        let cmd = UblkCtrlCmd {
+            dev_id: info.dev_id,
+            queue_id: u16::MAX,
+            len: std::mem::size_of::<UblkCtrlDevInfo>() as u16,
+            addr: &mut info as *mut UblkCtrlDevInfo as u64,
+            ..Default::default()
+        };
+        ublk_ctrl_cmd(ctrl.as_raw_fd(), UBLK_CMD_ADD_DEV, cmd)?;
+
+        let mut service = UblkService {
+            blob_id: device.meta_blob_id().to_string(),
+            cache_mgr: device.cache_mgr(),
+            ctrl,
+            cdev: None,
+            dev_id: info.dev_id,
+            queues: info.nr_hw_queues,
+            depth: info.queue_depth,
+            buf_size: info.max_io_buf_bytes,
+        };
+        // The device gets deleted by drop() if failed to initialize it.
+        service.set_params(device)?;
+        service.cdev = Some(Arc::new(service.open_char_device()?));
+
+        Ok(service)
+    }
+
+    /// Get path of the exported block device.
+    pub fn block_device_path(&self) -> String {
+        format!("/dev/ublkb{}", self.dev_id)
+    }
+
+    /// Create a [UblkQueueWorker] to serve IO requests of ublk queue `q_id`.
+    pub fn create_worker(&self, q_id: u16) -> Result<UblkQueueWorker> {
+        if q_id >= self.queues {
+            return Err(einval!(format!("block_ublk: invalid queue id {}", q_id)));
+        }
+        let cdev = self
+            .cdev
+            .clone()
+            .ok_or_else(|| eother!("block_ublk: ublk device has been closed"))?;
+
+        Ok(UblkQueueWorker {
+            q_id,
+            depth: self.depth,
+            buf_size: self.buf_size as usize,
+            blob_id: self.blob_id.clone(),
+            cache_mgr: self.cache_mgr.clone(),
+            cdev,
+        })
+    }
+
+    /// Start the ublk device.
+    ///
+    /// The caller will get blocked until all queue workers are ready to serve IO requests.
+    pub fn run(&self) -> Result<()> {
+        let cmd = UblkCtrlCmd {
+            dev_id: self.dev_id,
+            queue_id: u16::MAX,
+            data: std::process::id() as u64,
+            ..Default::default()
+        };
+        ublk_ctrl_cmd(self.ctrl.as_raw_fd(), UBLK_CMD_START_DEV, cmd)?;
+        info!(
+            "block_ublk: export {} as {}",
+            self.blob_id,
+            self.block_device_path()
+        );
+
+        Ok(())
+    }
+
+    /// Stop the ublk device, all pending IO requests will get aborted and queue workers exit.
+    pub fn stop(&self) {
+        let cmd = UblkCtrlCmd {
+            dev_id: self.dev_id,
+            queue_id: u16::MAX,
+            ..Default::default()
+        };
+        if let Err(e) = ublk_ctrl_cmd(self.ctrl.as_raw_fd(), UBLK_CMD_STOP_DEV, cmd) {
+            warn!(
+                "block_ublk: failed to stop ublk device {}, {}",
+                self.dev_id, e
+            );
+        }
+    }
+
+    fn set_params(&self, device: &BlockDevice) -> Result<()> {
+        let bs_shift = device.block_size().trailing_zeros() as u8;
+        let mut params = UblkParams {
+            len: std::mem::size_of::<UblkParams>() as u32,
+            types: UBLK_PARAM_TYPE_BASIC,
+            basic: UblkParamBasic {
+                attrs: UBLK_ATTR_READ_ONLY,
+                logical_bs_shift: bs_shift,
+                physical_bs_shift: bs_shift,
+                io_opt_shift: bs_shift,
+                io_min_shift: bs_shift,
+                max_sectors: self.buf_size >> UBLK_SECTOR_SHIFT,
+                dev_sectors: device.blocks_to_size(device.blocks()) >> UBLK_SECTOR_SHIFT,
+                ..Default::default()
+            },
+        };
+        let cmd = UblkCtrlCmd {
+            dev_id: self.dev_id,
+            queue_id: u16::MAX,
+            len: std::mem::size_of::<UblkParams>() as u16,
+            addr: &mut params as *mut UblkParams as u64,
+            ..Default::default()
+        };
+        ublk_ctrl_cmd(self.ctrl.as_raw_fd(), UBLK_CMD_SET_PARAMS, cmd)?;
+
+        Ok(())
+    }
+
+    fn open_char_device(&self) -> Result<fs::File> {
+        // The device node is created by udev asynchronously, so wait for a while.
+        let path = format!("/dev/ublkc{}", self.dev_id);
+        let mut retry = 0;
+        loop {
+            match OpenOptions::new().read(true).write(true).open(&path) {
+                Ok(f) => return Ok(f),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && retry < 50 => {
+                    retry += 1;
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(e) => {
+                    error!("block_ublk: failed to open ublk device {}", path);
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for UblkService {
+    fn drop(&mut self) {
+        // The kernel waits for the character device to be closed before deleting the device.
+        self.cdev = None;
+        let cmd = UblkCtrlCmd {
+            dev_id: self.dev_id,
+            queue_id: u16::MAX,
+            ..Default::default()
+        };
+        if let Err(e) = ublk_ctrl_cmd(self.ctrl.as_raw_fd(), UBLK_CMD_DEL_DEV, cmd) {
+            warn!(
+                "block_ublk: failed to delete ublk device {}, {}",
+                self.dev_id, e
+            );
+        }
+    }
+}
+
+/// IO descriptor array of a ublk queue mapped from the ublk character device.
+struct UblkIoDescs {
+    addr: *mut libc::c_void,
+    size: usize,
+}
+
+impl UblkIoDescs {
+    fn new(cdev: &fs::File, q_id: u16, depth: u16) -> Result<Self> {
+        let size = ublk_queue_desc_size(depth);
+        let offset = q_id as u64
+            * round_up(
+                (UBLK_MAX_QUEUE_DEPTH * std::mem::size_of::<UblkIoDesc>()) as u64,
+                page_size(),
+            );
+        // Safe because we check the return value and unmap it on drop.
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                cdev.as_raw_fd(),
+                offset as libc::off_t,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(last_error!("block_ublk: failed to map IO descriptors"));
+        }
+
+        Ok(UblkIoDescs { addr, size })
+    }
+
+    fn get(&self, tag: u16) -> UblkIoDesc {
+        // Safe because the tag has been validated by the caller and the kernel has filled the
+        // descriptor before completing the fetch command.
+        unsafe { std::ptr::read_volatile((self.addr as *const UblkIoDesc).add(tag as usize)) }
+    }
+}
+
+impl Drop for UblkIoDescs {
+    fn drop(&mut self) {
+        // Safe because the address and size are returned by mmap().
+        unsafe { libc::munmap(self.addr, self.size) };
+    }
+}
+
+/// A worker to serve IO requests of a ublk queue in asynchronous mode.
+pub struct UblkQueueWorker {
+    q_id: u16,
+    depth: u16,
+    buf_size: usize,
+    blob_id: String,
+    cache_mgr: Arc<BlobCacheMgr>,
+    cdev: Arc<fs::File>,
+}
+
+impl UblkQueueWorker {
+    /// Run the event loop to serve IO requests from kernel in asynchronous mode.
+    ///
+    /// It must be called in a `tokio_uring` runtime, and all IO commands of a ublk queue must be
+    /// issued by the same thread. Completions of IO commands are polled by the runtime, and each
+    /// request is served by a dedicated task, which commits the result and fetches the next
+    /// request for its tag as soon as the request is done.
+    pub async fn run(self) {
+        if let Err(e) = self.serve().await {
+            warn!(
+                "block_ublk: queue {} of {} exits with error, {}",
+                self.q_id, self.blob_id, e
+            );
+        }
+    }
+
+    async fn serve(&self) -> Result<()> {
+        let device = Rc::new(BlockDevice::new_with_cache_manager(
+            self.blob_id.clone(),
+            self.cache_mgr.clone(),
+        )?);
+        let descs = UblkIoDescs::new(&self.cdev, self.q_id, self.depth)?;
+        let mut ring = IoUring::new((self.depth as u32).next_power_of_two())?;
+        // The ring becomes readable when there are completions, so let the runtime poll it.
+        let ring_fd = AsyncFd::with_interest(ring.as_raw_fd(), Interest::READABLE)?;
+        let (done_tx, mut done_rx) = mpsc::unbounded_channel::<(u16, i32, Vec<u8>)>();
+        let mut bufs: Vec<Option<Vec<u8>>> = (0..self.depth)
+            .map(|_| Some(alloc_buf(self.buf_size)))
+            .collect();
+
+        for tag in 0..self.depth {
+            let addr = bufs[tag as usize].as_ref().unwrap().as_ptr() as u64;
+            self.queue_io_cmd(&mut ring, UBLK_IO_FETCH_REQ, tag, 0, addr)?;
+        }
+        ring.submit()?;
+
+        let mut active = self.depth;
+        while active > 0 {
+            tokio::select! {
+                guard = ring_fd.readable() => {
+                    // Clear readiness before draining, so later completions wake us up again.
+                    guard?.clear_ready();
+                    let completions: Vec<(u16, i32)> = ring
+                        .completion()
+                        .map(|cqe| (cqe.user_data() as u16, cqe.result()))
+                        .collect();
+                    for (tag, res) in completions {
+                        if res != UBLK_IO_RES_OK || tag >= self.depth {
+                            // The queue is being aborted, no more requests for this tag.
+                            active -= 1;
+                            continue;
+                        }
+                        let desc = descs.get(tag);
+                        let buf = bufs[tag as usize].take().unwrap();
+                        let device = device.clone();
+                        let done_tx = done_tx.clone();
+                        tokio_uring::spawn(async move {
+                            let (res, buf) = Self::handle_request(&device, &desc, buf).await;
+                            // The receiver lives until all tags have been aborted.
+                            let _ = done_tx.send((tag, res, buf));
+                        });
+                    }
+                }
+                Some((tag, res, buf)) = done_rx.recv() => {
+                    let addr = buf.as_ptr() as u64;
+                    bufs[tag as usize] = Some(buf);
+                    self.queue_io_cmd(&mut ring, UBLK_IO_COMMIT_AND_FETCH_REQ, tag, res, addr)?;
+                    ring.submit()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_request(
+        device: &BlockDevice,
+        desc: &UblkIoDesc,
+        buf: Vec<u8>,
+    ) -> (i32, Vec<u8>) {
+        let op = desc.op_flags & 0xff;
+        let pos = desc.start_sector << UBLK_SECTOR_SHIFT;
+        let len = (desc.nr_sectors as u64) << UBLK_SECTOR_SHIFT;
+        let block_size = device.block_size();
+
+        if op != UBLK_IO_OP_READ {
+            return (-libc::EROFS, buf);
+        } else if pos % block_size != 0 || len % block_size != 0 || len > buf.capacity() as u64 {
+            warn!(
+                "block_ublk: invalid request op {}, pos 0x{:x}, len 0x{:x}",
+                op, pos, len
+            );
+            return (-libc::EINVAL, buf);
+        }
+
+        let start = (pos / block_size) as u32;
+        let count = (len / block_size) as u32;
+        let (res, buf) = device.async_read(start, count, buf).await;
+        match res {
+            Ok(sz) if sz == len as usize => (len as i32, buf),
+            Ok(sz) => {
+                warn!("block_ublk: got 0x{:x} bytes, expect 0x{:x}", sz, len);
+                (-libc::EIO, buf)
+            }
+            Err(e) => {
+                warn!("block_ublk: failed to read data from block device, {}", e);
+                (-libc::EIO, buf)
+            }
+        }
+    }
+
+    fn queue_io_cmd(
+        &self,
+        ring: &mut IoUring,
+        op: u32,
+        tag: u16,
+        result: i32,
+        addr: u64,
+    ) -> Result<()> {
+        let cmd = UblkIoCmd {
+            q_id: self.q_id,
+            tag,
+            result,
+            addr,
+        };
+        let mut buf = [0u8; 16];
+        // Safe because UblkIoCmd is a plain old data structure of 16 bytes.
+        unsafe { std::ptr::write_unaligned(buf.as_mut_ptr() as *mut UblkIoCmd, cmd) };
+        let entry = opcode::UringCmd16::new(types::Fd(self.cdev.as_raw_fd()), ublk_io_cmd_op(op))
+            .cmd(buf)
+            .build()
+            .user_data(tag as u64);
+        // Safe because the data buffer is owned by the worker and outlives the command.
+        unsafe {
+            ring.submission()
+                .push(&entry)
+                .map_err(|_| eother!("block_ublk: io_uring submission queue is full"))
+        }
+    }
+}
+
+/// A [NydusDaemon] implementation to expose RAFS v6 images as block devices through ublk.
+pub struct UblkDaemon {
+    cache_mgr: Arc<BlobCacheMgr>,
+    service: Arc<UblkService>,
+
+    bti: BuildTimeInfo,
+    id: Option<String>,
+    supervisor: Option<String>,
+
+    ublk_control_thread: Mutex<Option<JoinHandle<()>>>,
+    ublk_service_threads: Mutex<Vec<JoinHandle<Result<()>>>>,
+    request_sender: Arc<Mutex<std::sync::mpsc::Sender<DaemonStateMachineInput>>>,
+    result_receiver: Mutex<std::sync::mpsc::Receiver<NydusResult<()>>>,
+    state: AtomicI32,
+    state_machine_thread: Mutex<Option<JoinHandle<Result<()>>>>,
+    waker: Arc<Waker>,
+}
+
+impl UblkDaemon {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        dev_id: Option<u32>,
+        queues: u16,
+        depth: u16,
+        blob_entry: BlobCacheEntry,
+        trigger: std::sync::mpsc::Sender<DaemonStateMachineInput>,
+        receiver: std::sync::mpsc::Receiver<NydusResult<()>>,
+        waker: Arc<Waker>,
+        bti: BuildTimeInfo,
+        id: Option<String>,
+        supervisor: Option<String>,
+    ) -> Result<Self> {
+        let blob_id = generate_blob_key(&blob_entry.domain_id, &blob_entry.blob_id);
+        let cache_mgr = Arc::new(BlobCacheMgr::new());
+        cache_mgr.add_blob_entry(&blob_entry)?;
+        let block_device = BlockDevice::new_with_cache_manager(blob_id, cache_mgr.clone())?;
+        let ublk_service = UblkService::new(&block_device, dev_id, queues, depth)?;
+
+        Ok(UblkDaemon {
+            cache_mgr,
+            service: Arc::new(ublk_service),
+
+            bti,
+            id,
+            supervisor,
+
+            ublk_control_thread: Mutex::new(None),
+            ublk_service_threads: Mutex::new(Vec::new()),
+            state: AtomicI32::new(DaemonState::INIT as i32),
+            request_sender: Arc::new(Mutex::new(trigger)),
+            result_receiver: Mutex::new(receiver),
+            state_machine_thread: Mutex::new(None),
+            waker,
+        })
+    }
+}
+
+impl DaemonStateMachineSubscriber for UblkDaemon {
+    fn on_event(&self, event: DaemonStateMachineInput) -> NydusResult<()> {
+        self.request_sender
+            .lock()
+            .expect("block_ublk: failed to lock request sender!")
+            .send(event)
+            .map_err(NydusError::ChannelSend)?;
+
+        self.result_receiver
+            .lock()
+            .expect("block_ublk: failed to lock result receiver!")
+            .recv()
+            .map_err(NydusError::ChannelReceive)?
+    }
+}
+
+impl NydusDaemon for UblkDaemon {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn id(&self) -> Option<String> {
+        self.id.clone()
+    }
+
+    fn version(&self) -> BuildTimeInfo {
+        self.bti.clone()
+    }
+
+    fn get_state(&self) -> DaemonState {
+        self.state.load(Ordering::Relaxed).into()
+    }
+
+    fn set_state(&self, state: DaemonState) {
+        self.state.store(state as i32, Ordering::Relaxed);
+    }
+
+    fn start(&self) -> NydusResult<()> {
+        info!("start ublk service with {} queues", self.service.queues);
+        for q_id in 0..self.service.queues {
+            let waker = self.waker.clone();
+            let worker = self
+                .service
+                .create_worker(q_id)
+                .map_err(|e| NydusError::StartService(format!("{}", e)))?;
+            let thread = std::thread::Builder::new()
+                .name(format!("ublk_queue_{}", q_id))
+                .spawn(move || {
+                    tokio_uring::start(async move {
+                        worker.run().await;
+                        // Notify the daemon controller that one working thread has exited.
+                        if let Err(err) = waker.wake() {
+                            error!("block_ublk: fail to exit daemon, error: {:?}", err);
+                        }
+                    });
+                    Ok(())
+                })
+                .map_err(NydusError::ThreadSpawn)?;
+            self.ublk_service_threads.lock().unwrap().push(thread);
+        }
+
+        let ublk = self.service.clone();
+        let thread = std::thread::spawn(move || {
+            if let Err(e) = ublk.run() {
+                error!("block_ublk: failed to start ublk device, {e}");
+            }
+        });
+        *self.ublk_control_thread.lock().unwrap() = Some(thread);
+
+        Ok(())
+    }
+
+    fn umount(&self) -> NydusResult<()> {
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.service.stop();
+    }
+
+    fn wait(&self) -> NydusResult<()> {
+        self.wait_state_machine()?;
+        self.wait_service()
+    }
+
+    fn wait_service(&self) -> NydusResult<()> {
+        loop {
+            let handle = self.ublk_service_threads.lock().unwrap().pop();
+            if let Some(handle) = handle {
+                handle
+                    .join()
+                    .map_err(|e| {
+                        let e = *e
+                            .downcast::<Error>()
+                            .unwrap_or_else(|e| Box::new(eother!(e)));
+                        NydusError::WaitDaemon(e)
+                    })?
+                    .map_err(NydusError::WaitDaemon)?;
+            } else {
+                // No more handles to wait
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn wait_state_machine(&self) -> NydusResult<()> {
+        let mut guard = self.state_machine_thread.lock().unwrap();
+        if let Some(handler) = guard.take() {
+            let result = handler.join().map_err(|e| {
+                let e = *e
+                    .downcast::<Error>()
+                    .unwrap_or_else(|e| Box::new(eother!(e)));
+                NydusError::WaitDaemon(e)
+            })?;
+            result.map_err(NydusError::WaitDaemon)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn supervisor(&self) -> Option<String> {
+        self.supervisor.clone()
+    }
+
+    fn save(&self) -> NydusResult<()> {
+        unimplemented!()
+    }
+
+    fn restore(&self) -> NydusResult<()> {
+        unimplemented!()
+    }
+
+    fn get_blob_cache_mgr(&self) -> Option<Arc<BlobCacheMgr>> {
+        Some(self.cache_mgr.clone())
+    }
+}
+
+/// Create and start a [UblkDaemon] instance to expose a RAFS v6 image as a block device through
+/// ublk.
+#[allow(clippy::too_many_arguments)]
+pub fn create_ublk_daemon(
+    dev_id: Option<u32>,
+    queues: u16,
+    depth: u16,
+    blob_entry: BlobCacheEntry,
+    bti: BuildTimeInfo,
+    id: Option<String>,
+    supervisor: Option<String>,
+    waker: Arc<Waker>,
+) -> Result<Arc<dyn NydusDaemon>> {
+    let (trigger, events_rx) = std::sync::mpsc::channel::<DaemonStateMachineInput>();
+    let (result_sender, result_receiver) = std::sync::mpsc::channel::<NydusResult<()>>();
+    let daemon = UblkDaemon::new(
+        dev_id,
+        queues,
+        depth,
+        blob_entry,
+        trigger,
+        result_receiver,
+        waker,
+        bti,
+        id,
+        supervisor,
+    )?;
+    let daemon = Arc::new(daemon);
+    let machine = DaemonStateMachineContext::new(daemon.clone(), events_rx, result_sender);
+    let machine_thread = machine.kick_state_machine()?;
+    *daemon.state_machine_thread.lock().unwrap() = Some(machine_thread);
+    daemon
+        .on_event(DaemonStateMachineInput::Mount)
+        .map_err(|e| eother!(e))?;
+    daemon
+        .on_event(DaemonStateMachineInput::Start)
+        .map_err(|e| eother!(e))?;
+
+    Ok(daemon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_ublk_abi() {
+        assert_eq!(std::mem::size_of::<UblkCtrlCmd>(), 32);
+        assert_eq!(std::mem::size_of::<UblkCtrlDevInfo>(), 64);
+        assert_eq!(std::mem::size_of::<UblkParamBasic>(), 32);
+        assert_eq!(std::mem::size_of::<UblkParams>(), 40);
+        assert_eq!(std::mem::size_of::<UblkIoDesc>(), 24);
+        assert_eq!(std::mem::size_of::<UblkIoCmd>(), 16);
+        assert_eq!(ublk_ctrl_cmd_op(UBLK_CMD_ADD_DEV), 0xc0207504);
+        assert_eq!(ublk_ctrl_cmd_op(UBLK_CMD_START_DEV), 0xc0207506);
+        assert_eq!(ublk_io_cmd_op(UBLK_IO_FETCH_REQ), 0xc0107520);
+        assert_eq!(ublk_io_cmd_op(UBLK_IO_COMMIT_AND_FETCH_REQ), 0xc0107521);
+    }
+
+    fn create_block_device(tmpdir: PathBuf) -> Result<BlockDevice> {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let mut source_path = PathBuf::from(root_dir);
+        source_path.push("../tests/texture/blobs/be7d77eeb719f70884758d1aa800ed0fb09d701aaec469964e9d54325f0d5fef");
+        let mut dest_path = tmpdir.clone();
+        dest_path.push("be7d77eeb719f70884758d1aa800ed0fb09d701aaec469964e9d54325f0d5fef");
+        fs::copy(&source_path, &dest_path).unwrap();
+
+        let mut source_path = PathBuf::from(root_dir);
+        source_path.push("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let config = r#"
+        {
+            "type": "bootstrap",
+            "id": "rafs-v6",
+            "domain_id": "domain2",
+            "config_v2": {
+                "version": 2,
+                "id": "factory1",
+                "backend": {
+                    "type": "localfs",
+                    "localfs": {
+                        "dir": "/tmp/nydus"
+                    }
+                },
+                "cache": {
+                    "type": "filecache",
+                    "filecache": {
+                        "work_dir": "/tmp/nydus"
+                    }
+                },
+                "metadata_path": "RAFS_V5"
+            }
+          }"#;
+        let content = config
+            .replace("/tmp/nydus", tmpdir.as_path().to_str().unwrap())
+            .replace("RAFS_V5", &source_path.display().to_string());
+        let mut entry: BlobCacheEntry = serde_json::from_str(&content).unwrap();
+        assert!(entry.prepare_configuration_info());
+
+        let mgr = Arc::new(BlobCacheMgr::new());
+        mgr.add_blob_entry(&entry).unwrap();
+        let blob_id = generate_blob_key(&entry.domain_id, &entry.blob_id);
+        let device = BlockDevice::new_with_cache_manager(blob_id, mgr).unwrap();
+
+        Ok(device)
+    }
+
+    #[ignore]
+    #[test]
+    fn test_ublk_device() {
+        let tmpdir = TempDir::new().unwrap();
+        let device = create_block_device(tmpdir.as_path().to_path_buf()).unwrap();
+        let ublk = Arc::new(UblkService::new(&device, None, 2, 32).unwrap());
+        let mut threads = Vec::new();
+        for q_id in 0..2 {
+            let worker = ublk.create_worker(q_id).unwrap();
+            threads.push(std::thread::spawn(move || {
+                tokio_uring::start(async move { worker.run().await })
+            }));
+        }
+        ublk.run().unwrap();
+
+        let mut buf = vec![0u8; 4096];
+        let disk = fs::File::open(ublk.block_device_path()).unwrap();
+        nix::sys::uio::pread(disk.as_raw_fd(), &mut buf, 0).unwrap();
+        drop(disk);
+
+        ublk.stop();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
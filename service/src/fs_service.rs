@@ -22,9 +22,9 @@ use fuse_backend_rs::api::{BackFileSystem, Vfs};
 use fuse_backend_rs::overlayfs::{config::Config as overlay_config, OverlayFs};
 #[cfg(target_os = "linux")]
 use fuse_backend_rs::passthrough::{CachePolicy, Config as passthrough_config, PassthroughFs};
-use nydus_api::ConfigV2;
+use nydus_api::{BackendConfigV2, ConfigV2};
 use nydus_rafs::fs::Rafs;
-use nydus_rafs::metadata::RafsInode;
+use nydus_rafs::metadata::{RafsInode, RafsSuper};
 use nydus_rafs::{RafsError, RafsIoRead};
 use nydus_storage::factory::BLOB_FACTORY;
 use serde::{Deserialize, Serialize};
@@ -88,6 +88,13 @@ impl FsBackendCollection {
         Ok(())
     }
 
+    fn update_backend(&mut self, id: &str, backend: &BackendConfigV2) {
+        if let Some(cfg) = self.0.get_mut(id).and_then(|desc| desc.config.as_mut()) {
+            cfg.backend = Some(backend.clone());
+            *cfg = cfg.clone_without_secrets();
+        }
+    }
+
     pub fn del(&mut self, id: &str) {
         self.0.remove(id);
     }
@@ -138,13 +145,17 @@ pub trait FsService: Send + Sync {
         let (rootfs, _) = self
             .backend_from_mountpoint(&cmd.mountpoint)?
             .ok_or(Error::NotFound)?;
-        let mut bootstrap = <dyn RafsIoRead>::from_file(&cmd.source)?;
         let any_fs = rootfs.deref().as_any();
         let rafs = any_fs
             .downcast_ref::<Rafs>()
             .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
         let rafs_cfg = ConfigV2::from_str(&cmd.config).map_err(RafsError::LoadConfig)?;
         let rafs_cfg = Arc::new(rafs_cfg);
+        let mut bootstrap = <dyn RafsIoRead>::from_file(&cmd.source)?;
+        if let Some(cfg) = rafs_cfg.rafs.as_ref() {
+            RafsSuper::verify_signature(&cmd.source, &mut bootstrap, cfg)
+                .map_err(RafsError::VerifySignature)?;
+        }
 
         rafs.update(&mut bootstrap, &rafs_cfg)
             .map_err(|e| match e {
@@ -228,9 +239,90 @@ pub trait FsService: Send + Sync {
         Ok(resp)
     }
 
+    /// Update storage backend configuration of a mounted RAFS filesystem in place.
+    fn update_backend(&self, mountpoint: &str, config: &BackendConfigV2) -> Result<()> {
+        let (fs, _) = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let any_fs = fs.deref().as_any();
+        let rafs = any_fs
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        rafs.update_backend(config).map_err(Error::Rafs)?;
+        // To report the updated backend configuration.
+        self.backend_collection().update_backend(mountpoint, config);
+
+        Ok(())
+    }
+
+    /// Export files read by a RAFS filesystem as a prefetch file list.
+    ///
+    /// The only RAFS filesystem is used if `mountpoint` is not specified.
+    fn export_prefetch_list(&self, mountpoint: Option<&str>) -> Result<String> {
+        let mountpoint = match mountpoint {
+            Some(v) => v.to_string(),
+            None => {
+                let collection = self.backend_collection();
+                let mut rafs = collection
+                    .0
+                    .values()
+                    .filter(|v| v.backend_type == FsBackendType::Rafs);
+                match (rafs.next(), rafs.next()) {
+                    (Some(v), None) => v.mountpoint.clone(),
+                    _ => return Err(Error::InvalidArguments("mountpoint".to_string())),
+                }
+            }
+        };
+        let (fs, _) = self
+            .backend_from_mountpoint(&mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let any_fs = fs.deref().as_any();
+        let rafs = any_fs
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        rafs.export_prefetch_list()
+            .map_err(|e| Error::Rafs(RafsError::ReadMetadata(e, mountpoint)))
+    }
+
+    /// Prefetch files or directories of a mounted RAFS filesystem in background.
+    ///
+    /// Return id of the prefetch job, which may be used to query progress of the job.
+    fn prefetch_files(&self, mountpoint: &str, files: &[String]) -> Result<u64> {
+        let files = validate_prefetch_file_list(&Some(files.to_vec()))?.unwrap_or_default();
+        let (fs, _) = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let any_fs = fs.deref().as_any();
+        let rafs = any_fs
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        rafs.prefetch_files_on_demand(&files)
+            .map_err(|e| Error::Rafs(RafsError::Prefetch(e.to_string())))
+    }
+
+    /// Get progress of a prefetch job in json.
+    fn export_prefetch_progress(&self, mountpoint: &str, job_id: u64) -> Result<String> {
+        let (fs, _) = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(Error::NotFound)?;
+        let any_fs = fs.deref().as_any();
+        let rafs = any_fs
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| Error::FsTypeMismatch("RAFS".to_string()))?;
+        let progress = rafs
+            .prefetch_job_progress(job_id)
+            .map_err(|e| Error::Rafs(RafsError::Prefetch(e.to_string())))?;
+        serde_json::to_string(&progress).map_err(Error::Serde)
+    }
+
     /// Export metrics about in-flight operations.
     fn export_inflight_ops(&self) -> Result<Option<String>>;
 
+    /// Get number of in-flight operations, `None` if not supported.
+    fn inflight_ops_count(&self) -> Option<usize> {
+        None
+    }
+
     /// Recursively walk the inode tree and send cache invalidation notifications.
     fn walk_and_notify_invalidation(
         &self,
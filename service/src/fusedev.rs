@@ -339,6 +339,15 @@ impl FsService for FusedevFsService {
         }
     }
 
+    fn inflight_ops_count(&self) -> Option<usize> {
+        let ops = self.inflight_ops.lock().unwrap();
+        Some(
+            ops.iter()
+                .filter(|w| w.op.lock().unwrap().is_some())
+                .count(),
+        )
+    }
+
     /// Recursively walk the inode tree and send cache invalidation notifications.
     fn walk_and_notify_invalidation(
         &self,
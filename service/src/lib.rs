@@ -49,6 +49,8 @@ pub mod blob_cache;
 pub mod block_device;
 #[cfg(all(target_os = "linux", feature = "block-nbd"))]
 pub mod block_nbd;
+#[cfg(all(target_os = "linux", feature = "block-ublk"))]
+pub mod block_ublk;
 #[cfg(target_os = "linux")]
 mod fs_cache;
 
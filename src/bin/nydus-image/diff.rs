@@ -0,0 +1,521 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compare two RAFS filesystems and report the differences between them.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nydus_api::ConfigV2;
+use nydus_builder::{HashChunkDict, Overlay, Tree, WhiteoutSpec, WhiteoutType};
+use nydus_rafs::metadata::RafsSuper;
+use nydus_utils::digest::RafsDigest;
+use serde::Serialize;
+
+/// Type of difference detected for a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Modified,
+    Whiteout,
+    Opaque,
+}
+
+impl DiffKind {
+    fn tag(&self) -> &'static str {
+        match self {
+            DiffKind::Added => "A",
+            DiffKind::Removed => "D",
+            DiffKind::Modified => "M",
+            DiffKind::Whiteout => "W",
+            DiffKind::Opaque => "O",
+        }
+    }
+}
+
+/// Difference of a path between the old and new RAFS filesystems.
+#[derive(Debug, Serialize)]
+pub struct DiffEntry {
+    pub path: String,
+    pub kind: DiffKind,
+    pub file_type: String,
+    /// Changed attributes of modified paths, such as "mode", "uid", "xattrs" and "content".
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<&'static str>,
+    /// Path hidden by a whiteout or opaque entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Uncompressed size of data chunks only referenced by the new path.
+    pub added_bytes: u64,
+    /// Uncompressed size of data chunks only referenced by the old path.
+    pub removed_bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DiffSummary {
+    pub added: u32,
+    pub removed: u32,
+    pub modified: u32,
+    pub whiteouts: u32,
+    pub opaques: u32,
+    /// Data chunks only referenced by the new filesystem.
+    pub added_chunks: u32,
+    pub added_comp_size: u64,
+    pub added_uncomp_size: u64,
+    /// Data chunks only referenced by the old filesystem.
+    pub removed_chunks: u32,
+    pub removed_comp_size: u64,
+    pub removed_uncomp_size: u64,
+    /// Data chunks referenced by both filesystems.
+    pub shared_chunks: u32,
+    pub shared_uncomp_size: u64,
+}
+
+#[derive(Clone, Copy)]
+struct ChunkSnapshot {
+    digest: RafsDigest,
+    comp_size: u32,
+    uncomp_size: u32,
+}
+
+struct NodeSnapshot {
+    file_type: String,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    rdev: u64,
+    is_reg: bool,
+    symlink: Option<OsString>,
+    xattrs: BTreeMap<OsString, Vec<u8>>,
+    chunks: Vec<ChunkSnapshot>,
+    whiteout: Option<(WhiteoutType, PathBuf)>,
+}
+
+impl NodeSnapshot {
+    fn chunk_bytes(&self) -> u64 {
+        self.chunks.iter().map(|c| c.uncomp_size as u64).sum()
+    }
+
+    fn changes(&self, old: &NodeSnapshot) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.file_type != old.file_type {
+            changes.push("type");
+        } else if self.mode & 0o7777 != old.mode & 0o7777 {
+            changes.push("mode");
+        }
+        if self.uid != old.uid {
+            changes.push("uid");
+        }
+        if self.gid != old.gid {
+            changes.push("gid");
+        }
+        if self.xattrs != old.xattrs {
+            changes.push("xattrs");
+        }
+        if self.is_reg && old.is_reg {
+            if self.size != old.size {
+                changes.push("size");
+            }
+            if self.chunks.len() != old.chunks.len()
+                || self
+                    .chunks
+                    .iter()
+                    .zip(old.chunks.iter())
+                    .any(|(a, b)| a.digest != b.digest)
+            {
+                changes.push("content");
+            }
+        }
+        if self.symlink != old.symlink {
+            changes.push("symlink");
+        }
+        if self.rdev != old.rdev {
+            changes.push("rdev");
+        }
+        changes
+    }
+}
+
+/// Differences between two RAFS filesystems.
+#[derive(Debug, Default, Serialize)]
+pub struct ImageDiff {
+    pub old: String,
+    pub new: String,
+    pub entries: Vec<DiffEntry>,
+    pub summary: DiffSummary,
+}
+
+impl ImageDiff {
+    /// Compare the RAFS filesystems loaded from bootstrap files `old` and `new`.
+    pub fn diff(old: &Path, new: &Path, spec: WhiteoutSpec, config: Arc<ConfigV2>) -> Result<Self> {
+        let old_tree = Self::load_tree(old, config.clone())?;
+        let new_tree = Self::load_tree(new, config)?;
+        let mut diff = Self::compare(&old_tree, &new_tree, spec)?;
+        diff.old = old.display().to_string();
+        diff.new = new.display().to_string();
+        Ok(diff)
+    }
+
+    fn load_tree(path: &Path, config: Arc<ConfigV2>) -> Result<Tree> {
+        let (rs, _) = RafsSuper::load_from_file(path, config, false)
+            .with_context(|| format!("failed to load bootstrap {}", path.display()))?;
+        let mut dict = HashChunkDict::new(rs.meta.get_digester());
+        Tree::from_bootstrap(&rs, &mut dict)
+            .with_context(|| format!("failed to load bootstrap {} for diff", path.display()))
+    }
+
+    /// Compare two in-memory filesystem trees, whiteouts are detected in the `new` tree only.
+    pub fn compare(old: &Tree, new: &Tree, spec: WhiteoutSpec) -> Result<Self> {
+        let old_nodes = Self::snapshot(old, None)?;
+        let new_nodes = Self::snapshot(new, Some(spec))?;
+        let mut diff = ImageDiff::default();
+
+        for (path, old_node) in old_nodes.iter() {
+            if !new_nodes.contains_key(path) {
+                diff.push(DiffEntry {
+                    path: path.clone(),
+                    kind: DiffKind::Removed,
+                    file_type: old_node.file_type.clone(),
+                    changes: Vec::new(),
+                    target: None,
+                    added_bytes: 0,
+                    removed_bytes: old_node.chunk_bytes(),
+                });
+            }
+        }
+
+        for (path, new_node) in new_nodes.iter() {
+            let (kind, changes, added_bytes, removed_bytes) = match old_nodes.get(path) {
+                None => (DiffKind::Added, Vec::new(), new_node.chunk_bytes(), 0),
+                Some(old_node) => {
+                    let changes = new_node.changes(old_node);
+                    if changes.is_empty() {
+                        continue;
+                    }
+                    let (added, removed) = Self::chunk_delta(&old_node.chunks, &new_node.chunks);
+                    (DiffKind::Modified, changes, added, removed)
+                }
+            };
+            let (kind, target) = match new_node.whiteout.as_ref() {
+                Some((t, target)) if t.is_removal() => {
+                    (DiffKind::Whiteout, Some(target.display().to_string()))
+                }
+                Some((_, target)) => (DiffKind::Opaque, Some(target.display().to_string())),
+                None => (kind, None),
+            };
+            diff.push(DiffEntry {
+                path: path.clone(),
+                kind,
+                file_type: new_node.file_type.clone(),
+                changes,
+                target,
+                added_bytes,
+                removed_bytes,
+            });
+        }
+        diff.entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let old_chunks = Self::unique_chunks(&old_nodes);
+        let new_chunks = Self::unique_chunks(&new_nodes);
+        let summary = &mut diff.summary;
+        for (digest, chunk) in new_chunks.iter() {
+            if old_chunks.contains_key(digest) {
+                summary.shared_chunks += 1;
+                summary.shared_uncomp_size += chunk.uncomp_size as u64;
+            } else {
+                summary.added_chunks += 1;
+                summary.added_comp_size += chunk.comp_size as u64;
+                summary.added_uncomp_size += chunk.uncomp_size as u64;
+            }
+        }
+        for (digest, chunk) in old_chunks.iter() {
+            if !new_chunks.contains_key(digest) {
+                summary.removed_chunks += 1;
+                summary.removed_comp_size += chunk.comp_size as u64;
+                summary.removed_uncomp_size += chunk.uncomp_size as u64;
+            }
+        }
+
+        Ok(diff)
+    }
+
+    fn push(&mut self, entry: DiffEntry) {
+        match entry.kind {
+            DiffKind::Added => self.summary.added += 1,
+            DiffKind::Removed => self.summary.removed += 1,
+            DiffKind::Modified => self.summary.modified += 1,
+            DiffKind::Whiteout => self.summary.whiteouts += 1,
+            DiffKind::Opaque => self.summary.opaques += 1,
+        }
+        self.entries.push(entry);
+    }
+
+    fn snapshot(tree: &Tree, spec: Option<WhiteoutSpec>) -> Result<BTreeMap<String, NodeSnapshot>> {
+        let mut nodes = BTreeMap::new();
+        tree.walk_dfs_pre(&mut |t: &Tree| -> Result<()> {
+            let mut node = t.borrow_mut_node();
+            let whiteout = match spec {
+                Some(spec) => {
+                    // Nodes loaded from bootstrap are marked as lower nodes, which never get
+                    // whited out, so treat them as upper nodes as `nydus-image merge` does.
+                    let overlay = std::mem::replace(&mut node.overlay, Overlay::UpperAddition);
+                    let whiteout = node.whiteout_type(spec);
+                    node.overlay = overlay;
+                    whiteout.map(|t| {
+                        let path = node.target();
+                        let parent = path.parent().unwrap_or(path);
+                        let target = match t {
+                            WhiteoutType::OciRemoval => {
+                                // Safe to unwrap because it's an OCI removal whiteout.
+                                parent.join(node.origin_name(t).unwrap())
+                            }
+                            WhiteoutType::OciOpaque => parent.to_path_buf(),
+                            _ => path.clone(),
+                        };
+                        (t, target)
+                    })
+                }
+                None => None,
+            };
+            let snapshot = NodeSnapshot {
+                file_type: node.file_type().to_string(),
+                mode: node.inode.mode(),
+                uid: node.inode.uid(),
+                gid: node.inode.gid(),
+                size: node.inode.size(),
+                rdev: node.info.rdev,
+                is_reg: node.is_reg(),
+                symlink: node.info.symlink.clone(),
+                xattrs: node
+                    .info
+                    .xattrs
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+                chunks: node
+                    .chunks
+                    .iter()
+                    .map(|c| ChunkSnapshot {
+                        digest: *c.inner.id(),
+                        comp_size: c.inner.compressed_size(),
+                        uncomp_size: c.inner.uncompressed_size(),
+                    })
+                    .collect(),
+                whiteout,
+            };
+            nodes.insert(node.target().display().to_string(), snapshot);
+            Ok(())
+        })?;
+        Ok(nodes)
+    }
+
+    /// Get uncompressed size of chunks only in `new` and only in `old`.
+    fn chunk_delta(old: &[ChunkSnapshot], new: &[ChunkSnapshot]) -> (u64, u64) {
+        let mut counts: HashMap<RafsDigest, i64> = HashMap::new();
+        let mut sizes = HashMap::new();
+        for c in new {
+            *counts.entry(c.digest).or_default() += 1;
+            sizes.insert(c.digest, c.uncomp_size as u64);
+        }
+        for c in old {
+            *counts.entry(c.digest).or_default() -= 1;
+            sizes.insert(c.digest, c.uncomp_size as u64);
+        }
+
+        let mut added = 0;
+        let mut removed = 0;
+        for (digest, count) in counts {
+            let size = sizes[&digest];
+            match count.cmp(&0) {
+                Ordering::Greater => added += count as u64 * size,
+                Ordering::Less => removed += count.unsigned_abs() * size,
+                Ordering::Equal => {}
+            }
+        }
+        (added, removed)
+    }
+
+    fn unique_chunks(nodes: &BTreeMap<String, NodeSnapshot>) -> HashMap<RafsDigest, ChunkSnapshot> {
+        let mut chunks = HashMap::new();
+        for node in nodes.values() {
+            for c in node.chunks.iter() {
+                chunks.entry(c.digest).or_insert(*c);
+            }
+        }
+        chunks
+    }
+
+    pub fn dump_json(&self, path: &Path) -> Result<()> {
+        let w = OpenOptions::new()
+            .truncate(true)
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Output file {:?} can't be opened", path))?;
+
+        serde_json::to_writer(w, self).context("Write output file failed")?;
+
+        Ok(())
+    }
+
+    pub fn dump(&self) {
+        for entry in self.entries.iter() {
+            let mut line = format!("{}  {} ({})", entry.kind.tag(), entry.path, entry.file_type);
+            if let Some(target) = entry.target.as_ref() {
+                line += &format!(" -> {}", target);
+            }
+            if !entry.changes.is_empty() {
+                line += &format!(" [{}]", entry.changes.join(","));
+            }
+            if entry.added_bytes != 0 {
+                line += &format!(" +{}", entry.added_bytes);
+            }
+            if entry.removed_bytes != 0 {
+                line += &format!(" -{}", entry.removed_bytes);
+            }
+            println!("{}", line);
+        }
+
+        let s = &self.summary;
+        println!(
+            r#"
+Added Paths:            {}
+Removed Paths:          {}
+Modified Paths:         {}
+Whiteouts:              {}
+Opaques:                {}
+Added Chunks:           {}
+Added Comp Size:        0x{:x}
+Added Uncomp Size:      0x{:x}
+Removed Chunks:         {}
+Removed Comp Size:      0x{:x}
+Removed Uncomp Size:    0x{:x}
+Shared Chunks:          {}
+Shared Uncomp Size:     0x{:x}"#,
+            s.added,
+            s.removed,
+            s.modified,
+            s.whiteouts,
+            s.opaques,
+            s.added_chunks,
+            s.added_comp_size,
+            s.added_uncomp_size,
+            s.removed_chunks,
+            s.removed_comp_size,
+            s.removed_uncomp_size,
+            s.shared_chunks,
+            s.shared_uncomp_size,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Deref;
+
+    fn load_fixture() -> Tree {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("tests/texture/bootstrap/rafs-v6-2.2.boot");
+        ImageDiff::load_tree(&path, Arc::new(ConfigV2::default())).unwrap()
+    }
+
+    fn find_file(tree: &Tree) -> PathBuf {
+        let mut found = None;
+        tree.walk_dfs_pre(&mut |t: &Tree| -> Result<()> {
+            let node = t.borrow_mut_node();
+            if found.is_none() && node.is_reg() && !node.chunks.is_empty() {
+                found = Some(node.target().clone());
+            }
+            Ok(())
+        })
+        .unwrap();
+        found.unwrap()
+    }
+
+    #[test]
+    fn test_diff_identical_images() {
+        let old = load_fixture();
+        let new = load_fixture();
+        let diff = ImageDiff::compare(&old, &new, WhiteoutSpec::Oci).unwrap();
+        assert!(diff.entries.is_empty());
+        assert_eq!(diff.summary.added_chunks, 0);
+        assert_eq!(diff.summary.removed_chunks, 0);
+        assert!(diff.summary.shared_chunks > 0);
+    }
+
+    #[test]
+    fn test_diff_modified_images() {
+        let old = load_fixture();
+        let mut new = load_fixture();
+
+        // Change permission and drop the last chunk of a regular file.
+        let file = find_file(&new);
+        let removed = {
+            let t = new.get_node(&file).unwrap();
+            let mut node = t.borrow_mut_node();
+            let mode = node.inode.mode();
+            node.inode.set_mode(mode ^ 0o111);
+            node.chunks.pop().unwrap().inner.uncompressed_size() as u64
+        };
+
+        // Remove a top level entry which doesn't contain the modified file.
+        let idx = new
+            .children
+            .iter()
+            .position(|t| !file.starts_with(t.borrow_mut_node().target()))
+            .unwrap();
+        let gone = new.children.remove(idx);
+        let gone_path = gone.borrow_mut_node().target().display().to_string();
+
+        // Add an OCI whiteout for the removed entry.
+        let mut node = new.children[0].borrow_mut_node().clone();
+        let name = format!(
+            ".wh.{}",
+            Path::new(&gone_path).file_name().unwrap().to_str().unwrap()
+        );
+        let mut info = node.info.deref().clone();
+        info.target = PathBuf::from("/").join(&name);
+        info.target_vec = vec![OsString::from("/"), OsString::from(&name)];
+        node.info = Arc::new(info);
+        new.insert_child(Tree::new(node));
+
+        let diff = ImageDiff::compare(&old, &new, WhiteoutSpec::Oci).unwrap();
+        let entry = diff
+            .entries
+            .iter()
+            .find(|e| e.path == file.display().to_string())
+            .unwrap();
+        assert_eq!(entry.kind, DiffKind::Modified);
+        assert!(entry.changes.contains(&"mode"));
+        assert!(entry.changes.contains(&"content"));
+        assert_eq!(entry.removed_bytes, removed);
+        assert_eq!(entry.added_bytes, 0);
+
+        let entry = diff.entries.iter().find(|e| e.path == gone_path).unwrap();
+        assert_eq!(entry.kind, DiffKind::Removed);
+        assert!(diff.summary.removed >= 1);
+
+        let entry = diff
+            .entries
+            .iter()
+            .find(|e| e.kind == DiffKind::Whiteout)
+            .unwrap();
+        assert_eq!(entry.path, format!("/{}", name));
+        assert_eq!(entry.target.as_deref(), Some(gone_path.as_str()));
+        assert_eq!(diff.summary.whiteouts, 1);
+        assert_eq!(diff.summary.added, 0);
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["summary"]["whiteouts"], 1);
+    }
+}
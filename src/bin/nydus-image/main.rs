@@ -19,6 +19,7 @@ use crate::deduplicate::{
 };
 use std::convert::TryFrom;
 use std::fs::{self, metadata, DirEntry, OpenOptions};
+use std::io::Write;
 use std::os::unix::fs::FileTypeExt;
 use std::path::{Path, PathBuf};
 use std::result::Result::Ok;
@@ -29,18 +30,23 @@ use clap::parser::ValueSource;
 use clap::{Arg, ArgAction, ArgMatches, Command as App};
 use nix::unistd::{getegid, geteuid};
 use nydus::{get_build_time_info, setup_logging};
+#[cfg(feature = "backend-registry")]
+use nydus_api::RegistryConfig;
 use nydus_api::{BuildTimeInfo, ConfigV2, LocalFsConfig};
 use nydus_builder::{
-    attributes::Attributes, generate_prefetch_file_info, parse_chunk_dict_arg,
-    update_ctx_from_bootstrap, ArtifactStorage, BlobCacheGenerator, BlobCompactor, BlobManager,
-    BootstrapManager, BuildContext, BuildOutput, Builder, ChunkdictBlobInfo, ChunkdictChunkInfo,
-    ConversionType, DirectoryBuilder, Feature, Features, Generator, HashChunkDict, Merger,
-    OptimizePrefetch, Prefetch, PrefetchPolicy, StargzBuilder, TarballBuilder, Tree, WhiteoutSpec,
+    attributes::Attributes, generate_prefetch_file_info,
+    generate_prefetch_file_info_from_access_trace, parse_chunk_dict_arg, update_ctx_from_bootstrap,
+    ArtifactStorage, BlobCacheGenerator, BlobCompactor, BlobManager, BootstrapManager,
+    BuildContext, BuildOutput, Builder, ChunkdictBlobInfo, ChunkdictChunkInfo, ConversionType,
+    DirectoryBuilder, Feature, Features, Generator, HashChunkDict, Merger, OptimizePrefetch,
+    Prefetch, PrefetchPolicy, StargzBuilder, TarballBuilder, Tree, WhiteoutSpec,
 };
 
 use nydus_rafs::metadata::{MergeError, RafsSuper, RafsSuperConfig, RafsVersion};
 use nydus_storage::backend::localfs::LocalFs;
-use nydus_storage::backend::BlobBackend;
+#[cfg(feature = "backend-registry")]
+use nydus_storage::backend::registry::Registry;
+use nydus_storage::backend::{BlobBackend, BlobUploader};
 use nydus_storage::device::BlobFeatures;
 use nydus_storage::factory::BlobFactory;
 use nydus_storage::meta::{format_blob_features, BatchContextGenerator};
@@ -51,8 +57,8 @@ use nydus_utils::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::unpack::{OCIUnpacker, Unpacker};
-use crate::validator::Validator;
+use crate::unpack::{DirUnpacker, OCIUnpacker, Unpacker};
+use crate::validator::{DataVerification, Validator};
 use nydus_rafs::metadata::layout::v5::{RafsV5BlobTable, RafsV5ExtBlobTable};
 use nydus_rafs::metadata::layout::v6::RafsV6BlobTable;
 
@@ -64,6 +70,7 @@ use nydus_service::ServiceArgs;
 use std::str::FromStr;
 
 mod deduplicate;
+mod diff;
 mod inspect;
 mod stat;
 mod unpack;
@@ -77,6 +84,9 @@ pub struct OutputSerializer {
     version: String,
     /// RAFS meta data file path.
     bootstrap: String,
+    /// Sha256 digest of the RAFS meta data blob pushed to registry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bootstrap_digest: Option<String>,
     /// Represents all blob in blob table ordered by blob index, this field
     /// only include the layer that does have a blob, and should be deprecated
     /// in future, use `artifacts` field to replace.
@@ -91,6 +101,9 @@ pub struct OutputSerializer {
     fs_version: String,
     /// Chunk compression algorithm.
     compressor: String,
+    /// Result of verifying chunk data, only available for `check --verify-data`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_verification: Option<DataVerification>,
 }
 
 impl OutputSerializer {
@@ -117,12 +130,14 @@ impl OutputSerializer {
             let output = Self {
                 version,
                 bootstrap: build_output.bootstrap_path.unwrap_or_default(),
+                bootstrap_digest: build_output.bootstrap_digest,
                 blobs: build_output.blobs,
                 external_bootstrap: build_output.external_bootstrap_path.unwrap_or_default(),
                 external_blobs: build_output.external_blobs,
                 trace,
                 fs_version: fs_version.to_string(),
                 compressor: compressor.to_string(),
+                data_verification: None,
             };
 
             serde_json::to_writer_pretty(w, &output)
@@ -139,6 +154,7 @@ impl OutputSerializer {
         bootstrap: &Path,
         compressor: compress::Algorithm,
         fs_version: RafsVersion,
+        data_verification: Option<DataVerification>,
     ) -> Result<()> {
         let output_json: Option<PathBuf> = matches
             .get_one::<String>("output-json")
@@ -156,12 +172,14 @@ impl OutputSerializer {
             let output = Self {
                 version,
                 bootstrap: bootstrap.display().to_string(),
+                bootstrap_digest: None,
                 external_bootstrap: String::new(),
                 blobs: blob_ids,
                 external_blobs: Vec::new(),
                 trace,
                 fs_version: fs_version.to_string(),
                 compressor: compressor.to_string(),
+                data_verification,
             };
 
             serde_json::to_writer(w, &output).context("failed to write result to output file")?;
@@ -240,6 +258,8 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                             "tar-tarfs",
                             "targz-rafs",
                             "targz-ref",
+                            "tarzstd-ref",
+                            "tarxz-ref",
                             "stargz_index",
                         ])
                 )
@@ -279,6 +299,13 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .conflicts_with("blob-id")
                         .required(false),
                 )
+                .arg(
+                    Arg::new("push-config")
+                        .long("push-config")
+                        .help("Registry configuration file to stream generated RAFS data and metadata blobs into")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .conflicts_with_all(["blob", "blob-id", "blob-inline-meta", "blob-cache-dir"]),
+                )
                 .arg(
                     Arg::new("blob-id")
                         .long("blob-id")
@@ -316,7 +343,7 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("Algorithm to compress data chunks:")
                         .required(false)
                         .default_value("zstd")
-                        .value_parser(["none", "lz4_block", "zstd"]),
+                        .value_parser(["none", "lz4_block", "zstd", "zstd-dict"]),
                 )
                 .arg(
                     Arg::new("digester")
@@ -350,6 +377,18 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("File path of the parent/referenced RAFS metadata blob (optional)")
                         .required(false),
                 )
+                .arg(
+                    Arg::new("incremental-cache")
+                        .long("incremental-cache")
+                        .help("File path of the cache to reuse chunks of unchanged files from the parent RAFS metadata blob, created if not exists")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("verity-files")
+                        .long("verity-files")
+                        .help("Generate fs-verity digests for regular files to verify file data on read")
+                        .action(ArgAction::SetTrue)
+                )
                 .arg(
                     Arg::new("aligned-chunk")
                         .long("aligned-chunk")
@@ -363,6 +402,14 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .action(ArgAction::SetTrue)
                         .required(false),
                 )
+                .arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .default_value("1")
+                        .help("Number of worker threads to hash and compress file data, the generated data blob is the same as single thread")
+                        .value_parser(Command::thread_validator)
+                        .required(false),
+                )
                 .arg(
                     Arg::new("disable-check")
                         .long("disable-check")
@@ -554,6 +601,13 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                     .action(ArgAction::SetTrue)
                     .required(false),
             )
+            .arg(
+                Arg::new("verify-data")
+                    .long("verify-data")
+                    .help("Read all chunks from the storage backend and verify chunk data")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
             .arg(arg_output_json.clone()),
     );
 
@@ -575,6 +629,14 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                     .action(ArgAction::Set)
                     .num_args(1),
             )
+            .arg(
+                Arg::new("access-trace")
+                    .long("access-trace")
+                    .help("Path to file access patterns exported by nydusd from `/api/v1/metrics/pattern`, files are prefetched in order of first access")
+                    .action(ArgAction::Set)
+                    .num_args(1)
+                    .conflicts_with("prefetch-files"),
+            )
             .arg(arg_config.clone())
             .arg(
                 Arg::new("backend-type")
@@ -743,6 +805,21 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("Generate statistics information for all RAFS filesystems in the directory")
                         .required(false),
                 )
+                .arg(
+                    Arg::new("bootstrap-list")
+                        .long("bootstrap-list")
+                        .help("Generate statistics information for all RAFS filesystems listed in the file, one path per line")
+                        .conflicts_with_all(["bootstrap", "blob-dir"])
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("top-files")
+                        .long("top-files")
+                        .help("Number of most duplicated files to report for multiple RAFS filesystems")
+                        .default_value("10")
+                        .value_parser(clap::value_parser!(usize))
+                        .required(false),
+                )
                 .arg(
                     Arg::new("target")
                         .long("target")
@@ -764,6 +841,38 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                 )
         );
 
+    let app = app.subcommand(
+            App::new("diff")
+                .about("Compare two RAFS filesystems and report differences between them")
+                .arg(
+                    Arg::new("OLD")
+                        .help("File path of the old RAFS metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("NEW")
+                        .help("File path of the new RAFS metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("blob-dir")
+                        .long("blob-dir")
+                        .short('D')
+                        .help("Directory for localfs storage backend, hosting data blobs and cache files"),
+                )
+                .arg(arg_config.clone())
+                .arg(
+                    Arg::new("whiteout-spec")
+                        .long("whiteout-spec")
+                        .help("Type of whiteout specification to detect whiteouts in the new RAFS filesystem:")
+                        .default_value("oci")
+                        .value_parser(["oci", "overlayfs", "none"])
+                )
+                .arg(
+                    arg_output_json.clone(),
+                )
+        );
+
     let app = app.subcommand(
             App::new("compact")
                 .about("(experimental)Compact specific nydus image, remove unused chunks in blobs, merge small blobs")
@@ -843,7 +952,7 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
 
     app.subcommand(
         App::new("unpack")
-            .about("Unpack a RAFS filesystem to a tar file")
+            .about("Unpack a RAFS filesystem to a tar file or a directory")
             .arg(
                 Arg::new("BOOTSTRAP")
                     .help("File path of RAFS metadata")
@@ -904,13 +1013,37 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
             .arg(
                 Arg::new("output")
                     .long("output")
-                    .help("Path for output tar file")
-                    .required(true),
+                    .help("Path for output tar file"),
+            )
+            .arg(
+                Arg::new("output-dir")
+                    .long("output-dir")
+                    .help("Path for output directory to unpack the RAFS filesystem into")
+                    .conflicts_with("output"),
+            )
+            .arg(
+                Arg::new("threads")
+                    .long("threads")
+                    .help("Number of worker threads to unpack file data into the output directory")
+                    .value_parser(Command::thread_validator)
+                    .requires("output-dir"),
+            )
+            .arg(
+                Arg::new("whiteout-spec")
+                    .long("whiteout-spec")
+                    .help("Apply whiteouts in the RAFS filesystem to existing files in the output directory according to the whiteout specification:")
+                    .value_parser(["oci", "overlayfs", "none"])
+                    .requires("output-dir"),
             )
             .group(
                 clap::ArgGroup::new("backend")
                     .args(["backend-type", "blob", "blob-dir"])
                     .required(false),
+            )
+            .group(
+                clap::ArgGroup::new("target")
+                    .args(["output", "output-dir"])
+                    .required(true),
             ),
     )
 }
@@ -976,6 +1109,8 @@ fn main() -> Result<()> {
         Command::inspect(matches)
     } else if let Some(matches) = cmd.subcommand_matches("stat") {
         Command::stat(matches)
+    } else if let Some(matches) = cmd.subcommand_matches("diff") {
+        Command::diff(matches)
     } else if let Some(matches) = cmd.subcommand_matches("compact") {
         Command::compact(matches, &build_info)
     } else if let Some(matches) = cmd.subcommand_matches("unpack") {
@@ -1022,6 +1157,21 @@ impl Command {
         } else {
             Self::get_blob_storage(matches, conversion_type)?
         };
+        let blob_uploader = Self::get_blob_uploader(matches)?;
+        if blob_uploader.is_some()
+            && !matches!(
+                conversion_type,
+                ConversionType::DirectoryToRafs
+                    | ConversionType::EStargzToRafs
+                    | ConversionType::TargzToRafs
+                    | ConversionType::TarToRafs
+            )
+        {
+            bail!(
+                "conversion type '{}' conflicts with '--push-config'",
+                conversion_type
+            );
+        }
 
         let aligned_chunk = if version.is_v6() && conversion_type != ConversionType::TarToTarfs {
             true
@@ -1034,7 +1184,7 @@ impl Command {
             .map(|s| s.as_str())
             .unwrap_or_default()
             .parse()?;
-        let mut compressor = matches
+        let mut compressor: compress::Algorithm = matches
             .get_one::<String>("compressor")
             .map(|s| s.as_str())
             .unwrap_or_default()
@@ -1055,28 +1205,51 @@ impl Command {
         match conversion_type {
             ConversionType::DirectoryToRafs => {
                 Self::ensure_directory(&source_path)?;
-                if blob_storage.is_none() && blob_cache_storage.is_none() {
-                    bail!("both --blob and --blob-dir or --blob-cache-dir are missing");
+                if blob_storage.is_none() && blob_cache_storage.is_none() && blob_uploader.is_none()
+                {
+                    bail!(
+                        "all of --blob, --blob-dir, --blob-cache-dir and --push-config are missing"
+                    );
+                }
+                if compressor.need_dict() && version != RafsVersion::V6 {
+                    bail!("'--compressor zstd-dict' is only supported by RAFS V6");
                 }
             }
             ConversionType::EStargzToRafs
             | ConversionType::TargzToRafs
             | ConversionType::TarToRafs => {
                 Self::ensure_file(&source_path)?;
-                if blob_storage.is_none() && blob_cache_storage.is_none() {
-                    bail!("both --blob and --blob-dir or --blob-cache-dir are missing");
+                if blob_storage.is_none() && blob_cache_storage.is_none() && blob_uploader.is_none()
+                {
+                    bail!(
+                        "all of --blob, --blob-dir, --blob-cache-dir and --push-config are missing"
+                    );
+                }
+                if compressor.need_dict() {
+                    bail!(
+                        "'--compressor zstd-dict' conflicts with conversion type '{}'",
+                        conversion_type
+                    );
                 }
             }
             ConversionType::TarToRef
             | ConversionType::TargzToRef
+            | ConversionType::TarZstdToRef
+            | ConversionType::TarXzToRef
             | ConversionType::EStargzToRef => {
                 Self::ensure_file(&source_path)?;
+                // The compressor is decided by the original compression format of the tarball.
+                let ref_compressor = match conversion_type {
+                    ConversionType::TarZstdToRef => compress::Algorithm::Zstd,
+                    ConversionType::TarXzToRef => compress::Algorithm::Xz,
+                    _ => compress::Algorithm::GZip,
+                };
                 if matches.value_source("compressor") != Some(ValueSource::DefaultValue)
-                    && compressor != compress::Algorithm::GZip
+                    && compressor != ref_compressor
                 {
                     info!(
-                        "only GZip is supported for conversion type {}, use GZip instead of {}",
-                        conversion_type, compressor
+                        "only {} is supported for conversion type {}, use {} instead of {}",
+                        ref_compressor, conversion_type, ref_compressor, compressor
                     );
                 }
                 if matches.value_source("digester") != Some(ValueSource::DefaultValue)
@@ -1087,7 +1260,7 @@ impl Command {
                         conversion_type, compressor
                     );
                 }
-                compressor = compress::Algorithm::GZip;
+                compressor = ref_compressor;
                 digester = digest::Algorithm::Sha256;
                 if blob_storage.is_none() && blob_cache_storage.is_none() {
                     bail!("all of --blob, --blob-dir and --blob-cache-dir are missing");
@@ -1277,12 +1450,37 @@ impl Command {
         build_ctx.set_fs_version(version);
         build_ctx.set_chunk_size(chunk_size);
         build_ctx.set_batch_size(batch_size);
+        build_ctx.threads = Self::get_threads(matches)?;
+        if let Some(cache) = matches.get_one::<String>("incremental-cache") {
+            if conversion_type != ConversionType::DirectoryToRafs {
+                bail!(
+                    "conversion type '{}' conflicts with '--incremental-cache'",
+                    conversion_type
+                );
+            }
+            build_ctx.incremental_cache = Some(PathBuf::from(cache));
+        }
+        if matches.get_flag("verity-files") {
+            if conversion_type == ConversionType::EStargzIndexToRef {
+                bail!(
+                    "conversion type '{}' conflicts with '--verity-files'",
+                    conversion_type
+                );
+            }
+            if matches.get_one::<PathBuf>("attributes").is_some() {
+                bail!("'--verity-files' conflicts with '--attributes'");
+            }
+            build_ctx.verity_files = true;
+        }
 
         let blob_cache_generator = match blob_cache_storage {
             Some(storage) => Some(BlobCacheGenerator::new(storage)?),
             None => None,
         };
         build_ctx.blob_cache_generator = blob_cache_generator;
+        if let Some(uploader) = blob_uploader.as_ref() {
+            build_ctx.blob_uploader = Some(uploader.clone());
+        }
 
         let mut config = Self::get_configuration(matches)?;
         if let Some(cache) = Arc::get_mut(&mut config).unwrap().cache.as_mut() {
@@ -1348,6 +1546,8 @@ impl Command {
             }
             ConversionType::EStargzToRef
             | ConversionType::TargzToRef
+            | ConversionType::TarZstdToRef
+            | ConversionType::TarXzToRef
             | ConversionType::TarToRef => {
                 if version.is_v5() {
                     bail!("conversion type {} conflicts with RAFS v5", conversion_type);
@@ -1367,7 +1567,7 @@ impl Command {
             | ConversionType::TarToStargz
             | ConversionType::TargzToStargz => unimplemented!(),
         };
-        let build_output = timing_tracer!(
+        let mut build_output = timing_tracer!(
             {
                 builder
                     .build(&mut build_ctx, &mut bootstrap_mgr, &mut blob_mgr)
@@ -1375,6 +1575,15 @@ impl Command {
             },
             "total_build"
         )?;
+        if let Some(uploader) = blob_uploader.as_ref() {
+            if let Some(bootstrap) = build_output.bootstrap_path.as_ref() {
+                let digest = timing_tracer!(
+                    { Self::push_bootstrap(uploader.as_ref(), Path::new(bootstrap)) },
+                    "push_bootstrap"
+                )?;
+                build_output.bootstrap_digest = Some(digest);
+            }
+        }
 
         lazy_drop(build_ctx);
 
@@ -1672,9 +1881,13 @@ impl Command {
 
     fn unpack(matches: &ArgMatches) -> Result<()> {
         let bootstrap = Self::get_bootstrap(matches)?;
-        let output = matches.get_one::<String>("output").expect("pass in output");
+        let output_dir = matches.get_one::<String>("output-dir");
+        let output = matches
+            .get_one::<String>("output")
+            .or(output_dir)
+            .expect("pass in output");
         if output.is_empty() {
-            return Err(anyhow!("invalid empty --output option"));
+            return Err(anyhow!("invalid empty --output or --output-dir option"));
         }
 
         let (config, backend): (Arc<ConfigV2>, Arc<dyn BlobBackend + Send + Sync>) =
@@ -1702,10 +1915,24 @@ impl Command {
                 }
             };
 
-        OCIUnpacker::new(bootstrap, Some(backend), output)
-            .with_context(|| "fail to create unpacker")?
-            .unpack(config)
-            .with_context(|| "fail to unpack")
+        let unpacker: Box<dyn Unpacker> = if output_dir.is_some() {
+            let threads = Self::get_threads(matches)?;
+            let whiteout_spec: WhiteoutSpec = matches
+                .get_one::<String>("whiteout-spec")
+                .map(|s| s.as_str())
+                .unwrap_or("none")
+                .parse()?;
+            Box::new(
+                DirUnpacker::new(bootstrap, Some(backend), output, threads, whiteout_spec)
+                    .with_context(|| "fail to create unpacker")?,
+            )
+        } else {
+            Box::new(
+                OCIUnpacker::new(bootstrap, Some(backend), output)
+                    .with_context(|| "fail to create unpacker")?,
+            )
+        };
+        unpacker.unpack(config).with_context(|| "fail to unpack")
     }
 
     fn check(matches: &ArgMatches, build_info: &BuildTimeInfo) -> Result<()> {
@@ -1717,6 +1944,15 @@ impl Command {
             .internal
             .set_blob_accessible(matches.get_one::<String>("bootstrap").is_none());
 
+        let backend = if matches.get_flag("verify-data") {
+            let backend_config = config.get_backend_config().context(
+                "--blob-dir or --config with storage backend is required to verify data",
+            )?;
+            Some(BlobFactory::new_backend(backend_config, "validator")?)
+        } else {
+            None
+        };
+
         let mut validator = Validator::new(bootstrap_path, config)?;
         let (blobs, compressor, fs_version) = validator
             .check(verbose)
@@ -1738,6 +1974,29 @@ impl Command {
             blob_ids.push(blob.blob_id().to_string());
         }
 
+        let data_verification = match backend {
+            Some(backend) => {
+                let result = validator
+                    .verify_data(backend)
+                    .with_context(|| format!("failed to verify data of {:?}", bootstrap_path))?;
+                println!(
+                    "Verified chunks: {}, skipped chunks: {}, corrupted chunks: {}",
+                    result.verified_chunks, result.skipped_chunks, result.corrupted_chunks
+                );
+                for file in result.corrupted_files.iter() {
+                    for chunk in file.chunks.iter() {
+                        println!(
+                            "\t corrupted file {}: blob {} chunk {}, {}",
+                            file.path, chunk.blob_id, chunk.chunk_index, chunk.error
+                        );
+                    }
+                }
+                Some(result)
+            }
+            None => None,
+        };
+        let is_valid = data_verification.as_ref().map_or(true, |v| v.is_valid());
+
         OutputSerializer::dump_for_check(
             matches,
             build_info,
@@ -1745,14 +2004,26 @@ impl Command {
             bootstrap_path,
             compressor,
             fs_version,
+            data_verification,
         )?;
 
+        if !is_valid {
+            bail!(
+                "data blobs referenced by {:?} are corrupted",
+                bootstrap_path
+            );
+        }
+
         Ok(())
     }
 
     fn optimize(matches: &ArgMatches, build_info: &BuildTimeInfo) -> Result<()> {
         let output_blob_dir_path = Self::get_output_blob_dir(matches)?;
-        let prefetch_file = Self::get_prefetch_files(matches)?;
+        let access_trace = matches.get_one::<String>("access-trace").map(Path::new);
+        let prefetch_file = match access_trace {
+            Some(_) => None,
+            None => Some(Self::get_prefetch_files(matches)?),
+        };
         let bootstrap_path = Self::get_bootstrap(matches)?;
         let dst_bootstrap = match matches.get_one::<String>("output-bootstrap") {
             None => ArtifactStorage::SingleFile(PathBuf::from("optimized_bootstrap")),
@@ -1777,7 +2048,10 @@ impl Command {
         let sb = update_ctx_from_bootstrap(&mut build_ctx, config, bootstrap_path)?;
         let mut tree = Tree::from_bootstrap(&sb, &mut ())?;
         let mut bootstrap_mgr = BootstrapManager::new(Some(dst_bootstrap), None);
-        let prefetch_nodes = generate_prefetch_file_info(prefetch_file)?;
+        let prefetch_nodes = match access_trace {
+            Some(t) => generate_prefetch_file_info_from_access_trace(&sb, t)?,
+            None => generate_prefetch_file_info(prefetch_file.unwrap())?,
+        };
         let blobs = sb.superblock.get_blob_infos();
 
         let mut blob_table = match build_ctx.fs_version {
@@ -1837,6 +2111,30 @@ impl Command {
         Ok(())
     }
 
+    fn diff(matches: &ArgMatches) -> Result<()> {
+        // Safe to unwrap because they are required arguments.
+        let old = matches.get_one::<String>("OLD").map(PathBuf::from).unwrap();
+        let new = matches.get_one::<String>("NEW").map(PathBuf::from).unwrap();
+        let whiteout_spec: WhiteoutSpec = matches
+            .get_one::<String>("whiteout-spec")
+            .map(|s| s.as_str())
+            .unwrap_or_default()
+            .parse()?;
+        let config = Self::get_configuration(matches)?;
+        config
+            .internal
+            .set_blob_accessible(matches.get_one::<String>("config").is_some());
+
+        let diff = diff::ImageDiff::diff(&old, &new, whiteout_spec, config)?;
+        if let Some(path) = matches.get_one::<String>("output-json").map(PathBuf::from) {
+            diff.dump_json(&path)?;
+        } else {
+            diff.dump();
+        }
+
+        Ok(())
+    }
+
     fn stat(matches: &ArgMatches) -> Result<()> {
         let digester = matches
             .get_one::<String>("digester")
@@ -1844,6 +2142,8 @@ impl Command {
             .unwrap_or_default()
             .parse()?;
         let mut stat = stat::ImageStat::new(digester);
+        // Safe to unwrap because it has a default value.
+        stat.top_files = *matches.get_one::<usize>("top-files").unwrap();
         let target = matches
             .get_one::<String>("target")
             .map(Path::new)
@@ -1878,8 +2178,24 @@ impl Command {
                     };
                 }
             }
+        } else if let Some(list) = matches.get_one::<String>("bootstrap-list") {
+            stat.dedup_enabled = true;
+
+            let content = fs::read_to_string(list)
+                .with_context(|| format!("failed to read bootstrap list {}", list))?;
+            for line in content.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let path = Path::new(line);
+                if path != target {
+                    stat.stat(path, true, config.clone())
+                        .with_context(|| format!("failed to process {}", line))?;
+                }
+            }
         } else {
-            bail!("one of `--bootstrap` and `--blob-dir` must be specified");
+            bail!("one of `--bootstrap`, `--blob-dir` and `--bootstrap-list` must be specified");
         }
 
         if let Some(blob) = matches.get_one::<String>("target").map(PathBuf::from) {
@@ -1963,6 +2279,58 @@ impl Command {
         }
     }
 
+    #[cfg(feature = "backend-registry")]
+    fn get_blob_uploader(matches: &ArgMatches) -> Result<Option<Arc<dyn BlobUploader>>> {
+        match matches.get_one::<PathBuf>("push-config") {
+            None => Ok(None),
+            Some(path) => {
+                let content = fs::read_to_string(path).with_context(|| {
+                    format!("failed to read registry configuration {}", path.display())
+                })?;
+                let config: RegistryConfig = serde_json::from_str(&content).with_context(|| {
+                    format!("invalid registry configuration {}", path.display())
+                })?;
+                let registry = Registry::new(&config, Some("push"))
+                    .context("failed to create registry uploader")?;
+                Ok(Some(Arc::new(registry)))
+            }
+        }
+    }
+
+    #[cfg(not(feature = "backend-registry"))]
+    fn get_blob_uploader(matches: &ArgMatches) -> Result<Option<Arc<dyn BlobUploader>>> {
+        if matches.get_one::<PathBuf>("push-config").is_some() {
+            bail!("'--push-config' requires the 'backend-registry' feature");
+        }
+        Ok(None)
+    }
+
+    /// Push the generated RAFS metadata blob and return its sha256 digest.
+    fn push_bootstrap(uploader: &dyn BlobUploader, bootstrap: &Path) -> Result<String> {
+        let data = fs::read(bootstrap)
+            .with_context(|| format!("failed to read bootstrap {}", bootstrap.display()))?;
+        let digest = digest::RafsDigest::from_buf(&data, digest::Algorithm::Sha256).to_string();
+        let mut writer = uploader
+            .create_writer()
+            .map_err(|e| anyhow!("failed to start bootstrap upload, {:?}", e))?;
+        writer
+            .write_all(&data)
+            .context("failed to upload bootstrap")?;
+        writer
+            .commit(&digest)
+            .map_err(|e| anyhow!("failed to commit bootstrap upload, {:?}", e))?;
+        Ok(digest)
+    }
+
+    fn get_threads(matches: &ArgMatches) -> Result<usize> {
+        match matches.get_one::<String>("threads") {
+            None => Ok(1),
+            Some(v) => v
+                .parse()
+                .with_context(|| format!("invalid thread number {}", v)),
+        }
+    }
+
     // Must specify a path to blob file.
     // For cli/binary interface compatibility sake, keep option `backend-config`, but
     // it only receives "localfs" backend type and it will be REMOVED in the future
@@ -2,9 +2,9 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::OpenOptions;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
@@ -12,7 +12,7 @@ use anyhow::{Context, Result};
 use nydus_api::ConfigV2;
 use nydus_builder::{ChunkDict, HashChunkDict, Tree};
 use nydus_rafs::metadata::RafsSuper;
-use nydus_utils::digest;
+use nydus_utils::digest::{self, RafsDigest};
 use serde::Serialize;
 
 #[derive(Copy, Clone, Default, Serialize)]
@@ -132,17 +132,136 @@ Compressed Size:        {comp_size}"#,
     }
 }
 
+// Images referring to a chunk, for cross-image deduplication analysis.
+struct ChunkUsage {
+    comp_size: u64,
+    uncomp_size: u64,
+    images: Vec<u32>,
+}
+
+// Regular files with identical content, for cross-image deduplication analysis.
+struct FileUsage {
+    path: PathBuf,
+    size: u64,
+    copies: u64,
+}
+
+#[derive(Default, Serialize)]
+struct ImageDedupInfo {
+    path: String,
+    // Number of distinct chunks in the image.
+    chunks: u64,
+    comp_size: u64,
+    uncomp_size: u64,
+    // Number of chunks not shared with any other image.
+    unique_chunks: u64,
+    unique_comp_size: u64,
+    unique_uncomp_size: u64,
+}
+
+#[derive(Serialize)]
+struct DuplicatedFile {
+    path: String,
+    size: u64,
+    copies: u64,
+    // Size of redundant copies, `(copies - 1) * size`.
+    wasted_size: u64,
+}
+
+#[derive(Default, Serialize)]
+struct DedupAnalysis {
+    images: Vec<ImageDedupInfo>,
+    // Element `[i][j]` is the uncompressed size of chunks shared by image `i` and image `j`.
+    shared_uncomp_size: Vec<Vec<u64>>,
+    // Sum of distinct chunks of each image, without cross-image deduplication.
+    total_chunks: u64,
+    total_comp_size: u64,
+    total_uncomp_size: u64,
+    // Distinct chunks of all images, with cross-image deduplication.
+    dedup_chunks: u64,
+    dedup_comp_size: u64,
+    dedup_uncomp_size: u64,
+    // Chunks shared by two or more images, which are candidates for a chunk dictionary.
+    dict_chunks: u64,
+    dict_comp_size: u64,
+    dict_uncomp_size: u64,
+    // Storage saved by building all images with a chunk dictionary of shared chunks.
+    saved_comp_size: u64,
+    saved_uncomp_size: u64,
+    top_files: Vec<DuplicatedFile>,
+}
+
+impl DedupAnalysis {
+    fn dump(&self) {
+        println!("Image:\t\tChunks:\t\tComp Size:\tUncomp Size:\tUnique Chunks:\tUnique Comp Size:\tUnique Uncomp Size:\tPath:");
+        for (idx, info) in self.images.iter().enumerate() {
+            println!(
+                "{:<16}0x{:<14x}0x{:<14x}0x{:<14x}0x{:<14x}0x{:<22x}0x{:<22x}{}",
+                idx,
+                info.chunks,
+                info.comp_size,
+                info.uncomp_size,
+                info.unique_chunks,
+                info.unique_comp_size,
+                info.unique_uncomp_size,
+                info.path,
+            );
+        }
+
+        println!("\nShared Uncomp Size Matrix:");
+        for (idx, row) in self.shared_uncomp_size.iter().enumerate() {
+            let row = row
+                .iter()
+                .map(|v| format!("0x{:<14x}", v))
+                .collect::<Vec<_>>()
+                .join("");
+            println!("{:<16}{}", idx, row.trim_end());
+        }
+
+        println!("\nTotal Chunk Count:\t{}", self.total_chunks);
+        println!("Total Comp Size:\t{}", self.total_comp_size);
+        println!("Total Uncomp Size:\t{}", self.total_uncomp_size);
+        println!("Dedup Chunk Count:\t{}", self.dedup_chunks);
+        println!("Dedup Comp Size:\t{}", self.dedup_comp_size);
+        println!("Dedup Uncomp Size:\t{}", self.dedup_uncomp_size);
+        println!("Dict Chunk Count:\t{}", self.dict_chunks);
+        println!("Dict Comp Size:\t\t{}", self.dict_comp_size);
+        println!("Dict Uncomp Size:\t{}", self.dict_uncomp_size);
+        println!("Saved Comp Size:\t{}", self.saved_comp_size);
+        println!("Saved Uncomp Size:\t{}", self.saved_uncomp_size);
+
+        println!("\nTop Duplicated Files:");
+        println!("Copies:\t\tSize:\t\tWasted Size:\tPath:");
+        for file in self.top_files.iter() {
+            println!(
+                "{:<16}0x{:<14x}0x{:<14x}{}",
+                file.copies, file.size, file.wasted_size, file.path
+            );
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub(crate) struct ImageStat {
     pub dedup_enabled: bool,
     pub target_enabled: bool,
+    #[serde(skip)]
+    pub top_files: usize,
 
     base_image: ImageInfo,
     target_image: ImageInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dedup_analysis: Option<DedupAnalysis>,
     #[serde(skip)]
     dedup_dict: HashChunkDict,
     #[serde(skip)]
     dedup_info: [DedupInfo; 20],
+    #[serde(skip)]
+    images: Vec<String>,
+    #[serde(skip)]
+    chunk_usage: HashMap<RafsDigest, ChunkUsage>,
+    #[serde(skip)]
+    file_usage: HashMap<RafsDigest, FileUsage>,
 }
 
 impl ImageStat {
@@ -150,11 +269,16 @@ impl ImageStat {
         ImageStat {
             dedup_enabled: false,
             target_enabled: false,
+            top_files: 10,
 
             base_image: ImageInfo::new(),
             target_image: ImageInfo::new(),
+            dedup_analysis: None,
             dedup_dict: HashChunkDict::new(digester),
             dedup_info: [Default::default(); 20],
+            images: Vec::new(),
+            chunk_usage: HashMap::new(),
+            file_usage: HashMap::new(),
         }
     }
 
@@ -169,6 +293,9 @@ impl ImageStat {
         } else {
             &mut self.target_image
         };
+        // Cross-image analysis is only available when collecting statistics for multiple images.
+        let analyze = is_base && self.dedup_enabled;
+        let mut files = Vec::new();
 
         let pre = &mut |t: &Tree| -> Result<()> {
             let node = t.borrow_mut_node();
@@ -191,6 +318,16 @@ impl ImageStat {
                     image.comp_size += chunk.inner.compressed_size() as u64;
                     image.uncomp_size += chunk.inner.uncompressed_size() as u64;
                 }
+                if analyze && !node.chunks.is_empty() {
+                    // Files are identified by digests of their chunk digest lists.
+                    let ids = node
+                        .chunks
+                        .iter()
+                        .flat_map(|c| c.inner.id().data)
+                        .collect::<Vec<u8>>();
+                    let digest = RafsDigest::from_buf(&ids, digest::Algorithm::Blake3);
+                    files.push((digest, node.target().clone(), file_size));
+                }
 
                 for sz in 12..=20 {
                     match node.chunk_count(1 << sz) {
@@ -207,6 +344,32 @@ impl ImageStat {
         };
         tree.walk_dfs_pre(pre)?;
 
+        if analyze {
+            let idx = self.images.len() as u32;
+            self.images.push(path.display().to_string());
+            for (digest, path, size) in files {
+                self.file_usage
+                    .entry(digest)
+                    .or_insert(FileUsage {
+                        path,
+                        size,
+                        copies: 0,
+                    })
+                    .copies += 1;
+            }
+            for (id, entry) in dict.hashmap().iter() {
+                self.chunk_usage
+                    .entry(*id)
+                    .or_insert(ChunkUsage {
+                        comp_size: entry.0.compressed_size() as u64,
+                        uncomp_size: entry.0.uncompressed_size() as u64,
+                        images: Vec::new(),
+                    })
+                    .images
+                    .push(idx);
+            }
+        }
+
         if is_base {
             for entry in dict.hashmap().values() {
                 image.own_chunks += 1;
@@ -273,7 +436,79 @@ impl ImageStat {
                 self.base_image.dedup_comp_size += entry.0.compressed_size() as u64;
                 self.base_image.dedup_uncomp_size += entry.0.uncompressed_size() as u64;
             }
+
+            self.dedup_analysis = Some(self.analyze());
+        }
+    }
+
+    fn analyze(&self) -> DedupAnalysis {
+        let count = self.images.len();
+        let mut analysis = DedupAnalysis {
+            images: self
+                .images
+                .iter()
+                .map(|path| ImageDedupInfo {
+                    path: path.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            shared_uncomp_size: vec![vec![0; count]; count],
+            ..Default::default()
+        };
+
+        for usage in self.chunk_usage.values() {
+            analysis.dedup_chunks += 1;
+            analysis.dedup_comp_size += usage.comp_size;
+            analysis.dedup_uncomp_size += usage.uncomp_size;
+            if usage.images.len() > 1 {
+                analysis.dict_chunks += 1;
+                analysis.dict_comp_size += usage.comp_size;
+                analysis.dict_uncomp_size += usage.uncomp_size;
+            }
+            for &i in usage.images.iter() {
+                let info = &mut analysis.images[i as usize];
+                info.chunks += 1;
+                info.comp_size += usage.comp_size;
+                info.uncomp_size += usage.uncomp_size;
+                if usage.images.len() == 1 {
+                    info.unique_chunks += 1;
+                    info.unique_comp_size += usage.comp_size;
+                    info.unique_uncomp_size += usage.uncomp_size;
+                }
+                for &j in usage.images.iter() {
+                    analysis.shared_uncomp_size[i as usize][j as usize] += usage.uncomp_size;
+                }
+            }
+        }
+
+        for info in analysis.images.iter() {
+            analysis.total_chunks += info.chunks;
+            analysis.total_comp_size += info.comp_size;
+            analysis.total_uncomp_size += info.uncomp_size;
         }
+        analysis.saved_comp_size = analysis.total_comp_size - analysis.dedup_comp_size;
+        analysis.saved_uncomp_size = analysis.total_uncomp_size - analysis.dedup_uncomp_size;
+
+        let mut files = self
+            .file_usage
+            .values()
+            .filter(|f| f.copies > 1)
+            .map(|f| DuplicatedFile {
+                path: f.path.display().to_string(),
+                size: f.size,
+                copies: f.copies,
+                wasted_size: (f.copies - 1) * f.size,
+            })
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| {
+            b.wasted_size
+                .cmp(&a.wasted_size)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        files.truncate(self.top_files);
+        analysis.top_files = files;
+
+        analysis
     }
 
     pub fn dump_json(&self, path: &Path) -> Result<()> {
@@ -319,5 +554,49 @@ impl ImageStat {
                 );
             }
         }
+
+        if let Some(analysis) = self.dedup_analysis.as_ref() {
+            println!("\n\nCross-Image Deduplication Analysis:");
+            analysis.dump();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_analysis() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let config = Arc::new(ConfigV2::default());
+
+        let mut stat = ImageStat::new(digest::Algorithm::Blake3);
+        stat.dedup_enabled = true;
+        stat.stat(&path, true, config.clone()).unwrap();
+        stat.stat(&path, true, config).unwrap();
+        stat.finalize();
+
+        let analysis = stat.dedup_analysis.as_ref().unwrap();
+        assert_eq!(analysis.images.len(), 2);
+        let info = &analysis.images[0];
+        assert!(info.chunks > 0);
+        assert_eq!(info.unique_chunks, 0);
+        assert_eq!(analysis.total_chunks, 2 * info.chunks);
+        assert_eq!(analysis.dedup_chunks, info.chunks);
+        assert_eq!(analysis.dict_chunks, info.chunks);
+        assert_eq!(analysis.saved_comp_size, info.comp_size);
+        assert_eq!(analysis.shared_uncomp_size[0][1], info.uncomp_size);
+        assert_eq!(analysis.shared_uncomp_size[1][1], info.uncomp_size);
+        assert!(!analysis.top_files.is_empty());
+        assert!(analysis.top_files.len() <= 10);
+        assert!(analysis.top_files.iter().all(|f| f.copies >= 2));
+
+        let json = serde_json::to_value(&stat).unwrap();
+        assert_eq!(
+            json["dedup_analysis"]["images"].as_array().unwrap().len(),
+            2
+        );
     }
 }
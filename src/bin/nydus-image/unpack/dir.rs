@@ -0,0 +1,1058 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Unpack a RAFS filesystem into a directory on the local filesystem.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::sys::stat::{mknod, utimensat, Mode, SFlag, UtimensatFlags};
+use nix::sys::time::TimeSpec;
+use nix::unistd::{fchownat, FchownatFlags, Gid, Uid};
+use nydus_api::ConfigV2;
+use nydus_builder::{
+    WhiteoutSpec, OCISPEC_WHITEOUT_OPAQUE, OCISPEC_WHITEOUT_PREFIX, OVERLAYFS_WHITEOUT_OPAQUE,
+};
+use nydus_rafs::metadata::inode::InodeWrapper;
+use nydus_rafs::metadata::{RafsInodeExt, RafsSuper};
+use nydus_rafs::RafsIterator;
+use nydus_storage::backend::BlobBackend;
+use nydus_storage::device::BlobChunkInfo;
+
+use super::{BlobReaders, Unpacker};
+
+/// Granularity to detect holes in file content.
+const SPARSE_BLOCK_SIZE: usize = 0x1000;
+/// Size of buffer to copy file content.
+const COPY_BUFFER_SIZE: usize = 0x100000;
+
+/// Attributes to apply to an unpacked file.
+struct FileAttr {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    mtime_nsec: u32,
+    xattrs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// A regular file whose content is pending to be unpacked.
+struct FileJob {
+    path: PathBuf,
+    size: u64,
+    chunks: Vec<Arc<dyn BlobChunkInfo>>,
+    attr: FileAttr,
+}
+
+/// An unpacker to materialize a RAFS filesystem into a directory.
+///
+/// Ownership is only restored when running as root. When a whiteout specification is given,
+/// whiteouts in the RAFS filesystem are applied to existing content of the output directory
+/// instead of being unpacked, so the RAFS filesystem may be unpacked as an upper layer.
+pub struct DirUnpacker {
+    bootstrap: PathBuf,
+    blob_backend: Option<Arc<dyn BlobBackend + Send + Sync>>,
+    output: PathBuf,
+    threads: usize,
+    whiteout_spec: WhiteoutSpec,
+    same_owner: bool,
+}
+
+impl DirUnpacker {
+    pub fn new(
+        bootstrap: &Path,
+        blob_backend: Option<Arc<dyn BlobBackend + Send + Sync>>,
+        output: &str,
+        threads: usize,
+        whiteout_spec: WhiteoutSpec,
+    ) -> Result<Self> {
+        Ok(DirUnpacker {
+            bootstrap: bootstrap.to_path_buf(),
+            blob_backend,
+            output: PathBuf::from(output),
+            threads: std::cmp::max(threads, 1),
+            whiteout_spec,
+            same_owner: Uid::effective().is_root(),
+        })
+    }
+
+    /// Get path in the output directory for a path in the RAFS filesystem.
+    fn target_path(&self, path: &Path) -> Result<PathBuf> {
+        let mut target = self.output.clone();
+        for comp in path.components() {
+            match comp {
+                Component::RootDir => {}
+                Component::Normal(name) => target.push(name),
+                _ => bail!("invalid path {} in RAFS filesystem", path.display()),
+            }
+        }
+        Ok(target)
+    }
+
+    /// Check whether any parent directory of `target` in the output directory is a symlink.
+    fn has_symlink_ancestor(&self, target: &Path) -> bool {
+        let mut parent = target.parent();
+        while let Some(dir) = parent {
+            if dir == self.output || !dir.starts_with(&self.output) {
+                break;
+            }
+            if let Ok(md) = fs::symlink_metadata(dir) {
+                if md.file_type().is_symlink() {
+                    return true;
+                }
+            }
+            parent = dir.parent();
+        }
+        false
+    }
+
+    /// Apply whiteouts in the RAFS filesystem to the output directory.
+    ///
+    /// Return paths of whiteout files, which should not be unpacked.
+    fn apply_whiteouts(&self, rs: &RafsSuper) -> Result<HashSet<PathBuf>> {
+        let mut whiteouts = HashSet::new();
+        if self.whiteout_spec == WhiteoutSpec::None {
+            return Ok(whiteouts);
+        }
+
+        for (inode, path) in RafsIterator::new(rs) {
+            let name = inode.name();
+            let parent = path.parent().unwrap_or_else(|| Path::new("/"));
+            let (path, opaque) = match self.whiteout_spec {
+                WhiteoutSpec::Oci => {
+                    if name == OCISPEC_WHITEOUT_OPAQUE {
+                        whiteouts.insert(path.clone());
+                        (parent.to_path_buf(), true)
+                    } else if let Some(origin) = name
+                        .as_bytes()
+                        .strip_prefix(OCISPEC_WHITEOUT_PREFIX.as_bytes())
+                    {
+                        if origin.is_empty() || origin == b"." || origin == b".." {
+                            bail!("invalid whiteout file {}", path.display());
+                        }
+                        whiteouts.insert(path.clone());
+                        (parent.join(OsStr::from_bytes(origin)), false)
+                    } else {
+                        continue;
+                    }
+                }
+                WhiteoutSpec::Overlayfs => {
+                    if inode.is_chrdev() && inode.rdev() == 0 {
+                        whiteouts.insert(path.clone());
+                        (path, false)
+                    } else if inode.is_dir()
+                        && inode.get_xattr(OsStr::new(OVERLAYFS_WHITEOUT_OPAQUE))?
+                            == Some(b"y".to_vec())
+                    {
+                        (path, true)
+                    } else {
+                        continue;
+                    }
+                }
+                WhiteoutSpec::None => continue,
+            };
+
+            let target = self.target_path(&path)?;
+            if self.has_symlink_ancestor(&target) {
+                warn!(
+                    "skip whiteout for {} with symlink in its parent directories",
+                    target.display()
+                );
+            } else if opaque {
+                Self::clear_dir(&target)?;
+            } else {
+                Self::remove_path(&target)?;
+            }
+        }
+
+        Ok(whiteouts)
+    }
+
+    /// Remove a file or directory from the output directory, if it exists.
+    fn remove_path(path: &Path) -> Result<()> {
+        match fs::symlink_metadata(path) {
+            Ok(md) if md.is_dir() => fs::remove_dir_all(path),
+            Ok(_) => fs::remove_file(path),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+        .with_context(|| format!("failed to remove {}", path.display()))
+    }
+
+    /// Remove all children of a directory in the output directory, if it exists.
+    fn clear_dir(path: &Path) -> Result<()> {
+        match fs::symlink_metadata(path) {
+            Ok(md) if md.is_dir() => {
+                let entries = fs::read_dir(path)
+                    .with_context(|| format!("failed to read dir {}", path.display()))?;
+                for entry in entries {
+                    Self::remove_path(&entry?.path())?;
+                }
+                Ok(())
+            }
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("failed to stat {}", path.display())),
+        }
+    }
+
+    /// Prepare to unpack a file to `path` by removing any existing conflicting file.
+    ///
+    /// Return true if `path` is an existing directory and a directory is going to be unpacked.
+    fn prepare_path(path: &Path, is_dir: bool) -> Result<bool> {
+        match fs::symlink_metadata(path) {
+            Ok(md) if md.is_dir() && is_dir => return Ok(true),
+            Ok(_) => Self::remove_path(path)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("failed to stat {}", path.display())),
+        }
+        Ok(false)
+    }
+
+    fn get_file_attr(&self, inode: &Arc<dyn RafsInodeExt>) -> Result<FileAttr> {
+        let mut xattrs = Vec::new();
+        if inode.has_xattr() {
+            for name in inode.get_xattrs()? {
+                // The opaque flag has been applied to the output directory.
+                if self.whiteout_spec == WhiteoutSpec::Overlayfs
+                    && name == OVERLAYFS_WHITEOUT_OPAQUE.as_bytes()
+                {
+                    continue;
+                }
+                let value = inode
+                    .get_xattr(OsStr::from_bytes(&name))?
+                    .unwrap_or_default();
+                xattrs.push((name, value));
+            }
+        }
+
+        let inode = InodeWrapper::from_inode_info(inode.clone());
+        Ok(FileAttr {
+            mode: inode.mode(),
+            uid: inode.uid(),
+            gid: inode.gid(),
+            mtime: inode.mtime(),
+            mtime_nsec: inode.mtime_nsec(),
+            xattrs,
+        })
+    }
+
+    fn set_file_attr(&self, path: &Path, attr: &FileAttr, is_symlink: bool) -> Result<()> {
+        // Change owner before permission because chown() may clear the setuid/setgid bits.
+        if self.same_owner {
+            fchownat(
+                None,
+                path,
+                Some(Uid::from_raw(attr.uid)),
+                Some(Gid::from_raw(attr.gid)),
+                FchownatFlags::NoFollowSymlink,
+            )
+            .with_context(|| format!("failed to change owner of {}", path.display()))?;
+        }
+        // Restore extended attributes after chown() which drops `security.capability`, and before
+        // chmod() which may take away the write permission needed to set `user.*` attributes.
+        for (name, value) in attr.xattrs.iter() {
+            set_xattr(path, name, value)?;
+        }
+        if !is_symlink {
+            fs::set_permissions(path, Permissions::from_mode(attr.mode & 0o7777))
+                .with_context(|| format!("failed to set permission of {}", path.display()))?;
+        }
+        let mtime = TimeSpec::from_timespec(libc::timespec {
+            tv_sec: attr.mtime as libc::time_t,
+            tv_nsec: attr.mtime_nsec as libc::c_long,
+        });
+        utimensat(None, path, &mtime, &mtime, UtimensatFlags::NoFollowSymlink)
+            .with_context(|| format!("failed to set modification time of {}", path.display()))?;
+
+        Ok(())
+    }
+
+    fn unpack_file(&self, job: &FileJob, blob_readers: &BlobReaders) -> Result<()> {
+        let mut file = OpenOptions::new().write(true).open(&job.path)?;
+        let mut reader = blob_readers.chunk_reader(job.chunks.clone());
+        write_sparse(&mut reader, &mut file, job.size)?;
+        drop(file);
+
+        self.set_file_attr(&job.path, &job.attr, false)
+    }
+
+    /// Unpack content of regular files with worker threads.
+    fn unpack_files(&self, jobs: Vec<FileJob>, blob_readers: &BlobReaders) -> Result<()> {
+        let next = AtomicUsize::new(0);
+        let threads = std::cmp::max(std::cmp::min(self.threads, jobs.len()), 1);
+
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    s.spawn(|| -> Result<()> {
+                        loop {
+                            let job = match jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                                Some(job) => job,
+                                None => return Ok(()),
+                            };
+                            if let Err(e) = self.unpack_file(job, blob_readers) {
+                                // Stop other workers as soon as possible.
+                                next.store(jobs.len(), Ordering::Relaxed);
+                                return Err(e).with_context(|| {
+                                    format!("failed to unpack file {}", job.path.display())
+                                });
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            let mut result = Ok(());
+            for handle in handles {
+                let ret = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("unpack worker thread panicked")));
+                if result.is_ok() {
+                    result = ret;
+                }
+            }
+            result
+        })
+    }
+}
+
+impl Unpacker for DirUnpacker {
+    fn unpack(&self, config: Arc<ConfigV2>) -> Result<()> {
+        debug!(
+            "dir unpacker, bootstrap file: {:?}, output dir: {:?}",
+            self.bootstrap, self.output
+        );
+
+        let (rs, _) = RafsSuper::load_from_file(self.bootstrap.as_path(), config, false)?;
+        fs::create_dir_all(&self.output)
+            .with_context(|| format!("failed to create output dir {:?}", self.output))?;
+        let whiteouts = self.apply_whiteouts(&rs)?;
+        let blob_readers = BlobReaders::new(rs.superblock.get_blob_infos(), &self.blob_backend)?;
+
+        let mut links: HashMap<u64, PathBuf> = HashMap::new();
+        let mut dirs = Vec::new();
+        let mut jobs = Vec::new();
+        for (inode, path) in RafsIterator::new(&rs) {
+            if whiteouts.contains(&path) {
+                continue;
+            }
+            let target = self.target_path(&path)?;
+            let attr = self.get_file_attr(&inode)?;
+
+            // Directory attributes are applied after unpacking all children.
+            if inode.is_dir() {
+                if !Self::prepare_path(&target, true)? {
+                    fs::create_dir(&target)
+                        .with_context(|| format!("failed to create dir {}", target.display()))?;
+                }
+                dirs.push((target, attr));
+                continue;
+            }
+
+            Self::prepare_path(&target, false)?;
+            if inode.is_hardlink() {
+                if let Some(link) = links.get(&inode.ino()) {
+                    fs::hard_link(link, &target).with_context(|| {
+                        format!("failed to create hardlink {}", target.display())
+                    })?;
+                    continue;
+                }
+                links.insert(inode.ino(), target.clone());
+            }
+
+            if inode.is_reg() {
+                File::create(&target)
+                    .with_context(|| format!("failed to create file {}", target.display()))?;
+                let chunks = (0..inode.get_chunk_count())
+                    .map(|idx| inode.get_chunk_info(idx))
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                jobs.push(FileJob {
+                    path: target,
+                    size: inode.size(),
+                    chunks,
+                    attr,
+                });
+                continue;
+            } else if inode.is_symlink() {
+                symlink(inode.get_symlink()?, &target)
+                    .with_context(|| format!("failed to create symlink {}", target.display()))?;
+            } else if inode.is_sock() {
+                // Sockets are created by the processes listening on them.
+                links.remove(&inode.ino());
+                continue;
+            } else {
+                let kind = if inode.is_chrdev() {
+                    SFlag::S_IFCHR
+                } else if inode.is_blkdev() {
+                    SFlag::S_IFBLK
+                } else if inode.is_fifo() {
+                    SFlag::S_IFIFO
+                } else {
+                    bail!("node {:?} can not be unpacked", path);
+                };
+                let mode = Mode::from_bits_truncate(attr.mode & 0o7777);
+                match mknod(&target, kind, mode, inode.rdev() as libc::dev_t) {
+                    Ok(()) => {}
+                    Err(Errno::EPERM) => {
+                        warn!("no permission to create {}, skip it", target.display());
+                        links.remove(&inode.ino());
+                        continue;
+                    }
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("failed to create {}", target.display()))
+                    }
+                }
+            }
+            self.set_file_attr(&target, &attr, inode.is_symlink())?;
+        }
+
+        self.unpack_files(jobs, &blob_readers)?;
+
+        for (path, attr) in dirs.iter().rev() {
+            self.set_file_attr(path, attr, false)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Set an extended attribute of `path` without following symlinks.
+fn set_xattr(path: &Path, name: &[u8], value: &[u8]) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let c_name = CString::new(name)?;
+    // Safe because the arguments are valid and we have checked the result.
+    let ret = unsafe {
+        libc::lsetxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+    if ret != 0 {
+        let err = Errno::last();
+        let name = String::from_utf8_lossy(name);
+        // Trusted xattrs need CAP_SYS_ADMIN and user xattrs are not allowed on symlinks.
+        if err == Errno::EPERM || err == Errno::ENOTSUP {
+            warn!(
+                "failed to set xattr {} of {}, {}",
+                name,
+                path.display(),
+                err
+            );
+        } else {
+            bail!(
+                "failed to set xattr {} of {}, {}",
+                name,
+                path.display(),
+                err
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy data from `reader` into `file`, and leave holes for zero blocks.
+fn write_sparse(reader: &mut dyn Read, file: &mut File, size: u64) -> Result<()> {
+    let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+    loop {
+        let mut len = 0;
+        while len < buf.len() {
+            let count = reader.read(&mut buf[len..])?;
+            if count == 0 {
+                break;
+            }
+            len += count;
+        }
+
+        for block in buf[..len].chunks(SPARSE_BLOCK_SIZE) {
+            if block.iter().all(|v| *v == 0) {
+                file.seek(SeekFrom::Current(block.len() as i64))?;
+            } else {
+                file.write_all(block)?;
+            }
+        }
+        if len < buf.len() {
+            break;
+        }
+    }
+    // Extend the file if it ends with holes.
+    file.set_len(size)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+    use std::io::Cursor;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    use nydus_builder::attributes::Attributes;
+    use nydus_builder::{
+        ArtifactStorage, BlobManager, BootstrapManager, BuildContext, Builder, ConversionType,
+        Features, Prefetch, TarballBuilder,
+    };
+    use nydus_rafs::metadata::RafsVersion;
+    use nydus_utils::{compress, digest};
+    use tar::{Archive, EntryType, Header};
+    use vmm_sys_util::tempdir::TempDir;
+
+    use crate::unpack::OCIUnpacker;
+
+    fn create_backend(blob_dir: &Path) -> Arc<dyn BlobBackend + Send + Sync> {
+        let config = ConfigV2::new_localfs("", &blob_dir.display().to_string()).unwrap();
+        nydus_storage::factory::BlobFactory::new_backend(config.backend.as_ref().unwrap(), "test")
+            .unwrap()
+    }
+
+    fn texture_bootstrap() -> (PathBuf, PathBuf) {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let bootstrap = PathBuf::from(root_dir).join("tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let blob_dir = PathBuf::from(root_dir).join("tests/texture/blobs");
+        (bootstrap, blob_dir)
+    }
+
+    fn create_unpacker(
+        (bootstrap, blob_dir): &(PathBuf, PathBuf),
+        output: &Path,
+        threads: usize,
+        spec: WhiteoutSpec,
+    ) -> DirUnpacker {
+        DirUnpacker::new(
+            bootstrap,
+            Some(create_backend(blob_dir)),
+            &output.display().to_string(),
+            threads,
+            spec,
+        )
+        .unwrap()
+    }
+
+    /// Unpack the RAFS filesystem with `OCIUnpacker`, then extract the tarball into `output`.
+    fn unpack_by_oci_unpacker((bootstrap, blob_dir): &(PathBuf, PathBuf), output: &Path) {
+        let tar_path = output.with_extension("tar");
+        let unpacker = OCIUnpacker::new(
+            bootstrap,
+            Some(create_backend(blob_dir)),
+            tar_path.to_str().unwrap(),
+        )
+        .unwrap();
+        unpacker.unpack(Arc::new(ConfigV2::default())).unwrap();
+
+        let root = Uid::effective().is_root();
+        fs::create_dir(output).unwrap();
+        let mut archive = Archive::new(File::open(&tar_path).unwrap());
+        archive.set_preserve_permissions(true);
+        archive.set_preserve_mtime(true);
+        archive.set_preserve_ownerships(root);
+        archive.set_unpack_xattrs(true);
+        let mut times = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            // The tar crate only restores extended attributes of regular files.
+            let mut xattrs = Vec::new();
+            if let Some(exts) = entry.pax_extensions().unwrap() {
+                for ext in exts {
+                    let ext = ext.unwrap();
+                    if let Some(name) = ext.key_bytes().strip_prefix(b"SCHILY.xattr.") {
+                        xattrs.push((
+                            OsStr::from_bytes(name).to_owned(),
+                            ext.value_bytes().to_vec(),
+                        ));
+                    }
+                }
+            }
+            let header = entry.header();
+            let path = output.join(entry.path().unwrap());
+            let mtime = header.mtime().unwrap() as libc::time_t;
+            // The tar crate unpacks special files as regular files.
+            let kind = match header.entry_type() {
+                EntryType::Block => SFlag::S_IFBLK,
+                EntryType::Char => SFlag::S_IFCHR,
+                EntryType::Fifo => SFlag::S_IFIFO,
+                ty => {
+                    assert!(entry.unpack_in(output).unwrap());
+                    // Modification time of directories is changed when unpacking children.
+                    if ty == EntryType::Directory {
+                        for (name, value) in xattrs {
+                            xattr::set(&path, name, &value).unwrap();
+                        }
+                        times.push((path, mtime));
+                    }
+                    continue;
+                }
+            };
+            if kind != SFlag::S_IFIFO && !root {
+                continue;
+            }
+            let dev = libc::makedev(
+                header.device_major().unwrap().unwrap_or_default(),
+                header.device_minor().unwrap().unwrap_or_default(),
+            );
+            let mode = header.mode().unwrap();
+            let (uid, gid) = (header.uid().unwrap(), header.gid().unwrap());
+            mknod(&path, kind, Mode::from_bits_truncate(mode), dev).unwrap();
+            fs::set_permissions(&path, Permissions::from_mode(mode & 0o7777)).unwrap();
+            for (name, value) in xattrs {
+                xattr::set(&path, name, &value).unwrap();
+            }
+            if root {
+                fchownat(
+                    None,
+                    &path,
+                    Some(Uid::from_raw(uid as u32)),
+                    Some(Gid::from_raw(gid as u32)),
+                    FchownatFlags::NoFollowSymlink,
+                )
+                .unwrap();
+            }
+            times.push((path, mtime));
+        }
+        for (path, mtime) in times.iter().rev() {
+            let mtime = TimeSpec::from_timespec(libc::timespec {
+                tv_sec: *mtime,
+                tv_nsec: 0,
+            });
+            utimensat(None, path, &mtime, &mtime, UtimensatFlags::NoFollowSymlink).unwrap();
+        }
+    }
+
+    fn get_xattrs(path: &Path) -> Vec<(OsString, Option<Vec<u8>>)> {
+        let mut xattrs: Vec<_> = xattr::list(path)
+            .unwrap()
+            .map(|name| {
+                let value = xattr::get(path, &name).unwrap();
+                (name, value)
+            })
+            .collect();
+        xattrs.sort();
+        xattrs
+    }
+
+    /// Compare the directory unpacked by `DirUnpacker` with the reference one, byte for byte.
+    fn compare_dirs(expected: &Path, actual: &Path) {
+        let mut entries: Vec<_> = fs::read_dir(expected)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        entries.sort();
+        let mut names: Vec<_> = fs::read_dir(actual)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(entries, names, "entries of {}", actual.display());
+
+        for name in entries {
+            let (expected, actual) = (expected.join(&name), actual.join(&name));
+            let md1 = fs::symlink_metadata(&expected).unwrap();
+            let md2 = fs::symlink_metadata(&actual).unwrap();
+            let ty = md1.file_type();
+            let msg = actual.display().to_string();
+            assert_eq!(ty, md2.file_type(), "{}", msg);
+            assert_eq!(md1.mode(), md2.mode(), "{}", msg);
+            assert_eq!(md1.uid(), md2.uid(), "{}", msg);
+            assert_eq!(md1.gid(), md2.gid(), "{}", msg);
+            assert_eq!(md1.nlink(), md2.nlink(), "{}", msg);
+            assert_eq!(md1.mtime(), md2.mtime(), "{}", msg);
+            assert_eq!(get_xattrs(&expected), get_xattrs(&actual), "{}", msg);
+            if ty.is_dir() {
+                compare_dirs(&expected, &actual);
+            } else if ty.is_file() {
+                assert_eq!(
+                    fs::read(&expected).unwrap(),
+                    fs::read(&actual).unwrap(),
+                    "{}",
+                    msg
+                );
+            } else if ty.is_symlink() {
+                assert_eq!(
+                    fs::read_link(&expected).unwrap(),
+                    fs::read_link(&actual).unwrap(),
+                    "{}",
+                    msg
+                );
+            } else if ty.is_char_device() || ty.is_block_device() {
+                assert_eq!(md1.rdev(), md2.rdev(), "{}", msg);
+            }
+        }
+    }
+
+    fn append_entry(
+        builder: &mut tar::Builder<File>,
+        path: &str,
+        ty: EntryType,
+        data: &[u8],
+        link: Option<&str>,
+        xattrs: &[(&str, &[u8])],
+    ) {
+        if !xattrs.is_empty() {
+            let mut records = Vec::new();
+            for (name, value) in xattrs {
+                let record = [b" SCHILY.xattr.", name.as_bytes(), b"=", value, b"\n"].concat();
+                let mut len = record.len();
+                while len != record.len() + len.to_string().len() {
+                    len = record.len() + len.to_string().len();
+                }
+                records.extend_from_slice(len.to_string().as_bytes());
+                records.extend_from_slice(&record);
+            }
+            let mut header = Header::new_ustar();
+            header.set_entry_type(EntryType::XHeader);
+            header.set_size(records.len() as u64);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, "PaxHeaders/entry", records.as_slice())
+                .unwrap();
+        }
+
+        let mut header = Header::new_ustar();
+        header.set_entry_type(ty);
+        header.set_mode(match ty {
+            EntryType::Directory => 0o755,
+            EntryType::Symlink => 0o777,
+            EntryType::Regular if path.ends_with(".sh") => 0o4755,
+            _ => 0o644,
+        });
+        header.set_uid(1000);
+        header.set_gid(1001);
+        header.set_mtime(1700000000);
+        header.set_size(data.len() as u64);
+        if ty == EntryType::Char {
+            header.set_device_major(1).unwrap();
+            header.set_device_minor(3).unwrap();
+        } else if ty == EntryType::Block {
+            header.set_device_major(7).unwrap();
+            header.set_device_minor(1).unwrap();
+        } else if ty == EntryType::Fifo {
+            header.set_device_major(0).unwrap();
+            header.set_device_minor(0).unwrap();
+        }
+        match link {
+            Some(link) => builder.append_link(&mut header, path, link).unwrap(),
+            None => builder.append_data(&mut header, path, data).unwrap(),
+        }
+    }
+
+    // Version 2 file capabilities with CAP_NET_RAW in the permitted and effective sets.
+    const FILE_CAPS: &[u8] = &[
+        0x01, 0, 0, 0x02, 0, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    // Path, type, data, link target and extended attributes of a tarball entry.
+    type FixtureEntry<'a> = (
+        &'a str,
+        EntryType,
+        &'a [u8],
+        Option<&'a str>,
+        Vec<(&'a str, &'a [u8])>,
+    );
+
+    /// Build a RAFS filesystem from a tarball with all kinds of files, hardlinks, xattrs and
+    /// whiteouts, and return paths of the bootstrap and the blob directory.
+    fn create_fixture(dir: &Path) -> (PathBuf, PathBuf) {
+        let tar_path = dir.join("layer.tar");
+        let mut builder = tar::Builder::new(File::create(&tar_path).unwrap());
+        let mut data = vec![0u8; 0x234567];
+        data[0x1000..0x3000].fill(0x5a);
+        data[0x200000..].fill(0xa5);
+        let mut entries: Vec<FixtureEntry> = vec![
+            (
+                "dir",
+                EntryType::Directory,
+                b"",
+                None,
+                vec![("user.dir", b"d")],
+            ),
+            (
+                "dir/file",
+                EntryType::Regular,
+                &data,
+                None,
+                vec![("user.a", b"1"), ("user.b", b"\x00\x01\x02")],
+            ),
+            ("dir/empty", EntryType::Regular, b"", None, vec![]),
+            (
+                "dir/setuid.sh",
+                EntryType::Regular,
+                b"#!/bin/sh\n",
+                None,
+                vec![],
+            ),
+            (
+                "dir/hardlink",
+                EntryType::Link,
+                b"",
+                Some("dir/file"),
+                vec![],
+            ),
+            ("dir/symlink", EntryType::Symlink, b"", Some("file"), vec![]),
+            (
+                "dir/dangling",
+                EntryType::Symlink,
+                b"",
+                Some("/nonexist"),
+                vec![],
+            ),
+            ("dev", EntryType::Directory, b"", None, vec![]),
+            ("dev/fifo", EntryType::Fifo, b"", None, vec![]),
+            ("dev/char", EntryType::Char, b"", None, vec![]),
+            ("dev/block", EntryType::Block, b"", None, vec![]),
+            ("wh", EntryType::Directory, b"", None, vec![]),
+            ("wh/.wh.removed", EntryType::Regular, b"", None, vec![]),
+            ("wh/kept", EntryType::Regular, b"kept", None, vec![]),
+            ("opaque", EntryType::Directory, b"", None, vec![]),
+            ("opaque/.wh..wh..opq", EntryType::Regular, b"", None, vec![]),
+            ("opaque/new", EntryType::Regular, b"new", None, vec![]),
+        ];
+        // Setting file capabilities requires CAP_SETFCAP.
+        if Uid::effective().is_root() {
+            entries.push((
+                "dir/ping",
+                EntryType::Regular,
+                b"ping",
+                None,
+                vec![("security.capability", FILE_CAPS)],
+            ));
+        }
+        for (path, ty, data, link, xattrs) in entries {
+            append_entry(&mut builder, path, ty, data, link, &xattrs);
+        }
+        builder.finish().unwrap();
+        drop(builder);
+
+        let blob_dir = dir.join("blobs");
+        fs::create_dir(&blob_dir).unwrap();
+        let mut ctx = BuildContext::new(
+            String::new(),
+            true,
+            0,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::None,
+            ConversionType::TarToRafs,
+            tar_path,
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir((blob_dir.clone(), String::new()))),
+            None,
+            false,
+            Features::new(),
+            false,
+            Attributes::default(),
+        );
+        ctx.set_fs_version(RafsVersion::V6);
+        let bootstrap = dir.join("bootstrap");
+        let mut bootstrap_mgr =
+            BootstrapManager::new(Some(ArtifactStorage::SingleFile(bootstrap.clone())), None);
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256, false);
+        TarballBuilder::new(ConversionType::TarToRafs)
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+
+        (bootstrap, blob_dir)
+    }
+
+    #[test]
+    fn test_unpack_to_dir() {
+        let tmp_dir = TempDir::new().unwrap();
+        let texture = texture_bootstrap();
+        let expected = tmp_dir.as_path().join("expected");
+        unpack_by_oci_unpacker(&texture, &expected);
+
+        let output = tmp_dir.as_path().join("rootfs");
+        fs::create_dir(&output).unwrap();
+        fs::write(output.join("lib.rs"), b"stale").unwrap();
+        fs::create_dir(output.join("sync_io.rs")).unwrap();
+        fs::write(output.join("other"), b"other").unwrap();
+
+        let unpacker = create_unpacker(&texture, &output, 2, WhiteoutSpec::Oci);
+        unpacker.unpack(Arc::new(ConfigV2::default())).unwrap();
+
+        // Stale files are replaced and unrelated files are kept.
+        let md = fs::metadata(output.join("lib.rs")).unwrap();
+        assert!(md.is_file());
+        assert_eq!(md.len(), 16931);
+        assert_eq!(md.mode() & 0o7777, 0o644);
+        assert!(fs::metadata(output.join("sync_io.rs")).unwrap().is_file());
+        fs::remove_file(output.join("other")).unwrap();
+        compare_dirs(&expected, &output);
+    }
+
+    #[test]
+    fn test_unpack_fixture() {
+        let tmp_dir = TempDir::new().unwrap();
+        let fixture = create_fixture(tmp_dir.as_path());
+        let expected = tmp_dir.as_path().join("expected");
+        unpack_by_oci_unpacker(&fixture, &expected);
+        // Device nodes are skipped by both unpackers without permission.
+        let root = Uid::effective().is_root();
+
+        for threads in [1, 4] {
+            let output = tmp_dir.as_path().join(format!("rootfs-{}", threads));
+            let unpacker = create_unpacker(&fixture, &output, threads, WhiteoutSpec::None);
+            unpacker.unpack(Arc::new(ConfigV2::default())).unwrap();
+            compare_dirs(&expected, &output);
+
+            let md = fs::metadata(output.join("dir/file")).unwrap();
+            assert_eq!(
+                md.ino(),
+                fs::metadata(output.join("dir/hardlink")).unwrap().ino()
+            );
+            assert_eq!(md.nlink(), 2);
+            assert_eq!(md.len(), 0x234567);
+            assert_eq!(
+                xattr::get(output.join("dir/file"), "user.b").unwrap(),
+                Some(vec![0, 1, 2])
+            );
+            assert_eq!(
+                fs::read_link(output.join("dir/symlink")).unwrap(),
+                Path::new("file")
+            );
+            assert!(fs::symlink_metadata(output.join("dev/fifo"))
+                .unwrap()
+                .file_type()
+                .is_fifo());
+            // Whiteouts are unpacked as is without whiteout specification.
+            assert!(output.join("wh/.wh.removed").is_file());
+            assert!(output.join("opaque/.wh..wh..opq").is_file());
+            if root {
+                let md = fs::symlink_metadata(output.join("dev/char")).unwrap();
+                assert!(md.file_type().is_char_device());
+                assert_eq!(md.rdev(), libc::makedev(1, 3));
+                assert_eq!(md.uid(), 1000);
+                assert_eq!(md.gid(), 1001);
+                let md = fs::metadata(output.join("dir/setuid.sh")).unwrap();
+                assert_eq!(md.mode() & 0o7777, 0o4755);
+                // File capabilities survive changing owner of the file.
+                let md = fs::metadata(output.join("dir/ping")).unwrap();
+                assert_eq!(md.uid(), 1000);
+                assert_eq!(
+                    xattr::get(output.join("dir/ping"), "security.capability").unwrap(),
+                    Some(FILE_CAPS.to_vec())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_whiteouts() {
+        let tmp_dir = TempDir::new().unwrap();
+        let fixture = create_fixture(tmp_dir.as_path());
+        let (rs, _) =
+            RafsSuper::load_from_file(&fixture.0, Arc::new(ConfigV2::default()), false).unwrap();
+
+        let output = tmp_dir.as_path().join("rootfs");
+        fs::create_dir_all(output.join("wh/removed/sub")).unwrap();
+        fs::write(output.join("wh/removed/sub/file"), b"removed").unwrap();
+        fs::write(output.join("wh/lower"), b"lower").unwrap();
+        fs::create_dir_all(output.join("opaque/dir")).unwrap();
+        fs::write(output.join("opaque/dir/file"), b"lower").unwrap();
+        fs::write(output.join("opaque/file"), b"lower").unwrap();
+
+        // Whiteouts are ignored without whiteout specification.
+        let unpacker = create_unpacker(&fixture, &output, 1, WhiteoutSpec::None);
+        assert!(unpacker.apply_whiteouts(&rs).unwrap().is_empty());
+        assert!(output.join("wh/removed/sub/file").exists());
+
+        let unpacker = create_unpacker(&fixture, &output, 1, WhiteoutSpec::Oci);
+        let whiteouts = unpacker.apply_whiteouts(&rs).unwrap();
+        let mut whiteouts: Vec<_> = whiteouts.into_iter().collect();
+        whiteouts.sort();
+        assert_eq!(
+            whiteouts,
+            vec![
+                PathBuf::from("/opaque/.wh..wh..opq"),
+                PathBuf::from("/wh/.wh.removed")
+            ]
+        );
+        assert!(!output.join("wh/removed").exists());
+        assert!(output.join("wh/lower").exists());
+        assert!(output.join("opaque").is_dir());
+        assert_eq!(fs::read_dir(output.join("opaque")).unwrap().count(), 0);
+
+        // Whiteout files are not unpacked and the upper layer is merged with lower ones.
+        fs::write(output.join("opaque/file"), b"lower").unwrap();
+        unpacker.unpack(Arc::new(ConfigV2::default())).unwrap();
+        assert!(!output.join("wh/.wh.removed").exists());
+        assert!(!output.join("opaque/.wh..wh..opq").exists());
+        assert!(!output.join("opaque/file").exists());
+        assert_eq!(fs::read(output.join("opaque/new")).unwrap(), b"new");
+        assert_eq!(fs::read(output.join("wh/kept")).unwrap(), b"kept");
+        assert!(output.join("wh/lower").exists());
+
+        // Whiteouts under a symlink are not applied, to avoid escaping from the output directory.
+        let outside = tmp_dir.as_path().join("outside");
+        fs::create_dir_all(outside.join("removed")).unwrap();
+        fs::remove_dir_all(output.join("wh")).unwrap();
+        symlink(&outside, output.join("wh")).unwrap();
+        unpacker.apply_whiteouts(&rs).unwrap();
+        assert!(outside.join("removed").exists());
+    }
+
+    #[test]
+    fn test_whiteout_helpers() {
+        let tmp_dir = TempDir::new().unwrap();
+        let output = tmp_dir.as_path().to_path_buf();
+        let unpacker = create_unpacker(&texture_bootstrap(), &output, 1, WhiteoutSpec::Oci);
+
+        fs::create_dir_all(output.join("a/b")).unwrap();
+        fs::write(output.join("a/b/c"), b"c").unwrap();
+        fs::write(output.join("a/d"), b"d").unwrap();
+        DirUnpacker::clear_dir(&output.join("a/b")).unwrap();
+        assert!(output.join("a/b").is_dir());
+        assert!(!output.join("a/b/c").exists());
+        DirUnpacker::remove_path(&output.join("a/b")).unwrap();
+        assert!(!output.join("a/b").exists());
+        DirUnpacker::remove_path(&output.join("a/b")).unwrap();
+        assert!(output.join("a/d").exists());
+
+        symlink("/", output.join("link")).unwrap();
+        let target = unpacker.target_path(Path::new("/link/etc")).unwrap();
+        assert!(unpacker.has_symlink_ancestor(&target));
+        let target = unpacker.target_path(Path::new("/a/d")).unwrap();
+        assert!(!unpacker.has_symlink_ancestor(&target));
+        assert!(unpacker.target_path(Path::new("/a/../../etc")).is_err());
+
+        assert!(DirUnpacker::prepare_path(&output.join("a"), true).unwrap());
+        assert!(!DirUnpacker::prepare_path(&output.join("a"), false).unwrap());
+        assert!(!output.join("a").exists());
+    }
+
+    #[test]
+    fn test_write_sparse() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.as_path().join("sparse");
+        let mut data = vec![0u8; 0x8000];
+        data[0x4000..0x5000].fill(0x5a);
+        let size = 0x10000;
+
+        let mut file = File::create(&path).unwrap();
+        write_sparse(&mut Cursor::new(data.clone()), &mut file, size).unwrap();
+        drop(file);
+
+        let content = fs::read(&path).unwrap();
+        assert_eq!(content.len() as u64, size);
+        assert_eq!(&content[..data.len()], data.as_slice());
+        assert!(content[data.len()..].iter().all(|v| *v == 0));
+        let md = fs::metadata(&path).unwrap();
+        assert!(md.blocks() * 512 < size);
+    }
+}
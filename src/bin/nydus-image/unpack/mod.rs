@@ -16,16 +16,20 @@ use nydus_rafs::{
     metadata::{RafsInodeExt, RafsSuper},
     RafsIterator,
 };
-use nydus_storage::backend::BlobBackend;
-use nydus_storage::device::BlobInfo;
+use nydus_storage::backend::{BlobBackend, BlobReader};
+use nydus_storage::device::{BlobChunkInfo, BlobInfo};
+use nydus_storage::meta::BlobCompressionContextInfo;
+use nydus_utils::compress::{Algorithm, CompressionDict};
 use tar::{Builder, Header};
 
+pub use self::dir::DirUnpacker;
 use self::pax::{
-    OCIBlockBuilder, OCICharBuilder, OCIDirBuilder, OCIFifoBuilder, OCILinkBuilder, OCIRegBuilder,
-    OCISocketBuilder, OCISymlinkBuilder, PAXExtensionSectionBuilder, PAXLinkBuilder,
+    ChunkReader, OCIBlockBuilder, OCICharBuilder, OCIDirBuilder, OCIFifoBuilder, OCILinkBuilder,
+    OCIRegBuilder, OCISocketBuilder, OCISymlinkBuilder, PAXExtensionSectionBuilder, PAXLinkBuilder,
     PAXSpecialSectionBuilder,
 };
 
+mod dir;
 mod pax;
 
 pub trait Unpacker {
@@ -178,8 +182,33 @@ impl OCITarBuilderFactory {
         blobs: Vec<Arc<BlobInfo>>,
         blob_backend: &Option<Arc<dyn BlobBackend + Send + Sync>>,
     ) -> Result<OCIRegBuilder> {
+        let blob_readers = BlobReaders::new(blobs, blob_backend)?;
+
+        Ok(OCIRegBuilder::new(
+            Rc::new(PAXExtensionSectionBuilder::new()),
+            blob_readers.readers,
+            blob_readers.compressors,
+            blob_readers.dicts,
+        ))
+    }
+}
+
+/// Readers, compression algorithms and dictionaries of data blobs, indexed by blob index.
+#[derive(Clone)]
+struct BlobReaders {
+    readers: HashMap<u32, Arc<dyn BlobReader>>,
+    compressors: HashMap<u32, Algorithm>,
+    dicts: HashMap<u32, Arc<CompressionDict>>,
+}
+
+impl BlobReaders {
+    fn new(
+        blobs: Vec<Arc<BlobInfo>>,
+        blob_backend: &Option<Arc<dyn BlobBackend + Send + Sync>>,
+    ) -> Result<Self> {
         let mut readers = HashMap::new();
         let mut compressors = HashMap::new();
+        let mut dicts = HashMap::new();
         for blob in blobs {
             let blob_backend = blob_backend
                 .as_deref()
@@ -189,15 +218,32 @@ impl OCITarBuilderFactory {
                 .map_err(|err| anyhow!("fail to get reader, error {:?}", err))?;
 
             let compressor = blob.compressor();
+            if compressor.need_dict() {
+                let dict = BlobCompressionContextInfo::read_compression_dict(&blob, &reader)
+                    .with_context(|| {
+                        format!("fail to read compression dictionary of {}", blob.blob_id())
+                    })?
+                    .with_context(|| format!("no compression dictionary in {}", blob.blob_id()))?;
+                dicts.insert(blob.blob_index(), Arc::new(dict));
+            }
             readers.insert(blob.blob_index(), reader);
             compressors.insert(blob.blob_index(), compressor);
         }
 
-        Ok(OCIRegBuilder::new(
-            Rc::new(PAXExtensionSectionBuilder::new()),
+        Ok(BlobReaders {
             readers,
             compressors,
-        ))
+            dicts,
+        })
+    }
+
+    fn chunk_reader(&self, chunks: Vec<Arc<dyn BlobChunkInfo>>) -> ChunkReader {
+        ChunkReader::new(
+            self.compressors.clone(),
+            self.readers.clone(),
+            self.dicts.clone(),
+            chunks,
+        )
     }
 }
 
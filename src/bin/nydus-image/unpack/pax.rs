@@ -17,7 +17,7 @@ use anyhow::{Context, Result};
 use nydus_rafs::metadata::inode::InodeWrapper;
 use nydus_rafs::metadata::RafsInodeExt;
 use nydus_storage::{backend::BlobReader, device::BlobChunkInfo, utils::alloc_buf};
-use nydus_utils::compress::{self, Algorithm};
+use nydus_utils::compress::{self, Algorithm, CompressionDict};
 use tar::{EntryType, Header};
 
 use super::{SectionBuilder, TarSection};
@@ -37,7 +37,7 @@ impl OCISocketBuilder {
 
 impl SectionBuilder for OCISocketBuilder {
     fn can_handle(&mut self, node: Arc<dyn RafsInodeExt>, _: &Path) -> bool {
-        InodeWrapper::from_inode_info(node).is_sock()
+        node.is_sock()
     }
 
     fn build(&self, _: Arc<dyn RafsInodeExt>, _: &Path) -> Result<Vec<TarSection>> {
@@ -74,10 +74,11 @@ impl SectionBuilder for OCILinkBuilder {
     }
 
     fn build(&self, node: Arc<dyn RafsInodeExt>, path: &Path) -> Result<Vec<TarSection>> {
-        let link = self.links.get(&node.ino()).unwrap();
+        // Hardlink targets are paths in the tarball, just like entry paths.
+        let link = Util::normalize_path(self.links.get(&node.ino()).unwrap())?;
 
         self.pax_link_builder
-            .build(EntryType::hard_link(), node, path, link)
+            .build(EntryType::hard_link(), node, path, &link)
     }
 }
 
@@ -169,6 +170,7 @@ pub struct OCIRegBuilder {
     ext_builder: Rc<PAXExtensionSectionBuilder>,
     readers: HashMap<u32, Arc<dyn BlobReader>>,
     compressors: HashMap<u32, Algorithm>,
+    dicts: HashMap<u32, Arc<CompressionDict>>,
 }
 
 impl OCIRegBuilder {
@@ -176,11 +178,13 @@ impl OCIRegBuilder {
         ext_builder: Rc<PAXExtensionSectionBuilder>,
         readers: HashMap<u32, Arc<dyn BlobReader>>,
         compressors: HashMap<u32, Algorithm>,
+        dicts: HashMap<u32, Arc<CompressionDict>>,
     ) -> Self {
         OCIRegBuilder {
             ext_builder,
             readers,
             compressors,
+            dicts,
         }
     }
 
@@ -194,7 +198,7 @@ impl OCIRegBuilder {
 
         let mut readers = HashMap::new();
         readers.clone_from(&self.readers);
-        let reader = ChunkReader::new(compressors, readers, chunks);
+        let reader = ChunkReader::new(compressors, readers, self.dicts.clone(), chunks);
 
         Box::new(reader)
     }
@@ -274,7 +278,7 @@ impl OCIFifoBuilder {
 
 impl SectionBuilder for OCIFifoBuilder {
     fn can_handle(&mut self, node: Arc<dyn RafsInodeExt>, _: &Path) -> bool {
-        InodeWrapper::from_inode_info(node).is_fifo()
+        node.is_fifo()
     }
 
     fn build(&self, inode: Arc<dyn RafsInodeExt>, path: &Path) -> Result<Vec<TarSection>> {
@@ -297,7 +301,7 @@ impl OCICharBuilder {
 
 impl SectionBuilder for OCICharBuilder {
     fn can_handle(&mut self, node: Arc<dyn RafsInodeExt>, _: &Path) -> bool {
-        InodeWrapper::from_inode_info(node).is_chrdev()
+        node.is_chrdev()
     }
 
     fn build(&self, inode: Arc<dyn RafsInodeExt>, path: &Path) -> Result<Vec<TarSection>> {
@@ -320,7 +324,7 @@ impl OCIBlockBuilder {
 
 impl SectionBuilder for OCIBlockBuilder {
     fn can_handle(&mut self, node: Arc<dyn RafsInodeExt>, _: &Path) -> bool {
-        InodeWrapper::from_inode_info(node).is_blkdev()
+        node.is_blkdev()
     }
 
     fn build(&self, inode: Arc<dyn RafsInodeExt>, path: &Path) -> Result<Vec<TarSection>> {
@@ -698,23 +702,26 @@ impl Util {
     }
 }
 
-struct ChunkReader {
+pub struct ChunkReader {
     compressors: HashMap<u32, Algorithm>,
     readers: HashMap<u32, Arc<dyn BlobReader>>,
+    dicts: HashMap<u32, Arc<CompressionDict>>,
 
     chunks: IntoIter<Arc<dyn BlobChunkInfo>>,
     chunk: Cursor<Vec<u8>>,
 }
 
 impl ChunkReader {
-    fn new(
+    pub fn new(
         compressors: HashMap<u32, Algorithm>,
         readers: HashMap<u32, Arc<dyn BlobReader>>,
+        dicts: HashMap<u32, Arc<CompressionDict>>,
         chunks: Vec<Arc<dyn BlobChunkInfo>>,
     ) -> Self {
         Self {
             compressors,
             readers,
+            dicts,
             chunks: chunks.into_iter(),
             chunk: Cursor::new(Vec::new()),
         }
@@ -746,7 +753,8 @@ impl ChunkReader {
             .expect("No valid compressor");
 
         let mut data = vec![0u8; chunk.uncompressed_size() as usize];
-        compress::decompress(buf.as_mut_slice(), data.as_mut_slice(), compressor)
+        let dict = self.dicts.get(&chunk.blob_index()).map(|d| d.as_ref());
+        compress::decompress_with_dict(buf.as_mut_slice(), data.as_mut_slice(), compressor, dict)
             .with_context(|| "fail to decompress")?;
 
         self.chunk = Cursor::new(data);
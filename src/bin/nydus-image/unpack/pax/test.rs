@@ -225,7 +225,7 @@ fn create_compress_chunk_reader() -> ChunkReader {
     let mut compressors: HashMap<u32, Algorithm> = HashMap::new();
     compressors.insert(meta.blob_index(), Algorithm::GZip);
 
-    ChunkReader::new(compressors, readers, vec![meta])
+    ChunkReader::new(compressors, readers, HashMap::new(), vec![meta])
 }
 
 fn create_default_chunk_reader() -> ChunkReader {
@@ -257,5 +257,10 @@ fn create_default_chunk_reader() -> ChunkReader {
     compressors.insert(chunk_meta1.blob_index(), Algorithm::None);
     compressors.insert(chunk_meta2.blob_index(), Algorithm::None);
 
-    ChunkReader::new(compressors, readers, vec![chunk_meta1, chunk_meta2])
+    ChunkReader::new(
+        compressors,
+        readers,
+        HashMap::new(),
+        vec![chunk_meta1, chunk_meta2],
+    )
 }
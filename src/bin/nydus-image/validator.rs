@@ -4,15 +4,65 @@
 
 //! Validator for RAFS format
 
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use nydus_api::ConfigV2;
 use nydus_builder::Tree;
+use nydus_rafs::metadata::chunk::ChunkWrapper;
 use nydus_rafs::metadata::{RafsSuper, RafsVersion};
-use nydus_storage::device::BlobInfo;
-use nydus_utils::compress;
+use nydus_storage::backend::{BlobBackend, BlobReader};
+use nydus_storage::device::{BlobFeatures, BlobInfo};
+use nydus_storage::meta::BlobCompressionContextInfo;
+use nydus_storage::utils::{alloc_buf, check_crc, check_hash};
+use nydus_utils::compress::xz_random::XzBlockDecoder;
+use nydus_utils::compress::zlib_random::ZranDecoder;
+use nydus_utils::compress::zstd_random::ZstdFrameDecoder;
+use nydus_utils::digest::RafsDigest;
+use nydus_utils::{compress, crypt};
+use serde::{Deserialize, Serialize};
+
+/// Information about a chunk whose data is corrupted.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CorruptedChunk {
+    pub blob_id: String,
+    pub chunk_index: u32,
+    pub compressed_offset: u64,
+    pub compressed_size: u32,
+    pub uncompressed_offset: u64,
+    pub uncompressed_size: u32,
+    pub error: String,
+}
+
+/// Information about a file containing corrupted chunks.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CorruptedFile {
+    pub path: String,
+    pub chunks: Vec<CorruptedChunk>,
+}
+
+/// Result of verifying data blobs referenced by a RAFS filesystem.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DataVerification {
+    /// Number of chunks whose data has been verified.
+    pub verified_chunks: u64,
+    /// Number of chunks which have no digest to verify, such as chunks from legacy stargz blobs.
+    pub skipped_chunks: u64,
+    /// Number of corrupted chunks.
+    pub corrupted_chunks: u64,
+    /// Files containing corrupted chunks.
+    pub corrupted_files: Vec<CorruptedFile>,
+}
+
+impl DataVerification {
+    /// Check whether all verified chunks are valid.
+    pub fn is_valid(&self) -> bool {
+        self.corrupted_chunks == 0
+    }
+}
 
 pub struct Validator {
     sb: RafsSuper,
@@ -52,4 +102,399 @@ impl Validator {
             rafs_version,
         ))
     }
+
+    /// Read data of all chunks referenced by the filesystem from the storage backend, and verify
+    /// chunk data against chunk digests and the blob compression context information.
+    pub fn verify_data(
+        &mut self,
+        backend: Arc<dyn BlobBackend + Send + Sync>,
+    ) -> Result<DataVerification> {
+        let err = "failed to load bootstrap for validator";
+        let tree = Tree::from_bootstrap(&self.sb, &mut ()).context(err)?;
+
+        // Collect chunks referenced by each file, and verify each chunk only once.
+        let mut files = Vec::new();
+        let mut chunks = BTreeMap::new();
+        tree.walk_dfs_pre(&mut |t: &Tree| -> Result<()> {
+            let node = t.borrow_mut_node();
+            if node.is_reg() && !node.chunks.is_empty() {
+                let mut keys = Vec::with_capacity(node.chunks.len());
+                for chunk in node.chunks.iter() {
+                    let key = (chunk.inner.blob_index(), chunk.inner.index());
+                    chunks.entry(key).or_insert_with(|| chunk.inner.clone());
+                    keys.push(key);
+                }
+                files.push((node.target().clone(), keys));
+            }
+            Ok(())
+        })?;
+
+        let mut result = DataVerification::default();
+        let mut errors = HashMap::new();
+        let blobs = self.sb.superblock.get_blob_infos();
+        let mut verifier: Option<BlobDataVerifier> = None;
+        for ((blob_index, chunk_index), chunk) in chunks {
+            let blob = blobs
+                .get(blob_index as usize)
+                .with_context(|| format!("invalid blob index {}", blob_index))?;
+            if verifier.as_ref().map(|v| v.blob.blob_index()) != Some(blob_index) {
+                verifier = Some(BlobDataVerifier::new(blob.clone(), backend.as_ref()));
+            }
+            // Safe to unwrap because it has been set above.
+            let verifier = verifier.as_mut().unwrap();
+            match verifier.verify(&chunk) {
+                Ok(true) => result.verified_chunks += 1,
+                Ok(false) => result.skipped_chunks += 1,
+                Err(e) => {
+                    result.corrupted_chunks += 1;
+                    let info = CorruptedChunk {
+                        blob_id: blob.blob_id(),
+                        chunk_index,
+                        compressed_offset: chunk.compressed_offset(),
+                        compressed_size: chunk.compressed_size(),
+                        uncompressed_offset: chunk.uncompressed_offset(),
+                        uncompressed_size: chunk.uncompressed_size(),
+                        error: format!("{:#}", e),
+                    };
+                    errors.insert((blob_index, chunk_index), info);
+                }
+            }
+        }
+
+        for (path, keys) in files {
+            let chunks: Vec<CorruptedChunk> =
+                keys.iter().filter_map(|k| errors.get(k).cloned()).collect();
+            if !chunks.is_empty() {
+                result.corrupted_files.push(CorruptedFile {
+                    path: path.display().to_string(),
+                    chunks,
+                });
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Helper to read and verify chunk data from a data blob.
+struct BlobDataVerifier {
+    blob: Arc<BlobInfo>,
+    reader: Result<Arc<dyn BlobReader>>,
+    meta_reader: Option<Result<Arc<dyn BlobReader>>>,
+    meta: Option<Result<BlobCompressionContextInfo>>,
+    // Index and uncompressed data of the last batch or zran context.
+    context: Option<(u32, Vec<u8>)>,
+}
+
+impl BlobDataVerifier {
+    fn new(blob: Arc<BlobInfo>, backend: &(dyn BlobBackend + Send + Sync)) -> Self {
+        let reader = backend
+            .get_reader(&blob.blob_id())
+            .map_err(|e| anyhow!("failed to get reader for blob {}, {:?}", blob.blob_id(), e));
+        // Blob meta of blobs referencing OCI image layers is stored in a separate blob.
+        let meta_reader = if blob.has_feature(BlobFeatures::SEPARATE) {
+            let reader = blob
+                .get_blob_meta_id()
+                .map_err(|e| anyhow!("failed to get blob meta id, {}", e))
+                .and_then(|id| {
+                    backend
+                        .get_reader(&id)
+                        .map_err(|e| anyhow!("failed to get reader for blob meta {}, {:?}", id, e))
+                });
+            Some(reader)
+        } else {
+            None
+        };
+        BlobDataVerifier {
+            blob,
+            reader,
+            meta_reader,
+            meta: None,
+            context: None,
+        }
+    }
+
+    /// Verify data of the chunk, return `Ok(false)` if there's no digest to verify data.
+    fn verify(&mut self, chunk: &ChunkWrapper) -> Result<bool> {
+        if self.blob.is_legacy_stargz() {
+            return Ok(false);
+        }
+
+        if self.blob.meta_ci_is_valid() {
+            self.check_chunk_meta(chunk)?;
+        }
+        let data = if self.blob.has_feature(BlobFeatures::ZRAN) {
+            self.read_zran_chunk(chunk)?
+        } else if chunk.is_batch() {
+            self.read_batch_chunk(chunk)?
+        } else {
+            self.read_chunk(chunk)?
+        };
+        if data.len() != chunk.uncompressed_size() as usize {
+            bail!(
+                "size of uncompressed data doesn't match, expect {}, got {}",
+                chunk.uncompressed_size(),
+                data.len()
+            );
+        }
+
+        if chunk.has_crc32() {
+            if !check_crc(&data, chunk.crc32()) {
+                bail!("data crc32 value doesn't match");
+            }
+        } else if *chunk.id() == RafsDigest::default() {
+            return Ok(false);
+        } else if !check_hash(&data, chunk.id(), self.blob.digester()) {
+            bail!("data digest value doesn't match");
+        }
+
+        Ok(true)
+    }
+
+    /// Cross-check RAFS chunk information against the blob compression context information.
+    fn check_chunk_meta(&mut self, chunk: &ChunkWrapper) -> Result<()> {
+        let is_zran = self.blob.has_feature(BlobFeatures::ZRAN);
+        let meta = self.get_meta()?;
+        let index = chunk.index() as usize;
+        if index >= meta.get_chunk_count() {
+            bail!(
+                "chunk index {} is out of range of blob meta, chunk count {}",
+                index,
+                meta.get_chunk_count()
+            );
+        }
+        let info = meta.get_chunk_info(index);
+        if info.uncompressed_offset() != chunk.uncompressed_offset()
+            || info.uncompressed_size() != chunk.uncompressed_size()
+        {
+            bail!(
+                "uncompressed range 0x{:x}/0x{:x} doesn't match blob meta 0x{:x}/0x{:x}",
+                chunk.uncompressed_offset(),
+                chunk.uncompressed_size(),
+                info.uncompressed_offset(),
+                info.uncompressed_size()
+            );
+        }
+        if !is_zran
+            && !chunk.is_batch()
+            && (info.compressed_offset() != chunk.compressed_offset()
+                || info.compressed_size() != chunk.compressed_size()
+                || info.is_compressed() != chunk.is_compressed())
+        {
+            bail!(
+                "compressed range 0x{:x}/0x{:x} doesn't match blob meta 0x{:x}/0x{:x}",
+                chunk.compressed_offset(),
+                chunk.compressed_size(),
+                info.compressed_offset(),
+                info.compressed_size()
+            );
+        }
+        if let Some(digest) = meta.get_chunk_digest(index) {
+            if digest != chunk.id().as_ref() {
+                bail!("chunk digest doesn't match blob meta");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_chunk(&mut self, chunk: &ChunkWrapper) -> Result<Vec<u8>> {
+        let raw = self.read_raw(chunk.compressed_offset(), chunk.compressed_size() as usize)?;
+        let raw = crypt::decrypt_with_context(
+            &raw,
+            &self.blob.cipher_object(),
+            &self.blob.cipher_context(),
+            chunk.is_encrypted(),
+        )?;
+        if !chunk.is_compressed() {
+            return Ok(raw.to_vec());
+        }
+        self.decompress(&raw, chunk.uncompressed_size() as usize)
+    }
+
+    fn read_batch_chunk(&mut self, chunk: &ChunkWrapper) -> Result<Vec<u8>> {
+        let index = chunk.index();
+        let batch_index = self.get_meta()?.get_batch_index(index)?;
+        if self.context.as_ref().map(|c| c.0) != Some(batch_index) {
+            let ctx = self.get_meta()?.get_batch_context(batch_index)?;
+            let (c_size, d_size) = (
+                ctx.compressed_size() as usize,
+                ctx.uncompressed_batch_size() as usize,
+            );
+            let raw = self.read_raw(chunk.compressed_offset(), c_size)?;
+            let raw = crypt::decrypt_with_context(
+                &raw,
+                &self.blob.cipher_object(),
+                &self.blob.cipher_context(),
+                chunk.is_encrypted(),
+            )?;
+            let data = if c_size != d_size {
+                self.decompress(&raw, d_size)?
+            } else {
+                raw.to_vec()
+            };
+            self.context = Some((batch_index, data));
+        }
+
+        let offset = self
+            .get_meta()?
+            .get_uncompressed_offset_in_batch_buf(index)?;
+        self.slice_context(offset as usize, chunk.uncompressed_size() as usize)
+    }
+
+    fn read_zran_chunk(&mut self, chunk: &ChunkWrapper) -> Result<Vec<u8>> {
+        let index = chunk.index();
+        let zran_index = self.get_meta()?.get_zran_index(index)?;
+        if self.context.as_ref().map(|c| c.0) != Some(zran_index) {
+            let (ctx, dict) = self.get_meta()?.get_zran_context(zran_index)?;
+            let (ctx, dict) = (ctx, dict.to_vec());
+            let raw = self.read_raw(ctx.in_offset, ctx.in_len as usize)?;
+            let mut data = alloc_buf(ctx.out_len as usize);
+            if self.blob.compressor() == compress::Algorithm::Zstd {
+                ZstdFrameDecoder::new()?.uncompress(&ctx, &raw, &mut data)?;
+            } else if self.blob.compressor() == compress::Algorithm::Xz {
+                XzBlockDecoder::new()?.uncompress(&ctx, &dict, &raw, &mut data)?;
+            } else {
+                ZranDecoder::new()?.uncompress(&ctx, Some(&dict), &raw, &mut data)?;
+            }
+            self.context = Some((zran_index, data));
+        }
+
+        let offset = self.get_meta()?.get_zran_offset(index)?;
+        self.slice_context(offset as usize, chunk.uncompressed_size() as usize)
+    }
+
+    fn slice_context(&self, offset: usize, size: usize) -> Result<Vec<u8>> {
+        // Safe to unwrap because the context has been loaded by caller.
+        let data = &self.context.as_ref().unwrap().1;
+        match offset.checked_add(size) {
+            Some(end) if end <= data.len() => Ok(data[offset..end].to_vec()),
+            _ => bail!(
+                "chunk range 0x{:x}/0x{:x} exceeds decompressed data size 0x{:x}",
+                offset,
+                size,
+                data.len()
+            ),
+        }
+    }
+
+    fn read_raw(&self, offset: u64, size: usize) -> Result<Vec<u8>> {
+        let reader = self.reader.as_ref().map_err(|e| anyhow!("{}", e))?;
+        let mut buf = alloc_buf(size);
+        let sz = reader
+            .read(&mut buf, offset)
+            .map_err(|e| anyhow!("failed to read data from blob, {:?}", e))?;
+        if sz != size {
+            bail!(
+                "storage backend returns less data than requested, expect {}, got {}",
+                size,
+                sz
+            );
+        }
+        Ok(buf)
+    }
+
+    fn decompress(&mut self, raw: &[u8], size: usize) -> Result<Vec<u8>> {
+        let compressor = self.blob.compressor();
+        let meta = if compressor.need_dict() {
+            Some(self.get_meta()?)
+        } else {
+            None
+        };
+        let dict = meta.and_then(|m| m.get_compression_dict());
+        let mut data = alloc_buf(size);
+        let sz = compress::decompress_with_dict(raw, &mut data, compressor, dict)
+            .context("failed to decompress chunk data")?;
+        if sz != size {
+            bail!(
+                "size of decompressed data doesn't match, expect {}, got {}",
+                size,
+                sz
+            );
+        }
+        Ok(data)
+    }
+
+    fn get_meta(&mut self) -> Result<&BlobCompressionContextInfo> {
+        if self.meta.is_none() {
+            self.meta = Some(self.load_meta());
+        }
+        // Safe to unwrap because it has been set above.
+        match self.meta.as_ref().unwrap() {
+            Ok(meta) => Ok(meta),
+            Err(e) => bail!("{}", e),
+        }
+    }
+
+    fn load_meta(&self) -> Result<BlobCompressionContextInfo> {
+        let reader = match self.meta_reader.as_ref() {
+            Some(reader) => reader,
+            None => &self.reader,
+        };
+        let reader = reader.as_ref().map_err(|e| anyhow!("{}", e))?;
+
+        // Blob meta is downloaded into a temporary file, which is removed once loaded.
+        let path = std::env::temp_dir().join(format!(
+            "nydus-image-check-{}-{}",
+            std::process::id(),
+            self.blob.blob_id()
+        ));
+        let path = path.display().to_string();
+        let load_digest = self.blob.has_feature(BlobFeatures::INLINED_CHUNK_DIGEST);
+        let meta = BlobCompressionContextInfo::new(&path, &self.blob, Some(reader), load_digest)
+            .map_err(|e| anyhow!("failed to load blob meta, {}", e));
+        let _ = fs::remove_file(PathBuf::from(format!("{}.blob.meta", path)));
+        meta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_storage::factory::BlobFactory;
+    use std::io::{Seek, SeekFrom, Write};
+    use vmm_sys_util::tempdir::TempDir;
+
+    const BLOB_ID: &str = "be7d77eeb719f70884758d1aa800ed0fb09d701aaec469964e9d54325f0d5fef";
+
+    fn verify(blob_dir: &Path) -> DataVerification {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let config = Arc::new(ConfigV2::new_localfs("", &blob_dir.display().to_string()).unwrap());
+        let backend = BlobFactory::new_backend(config.backend.as_ref().unwrap(), "test").unwrap();
+        let mut validator = Validator::new(&path, config).unwrap();
+        validator.verify_data(backend).unwrap()
+    }
+
+    #[test]
+    fn test_verify_data() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let blob_dir = PathBuf::from(root_dir).join("tests/texture/blobs");
+        let result = verify(&blob_dir);
+        assert!(result.is_valid());
+        assert_eq!(result.verified_chunks, 2);
+        assert!(result.corrupted_files.is_empty());
+
+        let tmp_dir = TempDir::new().unwrap();
+        let blob_path = tmp_dir.as_path().join(BLOB_ID);
+        fs::copy(blob_dir.join(BLOB_ID), &blob_path).unwrap();
+        let mut file = fs::OpenOptions::new().write(true).open(&blob_path).unwrap();
+        file.seek(SeekFrom::Start(5000)).unwrap();
+        file.write_all(&[0xa5u8; 16]).unwrap();
+        drop(file);
+
+        let result = verify(tmp_dir.as_path());
+        assert!(!result.is_valid());
+        assert_eq!(result.corrupted_chunks, 1);
+        assert_eq!(result.verified_chunks, 1);
+        assert_eq!(result.corrupted_files.len(), 1);
+        assert_eq!(result.corrupted_files[0].path, "/sync_io.rs");
+        let chunk = &result.corrupted_files[0].chunks[0];
+        assert_eq!(chunk.blob_id, BLOB_ID);
+        assert_eq!(chunk.chunk_index, 1);
+        assert_eq!(chunk.compressed_offset, 4828);
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["corrupted_chunks"], 1);
+    }
 }
@@ -50,7 +50,8 @@ impl NydusdClient {
         Ok(b)
     }
 
-    pub async fn put(&self, path: &str, data: Option<String>) -> Result<()> {
+    /// Send a PUT request, return `Value::Null` if there's no response body.
+    pub async fn put(&self, path: &str, data: Option<String>) -> Result<Value> {
         let client = Client::unix();
         let uri = self.build_uri(path, None);
         let (body, _) = if let Some(d) = data {
@@ -75,7 +76,11 @@ impl NydusdClient {
             bail!("Request failed. {:?}", b);
         }
 
-        Ok(())
+        if buf.is_empty() {
+            Ok(Value::Null)
+        } else {
+            serde_json::from_slice(&buf).map_err(|e| anyhow!("deserialize: {}", e))
+        }
     }
 
     pub async fn post(
@@ -75,6 +75,8 @@ Total Read:                 {total_read}
 Directory:                  {directory}
 Files:                      {files}
 Persister Buffer:           {buffered}
+Evicted Blobs:              {evicted_blobs}
+Reclaimed Space:            {reclaimed_bytes} Bytes
 
 Prefetch Workers:           {workers}
 Prefetch Amount:            {prefetch_amount} = {prefetch_amount_kb} KB
@@ -101,6 +103,8 @@ Prefetch Unmerged:          {unmerged_blocks}
                 workers = m["prefetch_workers"],
                 unmerged_blocks = m["prefetch_unmerged_chunks"],
                 buffered = m["buffered_backend_size"],
+                evicted_blobs = m["evicted_blobs"],
+                reclaimed_bytes = m["reclaimed_bytes"],
                 prefetch_duration = prefetch_duration,
                 prefetch_bandwidth = prefetch_data_amount / 1024.0 / 1024.0 / prefetch_duration,
                 prefetch_request_latency = m["prefetch_cumulative_time_millis"].as_f64().unwrap()
@@ -113,6 +117,80 @@ Prefetch Unmerged:          {unmerged_blocks}
     }
 }
 
+pub(crate) struct CommandScrub {}
+
+impl CommandScrub {
+    pub async fn execute(
+        &self,
+        raw: bool,
+        client: &NydusdClient,
+        _params: Option<CommandParams>,
+    ) -> Result<()> {
+        let metrics = client.get("v1/metrics/blobcache").await?;
+        let m = &metrics["scrub"];
+
+        if raw {
+            println!("{}", m);
+        } else {
+            print!(
+                r#"
+Scrub Passes:               {passes}
+Last Pass End Time:         {last_pass_end_time}
+Scrubbed Chunks:            {scrubbed_chunks}
+Scrubbed Data:              {scrubbed_bytes} Bytes
+Corrupted Chunks:           {corrupted_chunks}
+"#,
+                passes = m["passes"],
+                last_pass_end_time = m["last_pass_end_time_secs"],
+                scrubbed_chunks = m["scrubbed_chunks"],
+                scrubbed_bytes = m["scrubbed_bytes"],
+                corrupted_chunks = m["corrupted_chunks"],
+            );
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) struct CommandGc {}
+
+impl CommandGc {
+    pub async fn execute(
+        &self,
+        raw: bool,
+        client: &NydusdClient,
+        _params: Option<CommandParams>,
+    ) -> Result<()> {
+        let result = client.put("v2/cas/gc", None).await?;
+        let stats = client.get("v2/cas").await?;
+
+        if raw {
+            println!("{}", json!({"gc": result, "stats": stats}));
+        } else {
+            print!(
+                r#"
+Removed Blobs:              {removed_blobs}
+Removed Chunks:             {removed_chunks}
+Blobs:                      {blobs}
+Referenced Blobs:           {referenced_blobs}
+Chunks:                     {chunks}
+Unique Chunks:              {unique_chunks}
+Database Size:              {db_size} Bytes
+"#,
+                removed_blobs = result["removed_blobs"],
+                removed_chunks = result["removed_chunks"],
+                blobs = stats["blobs"],
+                referenced_blobs = stats["referenced_blobs"],
+                chunks = stats["chunks"],
+                unique_chunks = stats["unique_chunks"],
+                db_size = stats["db_size"],
+            );
+        }
+
+        Ok(())
+    }
+}
+
 fn metric_delta(old: &serde_json::Value, new: &serde_json::Value, label: &str) -> u64 {
     new[label].as_u64().unwrap() - old[label].as_u64().unwrap()
 }
@@ -224,6 +302,25 @@ Read Errors:        {read_errors}
                 read_errors = m["read_errors"],
             );
 
+            if let Some(mirrors) = m.get("mirrors").and_then(|v| v.as_object()) {
+                if !mirrors.is_empty() {
+                    println!(
+                        "\n{:<40}{:<10}{:<12}{:<12}{:<10}",
+                        "Mirror:", "Healthy", "Requests", "Errors", "Disabled"
+                    );
+                }
+                for (host, mirror) in mirrors {
+                    println!(
+                        "{:<40}{:<10}{:<12}{:<12}{:<10}",
+                        host,
+                        mirror["healthy"],
+                        mirror["requests"],
+                        mirror["errors"],
+                        mirror["disabled_count"]
+                    );
+                }
+            }
+
             println!(
                 r#"
 {:<25}{:<8}{:<8}{:<8}{:<8}{:<8}{:<8}{:<8}{:<8}"#,
@@ -23,7 +23,8 @@ mod client;
 mod commands;
 
 use commands::{
-    CommandBackend, CommandCache, CommandDaemon, CommandFsStats, CommandMount, CommandUmount,
+    CommandBackend, CommandCache, CommandDaemon, CommandFsStats, CommandGc, CommandMount,
+    CommandScrub, CommandUmount,
 };
 use nydus::get_build_time_info;
 
@@ -93,6 +94,12 @@ async fn main() -> Result<()> {
                         .required(false),
                 ),
         )
+        .subcommand(
+            Command::new("scrub").about("Gets results of scrubbing cached data in background"),
+        )
+        .subcommand(
+            Command::new("gc").about("Removes stale records from the chunk deduplication database"),
+        )
         .subcommand(
             Command::new("mount")
                 .about("Mounts a new filesystem instance")
@@ -182,6 +189,12 @@ async fn main() -> Result<()> {
             }
             _ => println!("Illegal category"),
         }
+    } else if let Some(_matches) = cmd.subcommand_matches("scrub") {
+        let cmd = CommandScrub {};
+        cmd.execute(raw, &client, None).await?
+    } else if let Some(_matches) = cmd.subcommand_matches("gc") {
+        let cmd = CommandGc {};
+        cmd.execute(raw, &client, None).await?
     } else if let Some(matches) = cmd.subcommand_matches("mount") {
         // Safe to unwrap as it is required by clap
         let mut context = HashMap::new();
@@ -16,9 +16,12 @@ use nix::unistd::Pid;
 use nydus::daemon::NydusDaemon;
 use nydus::{FsBackendMountCmd, FsBackendType, FsBackendUmountCmd, FsService};
 use nydus_api::{
-    start_http_thread, ApiError, ApiMountCmd, ApiRequest, ApiResponse, ApiResponsePayload,
-    ApiResult, BlobCacheEntry, BlobCacheObjectId, DaemonConf, DaemonErrorKind, MetricsErrorKind,
+    start_http_thread, ApiError, ApiMountCmd, ApiPrefetchFilesCmd, ApiRequest, ApiResponse,
+    ApiResponsePayload, ApiResult, BackendConfigV2, BlobCacheEntry, BlobCacheObjectId, DaemonConf,
+    DaemonErrorKind, MetricsErrorKind,
 };
+#[cfg(feature = "dedup")]
+use nydus_storage::cache::CasMgr;
 use nydus_utils::metrics;
 
 use crate::DAEMON_CONTROLLER;
@@ -47,6 +50,7 @@ impl ApiServer {
             ApiRequest::Umount(mountpoint) => self.do_umount(mountpoint),
             ApiRequest::ExportBackendMetrics(id) => Self::export_backend_metrics(id),
             ApiRequest::ExportBlobcacheMetrics(id) => Self::export_blobcache_metrics(id),
+            ApiRequest::ExportPrometheusMetrics => self.export_prometheus_metrics(),
 
             // Nydus API v1
             ApiRequest::ExportFsGlobalMetrics(id) => Self::export_global_metrics(id),
@@ -54,7 +58,15 @@ impl ApiServer {
                 Self::export_files_metrics(id, latest_read_files)
             }
             ApiRequest::ExportFsAccessPatterns(id) => Self::export_access_patterns(id),
+            ApiRequest::ExportFsPrefetchList(id) => self.export_prefetch_list(id),
+            ApiRequest::PrefetchFiles(mountpoint, cmd) => self.prefetch_files(mountpoint, cmd),
+            ApiRequest::GetPrefetchProgress(mountpoint, job_id) => {
+                self.export_prefetch_progress(mountpoint, job_id)
+            }
             ApiRequest::ExportFsBackendInfo(mountpoint) => self.backend_info(&mountpoint),
+            ApiRequest::UpdateFsBackend(mountpoint, config) => {
+                self.update_backend(&mountpoint, &config)
+            }
             ApiRequest::ExportFsInflightMetrics => self.export_inflight_metrics(),
 
             // Nydus API v2
@@ -63,6 +75,8 @@ impl ApiServer {
             ApiRequest::CreateBlobObject(entry) => self.create_blob_cache_entry(&entry),
             ApiRequest::DeleteBlobObject(param) => self.remove_blob_cache_entry(&param),
             ApiRequest::DeleteBlobFile(blob_id) => self.blob_cache_gc(blob_id),
+            ApiRequest::GetCasStats => self.cas_stats(),
+            ApiRequest::CasGc => self.cas_gc(),
         };
 
         self.respond(resp);
@@ -154,6 +168,32 @@ impl ApiServer {
             .map_err(|e| ApiError::Metrics(MetricsErrorKind::Stats(e)))
     }
 
+    fn export_prefetch_list(&self, id: Option<String>) -> ApiResponse {
+        let list = self
+            .get_default_fs_service()?
+            .export_prefetch_list(id.as_deref())
+            .map_err(|e| ApiError::Metrics(MetricsErrorKind::Daemon(e.into())))?;
+        Ok(ApiResponsePayload::FsFilesPatterns(list))
+    }
+
+    fn prefetch_files(&self, mountpoint: String, cmd: ApiPrefetchFilesCmd) -> ApiResponse {
+        let job_id = self
+            .get_default_fs_service()?
+            .prefetch_files(&mountpoint, &cmd.files)
+            .map_err(|e| ApiError::Prefetch(e.into()))?;
+        Ok(ApiResponsePayload::FsPrefetchJob(
+            serde_json::json!({ "job_id": job_id }).to_string(),
+        ))
+    }
+
+    fn export_prefetch_progress(&self, mountpoint: String, job_id: u64) -> ApiResponse {
+        let progress = self
+            .get_default_fs_service()?
+            .export_prefetch_progress(&mountpoint, job_id)
+            .map_err(|e| ApiError::Prefetch(e.into()))?;
+        Ok(ApiResponsePayload::FsPrefetchJob(progress))
+    }
+
     fn export_backend_metrics(id: Option<String>) -> ApiResponse {
         metrics::export_backend_metrics(&id)
             .map(ApiResponsePayload::BackendMetrics)
@@ -166,6 +206,16 @@ impl ApiServer {
             .map_err(|e| ApiError::Metrics(MetricsErrorKind::Stats(e)))
     }
 
+    fn export_prometheus_metrics(&self) -> ApiResponse {
+        let inflight_ops = self
+            .get_default_fs_service()
+            .ok()
+            .and_then(|fs| fs.inflight_ops_count());
+        metrics::export_prometheus_metrics(inflight_ops)
+            .map(ApiResponsePayload::PrometheusMetrics)
+            .map_err(|e| ApiError::Metrics(MetricsErrorKind::Stats(e)))
+    }
+
     #[inline]
     fn get_daemon_object(&self) -> std::result::Result<Arc<dyn NydusDaemon>, ApiError> {
         Ok(DAEMON_CONTROLLER.get_daemon())
@@ -179,6 +229,13 @@ impl ApiServer {
         Ok(ApiResponsePayload::FsBackendInfo(info))
     }
 
+    fn update_backend(&self, mountpoint: &str, config: &BackendConfigV2) -> ApiResponse {
+        self.get_default_fs_service()?
+            .update_backend(mountpoint, config)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::MountFilesystem(e.into()))
+    }
+
     /// Detect if there is fop being hang.
     /// `ApiResponsePayload::Empty` will be converted to http status code 204, which means
     /// there is no requests being processed right now.
@@ -221,11 +278,14 @@ impl ApiServer {
     fn do_mount(&self, mountpoint: String, cmd: ApiMountCmd) -> ApiResponse {
         let fs_type = FsBackendType::from_str(&cmd.fs_type)
             .map_err(|e| ApiError::MountFilesystem(e.into()))?;
+        let config = cmd
+            .get_config()
+            .map_err(|e| ApiError::MountFilesystem(DaemonErrorKind::Other(e.to_string())))?;
         let fs = self.get_default_fs_service()?;
         fs.mount(FsBackendMountCmd {
             fs_type,
             mountpoint,
-            config: cmd.config,
+            config,
             source: cmd.source,
             prefetch_files: cmd.prefetch_files,
         })
@@ -236,11 +296,14 @@ impl ApiServer {
     fn do_remount(&self, mountpoint: String, cmd: ApiMountCmd) -> ApiResponse {
         let fs_type = FsBackendType::from_str(&cmd.fs_type)
             .map_err(|e| ApiError::MountFilesystem(e.into()))?;
+        let config = cmd
+            .get_config()
+            .map_err(|e| ApiError::MountFilesystem(DaemonErrorKind::Other(e.to_string())))?;
         self.get_default_fs_service()?
             .remount(FsBackendMountCmd {
                 fs_type,
                 mountpoint,
-                config: cmd.config,
+                config,
                 source: cmd.source,
                 prefetch_files: cmd.prefetch_files,
             })
@@ -311,9 +374,61 @@ impl ApiServer {
 
     fn blob_cache_gc(&self, blob_id: String) -> ApiResponse {
         self.get_daemon_object()?
-            .delete_blob(blob_id)
-            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
-            .map(|_| ApiResponsePayload::Empty)
+            .delete_blob(blob_id.clone())
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))?;
+
+        // Chunks in the removed cache files can't be used for deduplication any more.
+        #[cfg(feature = "dedup")]
+        if let Some(mgr) = CasMgr::get_singleton() {
+            match mgr.delete_blob(&blob_id) {
+                Ok(stats) => info!(
+                    "removed {} blob records and {} chunk records of blob {} from cas database",
+                    stats.removed_blobs, stats.removed_chunks, blob_id
+                ),
+                Err(e) => warn!("failed to remove blob {} from cas database, {}", blob_id, e),
+            }
+        }
+
+        Ok(ApiResponsePayload::Empty)
+    }
+
+    #[cfg(feature = "dedup")]
+    fn cas_stats(&self) -> ApiResponse {
+        let mgr = CasMgr::get_singleton()
+            .ok_or(ApiError::DaemonAbnormal(DaemonErrorKind::Unsupported))?;
+        let stats = mgr
+            .stats()
+            .map_err(|e| ApiError::DaemonAbnormal(DaemonErrorKind::Other(e.to_string())))?;
+        serde_json::to_string(&stats)
+            .map(ApiResponsePayload::CasStats)
+            .map_err(|e| ApiError::DaemonAbnormal(DaemonErrorKind::Serde(e)))
+    }
+
+    #[cfg(not(feature = "dedup"))]
+    fn cas_stats(&self) -> ApiResponse {
+        Err(ApiError::DaemonAbnormal(DaemonErrorKind::Unsupported))
+    }
+
+    #[cfg(feature = "dedup")]
+    fn cas_gc(&self) -> ApiResponse {
+        let mgr = CasMgr::get_singleton()
+            .ok_or(ApiError::DaemonAbnormal(DaemonErrorKind::Unsupported))?;
+        let stats = mgr
+            .gc()
+            .and_then(|stats| mgr.compact().map(|_| stats))
+            .map_err(|e| ApiError::DaemonAbnormal(DaemonErrorKind::Other(e.to_string())))?;
+        info!(
+            "cas gc: removed {} blob records and {} chunk records",
+            stats.removed_blobs, stats.removed_chunks
+        );
+        serde_json::to_string(&stats)
+            .map(ApiResponsePayload::CasStats)
+            .map_err(|e| ApiError::DaemonAbnormal(DaemonErrorKind::Serde(e)))
+    }
+
+    #[cfg(not(feature = "dedup"))]
+    fn cas_gc(&self) -> ApiResponse {
+        Err(ApiError::DaemonAbnormal(DaemonErrorKind::Unsupported))
     }
 
     fn do_start(&self) -> ApiResponse {
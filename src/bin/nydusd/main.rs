@@ -297,6 +297,8 @@ fn prepare_commandline_options() -> Command {
     let cmdline = append_virtiofs_subcmd_options(cmdline);
     #[cfg(feature = "block-nbd")]
     let cmdline = self::nbd::append_nbd_subcmd_options(cmdline);
+    #[cfg(feature = "block-ublk")]
+    let cmdline = self::ublk::append_ublk_subcmd_options(cmdline);
     append_singleton_subcmd_options(cmdline)
 }
 
@@ -596,41 +598,104 @@ fn process_singleton_arguments(
     Ok(())
 }
 
+#[cfg(any(feature = "block-nbd", feature = "block-ublk"))]
+const LOCALFS_DIR_HELP: &str =
+    "Path to the `localfs` working directory, which also enables the `localfs` storage backend";
+
+#[cfg(any(feature = "block-nbd", feature = "block-ublk"))]
+fn append_block_source_options(cmd: Command) -> Command {
+    cmd.arg(
+        Arg::new("bootstrap")
+            .long("bootstrap")
+            .short('B')
+            .help("Path to the RAFS filesystem metadata file")
+            .requires("localfs-dir")
+            .conflicts_with("config"),
+    )
+    .arg(
+        Arg::new("localfs-dir")
+            .long("localfs-dir")
+            .requires("bootstrap")
+            .short('D')
+            .help(LOCALFS_DIR_HELP)
+            .conflicts_with("config"),
+    )
+}
+
+/// Build the blob cache entry for the RAFS v6 image to be exported as a block device.
+#[cfg(any(feature = "block-nbd", feature = "block-ublk"))]
+fn parse_block_blob_entry(args: &SubCmdArgs, domain_id: &str) -> Result<nydus_api::BlobCacheEntry> {
+    use nydus_api::BlobCacheEntry;
+    use std::str::FromStr;
+
+    let mut entry = if let Some(bootstrap) = args.value_of("bootstrap") {
+        let dir = args
+            .value_of("localfs-dir")
+            .ok_or_else(|| einval!("option `-D/--localfs-dir` is required by `--boootstrap`"))?;
+        let config = r#"
+        {
+            "type": "bootstrap",
+            "id": "disk-default",
+            "domain_id": "DOMAIN_ID",
+            "config_v2": {
+                "version": 2,
+                "id": "DOMAIN_ID-factory",
+                "backend": {
+                    "type": "localfs",
+                    "localfs": {
+                        "dir": "LOCAL_FS_DIR"
+                    }
+                },
+                "cache": {
+                    "type": "filecache",
+                    "filecache": {
+                        "work_dir": "LOCAL_FS_DIR"
+                    }
+                },
+                "metadata_path": "META_FILE_PATH"
+            }
+        }"#;
+        let config = config
+            .replace("DOMAIN_ID", domain_id)
+            .replace("LOCAL_FS_DIR", dir)
+            .replace("META_FILE_PATH", bootstrap);
+        BlobCacheEntry::from_str(&config)?
+    } else if let Some(v) = args.value_of("config") {
+        BlobCacheEntry::from_file(v)?
+    } else {
+        return Err(einval!(
+            "both option `-C/--config` and `-B/--bootstrap` are missing"
+        ));
+    };
+    if !entry.prepare_configuration_info() {
+        return Err(einval!(
+            "invalid blob cache entry configuration information"
+        ));
+    }
+    if !entry.validate() {
+        return Err(einval!(
+            "invalid blob cache entry configuration information"
+        ));
+    }
+
+    Ok(entry)
+}
+
 #[cfg(feature = "block-nbd")]
 mod nbd {
     use super::*;
-    use nydus_api::BlobCacheEntry;
     use nydus_service::block_nbd::create_nbd_daemon;
-    use std::str::FromStr;
 
     pub(super) fn append_nbd_subcmd_options(cmd: Command) -> Command {
         let subcmd = Command::new("nbd")
             .about("Export a RAFS v6 image as a block device through NBD (Experiment)");
-        let subcmd = subcmd
+        let subcmd = append_block_source_options(subcmd)
             .arg(
                 Arg::new("DEVICE")
                     .help("NBD device node to attach the block device")
                     .required(true)
                     .num_args(1),
             )
-            .arg(
-                Arg::new("bootstrap")
-                    .long("bootstrap")
-                    .short('B')
-                    .help("Path to the RAFS filesystem metadata file")
-                    .requires("localfs-dir")
-                    .conflicts_with("config"),
-            )
-            .arg(
-                Arg::new("localfs-dir")
-                    .long("localfs-dir")
-                    .requires("bootstrap")
-                    .short('D')
-                    .help(
-                        "Path to the `localfs` working directory, which also enables the `localfs` storage backend"
-                    )
-                    .conflicts_with("config"),
-            )
             .arg(
                 Arg::new("threads")
                     .long("threads")
@@ -647,54 +712,7 @@ mod nbd {
         bti: BuildTimeInfo,
         _apisock: Option<&str>,
     ) -> Result<()> {
-        let mut entry = if let Some(bootstrap) = args.value_of("bootstrap") {
-            let dir = args.value_of("localfs-dir").ok_or_else(|| {
-                einval!("option `-D/--localfs-dir` is required by `--boootstrap`")
-            })?;
-            let config = r#"
-            {
-                "type": "bootstrap",
-                "id": "disk-default",
-                "domain_id": "block-nbd",
-                "config_v2": {
-                    "version": 2,
-                    "id": "block-nbd-factory",
-                    "backend": {
-                        "type": "localfs",
-                        "localfs": {
-                            "dir": "LOCAL_FS_DIR"
-                        }
-                    },
-                    "cache": {
-                        "type": "filecache",
-                        "filecache": {
-                            "work_dir": "LOCAL_FS_DIR"
-                        }
-                    },
-                    "metadata_path": "META_FILE_PATH"
-                }
-            }"#;
-            let config = config
-                .replace("LOCAL_FS_DIR", dir)
-                .replace("META_FILE_PATH", bootstrap);
-            BlobCacheEntry::from_str(&config)?
-        } else if let Some(v) = args.value_of("config") {
-            BlobCacheEntry::from_file(v)?
-        } else {
-            return Err(einval!(
-                "both option `-C/--config` and `-B/--bootstrap` are missing"
-            ));
-        };
-        if !entry.prepare_configuration_info() {
-            return Err(einval!(
-                "invalid blob cache entry configuration information"
-            ));
-        }
-        if entry.validate() == false {
-            return Err(einval!(
-                "invalid blob cache entry configuration information"
-            ));
-        }
+        let entry = parse_block_blob_entry(&args, "block-nbd")?;
 
         // Safe to unwrap because `DEVICE` is mandatory option.
         let device = args.value_of("DEVICE").unwrap().to_string();
@@ -728,6 +746,82 @@ mod nbd {
     }
 }
 
+#[cfg(feature = "block-ublk")]
+mod ublk {
+    use super::*;
+    use nydus_service::block_ublk::create_ublk_daemon;
+
+    pub(super) fn append_ublk_subcmd_options(cmd: Command) -> Command {
+        let subcmd = Command::new("ublk").about(
+            "Export a RAFS v6 image as a block device through Linux ublk driver (Experiment)",
+        );
+        let subcmd = append_block_source_options(subcmd)
+            .arg(
+                Arg::new("dev-id")
+                    .long("dev-id")
+                    .help("Id of the ublk device to create, allocated by kernel if not specified")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("queues")
+                    .long("queues")
+                    .default_value("1")
+                    .help("Number of ublk hardware queues, each served by a worker thread")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("queue-depth")
+                    .long("queue-depth")
+                    .default_value("128")
+                    .help("Number of IO requests in flight for each ublk hardware queue")
+                    .required(false),
+            );
+        cmd.subcommand(subcmd)
+    }
+
+    pub(super) fn process_ublk_service(
+        args: SubCmdArgs,
+        bti: BuildTimeInfo,
+        _apisock: Option<&str>,
+    ) -> Result<()> {
+        let entry = parse_block_blob_entry(&args, "block-ublk")?;
+        let dev_id = args
+            .value_of("dev-id")
+            .map(|v| v.parse::<u32>())
+            .transpose()
+            .map_err(|e| einval!(format!("invalid ublk device id, {}", e)))?;
+        // Safe to unwrap because they have default values.
+        let queues: u16 = args
+            .value_of("queues")
+            .unwrap()
+            .parse()
+            .map_err(|e| einval!(format!("invalid number of ublk queues, {}", e)))?;
+        let depth: u16 = args
+            .value_of("queue-depth")
+            .unwrap()
+            .parse()
+            .map_err(|e| einval!(format!("invalid ublk queue depth, {}", e)))?;
+        let id = args.value_of("id").map(|id| id.to_string());
+        let supervisor = args.value_of("supervisor").map(|s| s.to_string());
+
+        let daemon = create_ublk_daemon(
+            dev_id,
+            queues,
+            depth,
+            entry,
+            bti,
+            id,
+            supervisor,
+            DAEMON_CONTROLLER.alloc_waker(),
+        )
+        .inspect(|_| info!("ublk daemon started!"))
+        .inspect_err(|e| error!("Failed in starting ublk daemon: {}", e))?;
+        DAEMON_CONTROLLER.set_daemon(daemon);
+
+        Ok(())
+    }
+}
+
 extern "C" fn sig_exit(_sig: std::os::raw::c_int) {
     DAEMON_CONTROLLER.notify_shutdown();
 }
@@ -792,6 +886,13 @@ fn main() -> Result<()> {
             let subargs = SubCmdArgs::new(&args, subargs);
             self::nbd::process_nbd_service(subargs, bti, apisock)?;
         }
+        #[cfg(feature = "block-ublk")]
+        Some("ublk") => {
+            // Safe to unwrap because the subcommand is `ublk`.
+            let subargs = args.subcommand_matches("ublk").unwrap();
+            let subargs = SubCmdArgs::new(&args, subargs);
+            self::ublk::process_ublk_service(subargs, bti, apisock)?;
+        }
         _ => {
             let subargs = SubCmdArgs::new(&args, &args);
             process_fs_service(subargs, bti, apisock, true)?;
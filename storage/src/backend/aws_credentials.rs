@@ -0,0 +1,639 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resolve AWS credentials to sign S3 requests.
+//!
+//! Credentials are resolved in order:
+//! - static access key, secret and optional session token from the configuration.
+//! - web identity token file from the configuration, exchanged for temporary credentials by STS
+//!   `AssumeRoleWithWebIdentity`.
+//! - EC2 instance metadata service (IMDSv2, falling back to IMDSv1), if enabled by configuration.
+//! - static credentials from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+//! - web identity token file from `AWS_WEB_IDENTITY_TOKEN_FILE`, `AWS_ROLE_ARN` and
+//!   `AWS_ROLE_SESSION_NAME`, which are injected by IAM roles for Kubernetes service accounts.
+//!
+//! Temporary credentials are refreshed on demand before they expire.
+
+use std::env;
+use std::fs;
+use std::io::Result;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use nydus_api::S3Config;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::backend::credential_cache::{CredentialCache, Expiration};
+
+const ENV_ACCESS_KEY_ID: &str = "AWS_ACCESS_KEY_ID";
+const ENV_SECRET_ACCESS_KEY: &str = "AWS_SECRET_ACCESS_KEY";
+const ENV_SESSION_TOKEN: &str = "AWS_SESSION_TOKEN";
+const ENV_WEB_IDENTITY_TOKEN_FILE: &str = "AWS_WEB_IDENTITY_TOKEN_FILE";
+const ENV_ROLE_ARN: &str = "AWS_ROLE_ARN";
+const ENV_ROLE_SESSION_NAME: &str = "AWS_ROLE_SESSION_NAME";
+const DEFAULT_STS_ENDPOINT: &str = "https://sts.amazonaws.com";
+const DEFAULT_IMDS_ENDPOINT: &str = "http://169.254.169.254";
+const DEFAULT_ROLE_SESSION_NAME: &str = "nydus";
+const IMDS_TOKEN_TTL_SECONDS: &str = "21600";
+const IMDS_CREDENTIALS_PATH: &str = "/latest/meta-data/iam/security-credentials/";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// AWS credentials to sign requests.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    /// Expiration time of temporary credentials, `None` for long-term credentials.
+    pub expiration: Option<OffsetDateTime>,
+}
+
+impl Expiration for Credentials {
+    fn time_to_live(&self) -> Option<Duration> {
+        self.expiration.map(|e| {
+            (e - OffsetDateTime::now_utc())
+                .try_into()
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[derive(Debug)]
+enum CredentialSource {
+    Static,
+    WebIdentity {
+        token_file: PathBuf,
+        role_arn: String,
+        session_name: String,
+        sts_endpoint: String,
+    },
+    InstanceMetadata {
+        endpoint: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImdsCredentials {
+    #[serde(default)]
+    code: String,
+    access_key_id: String,
+    secret_access_key: String,
+    token: String,
+    expiration: String,
+}
+
+/// Provider of AWS credentials, which refreshes temporary credentials before they expire.
+#[derive(Debug)]
+pub struct CredentialProvider {
+    source: CredentialSource,
+    client: Option<Client>,
+    cache: CredentialCache<Credentials>,
+}
+
+impl CredentialProvider {
+    /// Create a new credential provider according to the S3 configuration and environment.
+    pub fn new(config: &S3Config) -> Result<Self> {
+        if !config.access_key_id.is_empty() || !config.access_key_secret.is_empty() {
+            return Ok(Self::new_static(
+                &config.access_key_id,
+                &config.access_key_secret,
+                &config.session_token,
+            ));
+        }
+        if !config.web_identity_token_file.is_empty() {
+            return Self::new_web_identity(
+                &config.web_identity_token_file,
+                &config.role_arn,
+                &config.role_session_name,
+                &config.sts_endpoint,
+            );
+        }
+        if config.use_instance_metadata {
+            let endpoint = if config.imds_endpoint.is_empty() {
+                DEFAULT_IMDS_ENDPOINT
+            } else {
+                config.imds_endpoint.as_str()
+            };
+            return Self::with_source(CredentialSource::InstanceMetadata {
+                endpoint: endpoint.trim_end_matches('/').to_string(),
+            });
+        }
+
+        let env = |key: &str| env::var(key).unwrap_or_default();
+        let access_key_id = env(ENV_ACCESS_KEY_ID);
+        let secret_access_key = env(ENV_SECRET_ACCESS_KEY);
+        if !access_key_id.is_empty() && !secret_access_key.is_empty() {
+            return Ok(Self::new_static(
+                &access_key_id,
+                &secret_access_key,
+                &env(ENV_SESSION_TOKEN),
+            ));
+        }
+        let token_file = env(ENV_WEB_IDENTITY_TOKEN_FILE);
+        if !token_file.is_empty() {
+            let session_name = if config.role_session_name.is_empty() {
+                env(ENV_ROLE_SESSION_NAME)
+            } else {
+                config.role_session_name.clone()
+            };
+            let role_arn = if config.role_arn.is_empty() {
+                env(ENV_ROLE_ARN)
+            } else {
+                config.role_arn.clone()
+            };
+            return Self::new_web_identity(
+                &token_file,
+                &role_arn,
+                &session_name,
+                &config.sts_endpoint,
+            );
+        }
+
+        Ok(Self::new_static("", "", ""))
+    }
+
+    fn new_static(access_key_id: &str, secret_access_key: &str, session_token: &str) -> Self {
+        let credentials = Credentials {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: if session_token.is_empty() {
+                None
+            } else {
+                Some(session_token.to_string())
+            },
+            expiration: None,
+        };
+        CredentialProvider {
+            source: CredentialSource::Static,
+            client: None,
+            cache: CredentialCache::new(Some(credentials)),
+        }
+    }
+
+    fn new_web_identity(
+        token_file: &str,
+        role_arn: &str,
+        session_name: &str,
+        sts_endpoint: &str,
+    ) -> Result<Self> {
+        if role_arn.is_empty() {
+            return Err(einval!(
+                "role_arn is required to assume role with web identity"
+            ));
+        }
+        Self::with_source(CredentialSource::WebIdentity {
+            token_file: PathBuf::from(token_file),
+            role_arn: role_arn.to_string(),
+            session_name: if session_name.is_empty() {
+                DEFAULT_ROLE_SESSION_NAME.to_string()
+            } else {
+                session_name.to_string()
+            },
+            sts_endpoint: if sts_endpoint.is_empty() {
+                DEFAULT_STS_ENDPOINT.to_string()
+            } else {
+                sts_endpoint.to_string()
+            },
+        })
+    }
+
+    fn with_source(source: CredentialSource) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .connect_timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(|e| eother!(format!("failed to create http client, {}", e)))?;
+        Ok(CredentialProvider {
+            source,
+            client: Some(client),
+            cache: CredentialCache::new(None),
+        })
+    }
+
+    /// Get valid credentials, refreshing temporary credentials if they are about to expire.
+    pub fn credentials(&self) -> Result<Credentials> {
+        self.cache.get(|| {
+            let credentials = self.fetch()?;
+            debug!(
+                "s3: refreshed credentials {}, expiration {:?}",
+                credentials.access_key_id, credentials.expiration
+            );
+            Ok(credentials)
+        })
+    }
+
+    fn fetch(&self) -> Result<Credentials> {
+        let client = match self.client.as_ref() {
+            Some(v) => v,
+            None => return Err(eother!("no credential service available")),
+        };
+        match &self.source {
+            CredentialSource::Static => Err(eother!("static credentials can't be refreshed")),
+            CredentialSource::WebIdentity {
+                token_file,
+                role_arn,
+                session_name,
+                sts_endpoint,
+            } => Self::assume_role_with_web_identity(
+                client,
+                token_file,
+                role_arn,
+                session_name,
+                sts_endpoint,
+            ),
+            CredentialSource::InstanceMetadata { endpoint } => {
+                Self::instance_metadata(client, endpoint)
+            }
+        }
+    }
+
+    fn assume_role_with_web_identity(
+        client: &Client,
+        token_file: &PathBuf,
+        role_arn: &str,
+        session_name: &str,
+        sts_endpoint: &str,
+    ) -> Result<Credentials> {
+        // The token file is rotated by kubelet, so always read the latest token.
+        let token = fs::read_to_string(token_file).map_err(|e| {
+            eother!(format!(
+                "failed to read web identity token file {:?}, {}",
+                token_file, e
+            ))
+        })?;
+        let params = [
+            ("Action", "AssumeRoleWithWebIdentity"),
+            ("Version", "2011-06-15"),
+            ("RoleArn", role_arn),
+            ("RoleSessionName", session_name),
+            ("WebIdentityToken", token.trim()),
+        ];
+        let body = client
+            .post(sts_endpoint)
+            .form(&params)
+            .send()
+            .and_then(|resp| resp.error_for_status())
+            .and_then(|resp| resp.text())
+            .map_err(|e| eother!(format!("failed to assume role with web identity, {}", e)))?;
+
+        let field = |tag: &str| {
+            xml_element(&body, tag).ok_or_else(|| {
+                eother!(format!(
+                    "invalid AssumeRoleWithWebIdentity response, missing {}",
+                    tag
+                ))
+            })
+        };
+        Ok(Credentials {
+            access_key_id: field("AccessKeyId")?,
+            secret_access_key: field("SecretAccessKey")?,
+            session_token: Some(field("SessionToken")?),
+            expiration: Some(parse_time(&field("Expiration")?)?),
+        })
+    }
+
+    fn instance_metadata(client: &Client, endpoint: &str) -> Result<Credentials> {
+        // Try IMDSv2 session token first, and fall back to IMDSv1 if it's not supported.
+        let token = client
+            .put(format!("{}/latest/api/token", endpoint))
+            .header(
+                "X-aws-ec2-metadata-token-ttl-seconds",
+                IMDS_TOKEN_TTL_SECONDS,
+            )
+            .send()
+            .and_then(|resp| resp.error_for_status())
+            .and_then(|resp| resp.text())
+            .ok();
+        let get = |path: &str| {
+            let mut req = client.get(format!("{}{}", endpoint, path));
+            if let Some(token) = token.as_ref() {
+                req = req.header("X-aws-ec2-metadata-token", token.as_str());
+            }
+            req.send()
+                .and_then(|resp| resp.error_for_status())
+                .and_then(|resp| resp.text())
+                .map_err(|e| {
+                    eother!(format!(
+                        "failed to get credentials from instance metadata, {}",
+                        e
+                    ))
+                })
+        };
+
+        let roles = get(IMDS_CREDENTIALS_PATH)?;
+        let role = roles
+            .lines()
+            .map(|l| l.trim())
+            .find(|l| !l.is_empty())
+            .ok_or_else(|| eother!("no IAM role attached to the instance"))?;
+        let body = get(&format!("{}{}", IMDS_CREDENTIALS_PATH, role))?;
+        let resp: ImdsCredentials = serde_json::from_str(&body)
+            .map_err(|e| eother!(format!("invalid credentials from instance metadata, {}", e)))?;
+        if !resp.code.is_empty() && resp.code != "Success" {
+            return Err(eother!(format!(
+                "failed to get credentials from instance metadata, code {}",
+                resp.code
+            )));
+        }
+
+        Ok(Credentials {
+            access_key_id: resp.access_key_id,
+            secret_access_key: resp.secret_access_key,
+            session_token: Some(resp.token),
+            expiration: Some(parse_time(&resp.expiration)?),
+        })
+    }
+}
+
+// Get text of the first XML element named `tag`.
+fn xml_element(body: &str, tag: &str) -> Option<String> {
+    let start_tag = format!("<{}>", tag);
+    let end_tag = format!("</{}>", tag);
+    let start = body.find(&start_tag)? + start_tag.len();
+    let end = start + body[start..].find(&end_tag)?;
+    Some(body[start..end].trim().to_string())
+}
+
+fn parse_time(value: &str) -> Result<OffsetDateTime> {
+    OffsetDateTime::parse(value, &Rfc3339)
+        .map_err(|e| eother!(format!("invalid expiration time {}, {}", value, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use time::format_description::well_known::Rfc3339;
+    use time::Duration;
+    use vmm_sys_util::tempfile::TempFile;
+
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    // Serve HTTP requests with responses generated by `handler`, recording the requests.
+    fn start_server<F>(handler: F) -> (String, Requests)
+    where
+        F: Fn(&str) -> (u16, String) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut req = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let text = String::from_utf8_lossy(&req).to_string();
+                let size = text
+                    .to_lowercase()
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length: ").map(|v| v.to_string()))
+                    .map(|v| v.trim().parse::<usize>().unwrap())
+                    .unwrap_or_default();
+                let pos = req.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+                while req.len() < pos + size {
+                    let n = stream.read(&mut buf).unwrap();
+                    req.extend_from_slice(&buf[..n]);
+                }
+                let text = String::from_utf8_lossy(&req).to_string();
+                let (status, body) = handler(&text);
+                recorded.lock().unwrap().push(text);
+                let resp = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(resp.as_bytes());
+            }
+        });
+        (addr, requests)
+    }
+
+    fn expiration(secs: i64) -> String {
+        (OffsetDateTime::now_utc() + Duration::seconds(secs))
+            .replace_nanosecond(0)
+            .unwrap()
+            .format(&Rfc3339)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_static_credentials() {
+        let config = S3Config {
+            access_key_id: "key".to_string(),
+            access_key_secret: "secret".to_string(),
+            session_token: "token".to_string(),
+            ..Default::default()
+        };
+        let provider = CredentialProvider::new(&config).unwrap();
+        let credentials = provider.credentials().unwrap();
+        assert_eq!(credentials.access_key_id, "key");
+        assert_eq!(credentials.secret_access_key, "secret");
+        assert_eq!(credentials.session_token, Some("token".to_string()));
+        assert!(credentials.expiration.is_none());
+
+        let config = S3Config {
+            web_identity_token_file: "/nonexist".to_string(),
+            ..Default::default()
+        };
+        assert!(CredentialProvider::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_web_identity_credentials() {
+        let expire = Arc::new(Mutex::new(expiration(3600)));
+        let expire2 = expire.clone();
+        let (addr, requests) = start_server(move |_| {
+            let body = format!(
+                r#"<AssumeRoleWithWebIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleWithWebIdentityResult>
+    <Credentials>
+      <AccessKeyId>ASIAKEY</AccessKeyId>
+      <SecretAccessKey>secret</SecretAccessKey>
+      <SessionToken>session-token</SessionToken>
+      <Expiration>{}</Expiration>
+    </Credentials>
+  </AssumeRoleWithWebIdentityResult>
+</AssumeRoleWithWebIdentityResponse>"#,
+                expire2.lock().unwrap()
+            );
+            (200, body)
+        });
+
+        let token_file = TempFile::new().unwrap();
+        fs::write(token_file.as_path(), "jwt-token\n").unwrap();
+        let config = S3Config {
+            web_identity_token_file: token_file.as_path().to_str().unwrap().to_string(),
+            role_arn: "arn:aws:iam::123456789012:role/nydus".to_string(),
+            sts_endpoint: addr,
+            ..Default::default()
+        };
+        let provider = CredentialProvider::new(&config).unwrap();
+        let credentials = provider.credentials().unwrap();
+        assert_eq!(credentials.access_key_id, "ASIAKEY");
+        assert_eq!(credentials.secret_access_key, "secret");
+        assert_eq!(credentials.session_token, Some("session-token".to_string()));
+        {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            assert!(requests[0].starts_with("POST / "));
+            assert!(requests[0].contains("Action=AssumeRoleWithWebIdentity"));
+            assert!(
+                requests[0].contains("RoleArn=arn%3Aaws%3Aiam%3A%3A123456789012%3Arole%2Fnydus")
+            );
+            assert!(requests[0].contains("RoleSessionName=nydus"));
+            assert!(
+                requests[0].contains("WebIdentityToken=jwt-token&")
+                    || requests[0].ends_with("WebIdentityToken=jwt-token")
+            );
+        }
+
+        // Credentials are cached until they are about to expire.
+        provider.credentials().unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // Force to refresh credentials expiring within the refresh window.
+        *expire.lock().unwrap() = expiration(60);
+        let mut credentials = provider.credentials().unwrap();
+        credentials.expiration = Some(OffsetDateTime::now_utc() + Duration::seconds(60));
+        provider.cache.set(credentials);
+        provider.credentials().unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        // Avoid refreshing repeatedly, the credentials still expire within the window.
+        provider.credentials().unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_instance_metadata_credentials() {
+        let expire = expiration(3600);
+        let (addr, requests) = start_server(move |req| {
+            if req.starts_with("PUT /latest/api/token ") {
+                (200, "imds-token".to_string())
+            } else if req.starts_with(&format!("GET {} ", IMDS_CREDENTIALS_PATH)) {
+                (200, "nydus-role\n".to_string())
+            } else if req.starts_with(&format!("GET {}nydus-role ", IMDS_CREDENTIALS_PATH)) {
+                let body = format!(
+                    r#"{{"Code":"Success","Type":"AWS-HMAC","AccessKeyId":"ASIAIMDS","SecretAccessKey":"imds-secret","Token":"imds-session","Expiration":"{}"}}"#,
+                    expire
+                );
+                (200, body)
+            } else {
+                (404, String::new())
+            }
+        });
+
+        let config = S3Config {
+            use_instance_metadata: true,
+            imds_endpoint: addr,
+            ..Default::default()
+        };
+        let provider = CredentialProvider::new(&config).unwrap();
+        let credentials = provider.credentials().unwrap();
+        assert_eq!(credentials.access_key_id, "ASIAIMDS");
+        assert_eq!(credentials.secret_access_key, "imds-secret");
+        assert_eq!(credentials.session_token, Some("imds-session".to_string()));
+        assert_eq!(
+            credentials.expiration,
+            Some(OffsetDateTime::parse(&expiration_str(&credentials), &Rfc3339).unwrap())
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0]
+            .to_lowercase()
+            .contains("x-aws-ec2-metadata-token-ttl-seconds: 21600"));
+        assert!(requests[1]
+            .to_lowercase()
+            .contains("x-aws-ec2-metadata-token: imds-token"));
+        assert!(requests[2]
+            .to_lowercase()
+            .contains("x-aws-ec2-metadata-token: imds-token"));
+    }
+
+    fn expiration_str(credentials: &Credentials) -> String {
+        credentials.expiration.unwrap().format(&Rfc3339).unwrap()
+    }
+
+    #[test]
+    fn test_credentials_refresh_failure() {
+        let (addr, _) = start_server(|_| (500, String::new()));
+        let config = S3Config {
+            use_instance_metadata: true,
+            imds_endpoint: addr,
+            ..Default::default()
+        };
+        let provider = CredentialProvider::new(&config).unwrap();
+        assert!(provider.credentials().is_err());
+
+        // Credentials which haven't expired yet are used if refresh fails.
+        let credentials = Credentials {
+            access_key_id: "old".to_string(),
+            expiration: Some(OffsetDateTime::now_utc() + Duration::seconds(60)),
+            ..Default::default()
+        };
+        provider.cache.set(credentials.clone());
+        assert_eq!(provider.credentials().unwrap(), credentials);
+
+        provider.cache.set(Credentials {
+            expiration: Some(OffsetDateTime::now_utc() - Duration::seconds(1)),
+            ..credentials
+        });
+        assert!(provider.credentials().is_err());
+    }
+
+    #[test]
+    fn test_credentials_refresh_without_lock() {
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let started_tx = Mutex::new(started_tx);
+        let release_rx = Mutex::new(release_rx);
+        let (addr, _) = start_server(move |_| {
+            started_tx.lock().unwrap().send(()).unwrap();
+            let _ = release_rx.lock().unwrap().recv();
+            (500, String::new())
+        });
+        let config = S3Config {
+            use_instance_metadata: true,
+            imds_endpoint: addr,
+            ..Default::default()
+        };
+        let provider = Arc::new(CredentialProvider::new(&config).unwrap());
+        let credentials = Credentials {
+            access_key_id: "old".to_string(),
+            expiration: Some(OffsetDateTime::now_utc() + Duration::seconds(60)),
+            ..Default::default()
+        };
+        provider.cache.set(credentials.clone());
+
+        let provider2 = provider.clone();
+        let refresher = thread::spawn(move || provider2.credentials());
+        started_rx.recv().unwrap();
+        // Cached credentials are served while another thread is fetching new ones.
+        assert_eq!(provider.credentials().unwrap(), credentials);
+        drop(release_tx);
+        assert_eq!(refresher.join().unwrap().unwrap(), credentials);
+    }
+
+    #[test]
+    fn test_xml_element() {
+        let body = "<A><B> value </B><C></C></A>";
+        assert_eq!(xml_element(body, "B"), Some("value".to_string()));
+        assert_eq!(xml_element(body, "C"), Some(String::new()));
+        assert_eq!(xml_element(body, "D"), None);
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to access blobs on Azure Blob Storage.
+//!
+//! Requests are authorized with the storage account shared key, or with a shared access
+//! signature (SAS) token appended to the request url if no account key is configured.
+
+use std::collections::BTreeMap;
+use std::io::Result;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use sha2::Sha256;
+use url::Url;
+
+use nydus_api::AzureConfig;
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::connection::{Connection, ConnectionConfig};
+use crate::backend::object_storage::{ObjectStorage, ObjectStorageState};
+
+const HEADER_AUTHORIZATION: &str = "Authorization";
+const HEADER_MS_DATE: &str = "x-ms-date";
+const HEADER_MS_VERSION: &str = "x-ms-version";
+const HEADER_MS_RANGE: &str = "x-ms-range";
+const AZURE_API_VERSION: &str = "2021-08-06";
+const AZURE_DEFAULT_ENDPOINT_SUFFIX: &str = "blob.core.windows.net";
+// Standard HTTP headers included in the string to sign, in order.
+const SIGNED_STANDARD_HEADERS: [&str; 11] = [
+    "content-encoding",
+    "content-language",
+    "content-length",
+    "content-md5",
+    "content-type",
+    "date",
+    "if-modified-since",
+    "if-match",
+    "if-none-match",
+    "if-unmodified-since",
+    "range",
+];
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug)]
+pub struct AzureState {
+    account_name: String,
+    account_key: Vec<u8>,
+    sas_token: String,
+    scheme: String,
+    endpoint: String,
+    container_name: String,
+    object_prefix: String,
+    retry_limit: u8,
+}
+
+impl AzureState {
+    // Build the string to sign for shared key authorization.
+    fn string_to_sign(&self, verb: &Method, headers: &HeaderMap, url: &str) -> Result<String> {
+        let url = Url::parse(url).map_err(|e| einval!(e))?;
+        let mut data = vec![verb.as_str().to_string()];
+
+        for name in SIGNED_STANDARD_HEADERS {
+            let value = match headers.get(name) {
+                Some(v) => v.to_str().map_err(|e| einval!(e))?,
+                None => "",
+            };
+            // Content-Length must be empty if it's zero.
+            if name == "content-length" && value == "0" {
+                data.push(String::new());
+            } else {
+                data.push(value.to_string());
+            }
+        }
+
+        let mut ms_headers = BTreeMap::new();
+        for (name, value) in headers.iter() {
+            let name = name.as_str().to_lowercase();
+            if name.starts_with("x-ms-") {
+                let value = value.to_str().map_err(|e| einval!(e))?;
+                ms_headers.insert(name, value.trim().to_string());
+            }
+        }
+        for (name, value) in ms_headers {
+            data.push(format!("{}:{}", name, value));
+        }
+
+        let mut resource = format!("/{}{}", self.account_name, url.path());
+        let mut params: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, value) in url.query_pairs() {
+            params
+                .entry(name.to_lowercase())
+                .or_default()
+                .push(value.to_string());
+        }
+        for (name, mut values) in params {
+            values.sort();
+            resource.push_str(&format!("\n{}:{}", name, values.join(",")));
+        }
+        data.push(resource);
+
+        Ok(data.join("\n"))
+    }
+
+    fn signature(&self, string_to_sign: &str) -> Result<String> {
+        let hmac = HmacSha256::new_from_slice(&self.account_key)
+            .map_err(|e| einval!(e))?
+            .chain_update(string_to_sign.as_bytes())
+            .finalize()
+            .into_bytes();
+        Ok(base64::engine::general_purpose::STANDARD.encode(hmac))
+    }
+}
+
+impl ObjectStorageState for AzureState {
+    fn url(&self, object_key: &str, query: &[&str]) -> (String, String) {
+        let resource = format!(
+            "/{}/{}{}",
+            self.container_name, self.object_prefix, object_key
+        );
+        let mut query = query.iter().map(|q| q.to_string()).collect::<Vec<_>>();
+        if self.account_key.is_empty() && !self.sas_token.is_empty() {
+            query.push(self.sas_token.trim_start_matches('?').to_string());
+        }
+        let url = if query.is_empty() {
+            format!("{}://{}{}", self.scheme, self.endpoint, resource)
+        } else {
+            format!(
+                "{}://{}{}?{}",
+                self.scheme,
+                self.endpoint,
+                resource,
+                query.join("&")
+            )
+        };
+        (resource, url)
+    }
+
+    /// generate azure request signature
+    fn sign(
+        &self,
+        verb: Method,
+        headers: &mut HeaderMap,
+        _: &str,
+        full_resource_url: &str,
+    ) -> Result<()> {
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        headers.insert(HEADER_MS_DATE, date.parse().map_err(|e| einval!(e))?);
+        headers.insert(
+            HEADER_MS_VERSION,
+            AZURE_API_VERSION.parse().map_err(|e| einval!(e))?,
+        );
+        // Requests are authorized by the SAS token in url, or anonymous access to public blobs.
+        if self.account_key.is_empty() {
+            return Ok(());
+        }
+
+        let string_to_sign = self.string_to_sign(&verb, headers, full_resource_url)?;
+        let authorization = format!(
+            "SharedKey {}:{}",
+            self.account_name,
+            self.signature(&string_to_sign)?
+        );
+        headers.insert(
+            HEADER_AUTHORIZATION,
+            authorization.parse().map_err(|e| einval!(e))?,
+        );
+
+        Ok(())
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.retry_limit
+    }
+
+    fn range_header(&self) -> &'static str {
+        HEADER_MS_RANGE
+    }
+}
+
+/// Storage backend to access data stored in Azure Blob Storage.
+pub type Azure = ObjectStorage<AzureState>;
+
+impl Azure {
+    /// Create a new Azure Blob Storage backend.
+    pub fn new(azure_config: &AzureConfig, id: Option<&str>) -> Result<Azure> {
+        let con_config: ConnectionConfig = azure_config.clone().into();
+        let retry_limit = con_config.retry_limit;
+        let connection = Connection::new(&con_config, None)?;
+        let account_key = base64::engine::general_purpose::STANDARD
+            .decode(azure_config.account_key.as_bytes())
+            .map_err(|e| einval!(format!("invalid azure account key, {}", e)))?;
+        let endpoint = if azure_config.endpoint.is_empty() {
+            format!(
+                "{}.{}",
+                azure_config.account_name, AZURE_DEFAULT_ENDPOINT_SUFFIX
+            )
+        } else {
+            azure_config.endpoint.clone()
+        };
+        let state = Arc::new(AzureState {
+            account_name: azure_config.account_name.clone(),
+            account_key,
+            sas_token: azure_config.sas_token.clone(),
+            scheme: azure_config.scheme.clone(),
+            endpoint,
+            container_name: azure_config.container_name.clone(),
+            object_prefix: azure_config.object_prefix.clone(),
+            retry_limit,
+        });
+        let metrics = id.map(|i| BackendMetrics::new(i, "azure"));
+
+        Ok(ObjectStorage::new_object_storage(
+            connection,
+            state,
+            metrics,
+            id.map(|i| i.to_string()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::mock_http::{start_server, MockResponse};
+    use crate::backend::BlobBackend;
+
+    use super::*;
+
+    fn get_test_azure_state(account_key: &[u8], sas_token: &str) -> AzureState {
+        AzureState {
+            account_name: "account".to_string(),
+            account_key: account_key.to_vec(),
+            sas_token: sas_token.to_string(),
+            scheme: "https".to_string(),
+            endpoint: "account.blob.core.windows.net".to_string(),
+            container_name: "images".to_string(),
+            object_prefix: "nydus/".to_string(),
+            retry_limit: 5,
+        }
+    }
+
+    #[test]
+    fn test_azure_state_url() {
+        let state = get_test_azure_state(b"key", "?sv=2021-08-06&sig=abc");
+        let (resource, url) = state.url("obj_key", &["comp=metadata"]);
+        assert_eq!(resource, "/images/nydus/obj_key");
+        assert_eq!(
+            url,
+            "https://account.blob.core.windows.net/images/nydus/obj_key?comp=metadata"
+        );
+
+        let state = get_test_azure_state(b"", "?sv=2021-08-06&sig=abc");
+        let (_, url) = state.url("obj_key", &[]);
+        assert_eq!(
+            url,
+            "https://account.blob.core.windows.net/images/nydus/obj_key?sv=2021-08-06&sig=abc"
+        );
+        let mut headers = HeaderMap::new();
+        state
+            .sign(Method::GET, &mut headers, &resource, &url)
+            .unwrap();
+        assert!(headers.get(HEADER_AUTHORIZATION).is_none());
+        assert_eq!(headers.get(HEADER_MS_VERSION).unwrap(), AZURE_API_VERSION);
+    }
+
+    #[test]
+    fn test_azure_state_sign() {
+        let state = get_test_azure_state(b"secret", "");
+        let url =
+            "https://account.blob.core.windows.net/images/nydus/obj_key?restype=x&comp=list&comp=a";
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HEADER_MS_DATE,
+            "Mon, 01 Jan 2024 00:00:00 GMT".parse().unwrap(),
+        );
+        headers.insert(HEADER_MS_VERSION, AZURE_API_VERSION.parse().unwrap());
+        headers.insert(HEADER_MS_RANGE, "bytes=0-1023".parse().unwrap());
+        headers.insert("Content-Length", "0".parse().unwrap());
+        let string_to_sign = state.string_to_sign(&Method::GET, &headers, url).unwrap();
+        assert_eq!(
+            string_to_sign,
+            "GET\n\n\n\n\n\n\n\n\n\n\n\n\
+             x-ms-date:Mon, 01 Jan 2024 00:00:00 GMT\n\
+             x-ms-range:bytes=0-1023\n\
+             x-ms-version:2021-08-06\n\
+             /account/images/nydus/obj_key\n\
+             comp:a,list\n\
+             restype:x"
+        );
+        assert_eq!(
+            state.signature(&string_to_sign).unwrap(),
+            "lFefQhkyOSpiYt0gb7+s5qSBqLg9rhG25YnC8T6o10k="
+        );
+
+        let mut headers = HeaderMap::new();
+        state.sign(Method::HEAD, &mut headers, "", url).unwrap();
+        let authorization = headers.get(HEADER_AUTHORIZATION).unwrap();
+        assert!(authorization
+            .to_str()
+            .unwrap()
+            .starts_with("SharedKey account:"));
+        assert!(headers.get(HEADER_MS_DATE).is_some());
+    }
+
+    #[test]
+    fn test_azure_new() {
+        let json_str = r#"{"account_name":"account","account_key":"c2VjcmV0","container_name":"images","retry_limit":5}"#;
+        let config: AzureConfig = serde_json::from_str(json_str).unwrap();
+        let azure = Azure::new(&config, Some("test-azure-new")).unwrap();
+        azure.metrics();
+
+        let reader = azure.get_reader("test").unwrap();
+        assert_eq!(reader.retry_limit(), 5);
+        azure.shutdown();
+
+        let json_str =
+            r#"{"account_name":"account","account_key":"%%%","container_name":"images"}"#;
+        let config: AzureConfig = serde_json::from_str(json_str).unwrap();
+        assert!(Azure::new(&config, Some("test-azure-new-invalid")).is_err());
+    }
+
+    #[test]
+    fn test_azure_read() {
+        let (endpoint, requests) = start_server(|_| MockResponse::new(206, "blob"));
+
+        let config = AzureConfig {
+            scheme: "http".to_string(),
+            endpoint,
+            account_name: "account".to_string(),
+            sas_token: "sv=2021-08-06&sig=abc".to_string(),
+            container_name: "images".to_string(),
+            timeout: 5,
+            connect_timeout: 5,
+            ..Default::default()
+        };
+        let azure = Azure::new(&config, Some("test-azure-read")).unwrap();
+        let reader = azure.get_reader("blob-id").unwrap();
+        let mut buf = vec![0u8; 4];
+        assert_eq!(reader.read(&mut buf, 4).unwrap(), 4);
+        assert_eq!(&buf, b"blob");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0]
+            .head
+            .starts_with("get /images/blob-id?sv=2021-08-06&sig=abc http/1.1"));
+        assert_eq!(requests[0].header("x-ms-range"), Some("bytes=4-7"));
+        assert_eq!(requests[0].header("x-ms-version"), Some("2021-08-06"));
+        assert!(requests[0].header("authorization").is_none());
+        azure.shutdown();
+    }
+}
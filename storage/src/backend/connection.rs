@@ -7,8 +7,8 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{Read, Result};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{fmt, thread};
 
@@ -17,12 +17,16 @@ use log::{max_level, Level};
 use reqwest::{
     self,
     blocking::{Body, Client, Response},
-    header::HeaderMap,
+    header::{HeaderMap, HeaderName, HeaderValue},
     redirect::Policy,
     Method, StatusCode, Url,
 };
 
-use nydus_api::{HttpProxyConfig, OssConfig, ProxyConfig, RegistryConfig, S3Config};
+use nydus_api::{
+    AzureConfig, GcsConfig, HttpProxyConfig, MirrorConfig, OssConfig, ProxyConfig, RegistryConfig,
+    S3Config,
+};
+use nydus_utils::metrics::{BackendMetrics, Metric, MirrorMetrics};
 use url::ParseError;
 
 const HEADER_AUTHORIZATION: &str = "Authorization";
@@ -68,6 +72,10 @@ pub(crate) struct ConnectionConfig {
     pub timeout: u32,
     pub connect_timeout: u32,
     pub retry_limit: u8,
+    /// Mirrors to try in order before requesting the origin server.
+    pub mirrors: Vec<MirrorConfig>,
+    /// URL of the origin server the mirrors stand in for, like `https://my-registry:5000`.
+    pub mirror_origin: String,
 }
 
 impl Default for ConnectionConfig {
@@ -78,6 +86,8 @@ impl Default for ConnectionConfig {
             timeout: 5,
             connect_timeout: 5,
             retry_limit: 0,
+            mirrors: Vec::new(),
+            mirror_origin: String::new(),
         }
     }
 }
@@ -90,6 +100,7 @@ impl From<OssConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            ..Default::default()
         }
     }
 }
@@ -102,18 +113,48 @@ impl From<S3Config> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<AzureConfig> for ConnectionConfig {
+    fn from(c: AzureConfig) -> ConnectionConfig {
+        ConnectionConfig {
+            proxy: c.proxy,
+            skip_verify: c.skip_verify,
+            timeout: c.timeout,
+            connect_timeout: c.connect_timeout,
+            retry_limit: c.retry_limit,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<GcsConfig> for ConnectionConfig {
+    fn from(c: GcsConfig) -> ConnectionConfig {
+        ConnectionConfig {
+            proxy: c.proxy,
+            skip_verify: c.skip_verify,
+            timeout: c.timeout,
+            connect_timeout: c.connect_timeout,
+            retry_limit: c.retry_limit,
+            ..Default::default()
         }
     }
 }
 
 impl From<RegistryConfig> for ConnectionConfig {
     fn from(c: RegistryConfig) -> ConnectionConfig {
+        let scheme = if c.scheme == "http" { "http" } else { "https" };
         ConnectionConfig {
             proxy: c.proxy,
             skip_verify: c.skip_verify,
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            mirrors: c.mirrors,
+            mirror_origin: format!("{}://{}", scheme, c.host),
         }
     }
 }
@@ -126,6 +167,7 @@ impl From<HttpProxyConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            ..Default::default()
         }
     }
 }
@@ -233,6 +275,109 @@ impl Proxy {
     }
 }
 
+#[derive(Debug)]
+struct Mirror {
+    config: MirrorConfig,
+    url: Url,
+    ping_url: Url,
+    headers: HeaderMap,
+    // Whether requests are sent to the mirror, cleared after `failure_limit` consecutive failures.
+    status: AtomicBool,
+    failed_times: AtomicU8,
+    metrics: Option<Arc<MirrorMetrics>>,
+}
+
+impl Mirror {
+    fn new(config: &MirrorConfig, metrics: Option<&BackendMetrics>) -> Result<Self> {
+        let url = Url::parse(&config.host)
+            .map_err(|e| einval!(format!("invalid mirror host {}, {}", config.host, e)))?;
+        if url.host_str().is_none() {
+            return Err(einval!(format!("invalid mirror host {}", config.host)));
+        }
+        let ping_url = if config.ping_url.is_empty() {
+            let mut ping_url = url.clone();
+            ping_url.set_path(&format!("{}/v2", url.path().trim_end_matches('/')));
+            ping_url
+        } else {
+            Url::parse(&config.ping_url).map_err(|e| {
+                einval!(format!(
+                    "invalid mirror ping url {}, {}",
+                    config.ping_url, e
+                ))
+            })?
+        };
+        let mut headers = HeaderMap::new();
+        for (key, value) in config.headers.iter() {
+            let key = HeaderName::from_str(key).map_err(|e| einval!(e))?;
+            let value = HeaderValue::from_str(value).map_err(|e| einval!(e))?;
+            headers.insert(key, value);
+        }
+
+        Ok(Mirror {
+            config: config.clone(),
+            url,
+            ping_url,
+            headers,
+            status: AtomicBool::new(true),
+            failed_times: AtomicU8::new(0),
+            metrics: metrics.map(|m| m.mirror(&config.host)),
+        })
+    }
+
+    fn ok(&self) -> bool {
+        self.status.load(Ordering::Relaxed)
+    }
+
+    // Replace scheme, host and port of `url` with the mirror's, and prepend the mirror's path.
+    fn mirror_url(&self, url: &Url) -> ConnectionResult<Url> {
+        let mut mirror_url = url.clone();
+        mirror_url
+            .set_scheme(self.url.scheme())
+            .map_err(|_| ConnectionError::Scheme(self.url.scheme().to_string()))?;
+        mirror_url
+            .set_host(self.url.host_str())
+            .map_err(|e| ConnectionError::Url(self.config.host.clone(), e))?;
+        mirror_url
+            .set_port(self.url.port())
+            .map_err(|_| ConnectionError::Scheme(self.url.scheme().to_string()))?;
+        let prefix = self.url.path().trim_end_matches('/');
+        if !prefix.is_empty() {
+            mirror_url.set_path(&format!("{}{}", prefix, url.path()));
+        }
+        Ok(mirror_url)
+    }
+
+    fn succeed(&self) {
+        self.failed_times.store(0, Ordering::Relaxed);
+    }
+
+    fn fail(&self) {
+        if let Some(m) = self.metrics.as_ref() {
+            m.errors.inc();
+        }
+        let failed_times = self.failed_times.fetch_add(1, Ordering::Relaxed) + 1;
+        if failed_times >= self.config.failure_limit && self.status.swap(false, Ordering::Relaxed) {
+            warn!(
+                "mirror {} failed {} times, mark it as unhealthy",
+                self.config.host, failed_times
+            );
+            if let Some(m) = self.metrics.as_ref() {
+                m.healthy.store(false, Ordering::Relaxed);
+                m.disabled_count.inc();
+            }
+        }
+    }
+
+    fn recover(&self) {
+        info!("mirror {} recovered", self.config.host);
+        self.failed_times.store(0, Ordering::Relaxed);
+        self.status.store(true, Ordering::Relaxed);
+        if let Some(m) = self.metrics.as_ref() {
+            m.healthy.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
 /// Check whether the HTTP status code is a success result.
 pub(crate) fn is_success_status(status: StatusCode) -> bool {
     status >= StatusCode::OK && status < StatusCode::BAD_REQUEST
@@ -253,6 +398,8 @@ pub(crate) fn respond(resp: Response, catch_status: bool) -> ConnectionResult<Re
 pub(crate) struct Connection {
     client: Client,
     proxy: Option<Arc<Proxy>>,
+    mirrors: Vec<Mirror>,
+    mirror_origin: Option<Url>,
     pub shutdown: AtomicBool,
     /// Timestamp of connection's last active request, represents as duration since UNIX_EPOCH in seconds.
     last_active: Arc<AtomicU64>,
@@ -260,7 +407,12 @@ pub(crate) struct Connection {
 
 impl Connection {
     /// Create a new connection according to the configuration.
-    pub fn new(config: &ConnectionConfig) -> Result<Arc<Connection>> {
+    ///
+    /// Statistics of mirror requests are recorded into `metrics` if provided.
+    pub fn new(
+        config: &ConnectionConfig,
+        metrics: Option<&BackendMetrics>,
+    ) -> Result<Arc<Connection>> {
         info!("backend config: {:?}", config);
         let client = Self::build_connection("", config)?;
 
@@ -285,9 +437,22 @@ impl Connection {
             None
         };
 
+        let mirrors = config
+            .mirrors
+            .iter()
+            .map(|c| Mirror::new(c, metrics))
+            .collect::<Result<Vec<_>>>()?;
+        let mirror_origin = if !mirrors.is_empty() {
+            Some(Url::parse(&config.mirror_origin).map_err(|e| einval!(e))?)
+        } else {
+            None
+        };
+
         let connection = Arc::new(Connection {
             client,
             proxy,
+            mirrors,
+            mirror_origin,
             shutdown: AtomicBool::new(false),
             last_active: Arc::new(AtomicU64::new(
                 SystemTime::now()
@@ -299,10 +464,55 @@ impl Connection {
 
         // Start proxy's health checking thread.
         connection.start_proxy_health_thread(config.connect_timeout as u64);
+        Self::start_mirrors_health_thread(&connection, config.connect_timeout as u64);
 
         Ok(connection)
     }
 
+    fn start_mirrors_health_thread(connection: &Arc<Connection>, connect_timeout: u64) {
+        let interval = match connection
+            .mirrors
+            .iter()
+            .map(|m| m.config.health_check_interval.max(1))
+            .min()
+        {
+            Some(v) => Duration::from_secs(v),
+            None => return,
+        };
+        let connection: Weak<Connection> = Arc::downgrade(connection);
+
+        // Spawn thread to probe unhealthy mirrors and put them back into service once recovered.
+        thread::spawn(move || {
+            let client = Client::new();
+            loop {
+                thread::sleep(interval);
+                let connection = match connection.upgrade() {
+                    Some(c) if !c.shutdown.load(Ordering::Acquire) => c,
+                    _ => break,
+                };
+                for mirror in connection.mirrors.iter().filter(|m| !m.ok()) {
+                    // Any response from the mirror indicates it's reachable again, registries
+                    // respond to the `/v2` endpoint with 401 if authorization is required.
+                    match client
+                        .get(mirror.ping_url.clone())
+                        .timeout(Duration::from_secs(connect_timeout))
+                        .send()
+                    {
+                        Ok(resp) if resp.status() < StatusCode::INTERNAL_SERVER_ERROR => {
+                            mirror.recover()
+                        }
+                        Ok(resp) => debug!(
+                            "mirror {} is still unhealthy, response status {}",
+                            mirror.config.host,
+                            resp.status()
+                        ),
+                        Err(e) => debug!("mirror {} is still unhealthy, {}", mirror.config.host, e),
+                    }
+                }
+            }
+        });
+    }
+
     fn start_proxy_health_thread(&self, connect_timeout: u64) {
         if let Some(proxy) = self.proxy.as_ref() {
             if proxy.health.ping_url.is_some() {
@@ -382,6 +592,27 @@ impl Connection {
             Ordering::Relaxed,
         );
 
+        // Mirrors only serve read requests, uploads always go to the origin server.
+        let read_only = method == Method::GET || method == Method::HEAD;
+        if let Some(origin) = self.mirror_origin.as_ref().filter(|_| read_only) {
+            if let Ok(request_url) = Url::parse(url) {
+                if request_url.host_str() == origin.host_str()
+                    && request_url.port_or_known_default() == origin.port_or_known_default()
+                {
+                    if let Some(resp) = self.call_mirrors(
+                        &method,
+                        &request_url,
+                        &query,
+                        &data,
+                        headers,
+                        catch_status,
+                    )? {
+                        return Ok(resp);
+                    }
+                }
+            }
+        }
+
         if let Some(proxy) = &self.proxy {
             if proxy.health.ok() {
                 let data_cloned = data.as_ref().cloned();
@@ -447,6 +678,78 @@ impl Connection {
         )
     }
 
+    // Try mirrors in order, return `None` if none of them could serve the request so the caller
+    // falls back to the origin server.
+    #[allow(clippy::too_many_arguments)]
+    fn call_mirrors<R: Read + Clone + Send + 'static>(
+        &self,
+        method: &Method,
+        url: &Url,
+        query: &Option<&[(&str, &str)]>,
+        data: &Option<ReqBody<R>>,
+        headers: &HeaderMap,
+        catch_status: bool,
+    ) -> ConnectionResult<Option<Response>> {
+        for mirror in self.mirrors.iter() {
+            if !mirror.ok() {
+                continue;
+            }
+            // Without `auth_through`, unauthorized requests are sent to the origin server, so
+            // the authentication challenge comes from the registry rather than the mirror.
+            if !mirror.config.auth_through && !headers.contains_key(HEADER_AUTHORIZATION) {
+                continue;
+            }
+
+            let mirror_url = mirror.mirror_url(url)?;
+            let mut mirror_headers = headers.clone();
+            for (key, value) in mirror.headers.iter() {
+                mirror_headers.insert(key, value.clone());
+            }
+            if let Some(m) = mirror.metrics.as_ref() {
+                m.requests.inc();
+            }
+
+            match self.call_inner(
+                &self.client,
+                method.clone(),
+                mirror_url.as_str(),
+                query,
+                data.clone(),
+                &mirror_headers,
+                false,
+                false,
+            ) {
+                Ok(resp) if is_success_status(resp.status()) => {
+                    mirror.succeed();
+                    return respond(resp, catch_status).map(Some);
+                }
+                // The mirror is still healthy but can't serve the request, e.g. it doesn't have
+                // the blob or rejects the credentials, so try the next one.
+                Ok(resp) if resp.status() < StatusCode::INTERNAL_SERVER_ERROR => {
+                    warn!(
+                        "mirror {} can't serve the request, response status {}",
+                        mirror.config.host,
+                        resp.status()
+                    );
+                }
+                Ok(resp) => {
+                    warn!(
+                        "request mirror {} failed, response status {}",
+                        mirror.config.host,
+                        resp.status()
+                    );
+                    mirror.fail();
+                }
+                Err(e) => {
+                    warn!("request mirror {} failed, {}", mirror.config.host, e);
+                    mirror.fail();
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     fn build_connection(proxy: &str, config: &ConnectionConfig) -> Result<Client> {
         let connect_timeout = if config.connect_timeout != 0 {
             Some(Duration::from_secs(config.connect_timeout as u64))
@@ -544,7 +847,57 @@ impl Connection {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Cursor;
+    use std::io::{Cursor, Write};
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+
+    // Serve HTTP requests with a fixed status and body, recording the request headers.
+    fn start_server(status: u16, body: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut req = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !req.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&req).to_lowercase());
+                let resp = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(resp.as_bytes());
+            }
+        });
+        (addr, requests)
+    }
+
+    fn unused_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    fn get(conn: &Connection, url: &str, auth: bool) -> String {
+        let mut headers = HeaderMap::new();
+        if auth {
+            headers.insert(HEADER_AUTHORIZATION, "Bearer token".parse().unwrap());
+        }
+        conn.call::<&[u8]>(Method::GET, url, None, None, &mut headers, true)
+            .unwrap()
+            .text()
+            .unwrap()
+    }
 
     #[test]
     fn test_progress() {
@@ -586,6 +939,190 @@ mod tests {
         assert!(!is_success_status(StatusCode::BAD_REQUEST));
     }
 
+    #[test]
+    fn test_mirror_url() {
+        let config = MirrorConfig {
+            host: "http://127.0.0.1:65001".to_string(),
+            ..Default::default()
+        };
+        let mirror = Mirror::new(&config, None).unwrap();
+        assert_eq!(mirror.ping_url.as_str(), "http://127.0.0.1:65001/v2");
+        let url = Url::parse("https://my-registry:5000/v2/test/repo/blobs/sha256:abc").unwrap();
+        assert_eq!(
+            mirror.mirror_url(&url).unwrap().as_str(),
+            "http://127.0.0.1:65001/v2/test/repo/blobs/sha256:abc"
+        );
+
+        let config = MirrorConfig {
+            host: "https://mirror.example.com".to_string(),
+            ..Default::default()
+        };
+        let mirror = Mirror::new(&config, None).unwrap();
+        assert_eq!(
+            mirror.mirror_url(&url).unwrap().as_str(),
+            "https://mirror.example.com/v2/test/repo/blobs/sha256:abc"
+        );
+
+        // Path of the mirror host is kept as prefix.
+        let config = MirrorConfig {
+            host: "https://mirror.example.com/registry/".to_string(),
+            ..Default::default()
+        };
+        let mirror = Mirror::new(&config, None).unwrap();
+        assert_eq!(
+            mirror.ping_url.as_str(),
+            "https://mirror.example.com/registry/v2"
+        );
+        let url = Url::parse("https://my-registry:5000/v2/test/repo/blobs/uploads?a=b").unwrap();
+        assert_eq!(
+            mirror.mirror_url(&url).unwrap().as_str(),
+            "https://mirror.example.com/registry/v2/test/repo/blobs/uploads?a=b"
+        );
+
+        let config = MirrorConfig {
+            host: "127.0.0.1".to_string(),
+            ..Default::default()
+        };
+        assert!(Mirror::new(&config, None).is_err());
+        let config = MirrorConfig {
+            host: "http://127.0.0.1:65001".to_string(),
+            headers: [("bad header".to_string(), "value".to_string())].into(),
+            ..Default::default()
+        };
+        assert!(Mirror::new(&config, None).is_err());
+    }
+
+    #[test]
+    fn test_mirror_failover() {
+        let (origin, origin_requests) = start_server(200, "origin");
+        let (broken, _) = start_server(503, "broken");
+        let (mirror, mirror_requests) = start_server(200, "mirror");
+        let dead = unused_addr();
+        let other = format!(
+            "http://localhost:{}",
+            Url::parse(&origin).unwrap().port().unwrap()
+        );
+
+        let metrics = BackendMetrics::new("test_mirror_failover", "registry");
+        let config = ConnectionConfig {
+            mirrors: vec![
+                MirrorConfig {
+                    host: dead.clone(),
+                    failure_limit: 1,
+                    health_check_interval: 3600,
+                    ..Default::default()
+                },
+                MirrorConfig {
+                    host: broken.clone(),
+                    failure_limit: 2,
+                    ..Default::default()
+                },
+                MirrorConfig {
+                    host: mirror.clone(),
+                    headers: [("X-Mirror".to_string(), "nydus".to_string())].into(),
+                    ..Default::default()
+                },
+            ],
+            mirror_origin: origin.clone(),
+            ..Default::default()
+        };
+        let conn = Connection::new(&config, Some(&metrics)).unwrap();
+
+        let url = format!("{}/v2/test/repo/blobs/sha256:abc", origin);
+        assert_eq!(get(&conn, &url, true), "mirror");
+        assert!(!conn.mirrors[0].ok());
+        assert!(conn.mirrors[1].ok());
+        assert_eq!(get(&conn, &url, true), "mirror");
+        assert!(!conn.mirrors[1].ok());
+        assert_eq!(get(&conn, &url, true), "mirror");
+        assert!(mirror_requests.lock().unwrap()[0].contains("x-mirror: nydus"));
+        assert!(mirror_requests.lock().unwrap()[0].contains("/v2/test/repo/blobs/sha256:abc"));
+        assert!(origin_requests.lock().unwrap().is_empty());
+
+        // Unauthorized requests and requests to other hosts go to the origin server.
+        assert_eq!(get(&conn, &url, false), "origin");
+        assert_eq!(get(&conn, &format!("{}/token", other), true), "origin");
+        assert_eq!(origin_requests.lock().unwrap().len(), 2);
+        assert_eq!(mirror_requests.lock().unwrap().len(), 3);
+
+        // Fall back to the origin server once all mirrors are unhealthy.
+        conn.mirrors[2].status.store(false, Ordering::Relaxed);
+        assert_eq!(get(&conn, &url, true), "origin");
+
+        let dead_metrics = metrics.mirror(&dead);
+        assert!(!dead_metrics.healthy.load(Ordering::Relaxed));
+        assert_eq!(dead_metrics.requests.count(), 1);
+        assert_eq!(dead_metrics.disabled_count.count(), 1);
+        let broken_metrics = metrics.mirror(&broken);
+        assert_eq!(broken_metrics.requests.count(), 2);
+        assert_eq!(broken_metrics.errors.count(), 2);
+        assert_eq!(metrics.mirror(&mirror).requests.count(), 3);
+
+        // Unhealthy mirrors are put back into service once they respond to health checks.
+        conn.mirrors[1].recover();
+        assert!(metrics.mirror(&broken).healthy.load(Ordering::Relaxed));
+        assert_eq!(conn.mirrors[1].failed_times.load(Ordering::Relaxed), 0);
+
+        conn.shutdown();
+        metrics.release().unwrap();
+    }
+
+    #[test]
+    fn test_mirror_client_error() {
+        let (origin, origin_requests) = start_server(200, "origin");
+        let (missing, missing_requests) = start_server(404, "missing");
+        let (mirror, _) = start_server(200, "mirror");
+        let mirror_config = |host: &str| MirrorConfig {
+            host: host.to_string(),
+            failure_limit: 1,
+            ..Default::default()
+        };
+        let config = ConnectionConfig {
+            mirrors: vec![mirror_config(&missing), mirror_config(&mirror)],
+            mirror_origin: origin.clone(),
+            ..Default::default()
+        };
+        let conn = Connection::new(&config, None).unwrap();
+
+        // Client errors fail over to the next mirror without marking the mirror unhealthy.
+        let url = format!("{}/v2/test/repo/blobs/sha256:abc", origin);
+        assert_eq!(get(&conn, &url, true), "mirror");
+        assert_eq!(get(&conn, &url, true), "mirror");
+        assert!(conn.mirrors[0].ok());
+        assert_eq!(missing_requests.lock().unwrap().len(), 2);
+
+        // And to the origin server if no mirror could serve the request.
+        let config = ConnectionConfig {
+            mirrors: vec![mirror_config(&missing)],
+            mirror_origin: origin.clone(),
+            ..Default::default()
+        };
+        let conn = Connection::new(&config, None).unwrap();
+        assert_eq!(get(&conn, &url, true), "origin");
+        assert_eq!(origin_requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_mirror_auth_through() {
+        let (origin, origin_requests) = start_server(200, "origin");
+        let (mirror, _) = start_server(200, "mirror");
+        let config = ConnectionConfig {
+            mirrors: vec![MirrorConfig {
+                host: mirror,
+                auth_through: true,
+                ..Default::default()
+            }],
+            mirror_origin: origin.clone(),
+            ..Default::default()
+        };
+        let conn = Connection::new(&config, None).unwrap();
+
+        let url = format!("{}/v2/", origin);
+        assert_eq!(get(&conn, &url, false), "mirror");
+        assert_eq!(get(&conn, &url, true), "mirror");
+        assert!(origin_requests.lock().unwrap().is_empty());
+    }
+
     #[test]
     fn test_connection_config_default() {
         let config = ConnectionConfig::default();
@@ -598,5 +1135,22 @@ mod tests {
         assert!(config.proxy.fallback);
         assert_eq!(config.proxy.ping_url, "");
         assert_eq!(config.proxy.url, "");
+        assert!(config.mirrors.is_empty());
+    }
+
+    #[test]
+    fn test_connection_config_from_registry() {
+        let config = RegistryConfig {
+            scheme: "http".to_string(),
+            host: "my-registry:5000".to_string(),
+            mirrors: vec![MirrorConfig {
+                host: "http://127.0.0.1:65001".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let config: ConnectionConfig = config.into();
+        assert_eq!(config.mirror_origin, "http://my-registry:5000");
+        assert_eq!(config.mirrors.len(), 1);
     }
 }
@@ -0,0 +1,161 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cache of temporary credentials, which are refreshed on demand before they expire.
+
+use std::io::Result;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// Refresh credentials if they expire within the window.
+const REFRESH_WINDOW: Duration = Duration::from_secs(300);
+// Avoid refreshing credentials repeatedly when the credential service is unavailable.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Credentials with an optional expiration time.
+pub(crate) trait Expiration {
+    /// Get time left before the credentials expire, `None` for credentials never expire.
+    fn time_to_live(&self) -> Option<Duration>;
+
+    fn expired(&self) -> bool {
+        self.time_to_live().map(|t| t.is_zero()).unwrap_or(false)
+    }
+
+    fn expires_soon(&self) -> bool {
+        self.time_to_live()
+            .map(|t| t <= REFRESH_WINDOW)
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Debug)]
+struct CacheState<T> {
+    credentials: Option<T>,
+    last_refresh: Option<Instant>,
+    // Whether a thread is fetching new credentials.
+    refreshing: bool,
+}
+
+/// Cache of credentials, shared by concurrent requests to a storage backend.
+#[derive(Debug)]
+pub(crate) struct CredentialCache<T> {
+    state: Mutex<CacheState<T>>,
+    refreshed: Condvar,
+}
+
+impl<T: Clone + Expiration> CredentialCache<T> {
+    /// Create a new cache, with initial credentials if available.
+    pub fn new(credentials: Option<T>) -> Self {
+        CredentialCache {
+            state: Mutex::new(CacheState {
+                credentials,
+                last_refresh: None,
+                refreshing: false,
+            }),
+            refreshed: Condvar::new(),
+        }
+    }
+
+    /// Get valid credentials, refreshing them by `fetch` if they are about to expire.
+    ///
+    /// Credentials which haven't expired yet are still used if they can't be refreshed. Only one
+    /// caller fetches new credentials at a time without holding the lock, while other callers
+    /// keep using credentials which haven't expired yet, or wait for the refresh otherwise.
+    pub fn get<F>(&self, fetch: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let mut state = self.state.lock().unwrap();
+        let mut waited = false;
+        loop {
+            let recently_refreshed = state
+                .last_refresh
+                .map(|t| t.elapsed() < MIN_REFRESH_INTERVAL)
+                .unwrap_or(false);
+            if let Some(credentials) = state.credentials.as_ref() {
+                if !credentials.expires_soon()
+                    || (!credentials.expired() && (recently_refreshed || state.refreshing))
+                {
+                    return Ok(credentials.clone());
+                }
+            }
+            if state.refreshing {
+                state = self.refreshed.wait(state).unwrap();
+                waited = true;
+            } else if waited && recently_refreshed {
+                return Err(eother!("failed to refresh credentials"));
+            } else {
+                break;
+            }
+        }
+
+        state.refreshing = true;
+        state.last_refresh = Some(Instant::now());
+        drop(state);
+        let result = fetch();
+
+        let mut state = self.state.lock().unwrap();
+        state.refreshing = false;
+        self.refreshed.notify_all();
+        match result {
+            Ok(credentials) => {
+                state.credentials = Some(credentials.clone());
+                Ok(credentials)
+            }
+            Err(e) => match state.credentials.as_ref() {
+                Some(credentials) if !credentials.expired() => {
+                    warn!("failed to refresh credentials, {}", e);
+                    Ok(credentials.clone())
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Replace cached credentials, and allow to refresh them immediately.
+    #[cfg(test)]
+    pub fn set(&self, credentials: T) {
+        let mut state = self.state.lock().unwrap();
+        state.credentials = Some(credentials);
+        state.last_refresh = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Token(&'static str, Option<Instant>);
+
+    impl Expiration for Token {
+        fn time_to_live(&self) -> Option<Duration> {
+            self.1.map(|e| e.saturating_duration_since(Instant::now()))
+        }
+    }
+
+    #[test]
+    fn test_credential_cache() {
+        let never = Token("never", None);
+        let cache = CredentialCache::new(Some(never.clone()));
+        assert_eq!(cache.get(|| panic!("unexpected refresh")).unwrap(), never);
+
+        let cache = CredentialCache::new(None);
+        let fresh = Token("fresh", Some(Instant::now() + Duration::from_secs(3600)));
+        assert_eq!(cache.get(|| Ok(fresh.clone())).unwrap(), fresh);
+        assert_eq!(cache.get(|| panic!("unexpected refresh")).unwrap(), fresh);
+
+        // Credentials expiring within the window are refreshed, but not repeatedly.
+        let soon = Token("soon", Some(Instant::now() + Duration::from_secs(60)));
+        cache.set(soon.clone());
+        assert_eq!(cache.get(|| Ok(soon.clone())).unwrap(), soon);
+        assert_eq!(cache.get(|| panic!("unexpected refresh")).unwrap(), soon);
+
+        // Credentials which haven't expired yet are used if refresh fails.
+        cache.set(soon.clone());
+        assert_eq!(cache.get(|| Err(eother!("failure"))).unwrap(), soon);
+        cache.set(Token("expired", Some(Instant::now())));
+        assert!(cache.get(|| Err(eother!("failure"))).is_err());
+    }
+}
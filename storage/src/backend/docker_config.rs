@@ -0,0 +1,393 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resolve registry credentials from docker `config.json` and docker credential helpers.
+//!
+//! Credentials are looked up in the same order as the docker CLI:
+//! - `credHelpers`: per-registry credential helper, like `"my-registry.com": "ecr-login"`.
+//! - `credsStore`: default credential helper for all registries, like `"desktop"`.
+//! - `auths`: base64 encoded `username:password` or plain username and password.
+//!
+//! A credential helper is an executable named `docker-credential-<name>` in `PATH`, which reads
+//! the registry server address from stdin and writes the credentials in JSON to stdout.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{Read, Result, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use serde::Deserialize;
+
+const DOCKER_HUB_HOST: &str = "index.docker.io";
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+const DOCKER_HUB_ALIASES: [&str; 3] = ["docker.io", "registry-1.docker.io", "index.docker.io"];
+const CREDENTIAL_HELPER_PREFIX: &str = "docker-credential-";
+// Username returned by credential helpers for identity tokens.
+const IDENTITY_TOKEN_USERNAME: &str = "<token>";
+// Avoid executing credential helpers repeatedly when requests are rejected concurrently.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+// Credential helpers are killed if they don't exit in time, e.g. waiting for user input.
+const HELPER_TIMEOUT: Duration = Duration::from_secs(30);
+const HELPER_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Default, Deserialize)]
+struct AuthEntry {
+    #[serde(default)]
+    auth: String,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default, rename = "identitytoken")]
+    identity_token: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DockerConfigFile {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+    #[serde(default, rename = "credsStore")]
+    creds_store: String,
+    #[serde(default, rename = "credHelpers")]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelperCredential {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+/// Registry credentials resolved from docker `config.json`.
+#[derive(Debug)]
+pub(crate) struct DockerCredentials {
+    path: PathBuf,
+    host: String,
+    last_refresh: Mutex<Option<Instant>>,
+    helper_timeout: Duration,
+    // Directory to find credential helpers in, instead of searching `PATH`.
+    helper_dir: Option<PathBuf>,
+}
+
+impl DockerCredentials {
+    /// Create a resolver for registry `host` with docker config file `path`.
+    ///
+    /// Use `$DOCKER_CONFIG/config.json` or `$HOME/.docker/config.json` if `path` is empty.
+    pub fn new(path: &str, host: &str) -> Self {
+        let path = if path.is_empty() {
+            default_config_path()
+        } else {
+            PathBuf::from(path)
+        };
+
+        DockerCredentials {
+            path,
+            host: normalize_host(host).to_string(),
+            last_refresh: Mutex::new(None),
+            helper_timeout: HELPER_TIMEOUT,
+            helper_dir: None,
+        }
+    }
+
+    /// Resolve base64 encoded `username:password` for the registry.
+    pub fn resolve(&self) -> Result<Option<String>> {
+        *self.last_refresh.lock().unwrap() = Some(Instant::now());
+
+        let content = match fs::read_to_string(&self.path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let config: DockerConfigFile = serde_json::from_str(&content).map_err(|e| {
+            einval!(format!(
+                "invalid docker config file {}, {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+
+        let helper = config
+            .cred_helpers
+            .iter()
+            .find(|(k, _)| normalize_host(k) == self.host)
+            .map(|(_, v)| v.as_str())
+            .unwrap_or(config.creds_store.as_str());
+        if !helper.is_empty() {
+            return self.exec_helper(helper);
+        }
+
+        match config
+            .auths
+            .iter()
+            .find(|(k, _)| normalize_host(k) == self.host)
+        {
+            Some((_, entry)) if !entry.auth.is_empty() => Ok(Some(entry.auth.clone())),
+            Some((_, entry)) if !entry.username.is_empty() => {
+                Ok(Some(encode_auth(&entry.username, &entry.password)))
+            }
+            Some((_, entry)) if !entry.identity_token.is_empty() => {
+                warn!(
+                    "identity token for registry {} is not supported, ignore it",
+                    self.host
+                );
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Resolve credentials again unless they were resolved very recently.
+    pub fn refresh(&self) -> Option<String> {
+        if let Some(last) = *self.last_refresh.lock().unwrap() {
+            if last.elapsed() < MIN_REFRESH_INTERVAL {
+                return None;
+            }
+        }
+
+        self.resolve().unwrap_or_else(|e| {
+            warn!(
+                "failed to resolve credentials for registry {}, {}",
+                self.host, e
+            );
+            None
+        })
+    }
+
+    fn exec_helper(&self, helper: &str) -> Result<Option<String>> {
+        let program = format!("{}{}", CREDENTIAL_HELPER_PREFIX, helper);
+        let server = if self.host == DOCKER_HUB_HOST {
+            DOCKER_HUB_SERVER
+        } else {
+            self.host.as_str()
+        };
+        let command = match self.helper_dir.as_ref() {
+            Some(dir) => dir.join(&program),
+            None => PathBuf::from(&program),
+        };
+        let mut child = Command::new(command)
+            .arg("get")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| eother!(format!("failed to execute {}, {}", program, e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(server.as_bytes())?;
+        }
+        // Drain pipes in background, otherwise the helper may block on writing to a full pipe.
+        let stdout = child.stdout.take().map(read_pipe);
+        let stderr = child.stderr.take().map(read_pipe);
+
+        let deadline = Instant::now() + self.helper_timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(eother!(format!(
+                    "{} did not exit in {:?}, killed",
+                    program, self.helper_timeout
+                )));
+            }
+            thread::sleep(HELPER_POLL_INTERVAL);
+        };
+        let stdout = join_pipe(stdout);
+
+        if !status.success() {
+            let stdout = String::from_utf8_lossy(&stdout);
+            if stdout.contains("credentials not found") {
+                return Ok(None);
+            }
+            let stderr = join_pipe(stderr);
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(eother!(format!(
+                "{} exited with {}, {}{}",
+                program,
+                status,
+                stdout.trim(),
+                stderr.trim()
+            )));
+        }
+
+        let cred: HelperCredential = serde_json::from_slice(&stdout)
+            .map_err(|e| einval!(format!("invalid output of {}, {}", program, e)))?;
+        if cred.username == IDENTITY_TOKEN_USERNAME {
+            warn!(
+                "identity token from {} for registry {} is not supported, ignore it",
+                program, self.host
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(encode_auth(&cred.username, &cred.secret)))
+    }
+}
+
+fn read_pipe<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+fn join_pipe(handle: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    handle.and_then(|h| h.join().ok()).unwrap_or_default()
+}
+
+fn default_config_path() -> PathBuf {
+    match env::var_os("DOCKER_CONFIG") {
+        Some(dir) => PathBuf::from(dir).join("config.json"),
+        None => PathBuf::from(env::var_os("HOME").unwrap_or_default())
+            .join(".docker")
+            .join("config.json"),
+    }
+}
+
+// Convert `https://my-registry.com/v1/` or `my-registry.com` to `my-registry.com`.
+fn normalize_host(host: &str) -> &str {
+    let host = host
+        .strip_prefix("https://")
+        .or_else(|| host.strip_prefix("http://"))
+        .unwrap_or(host);
+    let host = host.split('/').next().unwrap_or_default();
+    if DOCKER_HUB_ALIASES.contains(&host) {
+        DOCKER_HUB_HOST
+    } else {
+        host
+    }
+}
+
+fn encode_auth(username: &str, password: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("my-registry.com"), "my-registry.com");
+        assert_eq!(
+            normalize_host("my-registry.com:5000"),
+            "my-registry.com:5000"
+        );
+        assert_eq!(
+            normalize_host("https://my-registry.com/v1/"),
+            "my-registry.com"
+        );
+        assert_eq!(normalize_host("http://my-registry.com"), "my-registry.com");
+        assert_eq!(normalize_host("docker.io"), DOCKER_HUB_HOST);
+        assert_eq!(normalize_host("registry-1.docker.io"), DOCKER_HUB_HOST);
+        assert_eq!(normalize_host(DOCKER_HUB_SERVER), DOCKER_HUB_HOST);
+    }
+
+    #[test]
+    fn test_resolve_auths() {
+        let tmpdir = TempDir::new().unwrap();
+        let path = tmpdir.as_path().join("config.json");
+        fs::write(
+            &path,
+            r#"{
+                "auths": {
+                    "https://index.docker.io/v1/": {"auth": "aHViOnB3ZA=="},
+                    "my-registry.com:5000": {"username": "user", "password": "pwd"},
+                    "token-registry.com": {"identitytoken": "token"}
+                }
+            }"#,
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let creds = DockerCredentials::new(path, "registry-1.docker.io");
+        assert_eq!(creds.resolve().unwrap(), Some("aHViOnB3ZA==".to_string()));
+        let creds = DockerCredentials::new(path, "my-registry.com:5000");
+        assert_eq!(creds.resolve().unwrap(), Some(encode_auth("user", "pwd")));
+        let creds = DockerCredentials::new(path, "token-registry.com");
+        assert_eq!(creds.resolve().unwrap(), None);
+        let creds = DockerCredentials::new(path, "other-registry.com");
+        assert_eq!(creds.resolve().unwrap(), None);
+
+        let missing = tmpdir.as_path().join("missing.json");
+        let creds = DockerCredentials::new(missing.to_str().unwrap(), "my-registry.com:5000");
+        assert_eq!(creds.resolve().unwrap(), None);
+
+        fs::write(tmpdir.as_path().join("invalid.json"), "invalid").unwrap();
+        let invalid = tmpdir.as_path().join("invalid.json");
+        let creds = DockerCredentials::new(invalid.to_str().unwrap(), "my-registry.com:5000");
+        assert!(creds.resolve().is_err());
+        assert_eq!(creds.refresh(), None);
+    }
+
+    #[test]
+    fn test_resolve_credential_helper() {
+        let tmpdir = TempDir::new().unwrap();
+        let dir = tmpdir.as_path();
+        // A fake helper which returns the server address as password.
+        let helper = dir.join("docker-credential-nydus-test");
+        fs::write(
+            &helper,
+            "#!/bin/sh\nread server\n[ \"$server\" = \"missing.com\" ] && echo 'credentials not found in native keychain' && exit 1\necho \"{\\\"Username\\\":\\\"user\\\",\\\"Secret\\\":\\\"$server\\\"}\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+        // A fake helper which never exits.
+        let helper = dir.join("docker-credential-nydus-slow");
+        fs::write(&helper, "#!/bin/sh\nread server\nexec sleep 60\n").unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+        let path = dir.join("config.json");
+        fs::write(
+            &path,
+            r#"{
+                "auths": {"my-registry.com": {"auth": "dW51c2VkOnVudXNlZA=="}},
+                "credsStore": "nydus-test",
+                "credHelpers": {
+                    "other-registry.com": "nydus-missing",
+                    "slow-registry.com": "nydus-slow"
+                }
+            }"#,
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let new_creds = |host: &str| {
+            let mut creds = DockerCredentials::new(path, host);
+            creds.helper_dir = Some(dir.to_path_buf());
+            creds
+        };
+
+        let creds = new_creds("my-registry.com");
+        assert_eq!(
+            creds.resolve().unwrap(),
+            Some(encode_auth("user", "my-registry.com"))
+        );
+        let creds = new_creds("docker.io");
+        assert_eq!(
+            creds.resolve().unwrap(),
+            Some(encode_auth("user", DOCKER_HUB_SERVER))
+        );
+        assert_eq!(creds.refresh(), None);
+        let creds = new_creds("missing.com");
+        assert_eq!(creds.resolve().unwrap(), None);
+        let creds = new_creds("other-registry.com");
+        assert!(creds.resolve().is_err());
+        let mut creds = new_creds("slow-registry.com");
+        creds.helper_timeout = Duration::from_millis(200);
+        let start = Instant::now();
+        assert!(creds.resolve().is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,400 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to access blobs on Google Cloud Storage.
+//!
+//! Blobs are read through the GCS XML API. Requests are signed with HMAC keys by the V4 signing
+//! process, which is interoperable with S3, or authorized by an OAuth 2.0 bearer token. Bearer
+//! tokens are either given by configuration as is, or fetched from the GCE metadata server for
+//! the attached service account and refreshed before they expire.
+
+use std::io::Result;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::Client;
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use serde::Deserialize;
+
+use nydus_api::{GcsConfig, S3Config};
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::connection::{Connection, ConnectionConfig};
+use crate::backend::credential_cache::{CredentialCache, Expiration};
+use crate::backend::object_storage::{ObjectStorage, ObjectStorageState};
+use crate::backend::s3::S3State;
+
+const HEADER_AUTHORIZATION: &str = "Authorization";
+const GCS_DEFAULT_ENDPOINT: &str = "storage.googleapis.com";
+// Region to sign requests with HMAC keys.
+const GCS_HMAC_REGION: &str = "auto";
+const GCE_METADATA_DEFAULT_ENDPOINT: &str = "http://metadata.google.internal";
+const GCE_METADATA_TOKEN_PATH: &str = "/computeMetadata/v1/instance/service-accounts/default/token";
+const METADATA_HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+struct MetadataToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Clone, Debug)]
+struct AccessToken {
+    token: String,
+    // Expiration time of fetched tokens, `None` for static tokens.
+    expiration: Option<Instant>,
+}
+
+impl Expiration for AccessToken {
+    fn time_to_live(&self) -> Option<Duration> {
+        self.expiration
+            .map(|e| e.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// Source of OAuth 2.0 access tokens to authorize requests.
+#[derive(Debug)]
+struct TokenSource {
+    // Metadata server to fetch tokens from, `None` for the static token from configuration.
+    endpoint: Option<String>,
+    client: Option<Client>,
+    cache: CredentialCache<AccessToken>,
+}
+
+impl TokenSource {
+    fn new_static(token: &str) -> Self {
+        TokenSource {
+            endpoint: None,
+            client: None,
+            cache: CredentialCache::new(Some(AccessToken {
+                token: token.to_string(),
+                expiration: None,
+            })),
+        }
+    }
+
+    fn new_metadata(endpoint: &str) -> Result<Self> {
+        let endpoint = if endpoint.is_empty() {
+            GCE_METADATA_DEFAULT_ENDPOINT
+        } else {
+            endpoint
+        };
+        let client = Client::builder()
+            .timeout(METADATA_HTTP_TIMEOUT)
+            .connect_timeout(METADATA_HTTP_TIMEOUT)
+            .build()
+            .map_err(|e| eother!(format!("failed to create http client, {}", e)))?;
+        Ok(TokenSource {
+            endpoint: Some(endpoint.trim_end_matches('/').to_string()),
+            client: Some(client),
+            cache: CredentialCache::new(None),
+        })
+    }
+
+    /// Get a valid access token, an empty token means anonymous access.
+    fn token(&self) -> Result<String> {
+        let token = self.cache.get(|| {
+            let token = self.fetch()?;
+            Ok(AccessToken {
+                token: token.access_token,
+                expiration: Some(Instant::now() + Duration::from_secs(token.expires_in)),
+            })
+        })?;
+        Ok(token.token)
+    }
+
+    fn fetch(&self) -> Result<MetadataToken> {
+        let (client, endpoint) = match (self.client.as_ref(), self.endpoint.as_ref()) {
+            (Some(client), Some(endpoint)) => (client, endpoint),
+            _ => return Err(eother!("no metadata server available")),
+        };
+        let body = client
+            .get(format!("{}{}", endpoint, GCE_METADATA_TOKEN_PATH))
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .and_then(|resp| resp.error_for_status())
+            .and_then(|resp| resp.text())
+            .map_err(|e| {
+                eother!(format!(
+                    "failed to get access token from metadata server, {}",
+                    e
+                ))
+            })?;
+        serde_json::from_str(&body)
+            .map_err(|e| eother!(format!("invalid access token from metadata server, {}", e)))
+    }
+}
+
+#[derive(Debug)]
+pub struct GcsState {
+    // Sign requests with HMAC keys if available.
+    signer: Option<S3State>,
+    token_source: TokenSource,
+    scheme: String,
+    endpoint: String,
+    bucket_name: String,
+    object_prefix: String,
+    retry_limit: u8,
+}
+
+impl ObjectStorageState for GcsState {
+    fn url(&self, object_key: &str, query: &[&str]) -> (String, String) {
+        let query_str = if query.is_empty() {
+            "".to_string()
+        } else {
+            format!("?{}", query.join("&"))
+        };
+        let resource = format!(
+            "/{}/{}{}{}",
+            self.bucket_name, self.object_prefix, object_key, query_str
+        );
+        let url = format!("{}://{}{}", self.scheme, self.endpoint, resource);
+        (resource, url)
+    }
+
+    /// generate gcs request signature
+    fn sign(
+        &self,
+        verb: Method,
+        headers: &mut HeaderMap,
+        canonicalized_resource: &str,
+        full_resource_url: &str,
+    ) -> Result<()> {
+        if let Some(signer) = self.signer.as_ref() {
+            signer.sign(verb, headers, canonicalized_resource, full_resource_url)
+        } else {
+            // Anonymous access to public objects if no access token.
+            let token = self.token_source.token()?;
+            if !token.is_empty() {
+                let authorization = format!("Bearer {}", token);
+                headers.insert(
+                    HEADER_AUTHORIZATION,
+                    authorization.parse().map_err(|e| einval!(e))?,
+                );
+            }
+            Ok(())
+        }
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.retry_limit
+    }
+}
+
+/// Storage backend to access data stored in Google Cloud Storage.
+pub type Gcs = ObjectStorage<GcsState>;
+
+impl Gcs {
+    /// Create a new Google Cloud Storage backend.
+    pub fn new(gcs_config: &GcsConfig, id: Option<&str>) -> Result<Gcs> {
+        let con_config: ConnectionConfig = gcs_config.clone().into();
+        let retry_limit = con_config.retry_limit;
+        let connection = Connection::new(&con_config, None)?;
+        let endpoint = if gcs_config.endpoint.is_empty() {
+            GCS_DEFAULT_ENDPOINT.to_string()
+        } else {
+            gcs_config.endpoint.clone()
+        };
+        let signer = if gcs_config.access_key_id.is_empty() {
+            None
+        } else {
+            let s3_config = S3Config {
+                scheme: gcs_config.scheme.clone(),
+                endpoint: endpoint.clone(),
+                region: GCS_HMAC_REGION.to_string(),
+                bucket_name: gcs_config.bucket_name.clone(),
+                object_prefix: gcs_config.object_prefix.clone(),
+                access_key_id: gcs_config.access_key_id.clone(),
+                access_key_secret: gcs_config.access_key_secret.clone(),
+                ..Default::default()
+            };
+            Some(S3State::new(&s3_config, retry_limit)?)
+        };
+        let token_source = if gcs_config.use_metadata_server {
+            TokenSource::new_metadata(&gcs_config.metadata_endpoint)?
+        } else {
+            TokenSource::new_static(&gcs_config.access_token)
+        };
+        let state = Arc::new(GcsState {
+            signer,
+            token_source,
+            scheme: gcs_config.scheme.clone(),
+            endpoint,
+            bucket_name: gcs_config.bucket_name.clone(),
+            object_prefix: gcs_config.object_prefix.clone(),
+            retry_limit,
+        });
+        let metrics = id.map(|i| BackendMetrics::new(i, "gcs"));
+
+        Ok(ObjectStorage::new_object_storage(
+            connection,
+            state,
+            metrics,
+            id.map(|i| i.to_string()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::backend::mock_http::{start_server, MockResponse};
+    use crate::backend::BlobBackend;
+
+    use super::*;
+
+    #[test]
+    fn test_gcs_state() {
+        let state = GcsState {
+            signer: None,
+            token_source: TokenSource::new_static("token"),
+            scheme: "https".to_string(),
+            endpoint: GCS_DEFAULT_ENDPOINT.to_string(),
+            bucket_name: "images".to_string(),
+            object_prefix: "nydus/".to_string(),
+            retry_limit: 5,
+        };
+        let (resource, url) = state.url("obj_key", &["a=b"]);
+        assert_eq!(resource, "/images/nydus/obj_key?a=b");
+        assert_eq!(
+            url,
+            "https://storage.googleapis.com/images/nydus/obj_key?a=b"
+        );
+
+        let mut headers = HeaderMap::new();
+        state
+            .sign(Method::GET, &mut headers, &resource, &url)
+            .unwrap();
+        assert_eq!(headers.get(HEADER_AUTHORIZATION).unwrap(), "Bearer token");
+    }
+
+    #[test]
+    fn test_gcs_hmac_sign() {
+        let config = GcsConfig {
+            scheme: "https".to_string(),
+            bucket_name: "images".to_string(),
+            access_key_id: "GOOGKEY".to_string(),
+            access_key_secret: "secret".to_string(),
+            retry_limit: 3,
+            ..Default::default()
+        };
+        let gcs = Gcs::new(&config, Some("test-gcs-hmac")).unwrap();
+        gcs.metrics();
+        let reader = gcs.get_reader("test").unwrap();
+        assert_eq!(reader.retry_limit(), 3);
+        gcs.shutdown();
+
+        let s3_config = S3Config {
+            endpoint: GCS_DEFAULT_ENDPOINT.to_string(),
+            region: GCS_HMAC_REGION.to_string(),
+            access_key_id: "GOOGKEY".to_string(),
+            access_key_secret: "secret".to_string(),
+            ..Default::default()
+        };
+        let state = GcsState {
+            signer: Some(S3State::new(&s3_config, 3).unwrap()),
+            token_source: TokenSource::new_static(""),
+            scheme: "https".to_string(),
+            endpoint: GCS_DEFAULT_ENDPOINT.to_string(),
+            bucket_name: "images".to_string(),
+            object_prefix: "".to_string(),
+            retry_limit: 3,
+        };
+        let (resource, url) = state.url("obj_key", &[]);
+        let mut headers = HeaderMap::new();
+        state
+            .sign(Method::HEAD, &mut headers, &resource, &url)
+            .unwrap();
+        let authorization = headers.get(HEADER_AUTHORIZATION).unwrap().to_str().unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=GOOGKEY/"));
+        assert!(authorization.contains("/auto/s3/aws4_request"));
+        assert_eq!(headers.get("host").unwrap(), GCS_DEFAULT_ENDPOINT);
+    }
+
+    #[test]
+    fn test_gcs_read() {
+        let (endpoint, requests) = start_server(|_| MockResponse::new(206, "blob"));
+
+        let config = GcsConfig {
+            scheme: "http".to_string(),
+            endpoint,
+            bucket_name: "images".to_string(),
+            object_prefix: "nydus/".to_string(),
+            access_token: "token".to_string(),
+            timeout: 5,
+            connect_timeout: 5,
+            ..Default::default()
+        };
+        let gcs = Gcs::new(&config, Some("test-gcs-read")).unwrap();
+        let reader = gcs.get_reader("blob-id").unwrap();
+        let mut buf = vec![0u8; 4];
+        assert_eq!(reader.read(&mut buf, 8).unwrap(), 4);
+        assert_eq!(&buf, b"blob");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0]
+            .head
+            .starts_with("get /images/nydus/blob-id http/1.1"));
+        assert_eq!(requests[0].header("range"), Some("bytes=8-11"));
+        assert_eq!(requests[0].header("authorization"), Some("bearer token"));
+        gcs.shutdown();
+    }
+
+    #[test]
+    fn test_gcs_metadata_token() {
+        let expires_in = Arc::new(Mutex::new(3600));
+        let expires = expires_in.clone();
+        let (addr, requests) = start_server(move |req| {
+            let path = GCE_METADATA_TOKEN_PATH.to_lowercase();
+            if req.head.starts_with(&format!("get {} ", path)) {
+                let expires_in = *expires.lock().unwrap();
+                let body = format!(
+                    r#"{{"access_token":"token-{}","expires_in":{},"token_type":"Bearer"}}"#,
+                    expires_in, expires_in
+                );
+                MockResponse::new(200, body)
+            } else {
+                MockResponse::new(404, "")
+            }
+        });
+
+        let source = TokenSource::new_metadata(&format!("http://{}/", addr)).unwrap();
+        assert_eq!(source.token().unwrap(), "token-3600");
+        assert_eq!(source.token().unwrap(), "token-3600");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(
+            requests.lock().unwrap()[0].header("metadata-flavor"),
+            Some("google")
+        );
+
+        // Refresh tokens expiring within the refresh window.
+        *expires_in.lock().unwrap() = 60;
+        source.cache.set(AccessToken {
+            token: "token-3600".to_string(),
+            expiration: Some(Instant::now() + Duration::from_secs(60)),
+        });
+        assert_eq!(source.token().unwrap(), "token-60");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        // Avoid refreshing repeatedly, the token still expires within the window.
+        assert_eq!(source.token().unwrap(), "token-60");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        // Tokens which haven't expired yet are used if refresh fails.
+        let source = TokenSource::new_metadata(&format!("http://{}/invalid", addr)).unwrap();
+        assert!(source.token().is_err());
+        source.cache.set(AccessToken {
+            token: "old".to_string(),
+            expiration: Some(Instant::now() + Duration::from_secs(60)),
+        });
+        assert_eq!(source.token().unwrap(), "old");
+        source.cache.set(AccessToken {
+            token: "old".to_string(),
+            expiration: Some(Instant::now()),
+        });
+        assert!(source.token().is_err());
+    }
+}
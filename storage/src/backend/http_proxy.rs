@@ -274,7 +274,7 @@ impl HttpProxy {
     pub fn new(config: &HttpProxyConfig, id: Option<&str>) -> Result<HttpProxy> {
         let client = if config.addr.starts_with("http://") || config.addr.starts_with("https://") {
             let conn_cfg: ConnectionConfig = config.clone().into();
-            let conn = Connection::new(&conn_cfg)?;
+            let conn = Connection::new(&conn_cfg, None)?;
             Client::Remote(conn)
         } else {
             let client = HyperClient::unix();
@@ -15,7 +15,7 @@
 //! - [LocalDisk](localdisk/struct.LocalDisk.html): backend driver to access blobs on local disk.
 
 use std::fmt;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::{sync::Arc, time::Duration};
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
@@ -27,20 +27,37 @@ use nydus_utils::{
 use crate::utils::{alloc_buf, copyv};
 use crate::StorageError;
 
+#[cfg(feature = "backend-s3")]
+mod aws_credentials;
+#[cfg(feature = "backend-azure")]
+pub mod azure;
 #[cfg(any(
     feature = "backend-oss",
     feature = "backend-registry",
     feature = "backend-s3",
+    feature = "backend-azure",
+    feature = "backend-gcs",
     feature = "backend-http-proxy",
 ))]
 pub mod connection;
+#[cfg(feature = "backend-s3")]
+mod credential_cache;
+#[cfg(feature = "backend-registry")]
+mod docker_config;
+#[cfg(feature = "backend-gcs")]
+pub mod gcs;
 #[cfg(feature = "backend-http-proxy")]
 pub mod http_proxy;
 #[cfg(feature = "backend-localdisk")]
 pub mod localdisk;
 #[cfg(feature = "backend-localfs")]
 pub mod localfs;
-#[cfg(any(feature = "backend-oss", feature = "backend-s3"))]
+#[cfg(any(
+    feature = "backend-oss",
+    feature = "backend-s3",
+    feature = "backend-azure",
+    feature = "backend-gcs",
+))]
 pub mod object_storage;
 #[cfg(feature = "backend-oss")]
 pub mod oss;
@@ -65,7 +82,12 @@ pub enum BackendError {
     #[cfg(feature = "backend-localfs")]
     /// Error from LocalFs storage backend.
     LocalFs(self::localfs::LocalFsError),
-    #[cfg(any(feature = "backend-oss", feature = "backend-s3"))]
+    #[cfg(any(
+        feature = "backend-oss",
+        feature = "backend-s3",
+        feature = "backend-azure",
+        feature = "backend-gcs",
+    ))]
     /// Error from object storage backend.
     ObjectStorage(self::object_storage::ObjectStorageError),
     #[cfg(feature = "backend-http-proxy")]
@@ -82,7 +104,12 @@ impl fmt::Display for BackendError {
             BackendError::Registry(e) => write!(f, "{:?}", e),
             #[cfg(feature = "backend-localfs")]
             BackendError::LocalFs(e) => write!(f, "{}", e),
-            #[cfg(any(feature = "backend-oss", feature = "backend-s3"))]
+            #[cfg(any(
+                feature = "backend-oss",
+                feature = "backend-s3",
+                feature = "backend-azure",
+                feature = "backend-gcs",
+            ))]
             BackendError::ObjectStorage(e) => write!(f, "{}", e),
             #[cfg(feature = "backend-localdisk")]
             BackendError::LocalDisk(e) => write!(f, "{:?}", e),
@@ -217,6 +244,21 @@ pub trait BlobBackend: Send + Sync {
     fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>>;
 }
 
+/// Trait to stream a new blob into backend storages.
+pub trait BlobWriter: Write + Send {
+    /// Finish writing and save the written data as blob `blob_id`.
+    fn commit(&mut self, blob_id: &str) -> BackendResult<()>;
+
+    /// Cancel writing and discard the written data.
+    fn abort(&mut self) -> BackendResult<()>;
+}
+
+/// Trait to upload blob files to backend storages.
+pub trait BlobUploader: Send + Sync {
+    /// Create a writer to upload a new blob.
+    fn create_writer(&self) -> BackendResult<Box<dyn BlobWriter>>;
+}
+
 /// A buffered reader for `BlobReader` object.
 pub struct BlobBufReader {
     buf: Vec<u8>,
@@ -274,3 +316,113 @@ impl Read for BlobBufReader {
         Ok(sz)
     }
 }
+
+/// A minimal HTTP server to test network based storage backends.
+#[cfg(all(test, any(feature = "backend-azure", feature = "backend-gcs")))]
+#[allow(dead_code)]
+pub(crate) mod mock_http {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// A HTTP request received by the server.
+    #[derive(Clone, Debug)]
+    pub(crate) struct MockRequest {
+        /// Request line and headers, in lower case.
+        pub head: String,
+        pub body: Vec<u8>,
+    }
+
+    impl MockRequest {
+        /// Get value of the header `name`, which must be in lower case.
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.head.lines().find_map(|l| {
+                l.strip_prefix(name)
+                    .and_then(|v| v.strip_prefix(':'))
+                    .map(|v| v.trim())
+            })
+        }
+    }
+
+    /// A HTTP response to send back.
+    pub(crate) struct MockResponse {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl MockResponse {
+        pub fn new<B: Into<Vec<u8>>>(status: u16, body: B) -> Self {
+            MockResponse {
+                status,
+                headers: Vec::new(),
+                body: body.into(),
+            }
+        }
+
+        pub fn header(mut self, name: &str, value: &str) -> Self {
+            self.headers.push((name.to_string(), value.to_string()));
+            self
+        }
+    }
+
+    pub(crate) type MockRequests = Arc<Mutex<Vec<MockRequest>>>;
+
+    /// Serve requests one by one with responses generated by `handler`, recording the requests.
+    ///
+    /// Return the `host:port` address of the server.
+    pub(crate) fn start_server<F>(handler: F) -> (String, MockRequests)
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut req = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let pos = match req.windows(4).position(|w| w == b"\r\n\r\n") {
+                    Some(pos) => pos + 4,
+                    None => continue,
+                };
+                let mut request = MockRequest {
+                    head: String::from_utf8_lossy(&req[..pos]).to_lowercase(),
+                    body: req[pos..].to_vec(),
+                };
+                let size = request
+                    .header("content-length")
+                    .map(|v| v.parse::<usize>().unwrap())
+                    .unwrap_or_default();
+                while request.body.len() < size {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.body.extend_from_slice(&buf[..n]),
+                    }
+                }
+
+                let resp = handler(&request);
+                recorded.lock().unwrap().push(request);
+                let mut head = format!("HTTP/1.1 {} Status\r\n", resp.status);
+                for (name, value) in resp.headers.iter() {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                head.push_str(&format!(
+                    "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    resp.body.len()
+                ));
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&resp.body);
+            }
+        });
+        (addr, requests)
+    }
+}
@@ -3,22 +3,25 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-//! Base module used to implement object storage backend drivers (such as oss, s3, etc.).
+//! Base module used to implement object storage backend drivers (such as oss, s3, azure, etc.).
 
 use std::fmt;
 use std::fmt::Debug;
 use std::io::{Error, Result};
 use std::marker::Send;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use reqwest::header::{HeaderMap, CONTENT_LENGTH};
-use reqwest::Method;
+use reqwest::{Method, StatusCode};
 
 use nydus_utils::metrics::BackendMetrics;
 
-use super::connection::{Connection, ConnectionError};
+use super::connection::{respond, Connection, ConnectionError};
 use super::{BackendError, BackendResult, BlobBackend, BlobReader};
 
+// Maximum number of threads to read parts of a large request concurrently.
+const MAX_PART_READERS: usize = 8;
+
 /// Error codes related to object storage backend.
 #[derive(Debug)]
 pub enum ObjectStorageError {
@@ -63,6 +66,16 @@ pub trait ObjectStorageState: Send + Sync + Debug {
     ) -> Result<()>;
 
     fn retry_limit(&self) -> u8;
+
+    // `range_header` is the name of HTTP header to request a range of the object.
+    fn range_header(&self) -> &'static str {
+        "Range"
+    }
+
+    // `part_size` splits large reads into concurrent range requests of the size, 0 to disable.
+    fn part_size(&self) -> u64 {
+        0
+    }
 }
 
 struct ObjectStorageReader<T>
@@ -107,7 +120,59 @@ where
             })?)
     }
 
-    fn try_read(&self, mut buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let part_size = self.state.part_size() as usize;
+        if part_size == 0 || buf.len() <= part_size {
+            return self.read_range(buf, offset);
+        }
+
+        // Read parts by a bounded number of threads, each taking the next pending part.
+        let readers = std::cmp::min(buf.len().div_ceil(part_size), MAX_PART_READERS);
+        let parts = Mutex::new(buf.chunks_mut(part_size).enumerate());
+        let results = Mutex::new(Vec::new());
+        std::thread::scope(|s| {
+            for _ in 0..readers {
+                s.spawn(|| loop {
+                    let (idx, part) = match parts.lock().unwrap().next() {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    let part_offset = offset + (idx * part_size) as u64;
+                    let result = self.read_range(part, part_offset);
+                    results.lock().unwrap().push((idx, part.len(), result));
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(idx, _, _)| *idx);
+
+        // Data after a short read isn't contiguous, it only happens at the end of the object.
+        let mut size = 0;
+        for (_, len, result) in results {
+            let count = result?;
+            size += count;
+            if count < len {
+                break;
+            }
+        }
+        Ok(size)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        &self.metrics
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.state.retry_limit()
+    }
+}
+
+impl<T> ObjectStorageReader<T>
+where
+    T: ObjectStorageState,
+{
+    // Read a range of the object with a single request.
+    fn read_range(&self, mut buf: &mut [u8], offset: u64) -> BackendResult<usize> {
         let query = &[];
         let (resource, url) = self.state.url(&self.blob_id, query);
         let mut headers = HeaderMap::new();
@@ -115,7 +180,7 @@ where
         let range = format!("bytes={}-{}", offset, end_at);
 
         headers.insert(
-            "Range",
+            self.state.range_header(),
             range
                 .as_str()
                 .parse()
@@ -126,23 +191,20 @@ where
             .map_err(ObjectStorageError::Auth)?;
 
         // Safe because the the call() is a synchronous operation.
-        let mut resp = self
+        let resp = self
             .connection
-            .call::<&[u8]>(Method::GET, url.as_str(), None, None, &mut headers, true)
+            .call::<&[u8]>(Method::GET, url.as_str(), None, None, &mut headers, false)
             .map_err(ObjectStorageError::Request)?;
+        // The range starts at or beyond the end of the object.
+        if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(0);
+        }
+        let mut resp = respond(resp, true).map_err(ObjectStorageError::Request)?;
         Ok(resp
             .copy_to(&mut buf)
             .map_err(ObjectStorageError::Transport)
             .map(|size| size as usize)?)
     }
-
-    fn metrics(&self) -> &BackendMetrics {
-        &self.metrics
-    }
-
-    fn retry_limit(&self) -> u8 {
-        self.state.retry_limit()
-    }
 }
 
 #[derive(Debug)]
@@ -126,7 +126,7 @@ impl Oss {
     pub fn new(oss_config: &OssConfig, id: Option<&str>) -> Result<Oss> {
         let con_config: ConnectionConfig = oss_config.clone().into();
         let retry_limit = con_config.retry_limit;
-        let connection = Connection::new(&con_config)?;
+        let connection = Connection::new(&con_config, None)?;
         let state = Arc::new(OssState {
             scheme: oss_config.scheme.clone(),
             object_prefix: oss_config.object_prefix.clone(),
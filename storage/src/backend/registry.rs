@@ -5,7 +5,7 @@
 //! Storage backend driver to access blobs on container image registry.
 use std::collections::HashMap;
 use std::error::Error;
-use std::io::{Read, Result};
+use std::io::{Read, Result, Write};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Once, RwLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -15,7 +15,7 @@ use arc_swap::{ArcSwap, ArcSwapOption};
 use base64::Engine;
 use reqwest::blocking::Response;
 pub use reqwest::header::HeaderMap;
-use reqwest::header::{HeaderValue, CONTENT_LENGTH};
+use reqwest::header::{HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE};
 use reqwest::{Method, StatusCode};
 use url::{ParseError, Url};
 
@@ -25,7 +25,10 @@ use nydus_utils::metrics::BackendMetrics;
 use crate::backend::connection::{
     is_success_status, respond, Connection, ConnectionConfig, ConnectionError, ReqBody,
 };
-use crate::backend::{BackendError, BackendResult, BlobBackend, BlobReader};
+use crate::backend::docker_config::DockerCredentials;
+use crate::backend::{
+    BackendError, BackendResult, BlobBackend, BlobReader, BlobUploader, BlobWriter,
+};
 
 const REGISTRY_CLIENT_ID: &str = "nydus-registry-client";
 const HEADER_AUTHORIZATION: &str = "Authorization";
@@ -33,6 +36,9 @@ const HEADER_WWW_AUTHENTICATE: &str = "www-authenticate";
 
 const REGISTRY_DEFAULT_TOKEN_EXPIRATION: u64 = 10 * 60; // in seconds
 
+// Size of data to upload by each request when pushing blobs.
+const REGISTRY_UPLOAD_CHUNK_SIZE: usize = 0x100_0000;
+
 /// Error codes related to registry storage backend operations.
 #[derive(Debug)]
 pub enum RegistryError {
@@ -197,7 +203,9 @@ struct RegistryState {
     // Image repo name like: library/ubuntu
     repo: String,
     // Base64 encoded registry auth
-    auth: Option<String>,
+    auth: ArcSwapOption<String>,
+    // Resolve registry auth from docker config file, it's resolved again once rejected by registry.
+    docker_credentials: Option<DockerCredentials>,
     // Retry limit for read operation
     retry_limit: u8,
     // Scheme specified for blob server
@@ -312,7 +320,7 @@ impl RegistryState {
     ) -> Result<Response> {
         let mut headers = HeaderMap::new();
 
-        if let Some(auth) = &self.auth {
+        if let Some(auth) = self.auth.load().as_deref() {
             headers.insert(
                 HEADER_AUTHORIZATION,
                 format!("Basic {}", auth).parse().unwrap(),
@@ -367,7 +375,8 @@ impl RegistryState {
         match auth {
             Auth::Basic(_) => self
                 .auth
-                .as_ref()
+                .load()
+                .as_deref()
                 .map(|auth| format!("Basic {}", auth))
                 .ok_or_else(|| einval!("invalid auth config")),
             Auth::Bearer(auth) => {
@@ -377,6 +386,18 @@ impl RegistryState {
         }
     }
 
+    // Resolve registry auth from docker config file again, the credentials may have been rotated.
+    fn refresh_auth(&self) {
+        if let Some(creds) = self.docker_credentials.as_ref() {
+            if let Some(auth) = creds.refresh() {
+                if self.auth.load().as_deref() != Some(&auth) {
+                    info!("registry credentials of {} updated", self.host);
+                    self.auth.store(Some(Arc::new(auth)));
+                }
+            }
+        }
+    }
+
     /// Parse `www-authenticate` response header respond from registry server
     /// The header format like: `Bearer realm="https://auth.my-registry.com/token",service="my-registry.com",scope="repository:test/repo:pull,push"`
     fn parse_auth(source: &HeaderValue) -> Option<Auth> {
@@ -430,82 +451,6 @@ impl RegistryState {
         }
     }
 
-    fn fallback_http(&self) {
-        self.scheme.0.store(false, Ordering::Relaxed);
-    }
-}
-
-#[derive(Clone)]
-struct First {
-    inner: Arc<ArcSwap<Once>>,
-}
-
-impl First {
-    fn new() -> Self {
-        First {
-            inner: Arc::new(ArcSwap::new(Arc::new(Once::new()))),
-        }
-    }
-
-    fn once<F>(&self, f: F)
-    where
-        F: FnOnce(),
-    {
-        self.inner.load().call_once(f)
-    }
-
-    fn renew(&self) {
-        self.inner.store(Arc::new(Once::new()));
-    }
-
-    fn handle<F, T>(&self, handle: &mut F) -> Option<BackendResult<T>>
-    where
-        F: FnMut() -> BackendResult<T>,
-    {
-        let mut ret = None;
-        // Call once twice to ensure the subsequent requests use the new
-        // Once instance after renew happens.
-        for _ in 0..=1 {
-            self.once(|| {
-                ret = Some(handle().inspect_err(|_err| {
-                    // Replace the Once instance so that we can retry it when
-                    // the handle call failed.
-                    self.renew();
-                }));
-            });
-            if ret.is_some() {
-                break;
-            }
-        }
-        ret
-    }
-
-    /// When invoking concurrently, only one of the handle methods will be executed first,
-    /// then subsequent handle methods will be allowed to execute concurrently.
-    ///
-    /// Nydusd uses a registry backend which generates a surge of blob requests without
-    /// auth tokens on initial startup, this caused mirror backends (e.g. dragonfly)
-    /// to process very slowly. The method implements waiting for the first blob request
-    /// to complete before making other blob requests, this ensures the first request
-    /// caches a valid registry auth token, and subsequent concurrent blob requests can
-    /// reuse the cached token.
-    fn handle_force<F, T>(&self, handle: &mut F) -> BackendResult<T>
-    where
-        F: FnMut() -> BackendResult<T>,
-    {
-        self.handle(handle).unwrap_or_else(handle)
-    }
-}
-
-struct RegistryReader {
-    blob_id: String,
-    connection: Arc<Connection>,
-    state: Arc<RegistryState>,
-    metrics: Arc<BackendMetrics>,
-    first: First,
-}
-
-impl RegistryReader {
     /// Request registry server with `authorization` header
     ///
     /// Bearer token authenticate workflow:
@@ -534,6 +479,7 @@ impl RegistryReader {
     /// Response: status: 200 Ok
     fn request<R: Read + Clone + Send + 'static>(
         &self,
+        connection: &Arc<Connection>,
         method: Method,
         url: &str,
         data: Option<ReqBody<R>>,
@@ -542,7 +488,7 @@ impl RegistryReader {
     ) -> RegistryResult<Response> {
         // Try get authorization header from cache for this request
         let mut last_cached_auth = String::new();
-        let cached_auth = self.state.cached_auth.get();
+        let cached_auth = self.cached_auth.get();
         if !cached_auth.is_empty() {
             last_cached_auth = cached_auth.clone();
             headers.insert(
@@ -554,15 +500,13 @@ impl RegistryReader {
         // For upload request with payload, the auth header should be cached
         // after create_upload(), so we can request registry server directly
         if let Some(data) = data {
-            return self
-                .connection
+            return connection
                 .call(method, url, None, Some(data), &mut headers, catch_status)
                 .map_err(RegistryError::Request);
         }
 
         // Try to request registry server with `authorization` header
-        let mut resp = self
-            .connection
+        let mut resp = connection
             .call::<&[u8]>(method.clone(), url, None, None, &mut headers, false)
             .map_err(RegistryError::Request)?;
         if resp.status() == StatusCode::UNAUTHORIZED {
@@ -576,18 +520,17 @@ impl RegistryReader {
                 // resend the request to get the correct "www-authenticate" value.
                 headers.remove(HEADER_AUTHORIZATION);
 
-                resp = self
-                    .connection
+                resp = connection
                     .call::<&[u8]>(method.clone(), url, None, None, &mut headers, false)
                     .map_err(RegistryError::Request)?;
             };
 
             if let Some(resp_auth_header) = resp.headers().get(HEADER_WWW_AUTHENTICATE) {
                 // Get token from registry authorization server
-                if let Some(auth) = RegistryState::parse_auth(resp_auth_header) {
+                if let Some(auth) = Self::parse_auth(resp_auth_header) {
+                    self.refresh_auth();
                     let auth_header = self
-                        .state
-                        .get_auth_header(auth, &self.connection)
+                        .get_auth_header(auth, connection)
                         .map_err(|e| RegistryError::Common(e.to_string()))?;
 
                     headers.insert(
@@ -596,15 +539,14 @@ impl RegistryReader {
                     );
 
                     // Try to request registry server with `authorization` header again
-                    let resp = self
-                        .connection
+                    let resp = connection
                         .call(method, url, None, data, &mut headers, catch_status)
                         .map_err(RegistryError::Request)?;
 
                     let status = resp.status();
                     if is_success_status(status) {
                         // Cache authorization header for next request
-                        self.state.cached_auth.set(&last_cached_auth, auth_header)
+                        self.cached_auth.set(&last_cached_auth, auth_header)
                     }
                     return respond(resp, catch_status).map_err(RegistryError::Request);
                 }
@@ -614,6 +556,99 @@ impl RegistryReader {
         respond(resp, catch_status).map_err(RegistryError::Request)
     }
 
+    fn fallback_http(&self) {
+        self.scheme.0.store(false, Ordering::Relaxed);
+    }
+
+    // Get the absolute URL to continue a blob upload from the `location` response header.
+    fn upload_location(&self, resp: &Response) -> RegistryResult<String> {
+        let location = resp
+            .headers()
+            .get("location")
+            .ok_or_else(|| RegistryError::Common("no location in upload response".to_string()))?
+            .to_str()
+            .map_err(|e| RegistryError::Common(format!("invalid upload location, {}", e)))?;
+        let base = format!("{}://{}", self.scheme, self.host);
+        let url = Url::parse(&base).map_err(|e| RegistryError::Url(base, e))?;
+        let url = url
+            .join(location)
+            .map_err(|e| RegistryError::Url(location.to_string(), e))?;
+
+        Ok(url.to_string())
+    }
+}
+
+#[derive(Clone)]
+struct First {
+    inner: Arc<ArcSwap<Once>>,
+}
+
+impl First {
+    fn new() -> Self {
+        First {
+            inner: Arc::new(ArcSwap::new(Arc::new(Once::new()))),
+        }
+    }
+
+    fn once<F>(&self, f: F)
+    where
+        F: FnOnce(),
+    {
+        self.inner.load().call_once(f)
+    }
+
+    fn renew(&self) {
+        self.inner.store(Arc::new(Once::new()));
+    }
+
+    fn handle<F, T>(&self, handle: &mut F) -> Option<BackendResult<T>>
+    where
+        F: FnMut() -> BackendResult<T>,
+    {
+        let mut ret = None;
+        // Call once twice to ensure the subsequent requests use the new
+        // Once instance after renew happens.
+        for _ in 0..=1 {
+            self.once(|| {
+                ret = Some(handle().inspect_err(|_err| {
+                    // Replace the Once instance so that we can retry it when
+                    // the handle call failed.
+                    self.renew();
+                }));
+            });
+            if ret.is_some() {
+                break;
+            }
+        }
+        ret
+    }
+
+    /// When invoking concurrently, only one of the handle methods will be executed first,
+    /// then subsequent handle methods will be allowed to execute concurrently.
+    ///
+    /// Nydusd uses a registry backend which generates a surge of blob requests without
+    /// auth tokens on initial startup, this caused mirror backends (e.g. dragonfly)
+    /// to process very slowly. The method implements waiting for the first blob request
+    /// to complete before making other blob requests, this ensures the first request
+    /// caches a valid registry auth token, and subsequent concurrent blob requests can
+    /// reuse the cached token.
+    fn handle_force<F, T>(&self, handle: &mut F) -> BackendResult<T>
+    where
+        F: FnMut() -> BackendResult<T>,
+    {
+        self.handle(handle).unwrap_or_else(handle)
+    }
+}
+
+struct RegistryReader {
+    blob_id: String,
+    connection: Arc<Connection>,
+    state: Arc<RegistryState>,
+    metrics: Arc<BackendMetrics>,
+    first: First,
+}
+
+impl RegistryReader {
     /// Read data from registry server
     ///
     /// Step:
@@ -670,7 +705,8 @@ impl RegistryReader {
                 return self._try_read(buf, offset, false);
             }
         } else {
-            resp = match self.request::<&[u8]>(
+            resp = match self.state.request::<&[u8]>(
+                &self.connection,
                 Method::GET,
                 url.as_str(),
                 None,
@@ -687,7 +723,14 @@ impl RegistryReader {
                         .state
                         .url(url.as_str(), &[])
                         .map_err(|e| RegistryError::Url(url, e))?;
-                    self.request::<&[u8]>(Method::GET, url.as_str(), None, headers.clone(), false)?
+                    self.state.request::<&[u8]>(
+                        &self.connection,
+                        Method::GET,
+                        url.as_str(),
+                        None,
+                        headers.clone(),
+                        false,
+                    )?
                 }
                 Err(RegistryError::Request(ConnectionError::Common(e))) => {
                     if e.to_string().contains("self signed certificate") {
@@ -774,7 +817,8 @@ impl BlobReader for RegistryReader {
                 .url(&url, &[])
                 .map_err(|e| RegistryError::Url(url, e))?;
 
-            let resp = match self.request::<&[u8]>(
+            let resp = match self.state.request::<&[u8]>(
+                &self.connection,
                 Method::HEAD,
                 url.as_str(),
                 None,
@@ -791,7 +835,14 @@ impl BlobReader for RegistryReader {
                         .state
                         .url(&url, &[])
                         .map_err(|e| RegistryError::Url(url, e))?;
-                    self.request::<&[u8]>(Method::HEAD, url.as_str(), None, HeaderMap::new(), true)?
+                    self.state.request::<&[u8]>(
+                        &self.connection,
+                        Method::HEAD,
+                        url.as_str(),
+                        None,
+                        HeaderMap::new(),
+                        true,
+                    )?
                 }
                 Err(e) => {
                     return Err(BackendError::Registry(e));
@@ -828,6 +879,181 @@ impl BlobReader for RegistryReader {
     }
 }
 
+/// Writer to upload a blob to registry with the chunked upload workflow.
+///
+/// Request:  POST /v2/<repo>/blobs/uploads/
+/// Response: status: 202 Accepted
+///           header: location: /v2/<repo>/blobs/uploads/<uuid>
+///
+/// Request:  PATCH /v2/<repo>/blobs/uploads/<uuid>
+///           header: content-range: <start>-<end>
+/// Response: status: 202 Accepted
+///           header: location: /v2/<repo>/blobs/uploads/<uuid>
+///
+/// Request:  PUT /v2/<repo>/blobs/uploads/<uuid>?digest=sha256:<blob_id>
+/// Response: status: 201 Created
+struct RegistryBlobWriter {
+    connection: Arc<Connection>,
+    state: Arc<RegistryState>,
+    // Location to continue the upload, it's None once the upload is committed or aborted.
+    location: Option<String>,
+    buf: Vec<u8>,
+    chunk_size: usize,
+    offset: u64,
+}
+
+impl RegistryBlobWriter {
+    fn new(
+        connection: Arc<Connection>,
+        state: Arc<RegistryState>,
+        chunk_size: usize,
+    ) -> RegistryResult<Self> {
+        let location = Self::create_upload(&connection, &state)?;
+
+        Ok(RegistryBlobWriter {
+            connection,
+            state,
+            location: Some(location),
+            buf: Vec::with_capacity(chunk_size),
+            chunk_size,
+            offset: 0,
+        })
+    }
+
+    fn create_upload(
+        connection: &Arc<Connection>,
+        state: &RegistryState,
+    ) -> RegistryResult<String> {
+        let path = "/blobs/uploads/";
+        let url = state
+            .url(path, &[])
+            .map_err(|e| RegistryError::Url(path.to_string(), e))?;
+        let resp = match state.request::<&[u8]>(
+            connection,
+            Method::POST,
+            &url,
+            None,
+            HeaderMap::new(),
+            true,
+        ) {
+            Ok(resp) => resp,
+            Err(RegistryError::Request(ConnectionError::Common(e)))
+                if state.needs_fallback_http(&e) =>
+            {
+                state.fallback_http();
+                let url = state
+                    .url(path, &[])
+                    .map_err(|e| RegistryError::Url(path.to_string(), e))?;
+                state.request::<&[u8]>(
+                    connection,
+                    Method::POST,
+                    &url,
+                    None,
+                    HeaderMap::new(),
+                    true,
+                )?
+            }
+            Err(e) => return Err(e),
+        };
+
+        state.upload_location(&resp)
+    }
+
+    fn location(&self) -> RegistryResult<&str> {
+        self.location
+            .as_deref()
+            .ok_or_else(|| RegistryError::Common("upload has been finished".to_string()))
+    }
+
+    // Upload buffered data, with the digest to finish the upload if `blob_id` is given.
+    fn upload(&mut self, blob_id: Option<&str>) -> RegistryResult<()> {
+        let mut url = Url::parse(self.location()?)
+            .map_err(|e| RegistryError::Url(self.location.clone().unwrap_or_default(), e))?;
+        let method = if let Some(blob_id) = blob_id {
+            url.query_pairs_mut()
+                .append_pair("digest", &format!("sha256:{}", blob_id));
+            Method::PUT
+        } else {
+            Method::PATCH
+        };
+
+        let size = self.buf.len() as u64;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+        if size > 0 {
+            let range = format!("{}-{}", self.offset, self.offset + size - 1);
+            headers.insert(CONTENT_RANGE, range.parse().unwrap());
+        }
+        let data = std::mem::replace(&mut self.buf, Vec::with_capacity(self.chunk_size));
+        let resp = self.state.request::<&[u8]>(
+            &self.connection,
+            method,
+            url.as_str(),
+            Some(ReqBody::Buf(data)),
+            headers,
+            true,
+        )?;
+        self.offset += size;
+
+        if blob_id.is_some() {
+            self.location = None;
+        } else {
+            self.location = Some(self.state.upload_location(&resp)?);
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for RegistryBlobWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= self.chunk_size {
+            self.upload(None).map_err(|e| eio!(e))?;
+        }
+        Ok(buf.len())
+    }
+
+    // Buffered data is uploaded when the buffer is full or the upload is committed.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl BlobWriter for RegistryBlobWriter {
+    fn commit(&mut self, blob_id: &str) -> BackendResult<()> {
+        self.upload(Some(blob_id)).map_err(BackendError::Registry)
+    }
+
+    fn abort(&mut self) -> BackendResult<()> {
+        if let Some(location) = self.location.take() {
+            self.buf.clear();
+            self.state.request::<&[u8]>(
+                &self.connection,
+                Method::DELETE,
+                &location,
+                None,
+                HeaderMap::new(),
+                true,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RegistryBlobWriter {
+    fn drop(&mut self) {
+        if self.location.is_some() {
+            if let Err(e) = self.abort() {
+                warn!("failed to cancel blob upload, {}", e);
+            }
+        }
+    }
+}
+
 /// Storage backend based on image registry.
 pub struct Registry {
     connection: Arc<Connection>,
@@ -843,10 +1069,30 @@ impl Registry {
         let con_config: ConnectionConfig = config.clone().into();
 
         let retry_limit = con_config.retry_limit;
-        let connection = Connection::new(&con_config)?;
-        let auth = trim(config.auth.clone());
+        let mut auth = trim(config.auth.clone());
         let registry_token = trim(config.registry_token.clone());
         Self::validate_authorization_info(&auth)?;
+        let docker_credentials = config
+            .docker_config
+            .as_ref()
+            .map(|path| DockerCredentials::new(path, &config.host));
+        if let Some(creds) = docker_credentials.as_ref() {
+            match creds.resolve() {
+                Ok(Some(v)) => auth = Some(v),
+                Ok(None) => info!(
+                    "no credentials for registry {} in docker config",
+                    config.host
+                ),
+                Err(e) => warn!(
+                    "failed to resolve credentials for registry {}, {}",
+                    config.host, e
+                ),
+            }
+        }
+        let metrics = BackendMetrics::new(id, "registry");
+        let connection = Connection::new(&con_config, Some(&metrics)).inspect_err(|_| {
+            metrics.release().unwrap_or_else(|e| error!("{:?}", e));
+        })?;
         let cached_auth = if let Some(registry_token) = registry_token {
             // Store the registry bearer token to cached_auth, prefer to
             // use the token stored in cached_auth to request registry.
@@ -865,7 +1111,8 @@ impl Registry {
             scheme,
             host: config.host.clone(),
             repo: config.repo.clone(),
-            auth,
+            auth: ArcSwapOption::new(auth.map(Arc::new)),
+            docker_credentials,
             cached_auth,
             retry_limit,
             blob_url_scheme: config.blob_url_scheme.clone(),
@@ -879,7 +1126,7 @@ impl Registry {
         let registry = Registry {
             connection,
             state,
-            metrics: BackendMetrics::new(id, "registry"),
+            metrics,
             first: First::new(),
         };
 
@@ -987,6 +1234,17 @@ impl BlobBackend for Registry {
     }
 }
 
+impl BlobUploader for Registry {
+    fn create_writer(&self) -> BackendResult<Box<dyn BlobWriter>> {
+        let writer = RegistryBlobWriter::new(
+            self.connection.clone(),
+            self.state.clone(),
+            REGISTRY_UPLOAD_CHUNK_SIZE,
+        )?;
+        Ok(Box::new(writer))
+    }
+}
+
 impl Drop for Registry {
     fn drop(&mut self) {
         self.metrics.release().unwrap_or_else(|e| error!("{:?}", e));
@@ -1045,7 +1303,8 @@ mod tests {
             scheme: Scheme::new(false),
             host: "alibaba-inc.com".to_string(),
             repo: "nydus".to_string(),
-            auth: None,
+            auth: ArcSwapOption::new(None),
+            docker_credentials: None,
             retry_limit: 5,
             blob_url_scheme: "https".to_string(),
             blob_redirected_host: "oss.alibaba-inc.com".to_string(),
@@ -1066,6 +1325,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_refresh_auth() {
+        let tmpdir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let path = tmpdir.as_path().join("config.json");
+        std::fs::write(
+            &path,
+            r#"{"auths": {"my-registry.com": {"auth": "dXNlcjpwd2Q="}}}"#,
+        )
+        .unwrap();
+
+        let state = RegistryState {
+            scheme: Scheme::new(true),
+            host: "my-registry.com".to_string(),
+            repo: "nydus".to_string(),
+            auth: ArcSwapOption::new(Some(Arc::new("expired".to_string()))),
+            docker_credentials: Some(DockerCredentials::new(
+                path.to_str().unwrap(),
+                "my-registry.com",
+            )),
+            retry_limit: 5,
+            blob_url_scheme: String::new(),
+            blob_redirected_host: String::new(),
+            cached_auth_using_http_get: Default::default(),
+            cached_auth: Default::default(),
+            cached_redirect: Default::default(),
+            token_expired_at: ArcSwapOption::new(None),
+            cached_bearer_auth: ArcSwapOption::new(None),
+        };
+
+        state.refresh_auth();
+        assert_eq!(state.auth.load().as_deref().unwrap(), "dXNlcjpwd2Q=");
+        let header = state
+            .get_auth_header(
+                Auth::Basic(BasicAuth {
+                    realm: String::new(),
+                }),
+                &Connection::new(&ConnectionConfig::default(), None).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(header, "Basic dXNlcjpwd2Q=");
+    }
+
     #[test]
     fn test_parse_auth() {
         let str = "Bearer realm=\"https://auth.my-registry.com/token\",service=\"my-registry.com\",scope=\"repository:test/repo:pull,push\"";
@@ -1240,4 +1541,101 @@ mod tests {
         let result = TokenResponse::from_resp(response);
         assert!(result.is_err());
     }
+
+    type UploadRequests = Arc<std::sync::Mutex<Vec<(String, Vec<u8>)>>>;
+
+    // Serve the chunked upload workflow, recording request lines and bodies.
+    fn start_upload_server() -> (String, UploadRequests) {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for (idx, mut stream) in listener.incoming().flatten().enumerate() {
+                let mut req = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let pos = req.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+                let header = String::from_utf8_lossy(&req[..pos]).to_lowercase();
+                let mut body = req[pos..].to_vec();
+                let size = header
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length: "))
+                    .map(|v| v.trim().parse::<usize>().unwrap())
+                    .unwrap_or_default();
+                while body.len() < size {
+                    let n = stream.read(&mut buf).unwrap();
+                    body.extend_from_slice(&buf[..n]);
+                }
+                let line = header.lines().next().unwrap().to_string();
+                let status = if line.starts_with("put") {
+                    "201 Created"
+                } else if line.starts_with("delete") {
+                    "204 No Content"
+                } else {
+                    "202 Accepted"
+                };
+                recorded.lock().unwrap().push((header, body));
+                let resp = format!(
+                    "HTTP/1.1 {}\r\nLocation: /v2/test/repo/blobs/uploads/uuid-{}?state=s\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status, idx
+                );
+                let _ = stream.write_all(resp.as_bytes());
+            }
+        });
+        (addr, requests)
+    }
+
+    #[test]
+    fn test_registry_blob_writer() {
+        let (addr, requests) = start_upload_server();
+        let config = RegistryConfig {
+            host: addr,
+            repo: "test/repo".to_string(),
+            scheme: "http".to_string(),
+            ..Default::default()
+        };
+        let registry = Registry::new(&config, Some("upload")).unwrap();
+
+        let mut writer =
+            RegistryBlobWriter::new(registry.connection.clone(), registry.state.clone(), 4)
+                .unwrap();
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b"wo").unwrap();
+        writer.commit("digest").unwrap();
+        assert!(writer.commit("digest").is_err());
+        drop(writer);
+
+        let mut writer = registry.create_writer().unwrap();
+        writer.write_all(b"data").unwrap();
+        drop(writer);
+
+        let requests = requests.lock().unwrap();
+        let lines: Vec<&str> = requests
+            .iter()
+            .map(|(h, _)| h.lines().next().unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "post /v2/test/repo/blobs/uploads/ http/1.1",
+                "patch /v2/test/repo/blobs/uploads/uuid-0?state=s http/1.1",
+                "put /v2/test/repo/blobs/uploads/uuid-1?state=s&digest=sha256%3adigest http/1.1",
+                "post /v2/test/repo/blobs/uploads/ http/1.1",
+                "delete /v2/test/repo/blobs/uploads/uuid-3?state=s http/1.1",
+            ]
+        );
+        assert_eq!(requests[1].1, b"hello");
+        assert!(requests[1].0.contains("content-range: 0-4"));
+        assert_eq!(requests[2].1, b"wo");
+        assert!(requests[2].0.contains("content-range: 5-6"));
+    }
 }
@@ -19,6 +19,7 @@ use reqwest::Method;
 use sha2::{Digest, Sha256};
 use time::{format_description, OffsetDateTime};
 
+use crate::backend::aws_credentials::CredentialProvider;
 use crate::backend::connection::{Connection, ConnectionConfig};
 use crate::backend::object_storage::{ObjectStorage, ObjectStorageState};
 
@@ -26,18 +27,19 @@ const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495
 const HEADER_HOST: &str = "Host";
 const HEADER_AWZ_DATE: &str = "x-amz-date";
 const HEADER_AWZ_CONTENT_SHA256: &str = "x-amz-content-sha256";
+const HEADER_AWZ_SECURITY_TOKEN: &str = "x-amz-security-token";
 const S3_DEFAULT_ENDPOINT: &str = "s3.amazonaws.com";
 
 #[derive(Debug)]
 pub struct S3State {
     region: String,
-    access_key_id: String,
-    access_key_secret: String,
+    credentials: CredentialProvider,
     scheme: String,
     object_prefix: String,
     endpoint: String,
     bucket_name: String,
     retry_limit: u8,
+    part_size: u64,
 }
 
 /// Storage backend to access data stored in S3.
@@ -48,35 +50,40 @@ impl S3 {
     pub fn new(s3_config: &S3Config, id: Option<&str>) -> Result<S3> {
         let con_config: ConnectionConfig = s3_config.clone().into();
         let retry_limit = con_config.retry_limit;
-        let connection = Connection::new(&con_config)?;
+        let connection = Connection::new(&con_config, None)?;
+        let state = Arc::new(S3State::new(s3_config, retry_limit)?);
+        let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
+
+        Ok(ObjectStorage::new_object_storage(
+            connection,
+            state,
+            metrics,
+            id.map(|i| i.to_string()),
+        ))
+    }
+}
+
+impl S3State {
+    /// Create a new S3 state object to build urls and sign requests.
+    pub(crate) fn new(s3_config: &S3Config, retry_limit: u8) -> Result<Self> {
         let final_endpoint = if s3_config.endpoint.is_empty() {
             S3_DEFAULT_ENDPOINT.to_string()
         } else {
             s3_config.endpoint.clone()
         };
 
-        let state = Arc::new(S3State {
+        Ok(S3State {
             region: s3_config.region.clone(),
             scheme: s3_config.scheme.clone(),
             object_prefix: s3_config.object_prefix.clone(),
             endpoint: final_endpoint,
-            access_key_id: s3_config.access_key_id.clone(),
-            access_key_secret: s3_config.access_key_secret.clone(),
+            credentials: CredentialProvider::new(s3_config)?,
             bucket_name: s3_config.bucket_name.clone(),
             retry_limit,
-        });
-        let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
-
-        Ok(ObjectStorage::new_object_storage(
-            connection,
-            state,
-            metrics,
-            id.map(|i| i.to_string()),
-        ))
+            part_size: s3_config.part_size,
+        })
     }
-}
 
-impl S3State {
     // modified based on https://github.com/minio/minio-rs/blob/5fea81d68d381fd2a4c27e4d259f7012de08ab77/src/s3/utils.rs#L155-L200
     // under apache 2.0 license
     fn get_canonical_headers(&self, map: &HeaderMap) -> (String, String) {
@@ -131,9 +138,9 @@ impl S3State {
 
     // modified based on https://github.com/minio/minio-rs/blob/5fea81d68d381fd2a4c27e4d259f7012de08ab77/src/s3/signer.rs#L75-88
     // under apache 2.0 license
-    pub fn get_signing_key(&self, date: &OffsetDateTime) -> Vec<u8> {
+    pub fn get_signing_key(&self, secret: &str, date: &OffsetDateTime) -> Vec<u8> {
         let mut key: Vec<u8> = b"AWS4".to_vec();
-        key.extend(self.access_key_secret.as_bytes());
+        key.extend(secret.as_bytes());
 
         let date_key = hmac_hash(key.as_slice(), to_signer_date(date).as_bytes());
         let date_region_key = hmac_hash(date_key.as_slice(), self.region.as_bytes());
@@ -167,6 +174,7 @@ impl ObjectStorageState for S3State {
         _: &str,
         full_resource_url: &str,
     ) -> Result<()> {
+        let credentials = self.credentials.credentials()?;
         let date = OffsetDateTime::now_utc();
         let content_sha256 = EMPTY_SHA256;
         let parsed_uri = full_resource_url
@@ -186,6 +194,12 @@ impl ObjectStorageState for S3State {
             HEADER_AWZ_CONTENT_SHA256,
             EMPTY_SHA256.parse().map_err(|e| einval!(e))?,
         );
+        if let Some(token) = credentials.session_token.as_ref() {
+            headers.insert(
+                HEADER_AWZ_SECURITY_TOKEN,
+                token.parse().map_err(|e| einval!(e))?,
+            );
+        }
         let scope = format!(
             "{}/{}/{}/aws4_request",
             to_signer_date(&date),
@@ -207,11 +221,11 @@ impl ObjectStorageState for S3State {
             scope,
             canonical_request_hash
         );
-        let signing_key = self.get_signing_key(&date);
+        let signing_key = self.get_signing_key(&credentials.secret_access_key, &date);
         let signature = hmac_hash_hex(signing_key.as_slice(), string_to_sign.as_bytes());
         let authorization = format!(
             "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
-            self.access_key_id, scope, signed_headers, signature
+            credentials.access_key_id, scope, signed_headers, signature
         );
         headers.insert(
             "Authorization",
@@ -224,6 +238,10 @@ impl ObjectStorageState for S3State {
     fn retry_limit(&self) -> u8 {
         self.retry_limit
     }
+
+    fn part_size(&self) -> u64 {
+        self.part_size
+    }
 }
 
 // modified based on https://github.com/minio/minio-rs/blob/5fea81d68d381fd2a4c27e4d259f7012de08ab77/src/s3/utils.rs#L52-L56
@@ -267,22 +285,29 @@ mod tests {
     use http::{HeaderMap, Method};
     use nydus_api::S3Config;
 
+    use crate::backend::aws_credentials::CredentialProvider;
     use crate::backend::object_storage::ObjectStorageState;
     use crate::backend::s3::S3State;
     use crate::backend::BlobBackend;
 
     use super::S3;
 
-    fn get_test_s3_state() -> (S3State, String, String) {
-        let state = S3State {
-            region: "us-east-1".to_string(),
+    fn get_test_s3_state(session_token: &str) -> (S3State, String, String) {
+        let config = S3Config {
             access_key_id: "test-key".to_string(),
             access_key_secret: "test-key-secret".to_string(),
+            session_token: session_token.to_string(),
+            ..Default::default()
+        };
+        let state = S3State {
+            region: "us-east-1".to_string(),
+            credentials: CredentialProvider::new(&config).unwrap(),
             scheme: "http".to_string(),
             object_prefix: "test-prefix-".to_string(),
             endpoint: "localhost:9000".to_string(),
             bucket_name: "test-bucket".to_string(),
             retry_limit: 6,
+            part_size: 0,
         };
         let (resource, url) = state.url("test-object", &["a=b", "c=d"]);
         (state, resource, url)
@@ -312,7 +337,7 @@ mod tests {
 
     #[test]
     fn test_s3_state_url() {
-        let (_, resource, url) = get_test_s3_state();
+        let (_, resource, url) = get_test_s3_state("");
         assert_eq!(resource, "/test-bucket/test-prefix-test-object?a=b&c=d");
         assert_eq!(
             url,
@@ -322,7 +347,7 @@ mod tests {
 
     #[test]
     fn test_s3_state_sign() {
-        let (state, resource, url) = get_test_s3_state();
+        let (state, resource, url) = get_test_s3_state("");
         println!("{}", url);
         let mut headers = HeaderMap::new();
         headers.append("Range", "bytes=5242900-".parse().unwrap());
@@ -333,5 +358,113 @@ mod tests {
         let re = Regex::new(r"^AWS4-HMAC-SHA256 Credential=test-key/[0-9]{8}/us-east-1/s3/aws4_request, SignedHeaders=host;range;x-amz-content-sha256;x-amz-date, Signature=[A-Fa-f0-9]{64}$").unwrap();
         let authorization = headers.get("Authorization").unwrap();
         assert!(re.is_match(authorization.to_str().unwrap()));
+        assert!(headers.get("x-amz-security-token").is_none());
+    }
+
+    #[test]
+    fn test_s3_state_sign_with_session_token() {
+        let (state, resource, url) = get_test_s3_state("test-session-token");
+        let mut headers = HeaderMap::new();
+        state
+            .sign(Method::GET, &mut headers, &resource, &url)
+            .unwrap();
+
+        use regex::Regex;
+        let re = Regex::new(r"^AWS4-HMAC-SHA256 Credential=test-key/[0-9]{8}/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token, Signature=[A-Fa-f0-9]{64}$").unwrap();
+        let authorization = headers.get("Authorization").unwrap();
+        assert!(re.is_match(authorization.to_str().unwrap()));
+        assert_eq!(
+            headers.get("x-amz-security-token").unwrap(),
+            "test-session-token"
+        );
+    }
+
+    #[test]
+    fn test_s3_read_parts() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        use std::sync::{Arc, Mutex};
+
+        let data: Vec<u8> = (0..100u8).collect();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let recorded = ranges.clone();
+        let content = data.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut req = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let req = String::from_utf8_lossy(&req).to_lowercase();
+                let range = req
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .unwrap()
+                    .trim()
+                    .to_string();
+                let (start, end) = range.split_once('-').unwrap();
+                let start = start.parse::<usize>().unwrap();
+                let end = std::cmp::min(end.parse::<usize>().unwrap() + 1, content.len());
+                recorded.lock().unwrap().push(range.clone());
+                if start >= content.len() {
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    );
+                    continue;
+                }
+                let body = &content[start..end];
+                let header = format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+
+        let config = S3Config {
+            endpoint,
+            scheme: "http".to_string(),
+            region: "us-east-1".to_string(),
+            bucket_name: "test-bucket".to_string(),
+            access_key_id: "test-key".to_string(),
+            access_key_secret: "test-key-secret".to_string(),
+            part_size: 16,
+            retry_limit: 0,
+            timeout: 5,
+            connect_timeout: 5,
+            ..Default::default()
+        };
+        let s3 = S3::new(&config, Some("test-s3-read-parts")).unwrap();
+        let reader = s3.get_reader("test-object").unwrap();
+
+        let mut buf = vec![0u8; 40];
+        assert_eq!(reader.read(&mut buf, 10).unwrap(), 40);
+        assert_eq!(buf, data[10..50]);
+        let mut requested = ranges.lock().unwrap().clone();
+        requested.sort();
+        assert_eq!(requested, vec!["10-25", "26-41", "42-49"]);
+
+        // Short read at the end of the object.
+        ranges.lock().unwrap().clear();
+        let mut buf = vec![0u8; 40];
+        assert_eq!(reader.read(&mut buf, 80).unwrap(), 20);
+        assert_eq!(buf[..20], data[80..]);
+        assert_eq!(ranges.lock().unwrap().len(), 3);
+
+        // More parts than concurrent readers, parts beyond the object are treated as EOF.
+        ranges.lock().unwrap().clear();
+        let mut buf = vec![0u8; 160];
+        assert_eq!(reader.read(&mut buf, 0).unwrap(), 100);
+        assert_eq!(buf[..100], data[..]);
+        assert_eq!(ranges.lock().unwrap().len(), 10);
+
+        s3.shutdown();
     }
 }
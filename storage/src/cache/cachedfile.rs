@@ -28,6 +28,7 @@ use nydus_utils::{compress, digest, round_up_usize, DelayType, Delayer, FileRang
 use tokio::runtime::Runtime;
 
 use crate::backend::BlobReader;
+use crate::cache::memcache::ChunkMemCache;
 use crate::cache::state::ChunkMap;
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncPrefetchMessage, AsyncWorkerMgr};
 use crate::cache::{BlobCache, BlobIoMergeState, CasMgr};
@@ -190,6 +191,8 @@ pub(crate) struct FileCacheEntry {
     pub(crate) file: Arc<File>,
     pub(crate) file_path: Arc<String>,
     pub(crate) meta: Option<FileCacheMeta>,
+    // In-memory cache of decompressed chunk data shared by blobs of the blob cache manager.
+    pub(crate) mem_cache: Option<Arc<ChunkMemCache>>,
     pub(crate) metrics: Arc<BlobcacheMetrics>,
     pub(crate) prefetch_state: Arc<AtomicU32>,
     pub(crate) reader: Arc<dyn BlobReader>,
@@ -783,6 +786,26 @@ impl BlobCache for FileCacheEntry {
         }
     }
 
+    fn verify_cached_chunk(&self, chunk: &dyn BlobChunkInfo) -> Result<bool> {
+        // Data from tar files and legacy stargz blobs can't be verified by digest.
+        if self.is_tarfs || self.is_legacy_stargz() {
+            return Err(enosys!("cached data of the blob can't be verified"));
+        }
+
+        let mut buf = alloc_buf(chunk.uncompressed_size() as usize);
+        if let Err(e) = self.read_file_cache_data(chunk, &mut buf) {
+            warn!(
+                "storage: failed to read chunk {} from cache file of blob {}, {}",
+                chunk.id(),
+                self.blob_id(),
+                e
+            );
+            return Ok(false);
+        }
+
+        Ok(self.validate_chunk_data(chunk, &buf, true).is_ok())
+    }
+
     fn get_blob_meta_info(&self) -> Result<Option<Arc<BlobCompressionContextInfo>>> {
         if let Some(meta) = self.meta.as_ref() {
             if let Some(bm) = meta.get_blob_meta() {
@@ -1073,7 +1096,26 @@ impl FileCacheEntry {
 
         trace!("dispatch single io range {:?}", req);
         let mut blob_cci = BlobCCI::new();
+        // Data of chunks in `Memory` regions, in the order of chunks.
+        let mut mem_data = Vec::new();
         for (i, chunk) in req.chunks.iter().enumerate() {
+            if req.tags[i].is_user_io() {
+                if let Some(data) = self
+                    .chunk_mem_cache(chunk.as_ref())
+                    .and_then(|cache| cache.get(chunk.chunk_id()))
+                {
+                    mem_data.push(data);
+                    state.push(
+                        RegionType::Memory,
+                        chunk.uncompressed_offset(),
+                        chunk.uncompressed_size(),
+                        req.tags[i].clone(),
+                        Some(req.chunks[i].clone()),
+                    )?;
+                    continue;
+                }
+            }
+
             let mut is_ready = match self.chunk_map.check_ready_and_mark_pending(chunk.as_ref()) {
                 Ok(true) => true,
                 Ok(false) => false,
@@ -1094,6 +1136,8 @@ impl FileCacheEntry {
             // - the chunk is ready in the file cache
             // - data in the file cache is plaintext.
             // - data validation is disabled
+            // Chunks read by the fast path are not added into the memory cache, which needs
+            // data of the whole chunk.
             if is_ready && !self.is_raw_data && !self.is_cache_encrypted && !self.need_validation()
             {
                 // Internal IO should not be committed to local cache region, just
@@ -1149,6 +1193,7 @@ impl FileCacheEntry {
             }
         }
 
+        let mut mem_data = mem_data.into_iter();
         for r in &state.regions {
             use RegionType::*;
 
@@ -1156,6 +1201,7 @@ impl FileCacheEntry {
                 CacheFast => self.dispatch_cache_fast(cursor, r)?,
                 CacheSlow => self.dispatch_cache_slow(cursor, r)?,
                 Backend => self.dispatch_backend(cursor, r)?,
+                Memory => self.dispatch_memory(cursor, r, &mut mem_data)?,
             }
         }
 
@@ -1188,6 +1234,60 @@ impl FileCacheEntry {
         Ok(total_read)
     }
 
+    // Copy data requested by user from chunk data got from the memory cache, fallback to the file
+    // cache and storage backend if the cached data doesn't match the chunk.
+    fn dispatch_memory(
+        &self,
+        cursor: &mut MemSliceCursor,
+        region: &Region,
+        mem_data: &mut dyn Iterator<Item = Arc<Vec<u8>>>,
+    ) -> Result<usize> {
+        let mut total_read = 0;
+
+        for (i, c) in region.chunks.iter().enumerate() {
+            let user_offset = if i == 0 { region.seg.offset } else { 0 };
+            let size = std::cmp::min(
+                c.uncompressed_size() - user_offset,
+                region.seg.len - total_read as u32,
+            );
+            total_read += match mem_data.next() {
+                Some(data) if data.len() == c.uncompressed_size() as usize => {
+                    let read_size = copyv(
+                        &[data.as_slice()],
+                        cursor.inner_slice(),
+                        user_offset as usize,
+                        size as usize,
+                        cursor.index,
+                        cursor.offset,
+                    )
+                    .map(|r| r.0)
+                    .map_err(|e| {
+                        error!("failed to copy from chunk buf to buf: {:?}", e);
+                        eother!(e)
+                    })?;
+                    cursor.move_cursor(read_size);
+                    read_size
+                }
+                _ => {
+                    match self.chunk_map.check_ready_and_mark_pending(c.as_ref()) {
+                        Ok(_) | Err(StorageError::Timeout) => {}
+                        Err(e) => return Err(einval!(e)),
+                    }
+                    self.read_single_chunk(c.clone(), user_offset, size, cursor)?
+                }
+            };
+        }
+
+        Ok(total_read)
+    }
+
+    // Get the memory cache for the chunk if the chunk has a valid digest to key the memory cache.
+    fn chunk_mem_cache(&self, chunk: &dyn BlobChunkInfo) -> Option<&Arc<ChunkMemCache>> {
+        self.mem_cache
+            .as_ref()
+            .filter(|_| *chunk.chunk_id() != digest::RafsDigest::default())
+    }
+
     fn dispatch_backend(&self, mem_cursor: &mut MemSliceCursor, r: &Region) -> Result<usize> {
         let mut region = r;
         debug!(
@@ -1294,6 +1394,9 @@ impl FileCacheEntry {
         for (i, v) in bufs.enumerate() {
             let d = Arc::new(DataBuffer::Allocated(v?));
             if region.tags[i] {
+                if let Some(cache) = self.chunk_mem_cache(region.chunks[i].as_ref()) {
+                    cache.insert(region.chunks[i].chunk_id(), d.slice());
+                }
                 buffer_holder.push(d.clone());
             }
             if !self.is_raw_data {
@@ -1385,6 +1488,10 @@ impl FileCacheEntry {
             }
         };
 
+        if let Some(cache) = self.chunk_mem_cache(chunk.as_ref()) {
+            cache.insert(chunk.chunk_id(), buffer.slice());
+        }
+
         let dst_buffers = mem_cursor.inner_slice();
         let read_size = copyv(
             &[buffer.slice()],
@@ -1405,6 +1512,12 @@ impl FileCacheEntry {
     }
 
     fn read_file_cache(&self, chunk: &dyn BlobChunkInfo, buffer: &mut [u8]) -> Result<()> {
+        self.read_file_cache_data(chunk, buffer)?;
+        self.validate_chunk_data(chunk, buffer, false)?;
+        Ok(())
+    }
+
+    fn read_file_cache_data(&self, chunk: &dyn BlobChunkInfo, buffer: &mut [u8]) -> Result<()> {
         if self.is_raw_data {
             let offset = chunk.compressed_offset();
             let size = if self.is_legacy_stargz() {
@@ -1424,6 +1537,10 @@ impl FileCacheEntry {
                         "data size decoded by lz4_block doesn't match expected"
                     ));
                 }
+            } else if self.blob_compressor().need_dict() {
+                let mut buf = alloc_buf(size as usize);
+                reader.read_exact(&mut buf)?;
+                self.decompress_chunk_data(&buf, buffer, true)?;
             } else {
                 let mut decoder = Decoder::new(reader, self.blob_compressor())?;
                 decoder.read_exact(buffer)?;
@@ -1456,7 +1573,6 @@ impl FileCacheEntry {
             let size = chunk.uncompressed_size() as u64;
             FileRangeReader::new(&self.file, offset, size).read_exact(buffer)?;
         }
-        self.validate_chunk_data(chunk, buffer, false)?;
         Ok(())
     }
 
@@ -1488,6 +1604,11 @@ impl FileCacheEntry {
 impl Drop for FileCacheEntry {
     fn drop(&mut self) {
         if let Some(cas_mgr) = &self.cas_mgr {
+            if !self.file_path.is_empty() {
+                if let Err(e) = cas_mgr.unref_blob(&self.file_path) {
+                    warn!("failed to unreference cache file in cas database: {}", e);
+                }
+            }
             if let Err(e) = cas_mgr.gc() {
                 warn!("cas_mgr gc failed: {}", e);
             }
@@ -1558,6 +1679,8 @@ enum RegionType {
     CacheSlow,
     // Need to read data from storage backend.
     Backend,
+    // Data is available in the in-memory chunk cache.
+    Memory,
 }
 
 impl RegionType {
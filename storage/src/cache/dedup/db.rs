@@ -10,10 +10,12 @@ use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{Connection, DropBehavior, OpenFlags, OptionalExtension, Transaction};
 
-use super::Result;
+use super::{CasStats, Result};
 
 pub struct CasDb {
     pool: Pool<SqliteConnectionManager>,
+    // Process id of the owner of references taken by `ref_blob()`.
+    owner: u32,
 }
 
 impl CasDb {
@@ -24,6 +26,11 @@ impl CasDb {
     }
 
     pub fn from_file(db_path: impl AsRef<Path>) -> Result<CasDb> {
+        Self::with_owner(db_path, std::process::id())
+    }
+
+    /// Open the database, taking references to blob files on behalf of process `owner`.
+    pub fn with_owner(db_path: impl AsRef<Path>, owner: u32) -> Result<CasDb> {
         let mgr = SqliteConnectionManager::file(db_path)
             .with_flags(OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_READ_WRITE)
             .with_init(|c| c.execute_batch("PRAGMA journal_mode = WAL"));
@@ -52,8 +59,24 @@ impl CasDb {
             "CREATE INDEX IF NOT EXISTS ChunkIndex ON Chunks(ChunkId)",
             (),
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS ChunkBlobIndex ON Chunks(BlobId)",
+            (),
+        )?;
 
-        Ok(CasDb { pool })
+        // References to blob files, counted per process because the database may be shared.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS BlobRefs (
+            BlobId     INTEGER NOT NULL,
+            Owner      INTEGER NOT NULL,
+            RefCount   INTEGER NOT NULL,
+            PRIMARY KEY(BlobId, Owner),
+            FOREIGN KEY(BlobId) REFERENCES Blobs(BlobId)
+        )",
+            (),
+        )?;
+
+        Ok(CasDb { pool, owner })
     }
 
     pub fn get_blob_id_with_tx(tran: &Transaction, blob: &str) -> Result<Option<u64>> {
@@ -131,26 +154,194 @@ impl CasDb {
     }
 
     pub fn delete_blobs(&self, blobs: &[String]) -> Result<()> {
-        let delete_blobs_sql = "DELETE FROM Blobs WHERE BlobId = (?1)";
-        let delete_chunks_sql = "DELETE FROM Chunks WHERE BlobId = (?1)";
         let mut conn = self.get_connection()?;
         let tran = Self::begin_transaction(&mut conn)?;
 
         for blob in blobs {
             if let Some(id) = Self::get_blob_id_with_tx(&tran, blob)? {
-                if let Err(e) = tran.execute(delete_chunks_sql, [id]) {
-                    return Err(e.into());
-                }
-                if let Err(e) = tran.execute(delete_blobs_sql, [id]) {
-                    return Err(e.into());
-                }
+                Self::delete_blob_with_tx(&tran, id)?;
+            }
+        }
+        tran.commit()?;
+
+        Ok(())
+    }
+
+    /// Take a reference to the blob file, adding it into the database if needed.
+    ///
+    /// Return the number of references to the blob file held by all processes.
+    pub fn ref_blob(&self, blob: &str) -> Result<u64> {
+        let mut conn = self.get_connection()?;
+        let tran = Self::begin_transaction(&mut conn)?;
+        tran.execute("INSERT OR IGNORE INTO Blobs (FilePath) VALUES (?1)", [blob])?;
+        let id = tran.query_row(
+            "SELECT BlobId FROM Blobs WHERE FilePath = ?1",
+            [blob],
+            |row| row.get::<usize, u64>(0),
+        )?;
+        tran.execute(
+            "INSERT INTO BlobRefs (BlobId, Owner, RefCount) VALUES (?1, ?2, 1) \
+                ON CONFLICT(BlobId, Owner) DO UPDATE SET RefCount = RefCount + 1",
+            (id, self.owner),
+        )?;
+        let count = Self::get_refcount_with_tx(&tran, id)?;
+        tran.commit()?;
+
+        Ok(count)
+    }
+
+    /// Release a reference to the blob file taken by the owner process.
+    ///
+    /// Return the number of references to the blob file held by all processes, or `None` if the
+    /// blob is unknown.
+    pub fn unref_blob(&self, blob: &str) -> Result<Option<u64>> {
+        let mut conn = self.get_connection()?;
+        let tran = Self::begin_transaction(&mut conn)?;
+        let count = match Self::get_blob_id_with_tx(&tran, blob)? {
+            None => None,
+            Some(id) => {
+                tran.execute(
+                    "UPDATE BlobRefs SET RefCount = RefCount - 1 WHERE BlobId = ?1 AND Owner = ?2",
+                    (id, self.owner),
+                )?;
+                tran.execute(
+                    "DELETE FROM BlobRefs WHERE BlobId = ?1 AND RefCount <= 0",
+                    [id],
+                )?;
+                Some(Self::get_refcount_with_tx(&tran, id)?)
+            }
+        };
+        tran.commit()?;
+
+        Ok(count)
+    }
+
+    /// Get the number of references to the blob file, return `None` if the blob is unknown.
+    pub fn get_blob_refcount(&self, blob: &str) -> Result<Option<u64>> {
+        let sql = "SELECT (SELECT COALESCE(SUM(RefCount), 0) FROM BlobRefs \
+                WHERE BlobRefs.BlobId = Blobs.BlobId) \
+                FROM Blobs WHERE FilePath = ?";
+
+        let count = self
+            .get_connection()?
+            .query_row(sql, [blob], |row| row.get::<usize, u64>(0))
+            .optional()?;
+
+        Ok(count)
+    }
+
+    /// Drop references held by processes which have exited.
+    ///
+    /// References held by the owner process itself are kept. Return the number of dropped
+    /// references.
+    pub fn release_stale_refs(&self, alive: impl Fn(u32) -> bool) -> Result<u64> {
+        let mut conn = self.get_connection()?;
+        let tran = Self::begin_transaction(&mut conn)?;
+        let owners = {
+            let mut stmt = tran.prepare("SELECT DISTINCT Owner FROM BlobRefs")?;
+            let rows = stmt.query_map([], |row| row.get::<usize, u32>(0))?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+        let mut count = 0;
+        for owner in owners {
+            if owner != self.owner && !alive(owner) {
+                count += tran.query_row(
+                    "SELECT COALESCE(SUM(RefCount), 0) FROM BlobRefs WHERE Owner = ?1",
+                    [owner],
+                    |row| row.get::<usize, u64>(0),
+                )?;
+                tran.execute("DELETE FROM BlobRefs WHERE Owner = ?1", [owner])?;
+            }
+        }
+        tran.commit()?;
+
+        Ok(count)
+    }
+
+    /// Delete blobs which are not referenced anymore and their chunks.
+    ///
+    /// Return paths of deleted blobs and number of deleted chunks.
+    pub fn delete_unreferenced_blobs(&self, blobs: &[String]) -> Result<(Vec<String>, u64)> {
+        let mut conn = self.get_connection()?;
+        let tran = Self::begin_transaction(&mut conn)?;
+        let mut deleted = Vec::new();
+        let mut chunks = 0;
+
+        for blob in blobs {
+            let id = match Self::get_blob_id_with_tx(&tran, blob)? {
+                Some(id) => id,
+                None => continue,
+            };
+            if Self::get_refcount_with_tx(&tran, id)? == 0 {
+                chunks += Self::delete_blob_with_tx(&tran, id)?;
+                deleted.push(blob.to_string());
+            }
+        }
+        tran.commit()?;
+
+        Ok((deleted, chunks))
+    }
+
+    /// Drop stale records from the database.
+    ///
+    /// Blobs for which `exists()` returns false are removed with all their chunks, then chunks
+    /// not associated with any blob are removed. Return paths of deleted blobs and number of
+    /// deleted chunks.
+    pub fn gc(&self, exists: impl Fn(&str) -> bool) -> Result<(Vec<String>, u64)> {
+        let mut deleted = Vec::new();
+        let mut chunks = 0;
+        let mut conn = self.get_connection()?;
+        let tran = Self::begin_transaction(&mut conn)?;
+
+        let blobs = {
+            let mut stmt = tran.prepare("SELECT BlobId, FilePath FROM Blobs")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<usize, u64>(0)?, row.get::<usize, String>(1)?))
+            })?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+        for (id, path) in blobs {
+            if !exists(&path) {
+                chunks += Self::delete_blob_with_tx(&tran, id)?;
+                deleted.push(path);
             }
         }
+        chunks += tran.execute(
+            "DELETE FROM Chunks WHERE BlobId IS NULL OR BlobId NOT IN (SELECT BlobId FROM Blobs)",
+            (),
+        )? as u64;
+        tran.execute(
+            "DELETE FROM BlobRefs WHERE BlobId NOT IN (SELECT BlobId FROM Blobs)",
+            (),
+        )?;
         tran.commit()?;
 
+        Ok((deleted, chunks))
+    }
+
+    /// Rebuild the database file to reclaim space released by deleted records.
+    pub fn compact(&self) -> Result<()> {
+        let conn = self.get_connection()?;
+        conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
         Ok(())
     }
 
+    /// Get statistics information about the database.
+    pub fn get_stats(&self) -> Result<CasStats> {
+        let conn = self.get_connection()?;
+        let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<usize, u64>(0));
+        let page_count = count("PRAGMA page_count")?;
+        let page_size = count("PRAGMA page_size")?;
+
+        Ok(CasStats {
+            blobs: count("SELECT COUNT(*) FROM Blobs")?,
+            referenced_blobs: count("SELECT COUNT(DISTINCT BlobId) FROM BlobRefs")?,
+            chunks: count("SELECT COUNT(*) FROM Chunks")?,
+            unique_chunks: count("SELECT COUNT(DISTINCT ChunkId) FROM Chunks")?,
+            db_size: page_count * page_size,
+        })
+    }
+
     pub fn get_chunk_info(&self, chunk_id: &str) -> Result<Option<(String, u64)>> {
         let sql = "SELECT FilePath, ChunkOffset \
                 FROM Chunks INDEXED BY ChunkIndex \
@@ -209,6 +400,22 @@ impl CasDb {
         Ok(())
     }
 
+    fn delete_blob_with_tx(tran: &Transaction, id: u64) -> Result<u64> {
+        let chunks = tran.execute("DELETE FROM Chunks WHERE BlobId = (?1)", [id])?;
+        tran.execute("DELETE FROM BlobRefs WHERE BlobId = (?1)", [id])?;
+        tran.execute("DELETE FROM Blobs WHERE BlobId = (?1)", [id])?;
+        Ok(chunks as u64)
+    }
+
+    fn get_refcount_with_tx(tran: &Transaction, id: u64) -> Result<u64> {
+        let count = tran.query_row(
+            "SELECT COALESCE(SUM(RefCount), 0) FROM BlobRefs WHERE BlobId = ?1",
+            [id],
+            |row| row.get::<usize, u64>(0),
+        )?;
+        Ok(count)
+    }
+
     fn begin_transaction(
         conn: &mut PooledConnection<SqliteConnectionManager>,
     ) -> Result<Transaction> {
@@ -316,4 +523,106 @@ mod tests {
         let res = cas_mgr.get_chunk_info("chunk2").unwrap();
         assert!(res.is_none());
     }
+
+    #[test]
+    fn test_cas_refcount() {
+        let tmpdir = TempDir::new().unwrap();
+        let db = CasDb::new(tmpdir.as_path()).unwrap();
+
+        assert_eq!(db.ref_blob("/tmp/blob1").unwrap(), 1);
+        assert_eq!(db.ref_blob("/tmp/blob1").unwrap(), 2);
+        db.add_chunk("chunk1", 0, "/tmp/blob1").unwrap();
+        db.add_blob("/tmp/blob2").unwrap();
+        db.add_chunk("chunk2", 0, "/tmp/blob2").unwrap();
+        assert_eq!(db.get_blob_refcount("/tmp/blob2").unwrap(), Some(0));
+        assert_eq!(db.unref_blob("/tmp/blob3").unwrap(), None);
+
+        let blobs = ["/tmp/blob1".to_string(), "/tmp/blob2".to_string()];
+        let (deleted, chunks) = db.delete_unreferenced_blobs(&blobs).unwrap();
+        assert_eq!(deleted, vec!["/tmp/blob2".to_string()]);
+        assert_eq!(chunks, 1);
+
+        assert_eq!(db.unref_blob("/tmp/blob1").unwrap(), Some(1));
+        assert_eq!(db.unref_blob("/tmp/blob1").unwrap(), Some(0));
+        assert_eq!(db.unref_blob("/tmp/blob1").unwrap(), Some(0));
+        let (deleted, _) = db.delete_unreferenced_blobs(&blobs).unwrap();
+        assert_eq!(deleted, vec!["/tmp/blob1".to_string()]);
+        assert!(db.get_chunk_info("chunk1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cas_refcount_owners() {
+        let tmpdir = TempDir::new().unwrap();
+        let path = tmpdir.as_path().join("cas.db");
+        let db1 = CasDb::with_owner(&path, 1).unwrap();
+        let db2 = CasDb::with_owner(&path, 2).unwrap();
+
+        assert_eq!(db1.ref_blob("/tmp/blob1").unwrap(), 1);
+        assert_eq!(db2.ref_blob("/tmp/blob1").unwrap(), 2);
+        assert_eq!(db2.ref_blob("/tmp/blob1").unwrap(), 3);
+        // References held by other processes are not released.
+        assert_eq!(db1.unref_blob("/tmp/blob1").unwrap(), Some(2));
+        assert_eq!(db1.unref_blob("/tmp/blob1").unwrap(), Some(2));
+        assert_eq!(db1.get_stats().unwrap().referenced_blobs, 1);
+
+        assert_eq!(db1.release_stale_refs(|_| true).unwrap(), 0);
+        // References of the process itself are never released as stale.
+        assert_eq!(db2.release_stale_refs(|_| false).unwrap(), 0);
+        assert_eq!(db1.release_stale_refs(|_| false).unwrap(), 2);
+        assert_eq!(db2.get_blob_refcount("/tmp/blob1").unwrap(), Some(0));
+        assert_eq!(db2.get_stats().unwrap().referenced_blobs, 0);
+    }
+
+    #[test]
+    fn test_cas_gc_and_stats() {
+        let tmpdir = TempDir::new().unwrap();
+        let db = CasDb::new(tmpdir.as_path()).unwrap();
+
+        db.ref_blob("/tmp/blob1").unwrap();
+        db.add_blob("/tmp/blob2").unwrap();
+        db.add_chunk("chunk1", 0, "/tmp/blob1").unwrap();
+        db.add_chunk("chunk1", 4096, "/tmp/blob2").unwrap();
+        db.add_chunk("chunk2", 8192, "/tmp/blob2").unwrap();
+        // Chunk without associated blob.
+        db.add_chunk("chunk3", 0, "/tmp/blob3").unwrap();
+
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.blobs, 2);
+        assert_eq!(stats.referenced_blobs, 1);
+        assert_eq!(stats.chunks, 4);
+        assert_eq!(stats.unique_chunks, 3);
+        assert!(stats.db_size > 0);
+
+        let (deleted, chunks) = db.gc(|path| path == "/tmp/blob1").unwrap();
+        assert_eq!(deleted, vec!["/tmp/blob2".to_string()]);
+        assert_eq!(chunks, 3);
+        db.compact().unwrap();
+
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.blobs, 1);
+        assert_eq!(stats.chunks, 1);
+        assert_eq!(stats.unique_chunks, 1);
+        let (file, offset) = db.get_chunk_info("chunk1").unwrap().unwrap();
+        assert_eq!(&file, "/tmp/blob1");
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn test_cas_upgrade_schema() {
+        let tmpdir = TempDir::new().unwrap();
+        let path = tmpdir.as_path().join("cas.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute(
+            "CREATE TABLE Blobs (BlobId INTEGER PRIMARY KEY, FilePath TEXT NOT NULL UNIQUE)",
+            (),
+        )
+        .unwrap();
+        conn.execute("INSERT INTO Blobs (FilePath) VALUES ('/tmp/blob1')", ())
+            .unwrap();
+        drop(conn);
+
+        let db = CasDb::from_file(&path).unwrap();
+        assert_eq!(db.get_blob_refcount("/tmp/blob1").unwrap(), Some(0));
+        assert_eq!(db.ref_blob("/tmp/blob1").unwrap(), 1);
+    }
 }
@@ -10,9 +10,14 @@ use std::io::Error;
 use std::path::Path;
 use std::sync::{Arc, Mutex, RwLock};
 
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
 use nydus_utils::digest::RafsDigest;
+use serde::Serialize;
 
 use crate::cache::dedup::db::CasDb;
+use crate::cache::filecache::{BLOB_DATA_FILE_SUFFIX, BLOB_RAW_FILE_SUFFIX};
 use crate::device::{BlobChunkInfo, BlobInfo};
 use crate::utils::copy_file_range;
 
@@ -63,6 +68,30 @@ impl From<Error> for CasError {
 /// Specialized `Result` for local cas.
 type Result<T> = std::result::Result<T, CasError>;
 
+/// Statistics information about the local cas database.
+#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+pub struct CasStats {
+    /// Number of blob files recorded in the database.
+    pub blobs: u64,
+    /// Number of blob files still referenced by cache entries.
+    pub referenced_blobs: u64,
+    /// Number of chunk records.
+    pub chunks: u64,
+    /// Number of distinct chunks.
+    pub unique_chunks: u64,
+    /// Size of the database file in bytes.
+    pub db_size: u64,
+}
+
+/// Result of a garbage collection pass on the local cas database.
+#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+pub struct CasGcStats {
+    /// Number of blob records removed.
+    pub removed_blobs: u64,
+    /// Number of chunk records removed.
+    pub removed_chunks: u64,
+}
+
 pub struct CasMgr {
     db: CasDb,
     fds: RwLock<HashMap<String, Arc<File>>>,
@@ -70,7 +99,14 @@ pub struct CasMgr {
 
 impl CasMgr {
     pub fn new(db_path: impl AsRef<Path>) -> Result<Self> {
-        let db = CasDb::from_file(db_path.as_ref())?;
+        Self::with_owner(db_path, std::process::id())
+    }
+
+    fn with_owner(db_path: impl AsRef<Path>, owner: u32) -> Result<Self> {
+        let db = CasDb::with_owner(db_path.as_ref(), owner)?;
+        // The database may be shared by running nydusd instances, so only drop references left
+        // by processes which have exited.
+        db.release_stale_refs(process_alive)?;
 
         Ok(CasMgr {
             db,
@@ -177,6 +213,67 @@ impl CasMgr {
         Ok(())
     }
 
+    /// Take a reference to a cache file, so it won't be removed by `delete_blob()`.
+    pub fn ref_blob(&self, path: &str) -> Result<u64> {
+        self.db.ref_blob(path)
+    }
+
+    /// Release a reference to a cache file.
+    pub fn unref_blob(&self, path: &str) -> Result<Option<u64>> {
+        self.db.unref_blob(path)
+    }
+
+    /// Remove records of unreferenced cache files for blob `blob_id`.
+    ///
+    /// It's called when the blob cache files have been removed, so chunk data from those files
+    /// won't be used for dedup any more. Only cache files named by the blob id, optionally
+    /// followed by a known cache file suffix, are matched.
+    pub fn delete_blob(&self, blob_id: &str) -> Result<CasGcStats> {
+        if blob_id.is_empty() {
+            return Err(CasError::Io(einval!("blob id to delete is empty")));
+        }
+        let names: Vec<String> = ["", BLOB_DATA_FILE_SUFFIX, BLOB_RAW_FILE_SUFFIX]
+            .iter()
+            .map(|suffix| blob_id.to_string() + suffix)
+            .collect();
+        let blobs: Vec<String> = self
+            .db
+            .get_all_blobs()?
+            .into_iter()
+            .filter_map(|(_, path)| {
+                let name = Path::new(&path).file_name()?.to_str()?;
+                names.iter().any(|v| v == name).then_some(path)
+            })
+            .collect();
+        if blobs.is_empty() {
+            return Ok(CasGcStats::default());
+        }
+
+        let (deleted, removed_chunks) = self.db.delete_unreferenced_blobs(&blobs)?;
+        Ok(self.forget_blobs(deleted, removed_chunks))
+    }
+
+    /// Get statistics information about the local cas database.
+    pub fn stats(&self) -> Result<CasStats> {
+        self.db.get_stats()
+    }
+
+    /// Rebuild the database file to reclaim unused space.
+    pub fn compact(&self) -> Result<()> {
+        self.db.compact()
+    }
+
+    fn forget_blobs(&self, blobs: Vec<String>, removed_chunks: u64) -> CasGcStats {
+        let mut guard = self.fds.write().unwrap();
+        for path in blobs.iter() {
+            guard.remove(path);
+        }
+        CasGcStats {
+            removed_blobs: blobs.len() as u64,
+            removed_chunks,
+        }
+    }
+
     fn chunk_key(blob: &BlobInfo, chunk: &dyn BlobChunkInfo) -> String {
         let id = chunk.chunk_id();
         if *id == RafsDigest::default() {
@@ -187,30 +284,23 @@ impl CasMgr {
     }
 
     /// Check if blobs in the database still exist on the filesystem and perform garbage collection.
-    pub fn gc(&self) -> Result<()> {
-        let all_blobs = self.db.get_all_blobs()?;
-        let mut blobs_not_exist = Vec::new();
-        for (_, file_path) in all_blobs {
-            if !std::path::Path::new(&file_path).exists() {
-                blobs_not_exist.push(file_path);
-            }
-        }
-
-        // If there are any non-existent blobs, delete them from the database.
-        if !blobs_not_exist.is_empty() {
-            self.db.delete_blobs(&blobs_not_exist).map_err(|e| {
-                warn!("failed to delete blobs: {}", e);
-                e
-            })?;
-        }
-
-        let mut guard = self.fds.write().unwrap();
-        for path in blobs_not_exist {
-            // Remove the non-existent blob paths from the cache.
-            guard.remove(&path);
-        }
+    ///
+    /// References held by processes which have exited are dropped, then records of non-existent
+    /// blob files and chunks not associated with any blob are removed.
+    pub fn gc(&self) -> Result<CasGcStats> {
+        self.db.release_stale_refs(process_alive)?;
+        let (deleted, removed_chunks) = self
+            .db
+            .gc(|path| Path::new(path).exists())
+            .inspect_err(|e| warn!("failed to delete blobs: {}", e))?;
+        Ok(self.forget_blobs(deleted, removed_chunks))
+    }
+}
 
-        Ok(())
+fn process_alive(pid: u32) -> bool {
+    match kill(Pid::from_raw(pid as i32), None) {
+        Ok(_) | Err(Errno::EPERM) => true,
+        Err(_) => false,
     }
 }
 
@@ -221,6 +311,7 @@ mod tests {
     use crate::test::MockChunkInfo;
     use crate::RAFS_DEFAULT_CHUNK_SIZE;
     use std::io::{Read, Write};
+    use std::process::Command;
     use vmm_sys_util::tempfile::TempFile;
 
     #[test]
@@ -315,9 +406,84 @@ mod tests {
         assert_eq!(all_blobs_before_gc.len(), 1);
 
         drop(tmpfile);
-        mgr.gc().unwrap();
+        let stats = mgr.gc().unwrap();
+        assert_eq!(stats.removed_blobs, 1);
+        assert_eq!(stats.removed_chunks, 1);
 
         let all_blobs_after_gc = mgr.db.get_all_blobs().unwrap();
         assert_eq!(all_blobs_after_gc.len(), 0);
     }
+
+    #[test]
+    fn test_cas_delete_blob() {
+        let dbfile = TempFile::new().unwrap();
+        let mgr = CasMgr::new(dbfile.as_path()).unwrap();
+
+        mgr.record_chunk_raw("chunk1", "/cache/blob1.blob.data", 0)
+            .unwrap();
+        mgr.record_chunk_raw("chunk2", "/cache/blob2.blob.data", 0)
+            .unwrap();
+        assert_eq!(mgr.ref_blob("/cache/blob1.blob.data").unwrap(), 1);
+
+        // Referenced blob files are kept.
+        let stats = mgr.delete_blob("blob1").unwrap();
+        assert_eq!(stats, CasGcStats::default());
+        let stats = mgr.delete_blob("blob2").unwrap();
+        assert_eq!(stats.removed_blobs, 1);
+        assert_eq!(stats.removed_chunks, 1);
+
+        // Only cache files of the exact blob id are matched.
+        mgr.record_chunk_raw("chunk3", "/cache/blob10.blob.data", 0)
+            .unwrap();
+        mgr.record_chunk_raw("chunk4", "/cache/xblob1.blob.raw", 0)
+            .unwrap();
+        assert!(mgr.delete_blob("").is_err());
+        assert_eq!(mgr.delete_blob("blob").unwrap(), CasGcStats::default());
+
+        assert_eq!(mgr.unref_blob("/cache/blob1.blob.data").unwrap(), Some(0));
+        let stats = mgr.delete_blob("blob1").unwrap();
+        assert_eq!(stats.removed_blobs, 1);
+        let stats = mgr.delete_blob("blob10").unwrap();
+        assert_eq!(stats.removed_blobs, 1);
+        let stats = mgr.delete_blob("xblob1").unwrap();
+        assert_eq!(stats.removed_blobs, 1);
+        mgr.compact().unwrap();
+        assert_eq!(
+            mgr.stats().unwrap(),
+            CasStats {
+                db_size: mgr.stats().unwrap().db_size,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_cas_shared_db() {
+        let dbfile = TempFile::new().unwrap();
+        let blob_path = "/cache/blob1.blob.data";
+        let mgr1 = CasMgr::new(dbfile.as_path()).unwrap();
+        mgr1.record_chunk_raw("chunk1", blob_path, 0).unwrap();
+        assert_eq!(mgr1.ref_blob(blob_path).unwrap(), 1);
+
+        // Opening the database by another running daemon keeps references of the first one.
+        let mut daemon = Command::new("sleep").arg("60").spawn().unwrap();
+        let mgr2 = CasMgr::with_owner(dbfile.as_path(), daemon.id()).unwrap();
+        assert_eq!(mgr2.db.get_blob_refcount(blob_path).unwrap(), Some(1));
+        assert_eq!(mgr2.ref_blob(blob_path).unwrap(), 2);
+        assert_eq!(mgr1.unref_blob(blob_path).unwrap(), Some(1));
+        assert_eq!(mgr1.unref_blob(blob_path).unwrap(), Some(1));
+        assert_eq!(mgr1.delete_blob("blob1").unwrap(), CasGcStats::default());
+        assert_eq!(mgr1.stats().unwrap().referenced_blobs, 1);
+
+        // References left by an exited daemon are dropped when opening the database again.
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+        drop(mgr2);
+        let mgr3 = CasMgr::new(dbfile.as_path()).unwrap();
+        assert_eq!(mgr3.db.get_blob_refcount(blob_path).unwrap(), Some(0));
+        let stats = mgr3.delete_blob("blob1").unwrap();
+        assert_eq!(stats.removed_blobs, 1);
+        assert_eq!(stats.removed_chunks, 1);
+        drop(mgr1);
+    }
 }
@@ -0,0 +1,379 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reclaim disk space used by cached blobs in the filecache working directory.
+//!
+//! Cached blobs are evicted as a whole, including the data file, the chunk map and the blob meta
+//! file, in LRU order. The modification time of the blob data file is used as the last access
+//! time, which is refreshed when a blob is opened or released by a `FileCacheMgr`. Blobs which
+//! are still referenced by any `FileCacheMgr` instance in the process are never evicted.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Result;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use nydus_api::FileCacheConfig;
+use nydus_utils::metrics::{BlobcacheMetrics, Metric};
+
+use super::{BLOB_DATA_FILE_SUFFIX, BLOB_RAW_FILE_SUFFIX};
+use crate::cache::cachedfile::FileCacheEntry;
+
+lazy_static::lazy_static!(
+    // Blobs managed by all `FileCacheMgr` instances in the process, keyed by `$work_dir/$blob_id`.
+    // Multiple instances may share the same working directory, so a blob is only evictable when
+    // no other instance holds it.
+    static ref ACTIVE_BLOBS: Mutex<HashMap<PathBuf, u32>> = Mutex::new(HashMap::new());
+);
+
+fn blob_key(work_dir: &str, blob_id: &str) -> PathBuf {
+    let dir = Path::new(work_dir);
+    dir.canonicalize()
+        .unwrap_or_else(|_| dir.to_path_buf())
+        .join(blob_id)
+}
+
+/// Record that the blob is managed by a `FileCacheMgr` instance.
+pub(crate) fn register_blob(work_dir: &str, blob_id: &str) {
+    let key = blob_key(work_dir, blob_id);
+    *ACTIVE_BLOBS.lock().unwrap().entry(key).or_insert(0) += 1;
+}
+
+/// Record that the blob has been released by a `FileCacheMgr` instance.
+pub(crate) fn unregister_blob(work_dir: &str, blob_id: &str) {
+    let key = blob_key(work_dir, blob_id);
+    release_blob(&mut ACTIVE_BLOBS.lock().unwrap(), &key);
+}
+
+fn release_blob(active_blobs: &mut HashMap<PathBuf, u32>, key: &Path) {
+    if let Some(count) = active_blobs.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            active_blobs.remove(key);
+        }
+    }
+}
+
+/// Refresh the last access time of a cached blob.
+pub(crate) fn touch_blob_file(file: &File) {
+    if let Err(e) = file.set_modified(SystemTime::now()) {
+        debug!(
+            "filecache: failed to update access time of cache file, {}",
+            e
+        );
+    }
+}
+
+// Disk usage and last access time of a cached blob.
+#[derive(Default)]
+struct CachedBlobStat {
+    files: Vec<PathBuf>,
+    disk_usage: u64,
+    last_access: u64,
+}
+
+/// Background worker to evict cached blobs when disk usage exceeds the configured capacity or
+/// blobs have been idle for too long.
+pub(crate) struct BlobReclaimer {
+    work_dir: String,
+    capacity: u64,
+    max_idle_time: u64,
+    interval: Duration,
+    blobs: Arc<RwLock<HashMap<String, Arc<FileCacheEntry>>>>,
+    metrics: Arc<BlobcacheMetrics>,
+    closed: Arc<AtomicBool>,
+}
+
+impl BlobReclaimer {
+    /// Create a new instance of `BlobReclaimer`.
+    pub fn new(
+        config: &FileCacheConfig,
+        work_dir: &str,
+        blobs: Arc<RwLock<HashMap<String, Arc<FileCacheEntry>>>>,
+        metrics: Arc<BlobcacheMetrics>,
+        closed: Arc<AtomicBool>,
+    ) -> Self {
+        BlobReclaimer {
+            work_dir: work_dir.to_string(),
+            capacity: config.capacity,
+            max_idle_time: config.max_idle_time,
+            interval: Duration::from_secs(std::cmp::max(config.gc_interval, 1)),
+            blobs,
+            metrics,
+            closed,
+        }
+    }
+
+    /// Start a working thread to reclaim disk space periodically.
+    pub fn start(self) -> Result<()> {
+        thread::Builder::new()
+            .name("nydus_cache_reclaimer".to_string())
+            .spawn(move || {
+                let step = std::cmp::min(self.interval, Duration::from_secs(1));
+                let mut elapsed = Duration::ZERO;
+                while !self.closed.load(Ordering::Acquire) {
+                    thread::sleep(step);
+                    elapsed += step;
+                    if elapsed >= self.interval {
+                        elapsed = Duration::ZERO;
+                        if let Err(e) = self.reclaim() {
+                            warn!("filecache: failed to reclaim cached blobs, {}", e);
+                        }
+                    }
+                }
+                info!("filecache: reclaimer thread exits.");
+            })
+            .map(|_| ())
+    }
+
+    /// Evict cached blobs in LRU order until disk usage drops below capacity, and evict all
+    /// blobs which have been idle for more than `max_idle_time` seconds.
+    pub fn reclaim(&self) -> Result<()> {
+        let stats = self.scan_work_dir()?;
+        let mut total: u64 = stats.values().map(|s| s.disk_usage).sum();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut candidates: Vec<(&String, &CachedBlobStat)> = stats.iter().collect();
+        candidates.sort_by_key(|(_, s)| s.last_access);
+
+        let mut evicted = false;
+        for (blob_id, stat) in candidates {
+            let idle = self.max_idle_time > 0
+                && now.saturating_sub(stat.last_access) >= self.max_idle_time;
+            let over_capacity = self.capacity > 0 && total > self.capacity;
+            if !idle && !over_capacity {
+                break;
+            }
+            if self.try_evict(blob_id, stat) {
+                total = total.saturating_sub(stat.disk_usage);
+                evicted = true;
+            }
+        }
+
+        if evicted {
+            #[cfg(feature = "dedup")]
+            if let Some(cas_mgr) = crate::cache::CasMgr::get_singleton() {
+                if let Err(e) = cas_mgr.gc() {
+                    warn!("filecache: cas_mgr gc failed: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Collect disk usage and last access time of all cached blobs in the working directory.
+    fn scan_work_dir(&self) -> Result<HashMap<String, CachedBlobStat>> {
+        let mut files = Vec::new();
+        let mut stats: HashMap<String, CachedBlobStat> = HashMap::new();
+
+        for entry in fs::read_dir(&self.work_dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let blob_id = name
+                .strip_suffix(BLOB_DATA_FILE_SUFFIX)
+                .or_else(|| name.strip_suffix(BLOB_RAW_FILE_SUFFIX));
+            if let Some(blob_id) = blob_id {
+                stats.entry(blob_id.to_string()).or_default();
+            }
+            files.push((name, entry.path()));
+        }
+
+        // All state files of a blob share the same `$blob_id.` prefix, the raw blob file named
+        // `$blob_id` itself is used by tarfs and is never touched.
+        for (name, path) in files {
+            let stat = match name.split_once('.') {
+                Some((blob_id, _)) => match stats.get_mut(blob_id) {
+                    Some(stat) => stat,
+                    None => continue,
+                },
+                None => continue,
+            };
+            if let Ok(md) = fs::symlink_metadata(&path) {
+                if md.is_file() {
+                    stat.disk_usage += md.blocks() * 512;
+                    stat.last_access = std::cmp::max(stat.last_access, md.mtime() as u64);
+                    stat.files.push(path);
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn try_evict(&self, blob_id: &str, stat: &CachedBlobStat) -> bool {
+        // Both locks are held until the cache files are removed, so no `FileCacheMgr` instance
+        // could register the blob and open its cache files in the meantime.
+        let mut guard = self.blobs.write().unwrap();
+        let key = blob_key(&self.work_dir, blob_id);
+        let mut active_blobs = ACTIVE_BLOBS.lock().unwrap();
+        let owned = match guard.get(blob_id) {
+            Some(entry) if Arc::strong_count(entry) > 1 => return false,
+            Some(_) => true,
+            None => false,
+        };
+        let active = active_blobs.get(&key).copied().unwrap_or(0);
+        if active > owned as u32 {
+            return false;
+        }
+        if owned {
+            guard.remove(blob_id);
+            release_blob(&mut active_blobs, &key);
+        }
+
+        info!(
+            "filecache: evict cached blob {}, reclaim {} bytes",
+            blob_id, stat.disk_usage
+        );
+        for path in stat.files.iter() {
+            if let Err(e) = fs::remove_file(path) {
+                warn!(
+                    "filecache: failed to remove cache file {}, {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+        drop(active_blobs);
+        drop(guard);
+
+        self.metrics
+            .underlying_files
+            .lock()
+            .unwrap()
+            .remove(&(blob_id.to_string() + BLOB_DATA_FILE_SUFFIX));
+        self.metrics.evicted_blobs.inc();
+        self.metrics.reclaimed_bytes.add(stat.disk_usage);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn create_blob_files(dir: &Path, blob_id: &str, size: usize, mtime: SystemTime) {
+        for suffix in [BLOB_DATA_FILE_SUFFIX, ".blob.data.chunk_map", ".blob.meta"] {
+            let mut file = File::create(dir.join(format!("{}{}", blob_id, suffix))).unwrap();
+            file.write_all(&vec![0x5au8; size]).unwrap();
+            file.sync_all().unwrap();
+            file.set_modified(mtime).unwrap();
+        }
+    }
+
+    fn new_reclaimer(dir: &Path, capacity: u64, max_idle_time: u64) -> BlobReclaimer {
+        let work_dir = dir.to_str().unwrap();
+        let config = FileCacheConfig {
+            work_dir: work_dir.to_string(),
+            capacity,
+            max_idle_time,
+            ..Default::default()
+        };
+        BlobReclaimer::new(
+            &config,
+            work_dir,
+            Arc::new(RwLock::new(HashMap::new())),
+            BlobcacheMetrics::new("reclaimer-test", work_dir),
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    #[test]
+    fn test_reclaim_by_capacity() {
+        let tmpdir = TempDir::new().unwrap();
+        let dir = tmpdir.as_path();
+        let now = SystemTime::now();
+        create_blob_files(dir, "blob1", 0x10000, now - Duration::from_secs(300));
+        create_blob_files(dir, "blob2", 0x10000, now - Duration::from_secs(200));
+        create_blob_files(dir, "blob3", 0x10000, now - Duration::from_secs(100));
+        // The raw tarfs blob file must never be evicted.
+        File::create(dir.join("blob1")).unwrap();
+
+        let reclaimer = new_reclaimer(dir, 0x70000, 0);
+        let stats = reclaimer.scan_work_dir().unwrap();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats["blob1"].files.len(), 3);
+        assert!(stats["blob1"].disk_usage >= 0x30000);
+
+        reclaimer.reclaim().unwrap();
+        assert!(!dir.join("blob1.blob.data").exists());
+        assert!(!dir.join("blob1.blob.data.chunk_map").exists());
+        assert!(!dir.join("blob1.blob.meta").exists());
+        assert!(dir.join("blob1").exists());
+        assert!(dir.join("blob2.blob.data").exists());
+        assert!(dir.join("blob3.blob.data").exists());
+        assert_eq!(reclaimer.metrics.evicted_blobs.count(), 1);
+        assert!(reclaimer.metrics.reclaimed_bytes.count() >= 0x30000);
+    }
+
+    #[test]
+    fn test_reclaim_by_idle_time() {
+        let tmpdir = TempDir::new().unwrap();
+        let dir = tmpdir.as_path();
+        let now = SystemTime::now();
+        create_blob_files(dir, "blob1", 0x1000, now - Duration::from_secs(7200));
+        create_blob_files(dir, "blob2", 0x1000, now);
+
+        let reclaimer = new_reclaimer(dir, 0, 3600);
+        reclaimer.reclaim().unwrap();
+        assert!(!dir.join("blob1.blob.data").exists());
+        assert!(dir.join("blob2.blob.data").exists());
+        assert_eq!(reclaimer.metrics.evicted_blobs.count(), 1);
+    }
+
+    #[test]
+    fn test_reclaim_skip_active_blobs() {
+        let tmpdir = TempDir::new().unwrap();
+        let dir = tmpdir.as_path();
+        let work_dir = dir.to_str().unwrap();
+        let now = SystemTime::now();
+        create_blob_files(dir, "blob1", 0x1000, now - Duration::from_secs(7200));
+        create_blob_files(dir, "blob2", 0x1000, now - Duration::from_secs(7200));
+
+        register_blob(work_dir, "blob1");
+        let reclaimer = new_reclaimer(dir, 0, 3600);
+        reclaimer.reclaim().unwrap();
+        assert!(dir.join("blob1.blob.data").exists());
+        assert!(!dir.join("blob2.blob.data").exists());
+
+        unregister_blob(work_dir, "blob1");
+        reclaimer.reclaim().unwrap();
+        assert!(!dir.join("blob1.blob.data").exists());
+        assert_eq!(reclaimer.metrics.evicted_blobs.count(), 2);
+    }
+
+    #[test]
+    fn test_reclaim_race_with_register() {
+        let tmpdir = TempDir::new().unwrap();
+        let dir = tmpdir.as_path();
+        let work_dir = dir.to_str().unwrap();
+        let reclaimer = Arc::new(new_reclaimer(dir, 0, 3600));
+        let old = SystemTime::now() - Duration::from_secs(7200);
+
+        for _ in 0..50 {
+            create_blob_files(dir, "blob1", 0x1000, old);
+            let r = reclaimer.clone();
+            let handle = thread::spawn(move || r.reclaim().unwrap());
+            // Cache files found after registering the blob are never removed.
+            register_blob(work_dir, "blob1");
+            let exists = dir.join("blob1.blob.data").exists();
+            thread::sleep(Duration::from_millis(1));
+            handle.join().unwrap();
+            assert_eq!(dir.join("blob1.blob.data").exists(), exists);
+            assert_eq!(dir.join("blob1.blob.meta").exists(), exists);
+            unregister_blob(work_dir, "blob1");
+        }
+    }
+}
@@ -7,7 +7,7 @@ use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::Result;
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 use tokio::runtime::Runtime;
 
@@ -17,6 +17,7 @@ use nydus_utils::metrics::BlobcacheMetrics;
 
 use crate::backend::BlobBackend;
 use crate::cache::cachedfile::{FileCacheEntry, FileCacheMeta};
+use crate::cache::memcache::ChunkMemCache;
 use crate::cache::state::{
     BlobStateMap, ChunkMap, DigestedChunkMap, IndexedChunkMap, NoopChunkMap,
 };
@@ -25,6 +26,10 @@ use crate::cache::{BlobCache, BlobCacheMgr, CasMgr};
 use crate::device::{BlobFeatures, BlobInfo};
 use crate::utils::get_path_from_file;
 
+mod eviction;
+
+use eviction::{register_blob, touch_blob_file, unregister_blob, BlobReclaimer};
+
 pub const BLOB_RAW_FILE_SUFFIX: &str = ".blob.raw";
 pub const BLOB_DATA_FILE_SUFFIX: &str = ".blob.data";
 
@@ -35,6 +40,7 @@ pub struct FileCacheMgr {
     blobs: Arc<RwLock<HashMap<String, Arc<FileCacheEntry>>>>,
     backend: Arc<dyn BlobBackend>,
     metrics: Arc<BlobcacheMetrics>,
+    mem_cache: Option<Arc<ChunkMemCache>>,
     prefetch_config: Arc<AsyncPrefetchConfig>,
     runtime: Arc<Runtime>,
     worker_mgr: Arc<AsyncWorkerMgr>,
@@ -47,6 +53,7 @@ pub struct FileCacheMgr {
     cache_encryption_key: String,
     closed: Arc<AtomicBool>,
     user_io_batch_size: u32,
+    reclaimer: Arc<Mutex<Option<BlobReclaimer>>>,
 }
 
 impl FileCacheMgr {
@@ -62,12 +69,36 @@ impl FileCacheMgr {
         let work_dir = blob_cfg.get_work_dir()?;
         let metrics = BlobcacheMetrics::new(id, work_dir);
         let prefetch_config: Arc<AsyncPrefetchConfig> = Arc::new((&config.prefetch).into());
-        let worker_mgr = AsyncWorkerMgr::new(metrics.clone(), prefetch_config.clone())?;
+        let mut worker_mgr = AsyncWorkerMgr::new(metrics.clone(), prefetch_config.clone())?;
+        worker_mgr.enable_scrubber(blob_cfg.scrub_interval);
+        let blobs = Arc::new(RwLock::new(HashMap::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let reclaimer = if blob_cfg.eviction_enabled() {
+            Some(BlobReclaimer::new(
+                blob_cfg,
+                work_dir,
+                blobs.clone(),
+                metrics.clone(),
+                closed.clone(),
+            ))
+        } else {
+            None
+        };
+
+        let mem_cache = if config.mem_cache_size > 0 {
+            Some(Arc::new(ChunkMemCache::new(
+                config.mem_cache_size,
+                metrics.clone(),
+            )))
+        } else {
+            None
+        };
 
         Ok(FileCacheMgr {
-            blobs: Arc::new(RwLock::new(HashMap::new())),
+            blobs,
             backend,
             metrics,
+            mem_cache,
             prefetch_config,
             runtime,
             worker_mgr: Arc::new(worker_mgr),
@@ -78,8 +109,9 @@ impl FileCacheMgr {
             cache_encrypted: blob_cfg.enable_encryption,
             cache_convergent_encryption: blob_cfg.enable_convergent_encryption,
             cache_encryption_key: blob_cfg.encryption_key.clone(),
-            closed: Arc::new(AtomicBool::new(false)),
+            closed,
             user_io_batch_size,
+            reclaimer: Arc::new(Mutex::new(reclaimer)),
         })
     }
 
@@ -95,20 +127,33 @@ impl FileCacheMgr {
             return Ok(entry);
         }
 
-        let entry = FileCacheEntry::new_file_cache(
+        // Register the blob before opening its cache files, so they won't be evicted meanwhile.
+        let blob_id = blob.blob_id();
+        register_blob(&self.work_dir, &blob_id);
+        let entry = match FileCacheEntry::new_file_cache(
             self,
             blob.clone(),
             self.prefetch_config.clone(),
             self.runtime.clone(),
             self.worker_mgr.clone(),
-        )?;
-        let entry = Arc::new(entry);
+        ) {
+            Ok(entry) => Arc::new(entry),
+            Err(e) => {
+                unregister_blob(&self.work_dir, &blob_id);
+                return Err(e);
+            }
+        };
         let mut guard = self.blobs.write().unwrap();
-        if let Some(entry) = guard.get(&blob.blob_id()) {
+        if let Some(entry) = guard.get(&blob_id) {
+            unregister_blob(&self.work_dir, &blob_id);
             Ok(entry.clone())
         } else {
-            let blob_id = blob.blob_id();
+            if !entry.is_tarfs {
+                touch_blob_file(&entry.file);
+            }
             guard.insert(blob_id.clone(), entry.clone());
+            let cache: Arc<dyn BlobCache> = entry.clone();
+            self.worker_mgr.add_scrub_blob(&cache);
             self.metrics
                 .underlying_files
                 .lock()
@@ -121,13 +166,23 @@ impl FileCacheMgr {
 
 impl BlobCacheMgr for FileCacheMgr {
     fn init(&self) -> Result<()> {
-        AsyncWorkerMgr::start(self.worker_mgr.clone())
+        AsyncWorkerMgr::start(self.worker_mgr.clone())?;
+        if let Some(reclaimer) = self.reclaimer.lock().unwrap().take() {
+            reclaimer.start()?;
+        }
+        Ok(())
     }
 
     fn destroy(&self) {
         if !self.closed.load(Ordering::Acquire) {
             self.closed.store(true, Ordering::Release);
             self.worker_mgr.stop();
+            for (blob_id, entry) in self.blobs.read().unwrap().iter() {
+                if !entry.is_tarfs {
+                    touch_blob_file(&entry.file);
+                }
+                unregister_blob(&self.work_dir, blob_id);
+            }
             self.backend().shutdown();
             self.metrics.release().unwrap_or_else(|e| error!("{:?}", e));
         }
@@ -151,7 +206,11 @@ impl BlobCacheMgr for FileCacheMgr {
             let mut guard = self.blobs.write().unwrap();
             if let Some(entry) = guard.get(key) {
                 if Arc::strong_count(entry) == 1 {
+                    if !entry.is_tarfs {
+                        touch_blob_file(&entry.file);
+                    }
                     guard.remove(key);
+                    unregister_blob(&self.work_dir, key);
                 }
             }
         }
@@ -332,6 +391,15 @@ impl FileCacheEntry {
             }
         }
 
+        #[cfg(feature = "dedup")]
+        if let Some(mgr) = cas_mgr.as_ref() {
+            if !blob_data_file_path.is_empty() {
+                if let Err(e) = mgr.ref_blob(&blob_data_file_path) {
+                    warn!("failed to reference cache file in cas database: {}", e);
+                }
+            }
+        }
+
         trace!(
             "filecache entry: is_raw_data {}, direct {}, legacy_stargz {}, separate_meta {}, tarfs {}, batch {}, zran {}",
             mgr.cache_raw_data,
@@ -352,6 +420,7 @@ impl FileCacheEntry {
             file: Arc::new(file),
             file_path: Arc::new(blob_data_file_path),
             meta,
+            mem_cache: mgr.mem_cache.clone(),
             metrics: mgr.metrics.clone(),
             prefetch_state: Arc::new(AtomicU32::new(0)),
             reader,
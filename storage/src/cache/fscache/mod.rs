@@ -290,6 +290,15 @@ impl FileCacheEntry {
             }
         }
 
+        #[cfg(feature = "dedup")]
+        if let Some(mgr) = cas_mgr.as_ref() {
+            if !blob_data_file_path.is_empty() {
+                if let Err(e) = mgr.ref_blob(&blob_data_file_path) {
+                    warn!("failed to reference cache file in cas database: {}", e);
+                }
+            }
+        }
+
         Ok(FileCacheEntry {
             blob_id,
             blob_info: blob_info.clone(),
@@ -300,6 +309,7 @@ impl FileCacheEntry {
             file,
             file_path: Arc::new(blob_data_file_path),
             meta: Some(meta),
+            mem_cache: None,
             metrics: mgr.metrics.clone(),
             prefetch_state: Arc::new(AtomicU32::new(0)),
             reader,
@@ -0,0 +1,151 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-memory LRU cache for decompressed chunk data.
+//!
+//! The memory cache is shared by all blobs managed by a blob cache manager and chunks are keyed
+//! by their digests, so a chunk shared by multiple blobs, for example a file shipped by many
+//! images, is cached only once. Only chunks read by users are cached, to avoid hot chunks being
+//! flushed out by prefetch and read amplification.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use nydus_utils::digest::RafsDigest;
+use nydus_utils::metrics::{BlobcacheMetrics, Metric};
+
+struct Entry {
+    data: Arc<Vec<u8>>,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<RafsDigest, Entry>,
+    // Access order of entries, the entry with the smallest tick is the least recently used one.
+    order: BTreeMap<u64, RafsDigest>,
+    tick: u64,
+    size: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, digest: &RafsDigest) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(digest)?;
+        self.order.remove(&entry.tick);
+        self.order.insert(tick, *digest);
+        entry.tick = tick;
+        Some(entry.data.clone())
+    }
+}
+
+/// Bounded in-memory cache of decompressed chunk data, keyed by chunk digest.
+pub(crate) struct ChunkMemCache {
+    capacity: u64,
+    state: Mutex<LruState>,
+    metrics: Arc<BlobcacheMetrics>,
+}
+
+impl ChunkMemCache {
+    /// Create a memory cache holding at most `capacity` bytes of chunk data.
+    pub fn new(capacity: u64, metrics: Arc<BlobcacheMetrics>) -> Self {
+        ChunkMemCache {
+            capacity,
+            state: Mutex::new(LruState::default()),
+            metrics,
+        }
+    }
+
+    /// Get data of the chunk and mark it as the most recently used one.
+    ///
+    /// Each call accounts exactly one hit or miss.
+    pub fn get(&self, digest: &RafsDigest) -> Option<Arc<Vec<u8>>> {
+        let data = self.state.lock().unwrap().touch(digest);
+        self.metrics.mem_cache.record_lookup(data.is_some());
+        data
+    }
+
+    /// Add data of the chunk into the cache, evicting least recently used chunks if needed.
+    pub fn insert(&self, digest: &RafsDigest, data: &[u8]) {
+        let len = data.len() as u64;
+        if len == 0 || len > self.capacity {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.touch(digest).is_some() {
+            return;
+        }
+        while state.size + len > self.capacity {
+            let (_, victim) = match state.order.pop_first() {
+                Some(v) => v,
+                None => break,
+            };
+            if let Some(entry) = state.entries.remove(&victim) {
+                state.size -= entry.data.len() as u64;
+                self.metrics.mem_cache.evictions.inc();
+            }
+        }
+        let tick = state.tick;
+        state.entries.insert(
+            *digest,
+            Entry {
+                data: Arc::new(data.to_vec()),
+                tick,
+            },
+        );
+        state.order.insert(tick, *digest);
+        state.size += len;
+        self.metrics
+            .mem_cache
+            .entries
+            .set(state.entries.len() as u64);
+        self.metrics.mem_cache.cached_bytes.set(state.size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(v: u8) -> RafsDigest {
+        RafsDigest { data: [v; 32] }
+    }
+
+    fn contains(cache: &ChunkMemCache, digest: &RafsDigest) -> bool {
+        cache.state.lock().unwrap().entries.contains_key(digest)
+    }
+
+    #[test]
+    fn test_chunk_mem_cache() {
+        let metrics = Arc::new(BlobcacheMetrics::default());
+        let cache = ChunkMemCache::new(0x3000, metrics.clone());
+
+        assert!(!contains(&cache, &digest(1)));
+        assert!(cache.get(&digest(1)).is_none());
+        cache.insert(&digest(1), &[1u8; 0x1000]);
+        cache.insert(&digest(2), &[2u8; 0x1000]);
+        cache.insert(&digest(3), &[3u8; 0x1000]);
+        // Chunks larger than the capacity are never cached.
+        cache.insert(&digest(4), &[4u8; 0x4000]);
+        assert!(!contains(&cache, &digest(4)));
+        assert_eq!(metrics.mem_cache.cached_bytes.count(), 0x3000);
+
+        // Chunk 1 becomes the most recently used one, so chunk 2 gets evicted.
+        assert_eq!(cache.get(&digest(1)).unwrap()[0], 1);
+        cache.insert(&digest(5), &[5u8; 0x1000]);
+        assert!(contains(&cache, &digest(1)));
+        assert!(!contains(&cache, &digest(2)));
+        assert!(contains(&cache, &digest(3)));
+        assert!(contains(&cache, &digest(5)));
+        assert_eq!(metrics.mem_cache.evictions.count(), 1);
+        assert_eq!(metrics.mem_cache.entries.count(), 3);
+
+        // Only lookups by `get()` are accounted.
+        assert_eq!(metrics.mem_cache.hits.count(), 1);
+        assert_eq!(metrics.mem_cache.misses.count(), 1);
+        assert_eq!(metrics.mem_cache.hit_rate_percent.count(), 50);
+    }
+}
@@ -22,7 +22,9 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
+use nydus_utils::compress::xz_random::XzBlockDecoder;
 use nydus_utils::compress::zlib_random::ZranDecoder;
+use nydus_utils::compress::zstd_random::ZstdFrameDecoder;
 use nydus_utils::crypt::{self, Cipher, CipherContext};
 use nydus_utils::{compress, digest};
 
@@ -42,6 +44,7 @@ mod dummycache;
 mod filecache;
 #[cfg(target_os = "linux")]
 mod fscache;
+mod memcache;
 mod worker;
 
 pub mod state;
@@ -239,6 +242,11 @@ pub trait BlobCache: Send + Sync {
     /// Read chunk data described by the blob Io descriptors from the blob cache into the buffer.
     fn read(&self, iovec: &mut BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize>;
 
+    /// Verify data of a ready chunk in the blob cache, return `Ok(false)` if it's corrupted.
+    fn verify_cached_chunk(&self, _chunk: &dyn BlobChunkInfo) -> Result<bool> {
+        Err(enosys!("doesn't support verify_cached_chunk()"))
+    }
+
     /// Read multiple chunks from the blob cache in batch mode.
     ///
     /// This is an interface to optimize chunk data fetch performance by merging multiple continuous
@@ -355,10 +363,17 @@ pub trait BlobCache: Send + Sync {
     ) -> Result<()> {
         if is_compressed {
             let compressor = self.blob_compressor();
-            let ret = compress::decompress(raw_buffer, buffer, compressor).map_err(|e| {
-                error!("failed to decompress chunk: {}", e);
-                e
-            })?;
+            let meta = if compressor.need_dict() {
+                self.get_blob_meta_info()?
+            } else {
+                None
+            };
+            let dict = meta.as_ref().and_then(|m| m.get_compression_dict());
+            let ret = compress::decompress_with_dict(raw_buffer, buffer, compressor, dict)
+                .map_err(|e| {
+                    error!("failed to decompress chunk: {}", e);
+                    e
+                })?;
             if ret != buffer.len() {
                 return Err(einval!(format!(
                     "size of decompressed data doesn't match expected, {} vs {}, raw_buffer: {}",
@@ -514,8 +529,18 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
         let c_offset = (c_offset - self.blob_offset) as usize;
         let input = &self.c_buf[c_offset..c_offset + c_size as usize];
         let mut output = alloc_buf(ctx.out_len as usize);
-        let mut decoder = ZranDecoder::new()?;
-        decoder.uncompress(&ctx, Some(dict), input, &mut output)?;
+        if self.cache.blob_compressor() == compress::Algorithm::Zstd {
+            // Decode zstd frames referenced from seekable zstd stream.
+            let mut decoder = ZstdFrameDecoder::new()?;
+            decoder.uncompress(&ctx, input, &mut output)?;
+        } else if self.cache.blob_compressor() == compress::Algorithm::Xz {
+            // Decode xz blocks referenced from xz stream, with the stream header as dictionary.
+            let mut decoder = XzBlockDecoder::new()?;
+            decoder.uncompress(&ctx, dict, input, &mut output)?;
+        } else {
+            let mut decoder = ZranDecoder::new()?;
+            decoder.uncompress(&ctx, Some(dict), input, &mut output)?;
+        }
         self.d_buf = output;
 
         Ok(())
@@ -668,7 +693,7 @@ pub(crate) trait BlobCacheMgr: Send + Sync {
 }
 
 #[cfg(feature = "dedup")]
-pub use dedup::CasMgr;
+pub use dedup::{CasGcStats, CasMgr, CasStats};
 
 #[cfg(not(feature = "dedup"))]
 pub struct CasMgr {}
@@ -55,6 +55,10 @@ impl ChunkMap for IndexedChunkMap {
         self.map.set_chunk_ready(chunk.id())
     }
 
+    fn clear_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        self.map.clear_chunk_ready(chunk.id())
+    }
+
     fn is_persist(&self) -> bool {
         true
     }
@@ -292,6 +296,40 @@ mod tests {
         assert!(map.is_ready(chunk.as_base()).unwrap());
         map.set_ready_and_clear_pending(chunk.as_base()).unwrap();
         assert!(map.is_ready(chunk.as_base()).unwrap());
+
+        map.clear_ready(chunk.as_base()).unwrap();
+        assert!(!map.is_range_all_ready());
+        assert!(!map.is_ready(chunk.as_base()).unwrap());
+        drop(map);
+
+        // The all ready flag in the header should have been reset.
+        let map = IndexedChunkMap::new(&blob_path, 1, true).unwrap();
+        assert!(!map.is_range_all_ready());
+        assert!(!map.is_ready(chunk.as_base()).unwrap());
+    }
+
+    #[test]
+    fn test_indexed_clear_ready() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+        let map = IndexedChunkMap::new(&blob_path, 16, true).unwrap();
+
+        let mut chunk = MockChunkInfo::new();
+        chunk.index = 9;
+        assert!(!map.is_ready(chunk.as_base()).unwrap());
+        map.clear_ready(chunk.as_base()).unwrap();
+        assert_eq!(map.map.not_ready_count.load(Ordering::Acquire), 16);
+
+        map.set_ready_and_clear_pending(chunk.as_base()).unwrap();
+        assert!(map.is_ready(chunk.as_base()).unwrap());
+        assert_eq!(map.map.not_ready_count.load(Ordering::Acquire), 15);
+        map.clear_ready(chunk.as_base()).unwrap();
+        assert!(!map.is_ready(chunk.as_base()).unwrap());
+        assert_eq!(map.map.not_ready_count.load(Ordering::Acquire), 16);
+
+        chunk.index = 16;
+        assert!(map.clear_ready(chunk.as_base()).is_err());
     }
 
     #[test]
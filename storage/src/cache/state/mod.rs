@@ -91,6 +91,11 @@ pub trait ChunkMap: Any + Send + Sync {
         panic!("no support of clear_pending()");
     }
 
+    /// Clear the ready state of the chunk, so it will be fetched from the backend again.
+    fn clear_ready(&self, _chunk: &dyn BlobChunkInfo) -> Result<()> {
+        Err(enosys!("doesn't support clear_ready()"))
+    }
+
     /// Check whether the implementation supports state persistence.
     fn is_persist(&self) -> bool {
         false
@@ -191,6 +196,7 @@ mod tests {
         assert!(m.as_range_map().is_none());
         assert!(!m.is_persist());
         assert!(!m.is_ready(&chunk_info).unwrap());
+        assert!(m.clear_ready(&chunk_info).is_err());
     }
 
     #[test]
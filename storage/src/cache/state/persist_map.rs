@@ -227,6 +227,10 @@ impl PersistMap {
 
     pub fn set_chunk_ready(&self, index: u32) -> Result<()> {
         let index = self.validate_index(index)?;
+        // The bitmap may be out of date when the all ready flag is set in the header.
+        if self.is_range_all_ready() {
+            return Ok(());
+        }
 
         // Loop to atomically update the state bit corresponding to the chunk index.
         loop {
@@ -246,6 +250,35 @@ impl PersistMap {
         Ok(())
     }
 
+    pub fn clear_chunk_ready(&self, index: u32) -> Result<()> {
+        let index = self.validate_index(index)?;
+        if self.is_range_all_ready() {
+            // The bitmap may be out of date when the all ready flag is set in the header.
+            for idx in 0..self.count {
+                let start = HEADER_SIZE + (idx as usize >> 3);
+                let atomic_value = self.filemap.get_ref::<AtomicU8>(start)?;
+                atomic_value.fetch_or(Self::index_to_mask(idx), Ordering::AcqRel);
+            }
+        }
+
+        let mask = Self::index_to_mask(index);
+        let start = HEADER_SIZE + (index as usize >> 3);
+        let atomic_value = self.filemap.get_ref::<AtomicU8>(start)?;
+        if atomic_value.fetch_and(!mask, Ordering::AcqRel) & mask == mask
+            && self.not_ready_count.fetch_add(1, Ordering::AcqRel) == 0
+        {
+            // Reset the all ready flag in the header, otherwise the bitmap won't be checked again
+            // when reopening the chunk map file.
+            let all_ready = self
+                .filemap
+                .get_ref::<AtomicU32>(std::mem::offset_of!(Header, all_ready))?;
+            all_ready.store(0, Ordering::Release);
+            let _ = self.filemap.sync_data();
+        }
+
+        Ok(())
+    }
+
     fn mark_all_ready(&self) {
         if self.filemap.sync_data().is_ok() {
             /*
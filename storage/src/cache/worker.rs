@@ -3,11 +3,12 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
 use std::io::Result;
 use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Arc, Once};
+use std::sync::{Arc, Mutex, Once, Weak};
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use nydus_api::PrefetchConfigV2;
 use nydus_utils::async_helper::with_runtime;
@@ -17,8 +18,14 @@ use tokio::runtime::Runtime;
 use tokio::sync::Semaphore;
 
 use crate::cache::{BlobCache, BlobIoRange};
+use crate::device::BlobChunkInfo;
 use crate::factory::ASYNC_RUNTIME;
 
+// Maximum bandwidth for the scrubber to verify cached data, in unit of Bytes per second.
+const SCRUB_BANDWIDTH: u64 = 32 << 20;
+// Interval to check whether the scrubber should start a new pass or exit.
+const SCRUB_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Configuration information for asynchronous workers.
 pub(crate) struct AsyncPrefetchConfig {
     /// Whether or not to enable prefetch.
@@ -88,6 +95,10 @@ pub(crate) struct AsyncWorkerMgr {
     prefetch_consumed: AtomicUsize,
     #[cfg(feature = "prefetch-rate-limit")]
     prefetch_limiter: Option<Arc<leaky_bucket::RateLimiter>>,
+
+    // Interval in seconds to scrub cached data, zero means disabled.
+    scrub_interval: u64,
+    scrub_blobs: Mutex<HashMap<String, Weak<dyn BlobCache>>>,
 }
 
 impl AsyncWorkerMgr {
@@ -130,13 +141,34 @@ impl AsyncWorkerMgr {
             prefetch_consumed: AtomicUsize::new(0),
             #[cfg(feature = "prefetch-rate-limit")]
             prefetch_limiter,
+
+            scrub_interval: 0,
+            scrub_blobs: Mutex::new(HashMap::new()),
         })
     }
 
+    /// Enable the background scrubber to verify cached data every `interval` seconds.
+    pub fn enable_scrubber(&mut self, interval: u64) {
+        self.scrub_interval = interval;
+    }
+
+    /// Register a blob cache object to be verified by the background scrubber.
+    pub fn add_scrub_blob(&self, blob: &Arc<dyn BlobCache>) {
+        if self.scrub_interval > 0 {
+            self.scrub_blobs
+                .lock()
+                .unwrap()
+                .insert(blob.blob_id().to_string(), Arc::downgrade(blob));
+        }
+    }
+
     /// Create working threads and start the event loop.
     pub fn start(mgr: Arc<AsyncWorkerMgr>) -> Result<()> {
         if mgr.prefetch_config.enable {
-            Self::start_prefetch_workers(mgr)?;
+            Self::start_prefetch_workers(mgr.clone())?;
+        }
+        if mgr.scrub_interval > 0 {
+            Self::start_scrubber(mgr)?;
         }
 
         Ok(())
@@ -416,6 +448,120 @@ impl AsyncWorkerMgr {
         Ok(())
     }
 
+    fn start_scrubber(mgr: Arc<AsyncWorkerMgr>) -> Result<()> {
+        mgr.active.store(true, Ordering::Release);
+        mgr.grow_n(1);
+
+        let mgr2 = mgr.clone();
+        let res = thread::Builder::new()
+            .name("nydus_storage_scrubber".to_string())
+            .spawn(move || {
+                mgr2.handle_scrub_requests();
+                mgr2.shrink_n(1);
+                info!("storage: scrubber thread exits.")
+            });
+
+        if let Err(e) = res {
+            error!("storage: failed to create scrubber thread, {:?}", e);
+            mgr.shrink_n(1);
+            mgr.stop();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn handle_scrub_requests(&self) {
+        let interval = Duration::from_secs(self.scrub_interval);
+        let mut last_pass = Instant::now();
+
+        while self.active.load(Ordering::Acquire) {
+            if last_pass.elapsed() < interval {
+                thread::sleep(SCRUB_CHECK_INTERVAL);
+                continue;
+            }
+            self.scrub_blobs();
+            last_pass = Instant::now();
+        }
+    }
+
+    // Verify ready chunks of all registered blobs, in low priority.
+    fn scrub_blobs(&self) {
+        let blobs: Vec<Weak<dyn BlobCache>> = {
+            let mut guard = self.scrub_blobs.lock().unwrap();
+            guard.retain(|_, v| v.strong_count() > 0);
+            guard.values().cloned().collect()
+        };
+
+        for blob in blobs {
+            if !self.active.load(Ordering::Acquire) {
+                return;
+            }
+            if let Some(blob) = blob.upgrade() {
+                if let Err(e) = self.scrub_blob(blob.as_ref()) {
+                    if e.raw_os_error() != Some(libc::ENOSYS) {
+                        warn!("storage: failed to scrub blob {}, {}", blob.blob_id(), e);
+                    }
+                }
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        self.metrics.scrub.passes.inc();
+        self.metrics
+            .scrub
+            .last_pass_end_time_secs
+            .set(now.as_secs());
+    }
+
+    fn scrub_blob(&self, blob: &dyn BlobCache) -> Result<()> {
+        let meta = match blob.get_blob_meta_info()? {
+            Some(v) => v,
+            None => return Err(enosys!("no chunk information available for the blob")),
+        };
+        let chunks = (0..meta.get_chunk_count()).map(|idx| meta.get_chunk_info(idx));
+
+        self.scrub_chunks(blob, chunks)
+    }
+
+    // Verify data of ready chunks, and mark corrupted chunks as not ready so they will be fetched
+    // from the storage backend again.
+    fn scrub_chunks<I>(&self, blob: &dyn BlobCache, chunks: I) -> Result<()>
+    where
+        I: Iterator<Item = Arc<dyn BlobChunkInfo>>,
+    {
+        let chunk_map = blob.get_chunk_map();
+
+        for chunk in chunks {
+            if !self.active.load(Ordering::Acquire) {
+                break;
+            }
+            if !matches!(chunk_map.is_ready(chunk.as_ref()), Ok(true)) {
+                continue;
+            }
+
+            let size = chunk.uncompressed_size() as u64;
+            let valid = blob.verify_cached_chunk(chunk.as_ref())?;
+            self.metrics.scrub.scrubbed_chunks.inc();
+            self.metrics.scrub.scrubbed_bytes.add(size);
+            if !valid {
+                warn!(
+                    "storage: found corrupted chunk {} in cache of blob {}",
+                    chunk.id(),
+                    blob.blob_id()
+                );
+                self.metrics.scrub.corrupted_chunks.inc();
+                chunk_map.clear_ready(chunk.as_ref())?;
+            }
+
+            // Throttle the scrubber to reduce impact on user IO.
+            thread::sleep(Duration::from_micros(size * 1_000_000 / SCRUB_BANDWIDTH));
+        }
+
+        Ok(())
+    }
+
     fn shrink_n(&self, n: u32) {
         self.workers.fetch_sub(n, Ordering::Relaxed);
     }
@@ -428,6 +574,14 @@ impl AsyncWorkerMgr {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::BlobReader;
+    use crate::cache::state::{BlobStateMap, ChunkMap, IndexedChunkMap};
+    use crate::device::{BlobIoDesc, BlobIoVec, BlobPrefetchRequest};
+    use crate::test::{MockBackend, MockChunkInfo};
+    use crate::StorageResult;
+    use fuse_backend_rs::file_buf::FileVolatileSlice;
+    use nydus_utils::metrics::BackendMetrics;
+    use nydus_utils::{compress, crypt, digest};
     use vmm_sys_util::tempdir::TempDir;
 
     #[test]
@@ -469,6 +623,161 @@ mod tests {
             .is_err());
     }
 
+    struct MockScrubCache {
+        chunk_map: Arc<dyn ChunkMap>,
+        reader: Arc<dyn BlobReader>,
+    }
+
+    impl BlobCache for MockScrubCache {
+        fn blob_id(&self) -> &str {
+            "scrub-blob"
+        }
+
+        fn blob_uncompressed_size(&self) -> Result<u64> {
+            Ok(0x10000)
+        }
+
+        fn blob_compressed_size(&self) -> Result<u64> {
+            Ok(0x10000)
+        }
+
+        fn blob_compressor(&self) -> compress::Algorithm {
+            compress::Algorithm::None
+        }
+
+        fn blob_cipher(&self) -> crypt::Algorithm {
+            crypt::Algorithm::None
+        }
+
+        fn blob_cipher_object(&self) -> Arc<crypt::Cipher> {
+            Default::default()
+        }
+
+        fn blob_cipher_context(&self) -> Option<crypt::CipherContext> {
+            None
+        }
+
+        fn blob_digester(&self) -> digest::Algorithm {
+            digest::Algorithm::Blake3
+        }
+
+        fn is_legacy_stargz(&self) -> bool {
+            false
+        }
+
+        fn need_validation(&self) -> bool {
+            false
+        }
+
+        fn reader(&self) -> &dyn BlobReader {
+            self.reader.as_ref()
+        }
+
+        fn get_chunk_map(&self) -> &Arc<dyn ChunkMap> {
+            &self.chunk_map
+        }
+
+        fn get_chunk_info(&self, _chunk_index: u32) -> Option<Arc<dyn BlobChunkInfo>> {
+            None
+        }
+
+        fn start_prefetch(&self) -> StorageResult<()> {
+            Ok(())
+        }
+
+        fn stop_prefetch(&self) -> StorageResult<()> {
+            Ok(())
+        }
+
+        fn is_prefetch_active(&self) -> bool {
+            false
+        }
+
+        fn prefetch(
+            &self,
+            _cache: Arc<dyn BlobCache>,
+            _prefetches: &[BlobPrefetchRequest],
+            _bios: &[BlobIoDesc],
+        ) -> StorageResult<usize> {
+            Ok(0)
+        }
+
+        fn read(&self, _iovec: &mut BlobIoVec, _buffers: &[FileVolatileSlice]) -> Result<usize> {
+            Ok(0)
+        }
+
+        // Chunks with odd index are treated as corrupted.
+        fn verify_cached_chunk(&self, chunk: &dyn BlobChunkInfo) -> Result<bool> {
+            Ok(chunk.id() % 2 == 0)
+        }
+    }
+
+    #[test]
+    fn test_worker_mgr_scrubber() {
+        let tmpdir = TempDir::new().unwrap();
+        let metrics = BlobcacheMetrics::new("test_scrub", tmpdir.as_path().to_str().unwrap());
+        let config = Arc::new(AsyncPrefetchConfig {
+            enable: false,
+            threads_count: 1,
+            batch_size: 0x100000,
+            bandwidth_limit: 0,
+        });
+        let mut mgr = AsyncWorkerMgr::new(metrics, config).unwrap();
+        mgr.enable_scrubber(3600);
+        let mgr = Arc::new(mgr);
+        AsyncWorkerMgr::start(mgr.clone()).unwrap();
+        assert_eq!(mgr.workers.load(Ordering::Acquire), 1);
+
+        let blob_path = tmpdir.as_path().join("scrub-blob");
+        let chunk_map = IndexedChunkMap::new(blob_path.to_str().unwrap(), 4, false).unwrap();
+        let cache: Arc<dyn BlobCache> = Arc::new(MockScrubCache {
+            chunk_map: Arc::new(BlobStateMap::from(chunk_map)),
+            reader: Arc::new(MockBackend {
+                metrics: BackendMetrics::new("test_scrub", "mock"),
+            }),
+        });
+        mgr.add_scrub_blob(&cache);
+        assert_eq!(mgr.scrub_blobs.lock().unwrap().len(), 1);
+
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = (0..4)
+            .map(|idx| {
+                Arc::new(MockChunkInfo {
+                    index: idx,
+                    uncompress_size: 0x1000,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>
+            })
+            .collect();
+        let chunk_map = cache.get_chunk_map();
+        for chunk in &chunks[0..3] {
+            chunk_map
+                .set_ready_and_clear_pending(chunk.as_ref())
+                .unwrap();
+        }
+
+        mgr.scrub_chunks(cache.as_ref(), chunks.iter().cloned())
+            .unwrap();
+        assert_eq!(mgr.metrics.scrub.scrubbed_chunks.count(), 3);
+        assert_eq!(mgr.metrics.scrub.scrubbed_bytes.count(), 0x3000);
+        assert_eq!(mgr.metrics.scrub.corrupted_chunks.count(), 1);
+        assert!(chunk_map.is_ready(chunks[0].as_ref()).unwrap());
+        assert!(!chunk_map.is_ready(chunks[1].as_ref()).unwrap());
+        assert!(chunk_map.is_ready(chunks[2].as_ref()).unwrap());
+        assert!(!chunk_map.is_ready(chunks[3].as_ref()).unwrap());
+
+        // Blobs without chunk information are skipped.
+        mgr.scrub_blobs();
+        assert_eq!(mgr.metrics.scrub.passes.count(), 1);
+        assert_eq!(mgr.metrics.scrub.scrubbed_chunks.count(), 3);
+
+        drop(cache);
+        mgr.scrub_blobs();
+        assert!(mgr.scrub_blobs.lock().unwrap().is_empty());
+
+        mgr.stop();
+        assert_eq!(mgr.workers.load(Ordering::Acquire), 0);
+    }
+
     #[cfg(feature = "prefetch-rate-limit")]
     #[test]
     fn test_worker_mgr_rate_limiter() {
@@ -31,12 +31,12 @@ use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use fuse_backend_rs::api::filesystem::ZeroCopyWriter;
 use fuse_backend_rs::file_buf::FileVolatileSlice;
 use fuse_backend_rs::file_traits::FileReadWriteVolatile;
 
-use nydus_api::ConfigV2;
+use nydus_api::{BackendConfigV2, ConfigV2};
 use nydus_utils::compress;
 use nydus_utils::crypt::{self, Cipher, CipherContext};
 use nydus_utils::digest::{self, RafsDigest};
@@ -1170,6 +1170,7 @@ pub trait BlobObject: AsRawFd {
 pub struct BlobDevice {
     blobs: Arc<ArcSwap<Vec<Arc<dyn BlobCache>>>>,
     blob_count: usize,
+    config: Arc<ArcSwapOption<ConfigV2>>,
 }
 
 impl BlobDevice {
@@ -1184,6 +1185,7 @@ impl BlobDevice {
         Ok(BlobDevice {
             blobs: Arc::new(ArcSwap::new(Arc::new(blobs))),
             blob_count: blob_infos.len(),
+            config: Arc::new(ArcSwapOption::new(Some(config.clone()))),
         })
     }
 
@@ -1216,6 +1218,7 @@ impl BlobDevice {
             self.stop_prefetch();
         }
         self.blobs.store(Arc::new(blobs));
+        self.config.store(Some(config.clone()));
         if fs_prefetch {
             self.start_prefetch();
         }
@@ -1223,6 +1226,21 @@ impl BlobDevice {
         Ok(())
     }
 
+    /// Update storage backend configuration of the blob device without rebuilding blob caches.
+    ///
+    /// Requests in-flight complete with the old storage backend objects, and new requests are
+    /// served by storage backend objects created from `backend`.
+    pub fn update_backend(&self, backend: &BackendConfigV2) -> io::Result<()> {
+        let config = self
+            .config
+            .load_full()
+            .ok_or_else(|| enoent!("blob device has no storage backend"))?;
+        let config = BLOB_FACTORY.update_backend(&config, backend)?;
+        self.config.store(Some(config));
+
+        Ok(())
+    }
+
     /// Close the blob device.
     pub fn close(&self) -> io::Result<()> {
         Ok(())
@@ -1256,6 +1274,27 @@ impl BlobDevice {
         }
     }
 
+    /// Read a range of data from a data blob into the provided buffer.
+    pub fn read_to_buf(&self, buf: &mut [u8], desc: &mut BlobIoVec) -> io::Result<usize> {
+        let size = desc.bi_size as usize;
+        if buf.len() < size {
+            Err(einval!("buffer is too small for BlobIoVec."))
+        } else if desc.bi_vec.is_empty() {
+            if size == 0 {
+                Ok(0)
+            } else {
+                Err(einval!("BlobIoVec size doesn't match."))
+            }
+        } else if desc.blob_index() as usize >= self.blob_count {
+            Err(einval!("BlobIoVec has out of range blob_index."))
+        } else {
+            // Safe because the buffer is valid and exclusively borrowed during the read.
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), size) };
+            let mut f = BlobDeviceIoVec::new(self, desc);
+            f.read_vectored_at_volatile(&[slice], 0)
+        }
+    }
+
     /// Try to prefetch specified blob data.
     pub fn prefetch(
         &self,
@@ -1349,6 +1388,27 @@ impl BlobDevice {
         true
     }
 
+    /// Get number and compressed size of chunks in `descs` which are ready in the blob cache.
+    pub fn ready_chunks(&self, descs: &[BlobIoDesc]) -> (u64, u64) {
+        let state = self.blobs.load();
+        let mut count = 0;
+        let mut size = 0;
+        for desc in descs {
+            if let Some(blob) = state.get(desc.blob.blob_index() as usize) {
+                if blob
+                    .get_chunk_map()
+                    .is_ready(&desc.chunkinfo)
+                    .unwrap_or(false)
+                {
+                    count += 1;
+                    size += desc.chunkinfo.compressed_size() as u64;
+                }
+            }
+        }
+
+        (count, size)
+    }
+
     /// RAFS V6: create a `BlobIoChunk` for chunk with index `chunk_index`.
     pub fn create_io_chunk(&self, blob_index: u32, chunk_index: u32) -> Option<BlobIoChunk> {
         if (blob_index as usize) < self.blob_count {
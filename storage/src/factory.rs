@@ -13,17 +13,24 @@ use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::io::Result as IOResult;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 
+use arc_swap::ArcSwap;
+use fuse_backend_rs::file_buf::FileVolatileSlice;
 use lazy_static::lazy_static;
 use nydus_api::{
-    default_user_io_batch_size, BackendConfigV2, ConfigV2, HttpProxyConfig, LocalDiskConfig,
-    LocalFsConfig, OssConfig, RegistryConfig, S3Config,
+    default_user_io_batch_size, AzureConfig, BackendConfigV2, ConfigV2, GcsConfig, HttpProxyConfig,
+    LocalDiskConfig, LocalFsConfig, OssConfig, RegistryConfig, S3Config,
 };
+use nydus_utils::metrics::BackendMetrics;
 use tokio::runtime::{Builder, Runtime};
 use tokio::time;
 
+#[cfg(feature = "backend-azure")]
+use crate::backend::azure;
+#[cfg(feature = "backend-gcs")]
+use crate::backend::gcs;
 #[cfg(feature = "backend-http-proxy")]
 use crate::backend::http_proxy;
 #[cfg(feature = "backend-localdisk")]
@@ -36,7 +43,7 @@ use crate::backend::oss;
 use crate::backend::registry;
 #[cfg(feature = "backend-s3")]
 use crate::backend::s3;
-use crate::backend::BlobBackend;
+use crate::backend::{BackendResult, BlobBackend, BlobReader};
 use crate::cache::{BlobCache, BlobCacheMgr, DummyCacheMgr, FileCacheMgr};
 use crate::device::BlobInfo;
 
@@ -75,6 +82,166 @@ impl Hash for BlobCacheMgrKey {
     }
 }
 
+// A storage backend object, which is shut down when the last reference to it is dropped.
+struct BackendInstance(Arc<dyn BlobBackend>);
+
+impl Drop for BackendInstance {
+    fn drop(&mut self) {
+        self.0.shutdown();
+    }
+}
+
+// Blob reader object together with the storage backend object it comes from.
+struct ReaderSlot {
+    _backend: Arc<BackendInstance>,
+    reader: Arc<dyn BlobReader>,
+}
+
+/// Blob reader which may be switched to a new storage backend object at runtime.
+///
+/// Each request is forwarded to the reader in use when the request is issued, so requests
+/// in-flight complete with the old storage backend object after switching.
+struct ReloadableReader {
+    blob_id: String,
+    slot: ArcSwap<ReaderSlot>,
+    // Shared with the wrapped reader, which accounts IO metrics because `read()` is forwarded to it.
+    metrics: Arc<BackendMetrics>,
+}
+
+impl BlobReader for ReloadableReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.slot.load_full().reader.blob_size()
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        self.slot.load_full().reader.try_read(buf, offset)
+    }
+
+    fn read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        self.slot.load_full().reader.read(buf, offset)
+    }
+
+    fn readv(
+        &self,
+        bufs: &[FileVolatileSlice],
+        offset: u64,
+        max_size: usize,
+    ) -> BackendResult<usize> {
+        self.slot.load_full().reader.readv(bufs, offset, max_size)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        &self.metrics
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.slot.load().reader.retry_limit()
+    }
+}
+
+/// Storage backend whose configuration may be updated at runtime.
+///
+/// Updating configuration creates a new storage backend object and switches all blob readers
+/// created by the [ReloadableBackend] to it. The old storage backend object gets shut down
+/// once all requests in-flight have completed.
+struct ReloadableBackend {
+    blob_id: String,
+    backend_type: String,
+    current: ArcSwap<BackendInstance>,
+    readers: Mutex<Vec<Weak<ReloadableReader>>>,
+    // Registered metrics object shared by all storage backend objects for the blob.
+    metrics: Arc<BackendMetrics>,
+}
+
+impl ReloadableBackend {
+    fn new(config: &BackendConfigV2, blob_id: &str) -> IOResult<Self> {
+        let backend = BlobFactory::new_backend(config, blob_id)?;
+        Ok(ReloadableBackend {
+            blob_id: blob_id.to_string(),
+            backend_type: config.backend_type.clone(),
+            current: ArcSwap::new(Arc::new(BackendInstance(backend))),
+            readers: Mutex::new(Vec::new()),
+            metrics: BackendMetrics::new(blob_id, &config.backend_type),
+        })
+    }
+
+    fn reload(&self, config: &BackendConfigV2) -> IOResult<()> {
+        if config.backend_type != self.backend_type {
+            return Err(einval!(format!(
+                "can't change backend type from '{}' to '{}'",
+                self.backend_type, config.backend_type
+            )));
+        }
+
+        // Hold the lock to avoid creating readers from the old backend object during reload.
+        let mut readers = self.readers.lock().unwrap();
+        let backend = Arc::new(BackendInstance(BlobFactory::new_backend(
+            config,
+            &self.blob_id,
+        )?));
+        readers.retain(|r| r.strong_count() > 0);
+        let mut slots = Vec::with_capacity(readers.len());
+        for reader in readers.iter().filter_map(|r| r.upgrade()) {
+            let slot = ReaderSlot {
+                _backend: backend.clone(),
+                reader: backend.0.get_reader(&reader.blob_id).map_err(|e| {
+                    eother!(format!(
+                        "failed to get reader for blob {}, {}",
+                        reader.blob_id, e
+                    ))
+                })?,
+            };
+            slots.push((reader, slot));
+        }
+
+        self.current.store(backend);
+        for (reader, slot) in slots {
+            reader.slot.store(Arc::new(slot));
+        }
+        info!(
+            "storage backend for blob {} has been updated, {} readers switched",
+            self.blob_id,
+            readers.len()
+        );
+
+        Ok(())
+    }
+}
+
+impl BlobBackend for ReloadableBackend {
+    fn shutdown(&self) {
+        self.current.load().0.shutdown();
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        &self.metrics
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        let mut readers = self.readers.lock().unwrap();
+        let backend = self.current.load_full();
+        let reader = backend.0.get_reader(blob_id)?;
+        let reader = Arc::new(ReloadableReader {
+            blob_id: blob_id.to_string(),
+            slot: ArcSwap::new(Arc::new(ReaderSlot {
+                _backend: backend,
+                reader,
+            })),
+            metrics: self.metrics.clone(),
+        });
+        readers.retain(|r| r.strong_count() > 0);
+        readers.push(Arc::downgrade(&reader));
+
+        Ok(reader)
+    }
+}
+
+impl Drop for ReloadableBackend {
+    fn drop(&mut self) {
+        self.metrics.release().unwrap_or_else(|e| error!("{:?}", e));
+    }
+}
+
 lazy_static::lazy_static! {
     /// Default blob factory.
     pub static ref BLOB_FACTORY: BlobFactory = BlobFactory::new();
@@ -83,6 +250,7 @@ lazy_static::lazy_static! {
 /// Factory to create blob cache for blob objects.
 pub struct BlobFactory {
     mgrs: Mutex<HashMap<BlobCacheMgrKey, Arc<dyn BlobCacheMgr>>>,
+    backends: Mutex<HashMap<BlobCacheMgrKey, Arc<ReloadableBackend>>>,
     mgr_checker_active: AtomicBool,
 }
 
@@ -91,6 +259,7 @@ impl BlobFactory {
     pub fn new() -> Self {
         BlobFactory {
             mgrs: Mutex::new(HashMap::new()),
+            backends: Mutex::new(HashMap::new()),
             mgr_checker_active: AtomicBool::new(false),
         }
     }
@@ -132,7 +301,8 @@ impl BlobFactory {
         if let Some(mgr) = guard.get(&key) {
             return mgr.get_blob_cache(blob_info);
         }
-        let backend = Self::new_backend(backend_cfg, &blob_info.blob_id())?;
+        let reloadable = Arc::new(ReloadableBackend::new(backend_cfg, &blob_info.blob_id())?);
+        let backend = reloadable.clone() as Arc<dyn BlobBackend>;
         let mgr = match cache_cfg.cache_type.as_str() {
             "blobcache" | "filecache" => {
                 let mgr = FileCacheMgr::new(
@@ -164,6 +334,12 @@ impl BlobFactory {
             }
         };
 
+        self.backends.lock().unwrap().insert(
+            BlobCacheMgrKey {
+                config: config.clone(),
+            },
+            reloadable,
+        );
         let mgr = guard.entry(key).or_insert_with(|| mgr);
 
         mgr.get_blob_cache(blob_info)
@@ -199,17 +375,72 @@ impl BlobFactory {
         for (key, mgr) in mgrs {
             let mut guard = self.mgrs.lock().unwrap();
             if mgr.gc(None) {
+                self.backends.lock().unwrap().remove(&key);
                 guard.remove(&key);
             }
         }
     }
 
+    /// Update storage backend configuration for blob caches created with `config`.
+    ///
+    /// Credentials, endpoints, proxy settings and timeouts etc. may be changed, but the backend
+    /// type must be kept. Requests in-flight complete with the old storage backend object, and
+    /// new requests are served by a storage backend object created from `backend_cfg`.
+    ///
+    /// Return the updated configuration, which should be used to look up the blob caches later.
+    pub fn update_backend(
+        &self,
+        config: &Arc<ConfigV2>,
+        backend_cfg: &BackendConfigV2,
+    ) -> IOResult<Arc<ConfigV2>> {
+        if !backend_cfg.validate() {
+            return Err(einval!("invalid storage backend configuration"));
+        }
+        let key = BlobCacheMgrKey {
+            config: config.clone(),
+        };
+        let backend = self
+            .backends
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| enoent!("no storage backend found for the configuration"))?;
+        backend.reload(backend_cfg)?;
+
+        let mut new_config = config.as_ref().clone();
+        new_config.backend = Some(backend_cfg.clone());
+        let new_config = Arc::new(new_config);
+        // Blob cache managers are indexed by the whole configuration, lock in the same order as
+        // `new_blob_cache()`.
+        let mut mgrs = self.mgrs.lock().unwrap();
+        let mut backends = self.backends.lock().unwrap();
+        if let Some(backend) = backends.remove(&key) {
+            let new_key = BlobCacheMgrKey {
+                config: new_config.clone(),
+            };
+            backends.insert(new_key, backend);
+        }
+        if let Some(mgr) = mgrs.remove(&key) {
+            let new_key = BlobCacheMgrKey {
+                config: new_config.clone(),
+            };
+            mgrs.insert(new_key, mgr);
+        }
+
+        Ok(new_config)
+    }
+
     pub fn supported_backends() -> Vec<String> {
         let backends = vec![
             #[cfg(feature = "backend-oss")]
             "oss".to_string(),
             #[cfg(feature = "backend-s3")]
             "s3".to_string(),
+            #[cfg(feature = "backend-azure")]
+            "azure".to_string(),
+            #[cfg(feature = "backend-gcs")]
+            "gcs".to_string(),
             #[cfg(feature = "backend-registry")]
             "registry".to_string(),
             #[cfg(feature = "backend-localfs")]
@@ -239,6 +470,16 @@ impl BlobFactory {
                 config.get_s3_config()?,
                 Some(blob_id),
             )?)),
+            #[cfg(feature = "backend-azure")]
+            "azure" => Ok(Arc::new(azure::Azure::new(
+                config.get_azure_config()?,
+                Some(blob_id),
+            )?)),
+            #[cfg(feature = "backend-gcs")]
+            "gcs" => Ok(Arc::new(gcs::Gcs::new(
+                config.get_gcs_config()?,
+                Some(blob_id),
+            )?)),
             #[cfg(feature = "backend-registry")]
             "registry" => Ok(Arc::new(registry::Registry::new(
                 config.get_registry_config()?,
@@ -282,6 +523,16 @@ impl BlobFactory {
                 let cfg = serde_json::from_str::<S3Config>(&content)?;
                 Ok(Arc::new(s3::S3::new(&cfg, Some(blob_id))?))
             }
+            #[cfg(feature = "backend-azure")]
+            "azure" => {
+                let cfg = serde_json::from_str::<AzureConfig>(&content)?;
+                Ok(Arc::new(azure::Azure::new(&cfg, Some(blob_id))?))
+            }
+            #[cfg(feature = "backend-gcs")]
+            "gcs" => {
+                let cfg = serde_json::from_str::<GcsConfig>(&content)?;
+                Ok(Arc::new(gcs::Gcs::new(&cfg, Some(blob_id))?))
+            }
             #[cfg(feature = "backend-registry")]
             "registry" => {
                 let cfg = serde_json::from_str::<RegistryConfig>(&content)?;
@@ -322,3 +573,102 @@ impl Default for BlobFactory {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn localfs_config(dir: &TempDir) -> BackendConfigV2 {
+        BackendConfigV2 {
+            backend_type: "localfs".to_string(),
+            localfs: Some(LocalFsConfig {
+                blob_file: String::new(),
+                dir: dir.as_path().to_str().unwrap().to_string(),
+                alt_dirs: Vec::new(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reloadable_backend() {
+        let blob_id = "test_reloadable_backend";
+        let dir1 = TempDir::new().unwrap();
+        let dir2 = TempDir::new().unwrap();
+        std::fs::write(dir1.as_path().join(blob_id), b"aaaa").unwrap();
+        std::fs::write(dir2.as_path().join(blob_id), b"bbbbbb").unwrap();
+
+        let backend = ReloadableBackend::new(&localfs_config(&dir1), blob_id).unwrap();
+        let reader = backend.get_reader(blob_id).unwrap();
+        assert_eq!(reader.blob_size().unwrap(), 4);
+        let mut buf = vec![0u8; 4];
+        assert_eq!(reader.read(&mut buf, 0).unwrap(), 4);
+        assert_eq!(&buf, b"aaaa");
+
+        // Requests issued before reloading keep a reference to the old backend object.
+        let inflight = backend.current.load_full();
+        let mut config = localfs_config(&dir2);
+        config.backend_type = "oss".to_string();
+        assert!(backend.reload(&config).is_err());
+        config.backend_type = "localfs".to_string();
+        backend.reload(&config).unwrap();
+
+        let old_reader = inflight.0.get_reader(blob_id).unwrap();
+        assert_eq!(old_reader.blob_size().unwrap(), 4);
+        assert_eq!(reader.read(&mut buf, 2).unwrap(), 4);
+        assert_eq!(&buf, b"bbbb");
+        let reader2 = backend.get_reader(blob_id).unwrap();
+        assert_eq!(reader2.blob_size().unwrap(), 6);
+
+        // Counters are kept in the registered metrics object across reloading.
+        let metrics =
+            nydus_utils::metrics::export_backend_metrics(&Some(blob_id.to_string())).unwrap();
+        let metrics: serde_json::Value = serde_json::from_str(&metrics).unwrap();
+        assert_eq!(metrics["read_count"], 2);
+        assert_eq!(metrics["backend_type"], "localfs");
+        assert_eq!(reader.metrics() as *const _, backend.metrics() as *const _);
+        drop(inflight);
+        drop(old_reader);
+        assert!(nydus_utils::metrics::export_backend_metrics(&Some(blob_id.to_string())).is_ok());
+        drop(reader);
+        drop(reader2);
+        drop(backend);
+        assert!(nydus_utils::metrics::export_backend_metrics(&Some(blob_id.to_string())).is_err());
+    }
+
+    #[test]
+    fn test_update_backend() {
+        let blob_id = "test_update_backend";
+        let dir1 = TempDir::new().unwrap();
+        let dir2 = TempDir::new().unwrap();
+        std::fs::write(dir1.as_path().join(blob_id), b"aaaa").unwrap();
+        std::fs::write(dir2.as_path().join(blob_id), b"bbbbbb").unwrap();
+
+        let factory = BlobFactory::new();
+        let mut config = ConfigV2::new("test");
+        config.backend = Some(localfs_config(&dir1));
+        let config = Arc::new(config);
+        let backend = Arc::new(ReloadableBackend::new(&localfs_config(&dir1), blob_id).unwrap());
+        factory.backends.lock().unwrap().insert(
+            BlobCacheMgrKey {
+                config: config.clone(),
+            },
+            backend.clone(),
+        );
+
+        let new_config = factory
+            .update_backend(&config, &localfs_config(&dir2))
+            .unwrap();
+        assert_eq!(new_config.backend, Some(localfs_config(&dir2)));
+        assert_eq!(backend.get_reader(blob_id).unwrap().blob_size().unwrap(), 6);
+        // The storage backend is only found with the updated configuration.
+        assert!(factory
+            .update_backend(&config, &localfs_config(&dir2))
+            .is_err());
+        factory
+            .update_backend(&new_config, &localfs_config(&dir1))
+            .unwrap();
+        assert_eq!(backend.get_reader(blob_id).unwrap().blob_size().unwrap(), 4);
+    }
+}
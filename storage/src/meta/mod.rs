@@ -54,7 +54,7 @@ pub use chunk_info_v2::BlobChunkInfoV2Ondisk;
 pub mod toc;
 
 mod zran;
-pub use zran::{ZranContextGenerator, ZranInflateContext};
+pub use zran::{ZranContextGenerator, ZranContextReader, ZranInflateContext};
 
 mod batch;
 pub use batch::{BatchContextGenerator, BatchInflateContext};
@@ -66,7 +66,7 @@ const BLOB_CCT_CHUNK_SIZE_MASK: u64 = 0xff_ffff;
 const BLOB_CCT_V1_MAX_SIZE: u64 = RAFS_MAX_CHUNK_SIZE * 16;
 const BLOB_CCT_V2_MAX_SIZE: u64 = RAFS_MAX_CHUNK_SIZE * 24;
 //const BLOB_CCT_V1_RESERVED_SIZE: u64 = BLOB_METADATA_HEADER_SIZE - 44;
-const BLOB_CCT_V2_RESERVED_SIZE: u64 = BLOB_CCT_HEADER_SIZE - 64;
+const BLOB_CCT_V2_RESERVED_SIZE: u64 = BLOB_CCT_HEADER_SIZE - 76;
 
 /// File suffix for blob meta file.
 const BLOB_CCT_FILE_SUFFIX: &str = "blob.meta";
@@ -84,7 +84,7 @@ const BLOB_TOC_FILE_SUFFIX: &str = "blob.toc";
 ///
 /// The compression context table and header are arranged in the data blob as follows:
 ///
-/// `chunk data`  |  `compression context table`  |  `[ZRan context table | ZRan dictionary]`  |  `compression context table header`
+/// `chunk data`  |  `compression context table`  |  `[ZRan context table | ZRan dictionary]`  |  `[compression dictionary]`  |  `compression context table header`
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct BlobCompressionContextHeader {
@@ -108,6 +108,10 @@ pub struct BlobCompressionContextHeader {
     s_ci_zran_size: u64,
     /// Number of entries in the ZRan context table.
     s_ci_zran_count: u32,
+    /// Size of the optional compression dictionary for chunk data.
+    s_ci_dict_size: u32,
+    /// Offset of the optional compression dictionary, relative to the compression context table.
+    s_ci_dict_offset: u64,
 
     s_reserved: [u8; BLOB_CCT_V2_RESERVED_SIZE as usize],
     /// Second magic number to identify the blob meta data header.
@@ -127,6 +131,8 @@ impl Default for BlobCompressionContextHeader {
             s_ci_zran_offset: 0,
             s_ci_zran_size: 0,
             s_ci_zran_count: 0,
+            s_ci_dict_size: 0,
+            s_ci_dict_offset: 0,
             s_reserved: [0u8; BLOB_CCT_V2_RESERVED_SIZE as usize],
             s_magic2: BLOB_CCT_MAGIC,
         }
@@ -227,6 +233,26 @@ impl BlobCompressionContextHeader {
         self.s_ci_zran_size = size;
     }
 
+    /// Get offset of the compression dictionary for chunk data.
+    pub fn ci_dict_offset(&self) -> u64 {
+        self.s_ci_dict_offset
+    }
+
+    /// Set offset of the compression dictionary for chunk data.
+    pub fn set_ci_dict_offset(&mut self, offset: u64) {
+        self.s_ci_dict_offset = offset;
+    }
+
+    /// Get size of the compression dictionary for chunk data.
+    pub fn ci_dict_size(&self) -> u32 {
+        self.s_ci_dict_size
+    }
+
+    /// Set size of the compression dictionary for chunk data.
+    pub fn set_ci_dict_size(&mut self, size: u32) {
+        self.s_ci_dict_size = size;
+    }
+
     /// Check whether uncompressed chunks are 4k aligned.
     pub fn is_4k_aligned(&self) -> bool {
         self.has_feature(BlobFeatures::ALIGNED)
@@ -526,6 +552,25 @@ impl BlobCompressionContextInfo {
             state.zran_dict_table = ManuallyDrop::new(array);
         }
 
+        let header = state
+            .blob_meta_file_map
+            .get_mut::<BlobCompressionContextHeader>(aligned_uncompressed_size as usize)?;
+        let dict_offset = header.s_ci_dict_offset as usize;
+        let dict_size = header.s_ci_dict_size as usize;
+        if dict_size > 0 {
+            let ptr = state
+                .blob_meta_file_map
+                .validate_range(dict_offset, dict_size)?;
+            let data = unsafe { std::slice::from_raw_parts(ptr, dict_size) };
+            state.compression_dict = Some(compress::CompressionDict::new(data.to_vec())?);
+        } else if blob_info.compressor().need_dict() {
+            return Err(einval!(format!(
+                "blob {} compressed with {} has no compression dictionary",
+                blob_info.blob_id(),
+                blob_info.compressor()
+            )));
+        }
+
         if load_chunk_digest && blob_info.has_feature(BlobFeatures::INLINED_CHUNK_DIGEST) {
             let digest_path = PathBuf::from(format!("{}.{}", blob_path, BLOB_DIGEST_FILE_SUFFIX));
             if let Some(reader) = reader {
@@ -743,6 +788,39 @@ impl BlobCompressionContextInfo {
         self.state.get_zran_context(zran_index as usize)
     }
 
+    /// Get the compression dictionary for chunk data, if any.
+    pub fn get_compression_dict(&self) -> Option<&compress::CompressionDict> {
+        self.state.compression_dict.as_ref()
+    }
+
+    /// Read the compression dictionary for chunk data from the blob, without caching blob meta.
+    pub fn read_compression_dict(
+        blob_info: &BlobInfo,
+        reader: &Arc<dyn BlobReader>,
+    ) -> Result<Option<compress::CompressionDict>> {
+        let aligned_uncompressed_size = round_up_4k(blob_info.meta_ci_uncompressed_size());
+        let mut buffer = vec![0u8; (aligned_uncompressed_size + BLOB_CCT_HEADER_SIZE) as usize];
+        Self::read_metadata(blob_info, reader, &mut buffer)?;
+        let header = unsafe {
+            std::ptr::read_unaligned(buffer[aligned_uncompressed_size as usize..].as_ptr()
+                as *const BlobCompressionContextHeader)
+        };
+        if !Self::validate_header(blob_info, &header)? {
+            return Err(einval!(format!(
+                "blob meta header of blob {} is invalid",
+                blob_info.blob_id()
+            )));
+        }
+
+        let offset = header.s_ci_dict_offset as usize;
+        let size = header.s_ci_dict_size as usize;
+        if size == 0 {
+            Ok(None)
+        } else {
+            compress::CompressionDict::new(buffer[offset..offset + size].to_vec()).map(Some)
+        }
+    }
+
     fn read_metadata(
         blob_info: &BlobInfo,
         reader: &Arc<dyn BlobReader>,
@@ -908,6 +986,14 @@ impl BlobCompressionContextInfo {
 
         let info_size = u64::from_le(header.s_ci_uncompressed_size) as usize;
         let aligned_info_size = round_up_4k(info_size);
+        // The optional compression dictionary is appended to the compression context table.
+        let dict_size = header.s_ci_dict_size as usize;
+        if dict_size > 0
+            && header.s_ci_dict_offset.checked_add(dict_size as u64) != Some(info_size as u64)
+        {
+            return Ok(false);
+        }
+        let info_size = info_size - dict_size;
         if blob_info.has_feature(BlobFeatures::CHUNK_INFO_V2)
             && (blob_info.has_feature(BlobFeatures::ZRAN)
                 || blob_info.has_feature(BlobFeatures::BATCH))
@@ -966,6 +1052,7 @@ pub struct BlobCompressionContext {
     pub(crate) batch_info_array: ManuallyDrop<Vec<BatchInflateContext>>,
     pub(crate) zran_info_array: ManuallyDrop<Vec<ZranInflateContext>>,
     pub(crate) zran_dict_table: ManuallyDrop<Vec<u8>>,
+    pub(crate) compression_dict: Option<compress::CompressionDict>,
     blob_meta_file_map: FileMapState,
     chunk_digest_file_map: FileMapState,
     chunk_digest_default: RafsDigest,
@@ -2291,6 +2378,63 @@ pub(crate) mod tests {
             .is_err());
     }
 
+    #[test]
+    fn test_load_meta_ci_compression_dict() {
+        let tmpdir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let blob_path = tmpdir.as_path().join("blob");
+        let samples = vec![b"hello world".to_vec(), b"hello nydus".to_vec()];
+        let dict = compress::CompressionDict::train(&samples, 64).unwrap();
+
+        let ci_size = size_of::<BlobChunkInfoV2Ondisk>();
+        let mut data = vec![0u8; ci_size];
+        data.extend_from_slice(dict.as_bytes());
+        let info_size = data.len() as u64;
+        let mut header = BlobCompressionContextHeader::default();
+        header.set_ci_compressor(compress::Algorithm::None);
+        header.set_ci_entries(1);
+        header.set_ci_compressed_size(info_size);
+        header.set_ci_uncompressed_size(info_size);
+        header.set_chunk_info_v2(true);
+        header.set_ci_dict_offset(ci_size as u64);
+        header.set_ci_dict_size(dict.as_bytes().len() as u32);
+        data.resize(round_up_4k(data.len()), 0);
+        data.extend_from_slice(header.as_bytes());
+        let meta_path = format!("{}.{}", blob_path.display(), BLOB_CCT_FILE_SUFFIX);
+        std::fs::write(&meta_path, &data).unwrap();
+
+        let mut blob_info = BlobInfo::new(
+            0,
+            "blob".to_string(),
+            0x1000,
+            0x1000,
+            RAFS_DEFAULT_CHUNK_SIZE as u32,
+            1,
+            BlobFeatures::CHUNK_INFO_V2,
+        );
+        blob_info.set_compressor(compress::Algorithm::ZstdDict);
+        blob_info.set_blob_meta_info(0, info_size, info_size, compress::Algorithm::None as u32);
+        let meta =
+            BlobCompressionContextInfo::new(blob_path.to_str().unwrap(), &blob_info, None, false)
+                .unwrap();
+        assert_eq!(
+            meta.get_compression_dict().unwrap().as_bytes(),
+            dict.as_bytes()
+        );
+
+        // The compression dictionary must be at the end of the compression context table.
+        header.set_ci_dict_offset(0);
+        let len = data.len();
+        data[len - header.as_bytes().len()..].copy_from_slice(header.as_bytes());
+        std::fs::write(&meta_path, &data).unwrap();
+        assert!(BlobCompressionContextInfo::new(
+            blob_path.to_str().unwrap(),
+            &blob_info,
+            None,
+            false
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_blob_compression_context_header_getters_and_setters() {
         let mut header = BlobCompressionContextHeader::default();
@@ -2387,6 +2531,14 @@ pub(crate) mod tests {
         assert_eq!(header.ci_zran_size(), 0);
         header.set_ci_zran_size(1);
         assert_eq!(header.ci_zran_size(), 1);
+
+        assert_eq!(header.ci_dict_offset(), 0);
+        header.set_ci_dict_offset(1);
+        assert_eq!(header.ci_dict_offset(), 1);
+
+        assert_eq!(header.ci_dict_size(), 0);
+        header.set_ci_dict_size(1);
+        assert_eq!(header.ci_dict_size(), 1);
     }
 
     #[test]
@@ -770,6 +770,8 @@ mod tests {
             oss: None,
             registry: None,
             s3: None,
+            azure: None,
+            gcs: None,
             http_proxy: None,
         };
         let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
@@ -830,6 +832,8 @@ mod tests {
             oss: None,
             registry: None,
             s3: None,
+            azure: None,
+            gcs: None,
             http_proxy: None,
             localdisk: None,
         };
@@ -865,6 +869,8 @@ mod tests {
             oss: None,
             registry: None,
             s3: None,
+            azure: None,
+            gcs: None,
             localdisk: None,
             http_proxy: None,
         };
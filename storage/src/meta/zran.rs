@@ -2,17 +2,24 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::io::{BufReader, Read, Result};
+use std::io::{BufReader, Read, Result, Seek};
 use std::mem::size_of;
 use std::slice;
 
+use nydus_utils::compress::xz_random::{XzBlockGenerator, XzBlockReader};
 use nydus_utils::compress::zlib_random::{ZranContext, ZranGenerator, ZranReader};
+use nydus_utils::compress::zstd_random::{ZstdFrameGenerator, ZstdFrameReader};
+use sha2::Sha256;
 
 use crate::meta::chunk_info_v2::BlobChunkInfoV2Ondisk;
 use crate::meta::{round_up_4k, BlobMetaChunkInfo};
-use crate::RAFS_DEFAULT_CHUNK_SIZE;
+use crate::{RAFS_DEFAULT_CHUNK_SIZE, RAFS_MAX_CHUNK_SIZE};
 
-/// Context information to support random access to zlib/gzip stream .
+/// Context information to support random access to zlib/gzip stream, seekable zstd stream or xz
+/// stream.
+///
+/// For zstd streams, each context starts from a zstd frame boundary and has no dictionary. For xz
+/// streams, each context starts from an xz block boundary and the dictionary is the stream header.
 #[repr(C, packed)]
 pub struct ZranInflateContext {
     /// Offset in the original compression data stream.
@@ -101,10 +108,67 @@ impl From<&ZranInflateContext> for ZranContext {
     }
 }
 
-/// Struct to generate [ZranInflateContext] objects for zlib/gzip stream.
+/// Reader to decode data from a compressed stream with random access information generated.
+pub enum ZranContextReader<R> {
+    /// Reader for zlib/gzip stream.
+    Zlib(ZranReader<R>),
+    /// Reader for seekable zstd stream.
+    Zstd(ZstdFrameReader<R>),
+    /// Reader for xz stream.
+    Xz(XzBlockReader<R>),
+}
+
+impl<R> ZranContextReader<R> {
+    /// Get size of data read from the reader.
+    pub fn get_data_size(&self) -> u64 {
+        match self {
+            ZranContextReader::Zlib(r) => r.get_data_size(),
+            ZranContextReader::Zstd(r) => r.get_data_size(),
+            ZranContextReader::Xz(r) => r.get_data_size(),
+        }
+    }
+
+    /// Get sha256 hash value of data read from the reader.
+    pub fn get_data_digest(&self) -> Sha256 {
+        match self {
+            ZranContextReader::Zlib(r) => r.get_data_digest(),
+            ZranContextReader::Zstd(r) => r.get_data_digest(),
+            ZranContextReader::Xz(r) => r.get_data_digest(),
+        }
+    }
+}
+
+impl<R: Read> Read for ZranContextReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            ZranContextReader::Zlib(r) => r.read(buf),
+            ZranContextReader::Zstd(r) => r.read(buf),
+            ZranContextReader::Xz(r) => r.read(buf),
+        }
+    }
+}
+
+impl<R> Clone for ZranContextReader<R> {
+    fn clone(&self) -> Self {
+        match self {
+            ZranContextReader::Zlib(r) => ZranContextReader::Zlib(r.clone()),
+            ZranContextReader::Zstd(r) => ZranContextReader::Zstd(r.clone()),
+            ZranContextReader::Xz(r) => ZranContextReader::Xz(r.clone()),
+        }
+    }
+}
+
+enum ContextGenerator<R> {
+    Zlib(ZranGenerator<R>),
+    Zstd(ZstdFrameGenerator<R>),
+    Xz(XzBlockGenerator<R>),
+}
+
+/// Struct to generate [ZranInflateContext] objects for zlib/gzip stream, seekable zstd stream or
+/// xz stream.
 pub struct ZranContextGenerator<R> {
-    generator: ZranGenerator<R>,
-    reader: ZranReader<R>,
+    generator: ContextGenerator<R>,
+    reader: ZranContextReader<R>,
     uncomp_pos: u64,
 }
 
@@ -119,8 +183,8 @@ impl<R: Read> ZranContextGenerator<R> {
         generator.set_max_uncompressed_size(RAFS_DEFAULT_CHUNK_SIZE * 2);
 
         Ok(Self {
-            generator,
-            reader,
+            generator: ContextGenerator::Zlib(generator),
+            reader: ZranContextReader::Zlib(reader),
             uncomp_pos: 0,
         })
     }
@@ -139,35 +203,84 @@ impl<R: Read> ZranContextGenerator<R> {
         generator.set_max_uncompressed_size(RAFS_DEFAULT_CHUNK_SIZE * 2);
 
         Ok(Self {
-            generator,
-            reader,
+            generator: ContextGenerator::Zlib(generator),
+            reader: ZranContextReader::Zlib(reader),
+            uncomp_pos: 0,
+        })
+    }
+
+    /// Create a new instance of [ZranContextGenerator] for seekable zstd stream from a `BufReader`.
+    pub fn from_zstd_buf_reader(buf_reader: BufReader<R>) -> Result<Self> {
+        let buf = buf_reader.buffer().to_vec();
+        let file = buf_reader.into_inner();
+
+        let reader = ZstdFrameReader::new(file)?;
+        reader.set_initial_data(&buf);
+
+        let mut generator = ZstdFrameGenerator::new(reader.clone());
+        generator.set_max_compressed_size(RAFS_DEFAULT_CHUNK_SIZE);
+        generator.set_max_uncompressed_size(RAFS_DEFAULT_CHUNK_SIZE * 2);
+
+        Ok(Self {
+            generator: ContextGenerator::Zstd(generator),
+            reader: ZranContextReader::Zstd(reader),
             uncomp_pos: 0,
         })
     }
 
     /// Get reader to read decompressed data.
-    pub fn reader(&self) -> ZranReader<R> {
+    pub fn reader(&self) -> ZranContextReader<R> {
         self.reader.clone()
     }
 
     /// Get number of zlib/gzip inflate context entries.
     pub fn len(&self) -> usize {
-        self.generator.get_compression_ctx_array().len()
+        match &self.generator {
+            ContextGenerator::Zlib(g) => g.get_compression_ctx_array().len(),
+            ContextGenerator::Zstd(g) => g.len(),
+            ContextGenerator::Xz(g) => g.len(),
+        }
     }
 
     /// Check whether there's any zlib/gzip inflate context entries.
     pub fn is_empty(&self) -> bool {
-        self.generator.get_compression_ctx_array().is_empty()
+        self.len() == 0
     }
 
     /// Begin transaction to generate a data chunk for a file.
     pub fn start_chunk(&mut self, chunk_size: u64) -> Result<u32> {
-        self.generator.begin_read(chunk_size)
+        match &mut self.generator {
+            ContextGenerator::Zlib(g) => g.begin_read(chunk_size),
+            ContextGenerator::Zstd(g) => g.begin_read(chunk_size),
+            ContextGenerator::Xz(g) => g.begin_read(chunk_size),
+        }
     }
 
     /// Finish the transaction to generate a data chunk and return the chunk info struct.
     pub fn finish_chunk(&mut self) -> Result<BlobChunkInfoV2Ondisk> {
-        let info = self.generator.end_read()?;
+        let info = match &mut self.generator {
+            ContextGenerator::Zlib(g) => g.end_read()?,
+            ContextGenerator::Zstd(g) => {
+                let info = g.end_read()?;
+                // A zstd frame can't be split, so it must be small enough for random access.
+                if info.ci_offset as u64 + info.ci_len as u64 > RAFS_MAX_CHUNK_SIZE {
+                    return Err(einval!(
+                        "zstd frame is too big for random access, the zstd stream is not seekable"
+                    ));
+                }
+                info
+            }
+            ContextGenerator::Xz(g) => {
+                let info = g.end_read()?;
+                // An xz block can't be split, so it must be small enough for random access.
+                if info.ci_offset as u64 + info.ci_len as u64 > RAFS_MAX_CHUNK_SIZE {
+                    return Err(einval!(
+                        "xz block is too big for random access, please compress with smaller blocks"
+                    ));
+                }
+                info
+            }
+        };
         let mut chunk = BlobChunkInfoV2Ondisk::default();
         chunk.set_compressed_offset(info.in_pos);
         chunk.set_compressed_size(info.in_len);
@@ -184,10 +297,14 @@ impl<R: Read> ZranContextGenerator<R> {
         Ok(chunk)
     }
 
-    /// Convert all the zlib/gzip random access information to a u8 vector.
-    pub fn to_vec(&self) -> Result<(Vec<u8>, u32)> {
+    /// Convert all the random access information to a u8 vector.
+    pub fn to_vec(&mut self) -> Result<(Vec<u8>, u32)> {
         let mut data = Vec::new();
-        let records = self.generator.get_compression_ctx_array();
+        let records = match &mut self.generator {
+            ContextGenerator::Zlib(g) => g.get_compression_ctx_array(),
+            ContextGenerator::Zstd(g) => g.get_compression_ctx_array(),
+            ContextGenerator::Xz(g) => g.get_compression_ctx_array(),
+        };
         let mut dict_off = 0;
 
         for info in records {
@@ -216,9 +333,36 @@ impl<R: Read> ZranContextGenerator<R> {
     }
 }
 
+impl<R: Read + Seek> ZranContextGenerator<R> {
+    /// Create a new instance of [ZranContextGenerator] for xz stream from a `BufReader`.
+    ///
+    /// Xz blocks are located by indexes at the end of the stream, so the reader must be seekable.
+    pub fn from_xz_buf_reader(buf_reader: BufReader<R>) -> Result<Self> {
+        let buf = buf_reader.buffer().to_vec();
+        let file = buf_reader.into_inner();
+
+        let reader = XzBlockReader::new(file)?;
+        reader.set_initial_data(&buf);
+
+        let mut generator = XzBlockGenerator::new(reader.clone());
+        generator.set_max_compressed_size(RAFS_DEFAULT_CHUNK_SIZE);
+        generator.set_max_uncompressed_size(RAFS_DEFAULT_CHUNK_SIZE * 2);
+
+        Ok(Self {
+            generator: ContextGenerator::Xz(generator),
+            reader: ZranContextReader::Xz(reader),
+            uncomp_pos: 0,
+        })
+    }
+}
+
 impl<R: Read> Read for ZranContextGenerator<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.generator.read(buf)
+        match &mut self.generator {
+            ContextGenerator::Zlib(g) => g.read(buf),
+            ContextGenerator::Zstd(g) => g.read(buf),
+            ContextGenerator::Xz(g) => g.read(buf),
+        }
     }
 }
 
@@ -239,9 +383,11 @@ mod tests {
         let mut tar = Archive::new(generator.reader());
         tar.set_ignore_zeros(true);
 
-        generator.generator.set_min_compressed_size(1024);
-        generator.generator.set_max_compressed_size(2048);
-        generator.generator.set_max_uncompressed_size(4096);
+        if let ContextGenerator::Zlib(g) = &mut generator.generator {
+            g.set_min_compressed_size(1024);
+            g.set_max_compressed_size(2048);
+            g.set_max_uncompressed_size(4096);
+        }
 
         assert_eq!(generator.len(), 0);
 
@@ -263,4 +409,83 @@ mod tests {
 
         assert_eq!(generator.len(), 3);
     }
+
+    #[test]
+    fn test_generate_zstd_chunk_info() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/tar/all-entry-type.tar.zst");
+        let file = OpenOptions::new().read(true).open(path).unwrap();
+
+        let mut generator =
+            ZranContextGenerator::from_zstd_buf_reader(BufReader::new(file)).unwrap();
+        let mut tar = Archive::new(generator.reader());
+        assert_eq!(generator.len(), 0);
+
+        let mut chunks = Vec::new();
+        for entry in tar.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.header().entry_type() == EntryType::Regular {
+                let size = entry.header().size().unwrap() as usize;
+                generator.start_chunk(size as u64).unwrap();
+                let mut buf = vec![0u8; size];
+                entry.read_exact(&mut buf).unwrap();
+                chunks.push(generator.finish_chunk().unwrap());
+            }
+        }
+
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_zran());
+        assert_eq!(chunks[0].uncompressed_size(), 15);
+        assert_eq!(generator.len(), 1);
+        let (data, count) = generator.to_vec().unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(data.len(), size_of::<ZranInflateContext>());
+        let ctx = unsafe { &*(data.as_ptr() as *const ZranInflateContext) };
+        // The fixture is compressed into 2048-byte zstd frames.
+        assert_eq!(ctx.out_offset() % 2048, 0);
+        assert!(ctx.out_size() >= 15);
+        assert_eq!(ctx.dict_size(), 0);
+        assert!(ctx.in_offset() + ctx.in_size() as u64 <= generator.reader().get_data_size());
+    }
+
+    #[test]
+    fn test_generate_xz_chunk_info() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/tar/all-entry-type.tar.xz");
+        let file = OpenOptions::new().read(true).open(path).unwrap();
+
+        let mut generator = ZranContextGenerator::from_xz_buf_reader(BufReader::new(file)).unwrap();
+        let mut tar = Archive::new(generator.reader());
+        assert_eq!(generator.len(), 0);
+
+        let mut chunks = Vec::new();
+        for entry in tar.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.header().entry_type() == EntryType::Regular {
+                let size = entry.header().size().unwrap() as usize;
+                generator.start_chunk(size as u64).unwrap();
+                let mut buf = vec![0u8; size];
+                entry.read_exact(&mut buf).unwrap();
+                chunks.push(generator.finish_chunk().unwrap());
+            }
+        }
+
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_zran());
+        assert_eq!(chunks[0].uncompressed_size(), 15);
+        assert_eq!(generator.len(), 1);
+        let (data, count) = generator.to_vec().unwrap();
+        assert_eq!(count, 1);
+        // The stream header is stored as dictionary of the context.
+        assert_eq!(data.len(), size_of::<ZranInflateContext>() + 12);
+        let ctx = unsafe { &*(data.as_ptr() as *const ZranInflateContext) };
+        // The fixture has only one xz block, following the stream header.
+        assert_eq!(ctx.in_offset(), 12);
+        assert_eq!(ctx.out_offset(), 0);
+        assert_eq!(ctx.dict_size(), 12);
+        assert_eq!(
+            data[size_of::<ZranInflateContext>()..6 + size_of::<ZranInflateContext>()],
+            [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]
+        );
+    }
 }
@@ -11,8 +11,12 @@ use std::str::FromStr;
 mod lz4_standard;
 use self::lz4_standard::*;
 
+#[cfg(feature = "zran")]
+pub mod xz_random;
 #[cfg(feature = "zran")]
 pub mod zlib_random;
+#[cfg(feature = "zran")]
+pub mod zstd_random;
 
 const COMPRESSION_MINIMUM_RATIO: usize = 100;
 
@@ -25,6 +29,10 @@ pub enum Algorithm {
     Lz4Block = 1,
     GZip = 2,
     Zstd = 3,
+    /// Zstd with a dictionary shared by all chunks of a blob.
+    ZstdDict = 4,
+    /// Xz, only decompression is supported.
+    Xz = 5,
 }
 
 impl fmt::Display for Algorithm {
@@ -34,6 +42,8 @@ impl fmt::Display for Algorithm {
             Algorithm::Lz4Block => "lz4_block",
             Algorithm::GZip => "gzip",
             Algorithm::Zstd => "zstd",
+            Algorithm::ZstdDict => "zstd_dict",
+            Algorithm::Xz => "xz",
         };
         write!(f, "{}", output)
     }
@@ -48,7 +58,11 @@ impl FromStr for Algorithm {
             "lz4_block" => Ok(Self::Lz4Block),
             "gzip" => Ok(Self::GZip),
             "zstd" => Ok(Self::Zstd),
-            _ => Err(einval!("compression algorithm should be none or lz4_block")),
+            "zstd_dict" | "zstd-dict" => Ok(Self::ZstdDict),
+            "xz" => Ok(Self::Xz),
+            _ => Err(einval!(
+                "compression algorithm should be none, lz4_block, gzip, zstd, zstd_dict or xz"
+            )),
         }
     }
 }
@@ -65,6 +79,10 @@ impl TryFrom<u32> for Algorithm {
             Ok(Algorithm::GZip)
         } else if value == Algorithm::Zstd as u32 {
             Ok(Algorithm::Zstd)
+        } else if value == Algorithm::ZstdDict as u32 {
+            Ok(Algorithm::ZstdDict)
+        } else if value == Algorithm::Xz as u32 {
+            Ok(Algorithm::Xz)
         } else {
             Err(())
         }
@@ -83,6 +101,10 @@ impl TryFrom<u64> for Algorithm {
             Ok(Algorithm::GZip)
         } else if value == Algorithm::Zstd as u64 {
             Ok(Algorithm::Zstd)
+        } else if value == Algorithm::ZstdDict as u64 {
+            Ok(Algorithm::ZstdDict)
+        } else if value == Algorithm::Xz as u64 {
+            Ok(Algorithm::Xz)
         } else {
             Err(())
         }
@@ -94,10 +116,82 @@ impl Algorithm {
     pub fn is_none(self) -> bool {
         self == Self::None
     }
+
+    /// Check whether the compression algorithm needs a compression dictionary.
+    pub fn need_dict(self) -> bool {
+        self == Self::ZstdDict
+    }
+}
+
+/// Compression dictionary shared by all chunks of a blob, used by [Algorithm::ZstdDict].
+pub struct CompressionDict {
+    data: Vec<u8>,
+    encoder: zstd::dict::EncoderDictionary<'static>,
+    decoder: zstd::dict::DecoderDictionary<'static>,
+}
+
+impl CompressionDict {
+    /// Create a compression dictionary from raw dictionary content.
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        if data.is_empty() {
+            return Err(einval!("compression dictionary is empty"));
+        }
+        let encoder = zstd::dict::EncoderDictionary::copy(&data, zstd::DEFAULT_COMPRESSION_LEVEL);
+        let decoder = zstd::dict::DecoderDictionary::copy(&data);
+        Ok(CompressionDict {
+            data,
+            encoder,
+            decoder,
+        })
+    }
+
+    /// Train a compression dictionary of at most `max_size` bytes from data samples.
+    ///
+    /// Fall back to a raw content dictionary built from samples if there are too few samples
+    /// to train a dictionary.
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Self> {
+        match zstd::dict::from_samples(samples, max_size) {
+            Ok(data) => Self::new(data),
+            Err(_) => {
+                let mut data = Vec::with_capacity(max_size);
+                for sample in samples {
+                    let sample = sample.as_ref();
+                    let size = std::cmp::min(sample.len(), max_size - data.len());
+                    data.extend_from_slice(&sample[..size]);
+                    if data.len() >= max_size {
+                        break;
+                    }
+                }
+                Self::new(data)
+            }
+        }
+    }
+
+    /// Get raw content of the dictionary.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl fmt::Debug for CompressionDict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompressionDict")
+            .field("size", &self.data.len())
+            .finish()
+    }
 }
 
 /// Compress data with the specified compression algorithm.
 pub fn compress(src: &[u8], algorithm: Algorithm) -> Result<(Cow<[u8]>, bool)> {
+    compress_with_dict(src, algorithm, None)
+}
+
+/// Compress data with the specified compression algorithm and optional compression dictionary.
+pub fn compress_with_dict<'a>(
+    src: &'a [u8],
+    algorithm: Algorithm,
+    dict: Option<&CompressionDict>,
+) -> Result<(Cow<'a, [u8]>, bool)> {
     let src_size = src.len();
     if src_size == 0 {
         return Ok((Cow::Borrowed(src), false));
@@ -113,6 +207,17 @@ pub fn compress(src: &[u8], algorithm: Algorithm) -> Result<(Cow<[u8]>, bool)> {
             gz.finish()?
         }
         Algorithm::Zstd => zstd_compress(src)?,
+        Algorithm::ZstdDict => match dict {
+            Some(dict) => {
+                zstd::bulk::Compressor::with_prepared_dictionary(&dict.encoder)?.compress(src)?
+            }
+            None => {
+                return Err(einval!(
+                    "compression algorithm zstd_dict requires a dictionary"
+                ))
+            }
+        },
+        Algorithm::Xz => return Err(enosys!("compression algorithm xz is decompression only")),
     };
 
     // Abandon compressed data when compression ratio greater than COMPRESSION_MINIMUM_RATIO
@@ -128,6 +233,17 @@ pub fn compress(src: &[u8], algorithm: Algorithm) -> Result<(Cow<[u8]>, bool)> {
 /// Decompress a source slice or file stream into destination slice, with provided compression algorithm.
 /// Use the file as decompress source if provided.
 pub fn decompress(src: &[u8], dst: &mut [u8], algorithm: Algorithm) -> Result<usize> {
+    decompress_with_dict(src, dst, algorithm, None)
+}
+
+/// Decompress a source slice into destination slice, with provided compression algorithm and
+/// optional compression dictionary.
+pub fn decompress_with_dict(
+    src: &[u8],
+    dst: &mut [u8],
+    algorithm: Algorithm,
+    dict: Option<&CompressionDict>,
+) -> Result<usize> {
     match algorithm {
         Algorithm::None => {
             assert_eq!(src.len(), dst.len());
@@ -141,15 +257,28 @@ pub fn decompress(src: &[u8], dst: &mut [u8], algorithm: Algorithm) -> Result<us
             Ok(dst.len())
         }
         Algorithm::Zstd => zstd::bulk::decompress_to_buffer(src, dst),
+        Algorithm::ZstdDict => match dict {
+            Some(dict) => zstd::bulk::Decompressor::with_prepared_dictionary(&dict.decoder)?
+                .decompress_to_buffer(src, dst),
+            None => Err(einval!(
+                "compression algorithm zstd_dict requires a dictionary"
+            )),
+        },
+        Algorithm::Xz => {
+            let mut xz = xz2::bufread::XzDecoder::new_multi_decoder(src);
+            xz.read_exact(dst)?;
+            Ok(dst.len())
+        }
     }
 }
 
 #[allow(clippy::large_enum_variant)]
-/// Stream decoder for gzip/lz4/zstd.
+/// Stream decoder for gzip/zstd/xz.
 pub enum Decoder<'a, R: Read> {
     None(R),
     Gzip(flate2::bufread::MultiGzDecoder<BufReader<R>>),
     Zstd(zstd::stream::Decoder<'a, BufReader<R>>),
+    Xz(xz2::bufread::XzDecoder<BufReader<R>>),
 }
 
 impl<R: Read> Decoder<'_, R> {
@@ -162,6 +291,12 @@ impl<R: Read> Decoder<'_, R> {
             }
             Algorithm::Lz4Block => panic!("Decoder doesn't support lz4_block"),
             Algorithm::Zstd => Decoder::Zstd(zstd::stream::Decoder::new(reader)?),
+            Algorithm::ZstdDict => {
+                return Err(einval!("Decoder doesn't support zstd_dict"));
+            }
+            Algorithm::Xz => Decoder::Xz(xz2::bufread::XzDecoder::new_multi_decoder(
+                BufReader::new(reader),
+            )),
         };
         Ok(decoder)
     }
@@ -173,6 +308,7 @@ impl<R: Read> Read for Decoder<'_, R> {
             Decoder::None(r) => r.read(buf),
             Decoder::Gzip(r) => r.read(buf),
             Decoder::Zstd(r) => r.read(buf),
+            Decoder::Xz(r) => r.read(buf),
         }
     }
 }
@@ -491,6 +627,111 @@ mod tests {
         assert_eq!(buf, decompressed);
     }
 
+    fn dict_samples() -> Vec<Vec<u8>> {
+        (0..1000)
+            .map(|i| {
+                format!(
+                    "{{\"id\": {}, \"name\": \"file-{}\", \"mode\": \"0644\", \"owner\": \"root\"}}",
+                    i,
+                    i * 7
+                )
+                .into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_zstd_dict_compress_decompress() {
+        let samples = dict_samples();
+        let dict = CompressionDict::train(&samples, 4096).unwrap();
+        assert!(!dict.as_bytes().is_empty());
+        assert!(dict.as_bytes().len() <= 4096);
+
+        let buf =
+            b"{\"id\": 12345, \"name\": \"file-86415\", \"mode\": \"0644\", \"owner\": \"root\"}";
+        let (compressed, is_compressed) =
+            compress_with_dict(buf, Algorithm::ZstdDict, Some(&dict)).unwrap();
+        assert!(is_compressed);
+        let (plain, _) = compress(buf, Algorithm::Zstd).unwrap();
+        assert!(compressed.len() < plain.len());
+
+        let mut decompressed = vec![0u8; buf.len()];
+        let sz = decompress_with_dict(
+            &compressed,
+            decompressed.as_mut_slice(),
+            Algorithm::ZstdDict,
+            Some(&dict),
+        )
+        .unwrap();
+        assert_eq!(sz, buf.len());
+        assert_eq!(&decompressed, buf);
+
+        // A dictionary loaded from raw content works the same as the trained one.
+        let dict2 = CompressionDict::new(dict.as_bytes().to_vec()).unwrap();
+        let mut decompressed = vec![0u8; buf.len()];
+        decompress_with_dict(
+            &compressed,
+            decompressed.as_mut_slice(),
+            Algorithm::ZstdDict,
+            Some(&dict2),
+        )
+        .unwrap();
+        assert_eq!(&decompressed, buf);
+
+        assert!(compress(buf, Algorithm::ZstdDict).is_err());
+        assert!(decompress(
+            &compressed,
+            decompressed.as_mut_slice(),
+            Algorithm::ZstdDict
+        )
+        .is_err());
+        assert!(CompressionDict::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_zstd_dict_train_fallback() {
+        // Too few samples to train a dictionary, fall back to raw content dictionary.
+        let samples = vec![b"hello world".to_vec(), b"hello nydus".to_vec()];
+        let dict = CompressionDict::train(&samples, 16).unwrap();
+        assert_eq!(dict.as_bytes(), b"hello worldhello");
+
+        let buf = "hello world, hello nydus. ".repeat(8);
+        let buf = buf.as_bytes();
+        let (compressed, is_compressed) =
+            compress_with_dict(buf, Algorithm::ZstdDict, Some(&dict)).unwrap();
+        assert!(is_compressed);
+        let mut decompressed = vec![0u8; buf.len()];
+        decompress_with_dict(
+            &compressed,
+            decompressed.as_mut_slice(),
+            Algorithm::ZstdDict,
+            Some(&dict),
+        )
+        .unwrap();
+        assert_eq!(&decompressed, buf);
+    }
+
+    #[test]
+    fn test_xz_decompress() {
+        let buf = vec![0x3u8; 4097];
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(&buf).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut decompressed = vec![0u8; buf.len()];
+        let sz = decompress(&compressed, decompressed.as_mut_slice(), Algorithm::Xz).unwrap();
+        assert_eq!(sz, 4097);
+        assert_eq!(buf, decompressed);
+
+        let mut decoder = Decoder::new(compressed.as_slice(), Algorithm::Xz).unwrap();
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed).unwrap();
+        assert_eq!(buf, decompressed);
+
+        assert!(compress(&buf, Algorithm::Xz).is_err());
+        assert!(Decoder::new(compressed.as_slice(), Algorithm::ZstdDict).is_err());
+    }
+
     #[test]
     fn test_new_decoder_none() {
         let buf = b"This is a test";
@@ -559,6 +800,11 @@ mod tests {
         );
         assert_eq!(Algorithm::from_str("gzip").unwrap(), Algorithm::GZip);
         assert_eq!(Algorithm::from_str("zstd").unwrap(), Algorithm::Zstd);
+        assert_eq!(
+            Algorithm::from_str("zstd-dict").unwrap(),
+            Algorithm::ZstdDict
+        );
+        assert_eq!(Algorithm::from_str("xz").unwrap(), Algorithm::Xz);
         assert!(Algorithm::from_str("foo").is_err());
         assert_eq!(
             Algorithm::try_from(Algorithm::None as u32).unwrap(),
@@ -576,6 +822,14 @@ mod tests {
             Algorithm::try_from(Algorithm::Zstd as u32).unwrap(),
             Algorithm::Zstd
         );
+        assert_eq!(
+            Algorithm::try_from(Algorithm::ZstdDict as u32).unwrap(),
+            Algorithm::ZstdDict
+        );
+        assert_eq!(
+            Algorithm::try_from(Algorithm::Xz as u32).unwrap(),
+            Algorithm::Xz
+        );
         assert!(Algorithm::try_from(u32::MAX).is_err());
 
         assert_eq!(
@@ -594,11 +848,21 @@ mod tests {
             Algorithm::try_from(Algorithm::Zstd as u64).unwrap(),
             Algorithm::Zstd
         );
+        assert_eq!(
+            Algorithm::try_from(Algorithm::ZstdDict as u64).unwrap(),
+            Algorithm::ZstdDict
+        );
+        assert_eq!(
+            Algorithm::try_from(Algorithm::Xz as u64).unwrap(),
+            Algorithm::Xz
+        );
         assert!(Algorithm::try_from(u64::MAX).is_err());
         assert!(Algorithm::None.is_none());
         assert!(!Algorithm::Lz4Block.is_none());
         assert!(!Algorithm::GZip.is_none());
         assert!(!Algorithm::Zstd.is_none());
+        assert!(Algorithm::ZstdDict.need_dict());
+        assert!(!Algorithm::Zstd.need_dict());
     }
 
     #[test]
@@ -607,6 +871,8 @@ mod tests {
         assert_eq!(Algorithm::Lz4Block.to_string(), "lz4_block");
         assert_eq!(Algorithm::GZip.to_string(), "gzip");
         assert_eq!(Algorithm::Zstd.to_string(), "zstd");
+        assert_eq!(Algorithm::ZstdDict.to_string(), "zstd_dict");
+        assert_eq!(Algorithm::Xz.to_string(), "xz");
     }
 
     #[test]
@@ -627,6 +893,8 @@ mod tests {
             Algorithm::Lz4Block,
             Algorithm::GZip,
             Algorithm::Zstd,
+            Algorithm::ZstdDict,
+            Algorithm::Xz,
         ];
 
         for algo in algorithms {
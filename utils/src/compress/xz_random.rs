@@ -0,0 +1,665 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generate context information to randomly access xz streams.
+//!
+//! An xz stream is a sequence of blocks which are compressed independently, followed by an index
+//! recording compressed and uncompressed size of each block. Multi-threaded xz and `xz
+//! --block-size` split the tarball into many blocks, so each block boundary is a random access
+//! point. A block can't be decoded without the header of its stream, so the random access
+//! information is described by [ZranContext] objects with the stream header as dictionary, and
+//! may be stored into and loaded from the blob meta in the same way as gzip streams.
+
+use std::io::{Read, Result, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+use xz2::stream::{Action, Status, Stream, CONCATENATED};
+
+use super::zlib_random::{ZranChunkInfo, ZranContext, ZRAN_MAX_CI_ENTRIES, ZRAN_READER_BUF_SIZE};
+
+const XZ_MAX_COMP_SIZE: u64 = 2048 * 1024;
+const XZ_MAX_UNCOMP_SIZE: u64 = 2048 * 1024;
+const XZ_HEADER_MAGIC: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+const XZ_FOOTER_MAGIC: [u8; 2] = [0x59, 0x5a];
+const XZ_HEADER_SIZE: u64 = 12;
+const XZ_FOOTER_SIZE: u64 = 12;
+
+/// Xz decoder to randomly uncompress xz blocks.
+pub struct XzBlockDecoder {
+    stream: Stream,
+}
+
+impl XzBlockDecoder {
+    /// Create a new instance of `XzBlockDecoder`.
+    pub fn new() -> Result<Self> {
+        let stream = Stream::new_stream_decoder(u64::MAX, 0)?;
+        Ok(Self { stream })
+    }
+
+    /// Uncompress data from xz blocks.
+    ///
+    /// # Arguments
+    /// - ctx: context to random access compressed stream.
+    /// - dict: header of the xz stream containing the blocks
+    /// - input: input compressed data stream, starting from a block boundary
+    /// - output: buffer to receive uncompressed data
+    pub fn uncompress(
+        &mut self,
+        ctx: &ZranContext,
+        dict: &[u8],
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize> {
+        if input.len() != ctx.in_len as usize {
+            return Err(einval!("size of input buffer doesn't match"));
+        } else if ctx.out_len as usize > output.len() {
+            return Err(einval!("buffer to receive decompressed data is too small"));
+        } else if dict.len() != XZ_HEADER_SIZE as usize || !dict.starts_with(&XZ_HEADER_MAGIC) {
+            return Err(einval!("invalid xz stream header"));
+        }
+
+        // The decoder can't be reset, create a new one for each random access slice.
+        self.stream = Stream::new_stream_decoder(u64::MAX, 0)?;
+        let output = &mut output[..ctx.out_len as usize];
+        let mut out_pos = 0;
+        for data in [dict, input] {
+            let mut in_pos = 0;
+            while in_pos < data.len() && out_pos < output.len() {
+                let (total_in, total_out) = (self.stream.total_in(), self.stream.total_out());
+                let status =
+                    self.stream
+                        .process(&data[in_pos..], &mut output[out_pos..], Action::Run)?;
+                in_pos += (self.stream.total_in() - total_in) as usize;
+                out_pos += (self.stream.total_out() - total_out) as usize;
+                if status == Status::StreamEnd
+                    || (self.stream.total_in() == total_in && self.stream.total_out() == total_out)
+                {
+                    break;
+                }
+            }
+        }
+        if out_pos != output.len() {
+            return Err(eio!("failed to decode data from stream, size mismatch"));
+        }
+
+        Ok(out_pos)
+    }
+}
+
+/// Struct to generate random access information for OCIv1 image tarballs compressed by xz.
+///
+/// `XzBlockGenerator` has the same work flow as
+/// [ZstdFrameGenerator](super::zstd_random::ZstdFrameGenerator), but a random access slice
+/// always starts and ends at xz block boundaries and may contain multiple blocks of the same
+/// stream.
+pub struct XzBlockGenerator<R> {
+    reader: XzBlockReader<R>,
+    max_comp_size: u64,
+    max_uncomp_size: u64,
+    curr_ci_offset: u64,
+    curr_ci_idx: Option<usize>,
+    ci_array: Vec<ZranContext>,
+    // Index of the xz stream containing each random access slice.
+    ci_streams: Vec<usize>,
+}
+
+impl<R: Read> XzBlockGenerator<R> {
+    /// Create a new instance of `XzBlockGenerator` from a reader.
+    pub fn new(reader: XzBlockReader<R>) -> Self {
+        Self {
+            reader,
+            max_comp_size: XZ_MAX_COMP_SIZE,
+            max_uncomp_size: XZ_MAX_UNCOMP_SIZE,
+            curr_ci_offset: 0,
+            curr_ci_idx: None,
+            ci_array: Vec::new(),
+            ci_streams: Vec::new(),
+        }
+    }
+
+    /// Begin a transaction to read data from the xz stream.
+    ///
+    /// # Arguments
+    /// - `chunk_size`: size of data to be read from the xz stream.
+    pub fn begin_read(&mut self, chunk_size: u64) -> Result<u32> {
+        let out_pos = self.reader.get_data_pos();
+        let block = self.reader.get_block(out_pos)?;
+        let ci_idx = if let Some(idx) = self.curr_ci_idx {
+            let ctx = &self.ci_array[idx];
+            let comp_size = block.in_offset - ctx.in_offset;
+            let uncomp_size = out_pos - ctx.out_offset;
+            let enough = comp_size >= self.max_comp_size / 2
+                || uncomp_size + chunk_size >= self.max_uncomp_size;
+            // A new random access slice can only be started from a block boundary, and blocks
+            // of different streams can't be decoded together.
+            if block.in_offset > ctx.in_offset && (enough || block.stream != self.ci_streams[idx]) {
+                self.new_ci_entry(&block)
+            } else {
+                idx
+            }
+        } else {
+            self.new_ci_entry(&block)
+        };
+
+        if ci_idx > ZRAN_MAX_CI_ENTRIES {
+            Err(einval!("too many compression information entries"))
+        } else {
+            self.curr_ci_idx = Some(ci_idx);
+            self.curr_ci_offset = out_pos;
+            Ok(ci_idx as u32)
+        }
+    }
+
+    /// Mark end of a data read operation and returns information to decode data from the random
+    /// access slice.
+    pub fn end_read(&mut self) -> Result<ZranChunkInfo> {
+        let out_pos = self.reader.get_data_pos();
+        if let Some(idx) = self.curr_ci_idx {
+            if out_pos <= self.curr_ci_offset {
+                return Err(einval!("no data read from xz stream"));
+            }
+            let start = self.reader.get_block(self.curr_ci_offset)?;
+            let end = self.reader.get_block(out_pos - 1)?;
+            if end.stream != self.ci_streams[idx] {
+                return Err(einval!("data chunk crosses boundary of xz streams"));
+            }
+            let end_offset = end.in_offset + end.in_len;
+            let ctx = &mut self.ci_array[idx];
+            let ci = ZranChunkInfo {
+                ci_index: idx as u32,
+                ci_offset: (self.curr_ci_offset - ctx.out_offset) as u32,
+                ci_len: (out_pos - self.curr_ci_offset) as u32,
+                in_pos: start.in_offset,
+                in_len: (end_offset - start.in_offset) as u32,
+            };
+            ctx.out_len = (out_pos - ctx.out_offset) as u32;
+            ctx.in_len = (end_offset - ctx.in_offset) as u32;
+            Ok(ci)
+        } else {
+            Err(einval!("invalid compression state"))
+        }
+    }
+
+    /// Get an immutable reference to the random access context information array.
+    pub fn get_compression_ctx_array(&self) -> &[ZranContext] {
+        &self.ci_array
+    }
+
+    /// Get number of random access slices.
+    pub fn len(&self) -> usize {
+        self.ci_array.len()
+    }
+
+    /// Check whether there's any random access slices.
+    pub fn is_empty(&self) -> bool {
+        self.ci_array.is_empty()
+    }
+
+    /// Set maximum compressed size to emit an random access slice.
+    pub fn set_max_compressed_size(&mut self, sz: u64) {
+        self.max_comp_size = sz;
+    }
+
+    /// Set maximum uncompressed size to emit an random access slice.
+    pub fn set_max_uncompressed_size(&mut self, sz: u64) {
+        self.max_uncomp_size = sz;
+    }
+
+    fn new_ci_entry(&mut self, block: &XzBlockInfo) -> usize {
+        self.ci_array.push(ZranContext {
+            in_offset: block.in_offset,
+            out_offset: block.out_offset,
+            in_len: 0,
+            out_len: 0,
+            ctx_byte: 0,
+            ctx_bits: 0,
+            dict: self.reader.get_stream_header(block.stream),
+        });
+        self.ci_streams.push(block.stream);
+        self.ci_array.len() - 1
+    }
+}
+
+impl<R: Read> Read for XzBlockGenerator<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// A specialized xz reader for OCI image tarballs, which locates xz blocks by the stream index.
+pub struct XzBlockReader<R> {
+    inner: Arc<Mutex<XzBlockReaderState<R>>>,
+    index: Arc<XzIndex>,
+}
+
+impl<R: Read + Seek> XzBlockReader<R> {
+    /// Create a `XzBlockReader` from a reader.
+    ///
+    /// Block information is loaded from indexes at the end of the xz file, then the reader is
+    /// restored to the original position to decode data from there.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let pos = reader.stream_position()?;
+        let index = XzIndex::load(&mut reader)?;
+        reader.seek(SeekFrom::Start(pos))?;
+        let inner = XzBlockReaderState::new(reader)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+            index: Arc::new(index),
+        })
+    }
+}
+
+impl<R> XzBlockReader<R> {
+    /// Copy data from the buffer into the internal input buffer.
+    pub fn set_initial_data(&self, buf: &[u8]) {
+        let mut state = self.inner.lock().unwrap();
+        assert_eq!(state.in_start, state.in_end);
+        assert!(buf.len() <= state.input.len());
+
+        state.input[..buf.len()].copy_from_slice(buf);
+        state.reader_hash.update(buf);
+        state.reader_size += buf.len() as u64;
+        state.in_start = 0;
+        state.in_end = buf.len();
+    }
+
+    /// Get size of data read from the reader.
+    pub fn get_data_size(&self) -> u64 {
+        self.inner.lock().unwrap().reader_size
+    }
+
+    /// Get sha256 hash value of data read from the reader.
+    pub fn get_data_digest(&self) -> Sha256 {
+        self.inner.lock().unwrap().reader_hash.clone()
+    }
+
+    /// Get position of the uncompressed data stream.
+    fn get_data_pos(&self) -> u64 {
+        self.inner.lock().unwrap().out_pos
+    }
+
+    /// Get the block containing uncompressed data at `pos`.
+    fn get_block(&self, pos: u64) -> Result<XzBlockInfo> {
+        let blocks = &self.index.blocks;
+        let idx = blocks.partition_point(|b| b.out_offset + b.out_len <= pos);
+        match blocks.get(idx) {
+            Some(block) if block.out_offset <= pos => Ok(*block),
+            _ => Err(einval!(format!(
+                "no xz block for uncompressed data at 0x{:x}",
+                pos
+            ))),
+        }
+    }
+
+    /// Get header of the xz stream with index `stream`.
+    fn get_stream_header(&self, stream: usize) -> Vec<u8> {
+        self.index.headers[stream].to_vec()
+    }
+}
+
+impl<R: Read> Read for XzBlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.lock().unwrap().read(buf)
+    }
+}
+
+impl<R> Clone for XzBlockReader<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            index: self.index.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct XzBlockInfo {
+    // Index of the xz stream containing the block.
+    stream: usize,
+    in_offset: u64,
+    // Size of the block, including header, padding and check.
+    in_len: u64,
+    out_offset: u64,
+    out_len: u64,
+}
+
+/// Blocks of all streams in an xz file, loaded from stream indexes.
+#[derive(Debug, Default)]
+struct XzIndex {
+    headers: Vec<[u8; XZ_HEADER_SIZE as usize]>,
+    blocks: Vec<XzBlockInfo>,
+}
+
+impl XzIndex {
+    fn load<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let read_at = |reader: &mut R, offset: u64, buf: &mut [u8]| -> Result<()> {
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read_exact(buf)
+        };
+
+        // Walk streams backward from the end of the file, as the index follows the blocks.
+        let mut streams = Vec::new();
+        let mut end = reader.seek(SeekFrom::End(0))?;
+        while end > 0 {
+            if end % 4 != 0 || end < XZ_HEADER_SIZE + XZ_FOOTER_SIZE {
+                return Err(einval!("invalid xz file size or stream padding"));
+            }
+            let mut footer = [0u8; XZ_FOOTER_SIZE as usize];
+            read_at(reader, end - XZ_FOOTER_SIZE, &mut footer)?;
+            // Skip stream padding.
+            if footer[8..] == [0u8; 4] {
+                end -= 4;
+                continue;
+            }
+            if footer[10..] != XZ_FOOTER_MAGIC {
+                return Err(einval!("invalid xz stream footer"));
+            }
+            let index_size =
+                (u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]) as u64 + 1) * 4;
+            let index_offset = (end - XZ_FOOTER_SIZE)
+                .checked_sub(index_size)
+                .ok_or_else(|| einval!("invalid size of xz stream index"))?;
+            let mut index = vec![0u8; index_size as usize];
+            read_at(reader, index_offset, &mut index)?;
+            let records = Self::parse_records(&index)?;
+
+            let blocks_size = records.iter().map(|(size, _)| (size + 3) & !3).sum::<u64>();
+            let stream_offset = index_offset
+                .checked_sub(blocks_size + XZ_HEADER_SIZE)
+                .ok_or_else(|| einval!("invalid size of xz blocks"))?;
+            let mut header = [0u8; XZ_HEADER_SIZE as usize];
+            read_at(reader, stream_offset, &mut header)?;
+            if header[..6] != XZ_HEADER_MAGIC || header[6..8] != footer[8..10] {
+                return Err(einval!("invalid xz stream header"));
+            }
+
+            streams.push((stream_offset, header, records));
+            end = stream_offset;
+        }
+
+        let mut index = XzIndex::default();
+        let mut out_offset = 0;
+        for (stream, (stream_offset, header, records)) in streams.into_iter().rev().enumerate() {
+            let mut in_offset = stream_offset + XZ_HEADER_SIZE;
+            for (unpadded_size, uncompressed_size) in records {
+                let in_len = (unpadded_size + 3) & !3;
+                index.blocks.push(XzBlockInfo {
+                    stream,
+                    in_offset,
+                    in_len,
+                    out_offset,
+                    out_len: uncompressed_size,
+                });
+                in_offset += in_len;
+                out_offset += uncompressed_size;
+            }
+            index.headers.push(header);
+        }
+
+        Ok(index)
+    }
+
+    // Parse (unpadded size, uncompressed size) records of blocks from a stream index.
+    fn parse_records(index: &[u8]) -> Result<Vec<(u64, u64)>> {
+        let mut pos = 0;
+        let mut next = || -> Result<u64> {
+            let mut value = 0u64;
+            for i in 0..9 {
+                let byte = *index
+                    .get(pos)
+                    .ok_or_else(|| einval!("truncated xz stream index"))?;
+                pos += 1;
+                value |= ((byte & 0x7f) as u64) << (i * 7);
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err(einval!("invalid integer in xz stream index"))
+        };
+
+        if next()? != 0 {
+            return Err(einval!("invalid xz stream index indicator"));
+        }
+        let count = next()?;
+        let mut records = Vec::new();
+        for _ in 0..count {
+            let unpadded_size = next()?;
+            let uncompressed_size = next()?;
+            if unpadded_size == 0 {
+                return Err(einval!("invalid xz block size"));
+            }
+            records.push((unpadded_size, uncompressed_size));
+        }
+
+        Ok(records)
+    }
+}
+
+struct XzBlockReaderState<R> {
+    stream: Stream,
+    input: Vec<u8>,
+    in_start: usize,
+    in_end: usize,
+    eof: bool,
+    reader: R,
+    reader_hash: Sha256,
+    reader_size: u64,
+    out_pos: u64,
+}
+
+impl<R> XzBlockReaderState<R> {
+    fn new(reader: R) -> Result<Self> {
+        Ok(XzBlockReaderState {
+            stream: Stream::new_stream_decoder(u64::MAX, CONCATENATED)?,
+            input: vec![0u8; ZRAN_READER_BUF_SIZE],
+            in_start: 0,
+            in_end: 0,
+            eof: false,
+            reader,
+            reader_hash: Sha256::new(),
+            reader_size: 0,
+            out_pos: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for XzBlockReaderState<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            // Reload the input buffer when needed.
+            if self.in_start == self.in_end && !self.eof {
+                let sz = self.reader.read(self.input.as_mut_slice())?;
+                if sz == 0 {
+                    self.eof = true;
+                } else {
+                    self.reader_hash.update(&self.input[0..sz]);
+                    self.reader_size += sz as u64;
+                    self.in_start = 0;
+                    self.in_end = sz;
+                }
+            }
+
+            let action = if self.eof {
+                Action::Finish
+            } else {
+                Action::Run
+            };
+            let (total_in, total_out) = (self.stream.total_in(), self.stream.total_out());
+            let status =
+                self.stream
+                    .process(&self.input[self.in_start..self.in_end], buf, action)?;
+            let count = (self.stream.total_out() - total_out) as usize;
+            self.in_start += (self.stream.total_in() - total_in) as usize;
+            self.out_pos += count as u64;
+            if count > 0 {
+                return Ok(count);
+            } else if status == Status::StreamEnd {
+                return Ok(0);
+            } else if self.eof {
+                return Err(eio!("failed to decode data from truncated xz stream"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use xz2::stream::{Check, MtStreamBuilder};
+    use xz2::write::XzEncoder;
+
+    fn generate_blocks(data: &[u8], block_size: u64) -> Vec<u8> {
+        let stream = MtStreamBuilder::new()
+            .threads(1)
+            .block_size(block_size)
+            .preset(6)
+            .check(Check::Crc64)
+            .encoder()
+            .unwrap();
+        let mut encoder = XzEncoder::new_stream(Vec::new(), stream);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn generate_data(size: usize) -> Vec<u8> {
+        (0..size).map(|i| ((i / 7) % 251) as u8).collect()
+    }
+
+    fn reader(compressed: &[u8]) -> Result<XzBlockReader<Cursor<Vec<u8>>>> {
+        XzBlockReader::new(Cursor::new(compressed.to_vec()))
+    }
+
+    #[test]
+    fn test_xz_block_reader() {
+        let data = generate_data(100_000);
+        let compressed = generate_blocks(&data, 10_000);
+        let mut reader = reader(&compressed).unwrap();
+        assert_eq!(reader.index.blocks.len(), 10);
+        assert_eq!(reader.index.blocks[0].in_offset, XZ_HEADER_SIZE);
+        assert_eq!(reader.index.blocks[9].out_offset, 90_000);
+        assert_eq!(reader.get_block(99_999).unwrap().out_offset, 90_000);
+        assert!(reader.get_block(100_000).is_err());
+        assert_eq!(reader.get_data_size(), 0);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(reader.get_data_pos(), data.len() as u64);
+        assert_eq!(reader.get_data_size(), compressed.len() as u64);
+        assert_eq!(
+            reader.get_data_digest().finalize().to_vec(),
+            Sha256::digest(&compressed).to_vec()
+        );
+
+        // Reader positioned after data buffered by the caller.
+        let mut cursor = Cursor::new(compressed.clone());
+        cursor.set_position(10);
+        let reader = XzBlockReader::new(cursor).unwrap();
+        reader.set_initial_data(&compressed[..10]);
+        let mut buf = Vec::new();
+        reader.clone().read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        // Truncated or corrupted stream.
+        assert!(self::reader(&compressed[..compressed.len() - 4]).is_err());
+        let mut corrupted = compressed.clone();
+        corrupted[100] ^= 0xff;
+        let mut reader = self::reader(&corrupted).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_xz_multiple_streams() {
+        let data = generate_data(50_000);
+        let mut compressed = generate_blocks(&data[..20_000], 8_000);
+        // Stream padding between streams.
+        compressed.extend_from_slice(&[0u8; 8]);
+        compressed.extend_from_slice(&generate_blocks(&data[20_000..], 8_000));
+        let mut reader = reader(&compressed).unwrap();
+        assert_eq!(reader.index.headers.len(), 2);
+        assert_eq!(reader.index.blocks.len(), 7);
+        assert_eq!(reader.index.blocks[3].stream, 1);
+        assert_eq!(reader.index.blocks[3].out_offset, 20_000);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn test_xz_block_generator() {
+        let data = generate_data(1_000_000);
+        let compressed = generate_blocks(&data, 30_000);
+        let mut generator = XzBlockGenerator::new(reader(&compressed).unwrap());
+        generator.set_max_compressed_size(64 * 1024);
+        generator.set_max_uncompressed_size(128 * 1024);
+
+        let mut chunks = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let size = std::cmp::min(4096, data.len() - pos);
+            generator.begin_read(size as u64).unwrap();
+            let mut buf = vec![0u8; size];
+            generator.read_exact(&mut buf).unwrap();
+            chunks.push((pos, generator.end_read().unwrap()));
+            pos += size;
+        }
+
+        let contexts = generator.get_compression_ctx_array();
+        assert!(contexts.len() > 1);
+        let mut decoder = XzBlockDecoder::new().unwrap();
+        for (pos, chunk) in chunks {
+            let ctx = &contexts[chunk.ci_index as usize];
+            assert_eq!(ctx.dict, compressed[..XZ_HEADER_SIZE as usize]);
+            let input =
+                &compressed[ctx.in_offset as usize..(ctx.in_offset + ctx.in_len as u64) as usize];
+            let mut output = vec![0u8; ctx.out_len as usize];
+            let sz = decoder
+                .uncompress(ctx, &ctx.dict, input, &mut output)
+                .unwrap();
+            assert_eq!(sz, ctx.out_len as usize);
+            let start = chunk.ci_offset as usize;
+            let end = start + chunk.ci_len as usize;
+            assert_eq!(&output[start..end], &data[pos..pos + chunk.ci_len as usize]);
+        }
+
+        let ctx = &contexts[0];
+        let input = &compressed[ctx.in_offset as usize..ctx.in_offset as usize + 100];
+        let mut output = vec![0u8; ctx.out_len as usize];
+        assert!(decoder
+            .uncompress(ctx, &ctx.dict, input, &mut output)
+            .is_err());
+        let input =
+            &compressed[ctx.in_offset as usize..(ctx.in_offset + ctx.in_len as u64) as usize];
+        assert!(decoder.uncompress(ctx, &[], input, &mut output).is_err());
+    }
+
+    #[test]
+    fn test_xz_chunk_across_streams() {
+        let data = generate_data(20_000);
+        let mut compressed = generate_blocks(&data[..10_000], 4_000);
+        compressed.extend_from_slice(&generate_blocks(&data[10_000..], 4_000));
+        let mut generator = XzBlockGenerator::new(reader(&compressed).unwrap());
+
+        // Random access slices don't cross stream boundaries.
+        assert_eq!(generator.begin_read(10_000).unwrap(), 0);
+        generator.read_exact(&mut vec![0u8; 10_000]).unwrap();
+        generator.end_read().unwrap();
+        assert_eq!(generator.begin_read(5_000).unwrap(), 1);
+        generator.read_exact(&mut vec![0u8; 5_000]).unwrap();
+        generator.end_read().unwrap();
+        assert_eq!(generator.get_compression_ctx_array()[1].out_offset, 10_000);
+
+        let mut generator = XzBlockGenerator::new(reader(&compressed).unwrap());
+        generator.begin_read(15_000).unwrap();
+        generator.read_exact(&mut vec![0u8; 15_000]).unwrap();
+        assert!(generator.end_read().is_err());
+    }
+}
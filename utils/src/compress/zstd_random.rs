@@ -0,0 +1,468 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generate context information to randomly access seekable zstd streams.
+//!
+//! A zstd stream is a sequence of independent frames. Seekable zstd and `zstd:chunked` layers
+//! split the tarball into many small frames, so each frame boundary is a random access point
+//! which needs no extra decoding state, unlike inflate blocks in a gzip stream. The random
+//! access information is described by [ZranContext] objects with empty dictionaries, so it may
+//! be stored into and loaded from the blob meta in the same way as gzip streams.
+
+use std::io::{Read, Result};
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+use zstd::stream::raw::{Decoder, InBuffer, Operation, OutBuffer};
+
+use super::zlib_random::{ZranChunkInfo, ZranContext, ZRAN_MAX_CI_ENTRIES, ZRAN_READER_BUF_SIZE};
+
+const ZSTD_MAX_COMP_SIZE: u64 = 2048 * 1024;
+const ZSTD_MAX_UNCOMP_SIZE: u64 = 2048 * 1024;
+
+/// Zstd decoder to randomly uncompress seekable zstd stream.
+pub struct ZstdFrameDecoder {
+    decoder: Decoder<'static>,
+}
+
+impl ZstdFrameDecoder {
+    /// Create a new instance of `ZstdFrameDecoder`.
+    pub fn new() -> Result<Self> {
+        let decoder = Decoder::new()?;
+        Ok(Self { decoder })
+    }
+
+    /// Uncompress data from zstd frames.
+    ///
+    /// # Arguments
+    /// - ctx: context to random access compressed stream.
+    /// - input: input compressed data stream, starting from a frame boundary
+    /// - output: buffer to receive uncompressed data
+    pub fn uncompress(
+        &mut self,
+        ctx: &ZranContext,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize> {
+        if input.len() != ctx.in_len as usize {
+            return Err(einval!("size of input buffer doesn't match"));
+        } else if ctx.out_len as usize > output.len() {
+            return Err(einval!("buffer to receive decompressed data is too small"));
+        }
+
+        self.decoder.reinit()?;
+        let mut in_buf = InBuffer::around(input);
+        let mut out_buf = OutBuffer::around(&mut output[..ctx.out_len as usize]);
+        while out_buf.pos() < ctx.out_len as usize {
+            let in_pos = in_buf.pos;
+            let out_pos = out_buf.pos();
+            self.decoder.run(&mut in_buf, &mut out_buf)?;
+            if in_buf.pos == in_pos && out_buf.pos() == out_pos {
+                return Err(eio!("failed to decode data from stream, size mismatch"));
+            }
+        }
+
+        Ok(out_buf.pos())
+    }
+}
+
+/// Struct to generate random access information for OCIv1 image tarballs compressed by zstd.
+///
+/// `ZstdFrameGenerator` has the same work flow as
+/// [ZranGenerator](super::zlib_random::ZranGenerator), but a random access slice always starts
+/// and ends at zstd frame boundaries and may contain multiple frames. The zstd decoder may need
+/// data after the last byte consumed to decode a data chunk, so the compressed data of a random
+/// access slice is extended to the end of the last frame.
+pub struct ZstdFrameGenerator<R> {
+    reader: ZstdFrameReader<R>,
+    max_comp_size: u64,
+    max_uncomp_size: u64,
+    curr_ci_offset: u64,
+    curr_in_offset: u64,
+    curr_ci_idx: Option<usize>,
+    ci_array: Vec<ZranContext>,
+}
+
+impl<R: Read> ZstdFrameGenerator<R> {
+    /// Create a new instance of `ZstdFrameGenerator` from a reader.
+    pub fn new(reader: ZstdFrameReader<R>) -> Self {
+        Self {
+            reader,
+            max_comp_size: ZSTD_MAX_COMP_SIZE,
+            max_uncomp_size: ZSTD_MAX_UNCOMP_SIZE,
+            curr_ci_offset: 0,
+            curr_in_offset: 0,
+            curr_ci_idx: None,
+            ci_array: Vec::new(),
+        }
+    }
+
+    /// Begin a transaction to read data from the zstd stream.
+    ///
+    /// # Arguments
+    /// - `chunk_size`: size of data to be read from the zstd stream.
+    pub fn begin_read(&mut self, chunk_size: u64) -> Result<u32> {
+        let info = self.reader.get_current_frame_info();
+        let frame = self.reader.get_frame_start_info();
+        let ci_idx = if let Some(idx) = self.curr_ci_idx {
+            let ctx = &self.ci_array[idx];
+            let comp_size = info.in_pos - ctx.in_offset;
+            let uncomp_size = info.out_pos - ctx.out_offset;
+            let enough = comp_size >= self.max_comp_size / 2
+                || uncomp_size + chunk_size >= self.max_uncomp_size;
+            // A new random access slice can only be started from a frame boundary.
+            if frame.in_pos > ctx.in_offset && enough {
+                self.new_ci_entry(frame)
+            } else {
+                idx
+            }
+        } else {
+            self.new_ci_entry(frame)
+        };
+
+        if ci_idx > ZRAN_MAX_CI_ENTRIES {
+            Err(einval!("too many compression information entries"))
+        } else {
+            self.curr_ci_idx = Some(ci_idx);
+            self.curr_ci_offset = info.out_pos;
+            self.curr_in_offset = info.in_pos;
+            Ok(ci_idx as u32)
+        }
+    }
+
+    /// Mark end of a data read operation and returns information to decode data from the random
+    /// access slice.
+    pub fn end_read(&mut self) -> Result<ZranChunkInfo> {
+        let info = self.reader.get_current_frame_info();
+        if let Some(idx) = self.curr_ci_idx {
+            let ctx = &mut self.ci_array[idx];
+            let ci = ZranChunkInfo {
+                ci_index: idx as u32,
+                ci_offset: (self.curr_ci_offset - ctx.out_offset) as u32,
+                ci_len: (info.out_pos - self.curr_ci_offset) as u32,
+                in_pos: self.curr_in_offset,
+                in_len: (info.in_pos - self.curr_in_offset) as u32,
+            };
+            ctx.out_len = (info.out_pos - ctx.out_offset) as u32;
+            ctx.in_len = (info.in_pos - ctx.in_offset) as u32;
+            Ok(ci)
+        } else {
+            Err(einval!("invalid compression state"))
+        }
+    }
+
+    /// Get an immutable reference to the random access context information array.
+    ///
+    /// Compressed data of each random access slice is extended to the end of its last frame.
+    pub fn get_compression_ctx_array(&mut self) -> &[ZranContext] {
+        for ctx in self.ci_array.iter_mut() {
+            let end = ctx.in_offset + ctx.in_len as u64;
+            if let Some(frame_end) = self.reader.get_frame_end(end) {
+                ctx.in_len = (frame_end - ctx.in_offset) as u32;
+            }
+        }
+        &self.ci_array
+    }
+
+    /// Get number of random access slices.
+    pub fn len(&self) -> usize {
+        self.ci_array.len()
+    }
+
+    /// Check whether there's any random access slices.
+    pub fn is_empty(&self) -> bool {
+        self.ci_array.is_empty()
+    }
+
+    /// Set maximum compressed size to emit an random access slice.
+    pub fn set_max_compressed_size(&mut self, sz: u64) {
+        self.max_comp_size = sz;
+    }
+
+    /// Set maximum uncompressed size to emit an random access slice.
+    pub fn set_max_uncompressed_size(&mut self, sz: u64) {
+        self.max_uncomp_size = sz;
+    }
+
+    fn new_ci_entry(&mut self, frame: ZstdFrameInfo) -> usize {
+        self.ci_array.push(ZranContext {
+            in_offset: frame.in_pos,
+            out_offset: frame.out_pos,
+            in_len: 0,
+            out_len: 0,
+            ctx_byte: 0,
+            ctx_bits: 0,
+            dict: vec![],
+        });
+        self.ci_array.len() - 1
+    }
+}
+
+impl<R: Read> Read for ZstdFrameGenerator<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// A specialized zstd reader for OCI image tarballs, which tracks zstd frame boundaries.
+pub struct ZstdFrameReader<R> {
+    inner: Arc<Mutex<ZstdFrameReaderState<R>>>,
+}
+
+impl<R> ZstdFrameReader<R> {
+    /// Create a `ZstdFrameReader` from a reader.
+    pub fn new(reader: R) -> Result<Self> {
+        let inner = ZstdFrameReaderState::new(reader)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    /// Copy data from the buffer into the internal input buffer.
+    pub fn set_initial_data(&self, buf: &[u8]) {
+        let mut state = self.inner.lock().unwrap();
+        assert_eq!(state.in_start, state.in_end);
+        assert!(buf.len() <= state.input.len());
+
+        state.input[..buf.len()].copy_from_slice(buf);
+        state.reader_hash.update(buf);
+        state.reader_size += buf.len() as u64;
+        state.in_start = 0;
+        state.in_end = buf.len();
+    }
+
+    /// Get size of data read from the reader.
+    pub fn get_data_size(&self) -> u64 {
+        self.inner.lock().unwrap().reader_size
+    }
+
+    /// Get sha256 hash value of data read from the reader.
+    pub fn get_data_digest(&self) -> Sha256 {
+        self.inner.lock().unwrap().reader_hash.clone()
+    }
+
+    /// Get decoding position of the zstd stream.
+    fn get_current_frame_info(&self) -> ZstdFrameInfo {
+        self.inner.lock().unwrap().curr_info
+    }
+
+    /// Get start position of the zstd frame being decoded.
+    fn get_frame_start_info(&self) -> ZstdFrameInfo {
+        self.inner.lock().unwrap().frame_info
+    }
+
+    /// Get end position of the first decoded zstd frame ending at or after `pos`.
+    fn get_frame_end(&self, pos: u64) -> Option<u64> {
+        let state = self.inner.lock().unwrap();
+        let idx = state.frame_ends.partition_point(|end| *end < pos);
+        state.frame_ends.get(idx).copied()
+    }
+}
+
+impl<R: Read> Read for ZstdFrameReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.lock().unwrap().read(buf)
+    }
+}
+
+impl<R> Clone for ZstdFrameReader<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct ZstdFrameInfo {
+    in_pos: u64,
+    out_pos: u64,
+}
+
+struct ZstdFrameReaderState<R> {
+    decoder: Decoder<'static>,
+    input: Vec<u8>,
+    in_start: usize,
+    in_end: usize,
+    reader: R,
+    reader_hash: Sha256,
+    reader_size: u64,
+    curr_info: ZstdFrameInfo,
+    frame_info: ZstdFrameInfo,
+    frame_ends: Vec<u64>,
+    in_frame: bool,
+}
+
+impl<R> ZstdFrameReaderState<R> {
+    fn new(reader: R) -> Result<Self> {
+        Ok(ZstdFrameReaderState {
+            decoder: Decoder::new()?,
+            input: vec![0u8; ZRAN_READER_BUF_SIZE],
+            in_start: 0,
+            in_end: 0,
+            reader,
+            reader_hash: Sha256::new(),
+            reader_size: 0,
+            curr_info: ZstdFrameInfo::default(),
+            frame_info: ZstdFrameInfo::default(),
+            frame_ends: Vec::new(),
+            in_frame: false,
+        })
+    }
+}
+
+impl<R: Read> Read for ZstdFrameReaderState<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            // Reload the input buffer when needed.
+            if self.in_start == self.in_end {
+                let sz = self.reader.read(self.input.as_mut_slice())?;
+                if sz == 0 {
+                    return if self.in_frame {
+                        Err(eio!("failed to decode data from truncated zstd stream"))
+                    } else {
+                        Ok(0)
+                    };
+                }
+                self.reader_hash.update(&self.input[0..sz]);
+                self.reader_size += sz as u64;
+                self.in_start = 0;
+                self.in_end = sz;
+            }
+
+            let mut in_buf = InBuffer::around(&self.input[self.in_start..self.in_end]);
+            let mut out_buf = OutBuffer::around(&mut *buf);
+            let hint = self.decoder.run(&mut in_buf, &mut out_buf)?;
+            let count = out_buf.pos();
+            self.in_start += in_buf.pos;
+            self.curr_info.in_pos += in_buf.pos as u64;
+            self.curr_info.out_pos += count as u64;
+            if hint == 0 {
+                // The frame has been fully decoded and flushed, next frame starts here.
+                if self.curr_info.in_pos > self.frame_info.in_pos {
+                    self.frame_ends.push(self.curr_info.in_pos);
+                }
+                self.frame_info = self.curr_info;
+                self.in_frame = false;
+            } else {
+                self.in_frame = true;
+            }
+            if count > 0 {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn generate_frames(data: &[u8], frame_size: usize) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for frame in data.chunks(frame_size) {
+            buf.extend_from_slice(&zstd::bulk::compress(frame, 3).unwrap());
+        }
+        buf
+    }
+
+    fn generate_data(size: usize) -> Vec<u8> {
+        (0..size).map(|i| ((i / 7) % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_zstd_frame_reader() {
+        let data = generate_data(100_000);
+        let compressed = generate_frames(&data, 10_000);
+        let mut reader = ZstdFrameReader::new(Cursor::new(compressed.clone())).unwrap();
+        assert_eq!(reader.get_data_size(), 0);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(reader.get_data_size(), compressed.len() as u64);
+        let info = reader.get_frame_start_info();
+        assert_eq!(info.in_pos, compressed.len() as u64);
+        assert_eq!(info.out_pos, data.len() as u64);
+        assert_eq!(
+            reader.get_data_digest().finalize().to_vec(),
+            Sha256::digest(&compressed).to_vec()
+        );
+
+        // Truncated stream.
+        let reader = ZstdFrameReader::new(Cursor::new(compressed[10..].to_vec())).unwrap();
+        reader.set_initial_data(&compressed[..10]);
+        let mut buf = Vec::new();
+        reader.clone().read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, data);
+        let mut reader =
+            ZstdFrameReader::new(Cursor::new(compressed[..compressed.len() - 4].to_vec())).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_zstd_frame_generator() {
+        let data = generate_data(1_000_000);
+        let compressed = generate_frames(&data, 30_000);
+        let reader = ZstdFrameReader::new(Cursor::new(compressed.clone())).unwrap();
+        let mut generator = ZstdFrameGenerator::new(reader);
+        generator.set_max_compressed_size(64 * 1024);
+        generator.set_max_uncompressed_size(128 * 1024);
+
+        let mut chunks = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let size = std::cmp::min(4096, data.len() - pos);
+            generator.begin_read(size as u64).unwrap();
+            let mut buf = vec![0u8; size];
+            generator.read_exact(&mut buf).unwrap();
+            chunks.push((pos, generator.end_read().unwrap()));
+            pos += size;
+        }
+
+        let contexts = generator.get_compression_ctx_array();
+        assert!(contexts.len() > 1);
+        let mut decoder = ZstdFrameDecoder::new().unwrap();
+        for (pos, chunk) in chunks {
+            let ctx = &contexts[chunk.ci_index as usize];
+            assert!(ctx.dict.is_empty());
+            let input =
+                &compressed[ctx.in_offset as usize..(ctx.in_offset + ctx.in_len as u64) as usize];
+            let mut output = vec![0u8; ctx.out_len as usize];
+            let sz = decoder.uncompress(ctx, input, &mut output).unwrap();
+            assert_eq!(sz, ctx.out_len as usize);
+            let start = chunk.ci_offset as usize;
+            let end = start + chunk.ci_len as usize;
+            assert_eq!(&output[start..end], &data[pos..pos + chunk.ci_len as usize]);
+        }
+
+        let ctx = &contexts[0];
+        let input = &compressed[..ctx.in_len as usize - 1];
+        let mut output = vec![0u8; ctx.out_len as usize];
+        assert!(decoder.uncompress(ctx, input, &mut output).is_err());
+    }
+
+    #[test]
+    fn test_zstd_single_frame() {
+        // All data chunks share the same context if there's only one frame.
+        let data = generate_data(100_000);
+        let compressed = zstd::bulk::compress(&data, 3).unwrap();
+        let reader = ZstdFrameReader::new(Cursor::new(compressed)).unwrap();
+        let mut generator = ZstdFrameGenerator::new(reader);
+        generator.set_max_uncompressed_size(8192);
+        for _ in 0..10 {
+            assert_eq!(generator.begin_read(8192).unwrap(), 0);
+            let mut buf = vec![0u8; 8192];
+            generator.read_exact(&mut buf).unwrap();
+            generator.end_read().unwrap();
+        }
+        assert_eq!(generator.get_compression_ctx_array().len(), 1);
+        assert_eq!(generator.get_compression_ctx_array()[0].out_len, 81920);
+    }
+}
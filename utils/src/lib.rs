@@ -33,6 +33,8 @@ pub mod logger;
 pub mod metrics;
 pub mod mpmc;
 pub mod reader;
+#[cfg(feature = "encryption")]
+pub mod signature;
 pub mod trace;
 pub mod types;
 pub mod verity;
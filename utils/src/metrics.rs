@@ -11,6 +11,7 @@
 //! - Filesystem metrics of type ['FsIoStats`], supported by Rafs in fuse/virtiofs only.
 
 use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Write};
 use std::ops::{Deref, Drop};
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
@@ -300,6 +301,25 @@ impl FsIoStats {
         }
     }
 
+    /// Get inodes of files which have been read, ordered by their first access time.
+    pub fn get_accessed_inodes(&self) -> Vec<Inode> {
+        let records = self.access_patterns.read().unwrap();
+        let mut patterns = records
+            .values()
+            .filter(|r| r.nr_read.count() != 0)
+            .map(|r| {
+                (
+                    r.first_access_time_secs.load(Ordering::Relaxed),
+                    r.first_access_time_nanos.load(Ordering::Relaxed),
+                    r.ino,
+                )
+            })
+            .collect::<Vec<_>>();
+        patterns.sort_unstable();
+
+        patterns.into_iter().map(|(_, _, ino)| ino).collect()
+    }
+
     fn file_stats_update(&self, ino: Inode, fop: StatsFop, bsize: usize, success: bool) {
         self.fop_update(fop, bsize, success);
 
@@ -564,6 +584,498 @@ pub fn export_events() -> IoStatsResult<String> {
     serde_json::to_string(ERROR_HOLDER.lock().unwrap().deref()).map_err(MetricsError::Serialize)
 }
 
+// Names of filesystem operations, indexed by `StatsFop`.
+const FOP_NAMES: [&str; StatsFop::Max as usize] = [
+    "getattr",
+    "readlink",
+    "open",
+    "release",
+    "read",
+    "statfs",
+    "getxattr",
+    "listxattr",
+    "opendir",
+    "lookup",
+    "readdir",
+    "readdirplus",
+    "access",
+    "forget",
+    "batch_forget",
+];
+
+// Request size ranges, indexed by `request_size_index()`.
+const BLOCK_SIZE_NAMES: [&str; BLOCK_READ_SIZES_MAX] = [
+    "<1K",
+    "1K-4K",
+    "4K-16K",
+    "16K-64K",
+    "64K-128K",
+    "128K-512K",
+    "512K-1M",
+    ">=1M",
+];
+
+// Upper bounds in seconds of latency ranges, indexed by `latency_micros_range_index()`.
+const LATENCY_MICROS_BOUNDS: [&str; READ_LATENCY_RANGE_MAX] =
+    ["0.0002", "0.001", "0.02", "0.05", "0.5", "1", "2", "+Inf"];
+
+// Upper bounds in seconds of latency ranges, indexed by `latency_millis_range_index()`.
+const LATENCY_MILLIS_BOUNDS: [&str; READ_LATENCY_RANGE_MAX] =
+    ["0.001", "0.02", "0.05", "0.1", "0.5", "1", "2", "+Inf"];
+
+// Name, help message and value accessor of a blobcache metric.
+type BlobcacheMetricDesc = (&'static str, &'static str, fn(&BlobcacheMetrics) -> u64);
+
+/// Encoder to generate metrics in the Prometheus text exposition format.
+#[derive(Default)]
+struct PrometheusEncoder {
+    buf: String,
+}
+
+impl PrometheusEncoder {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.buf, "# HELP {} {}", name, help);
+        let _ = writeln!(self.buf, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample<V: Display>(&mut self, name: &str, labels: &[(&str, &str)], value: V) {
+        self.buf.push_str(name);
+        if !labels.is_empty() {
+            self.buf.push('{');
+            for (idx, (k, v)) in labels.iter().enumerate() {
+                if idx > 0 {
+                    self.buf.push(',');
+                }
+                let v = v
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                let _ = write!(self.buf, "{}=\"{}\"", k, v);
+            }
+            self.buf.push('}');
+        }
+        let _ = writeln!(self.buf, " {}", value);
+    }
+
+    // Convert per-range counters into cumulative Prometheus histogram buckets.
+    fn histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        bounds: &[&str],
+        counts: &[u64],
+        sum: f64,
+    ) {
+        let bucket = format!("{}_bucket", name);
+        let mut total = 0;
+        for (le, count) in bounds.iter().zip(counts) {
+            total += count;
+            let mut labels = labels.to_vec();
+            labels.push(("le", le));
+            self.sample(&bucket, &labels, total);
+        }
+        self.sample(&format!("{}_sum", name), labels, sum);
+        self.sample(&format!("{}_count", name), labels, total);
+    }
+
+    fn encode_fs_stats(&mut self, stats: &[Arc<FsIoStats>]) {
+        if stats.is_empty() {
+            return;
+        }
+
+        let name = "nydusd_fs_open_files";
+        self.family(name, "gauge", "Number of files currently opened.");
+        for s in stats {
+            self.sample(name, &[("id", &s.id)], s.nr_opens.count());
+        }
+
+        let name = "nydusd_fs_read_bytes_total";
+        self.family(name, "counter", "Total bytes read from the filesystem.");
+        for s in stats {
+            self.sample(name, &[("id", &s.id)], s.data_read.count());
+        }
+
+        let name = "nydusd_fs_read_requests_by_size_total";
+        self.family(name, "counter", "Number of read requests by request size.");
+        for s in stats {
+            for (size, v) in BLOCK_SIZE_NAMES.iter().zip(s.block_count_read.iter()) {
+                self.sample(name, &[("id", &s.id), ("size", size)], v.count());
+            }
+        }
+
+        let name = "nydusd_fs_operations_total";
+        self.family(
+            name,
+            "counter",
+            "Number of successful filesystem operations.",
+        );
+        for s in stats {
+            for (fop, v) in FOP_NAMES.iter().zip(s.fop_hits.iter()) {
+                self.sample(name, &[("id", &s.id), ("fop", fop)], v.count());
+            }
+        }
+
+        let name = "nydusd_fs_operation_errors_total";
+        self.family(name, "counter", "Number of failed filesystem operations.");
+        for s in stats {
+            for (fop, v) in FOP_NAMES.iter().zip(s.fop_errors.iter()) {
+                self.sample(name, &[("id", &s.id), ("fop", fop)], v.count());
+            }
+        }
+
+        let name = "nydusd_fs_operation_latency_microseconds_total";
+        self.family(
+            name,
+            "counter",
+            "Cumulative latency of filesystem operations in microseconds.",
+        );
+        for s in stats {
+            for (fop, v) in FOP_NAMES.iter().zip(s.fop_cumulative_latency_total.iter()) {
+                self.sample(name, &[("id", &s.id), ("fop", fop)], v.count());
+            }
+        }
+
+        let name = "nydusd_fs_operation_latency_seconds";
+        self.family(
+            name,
+            "histogram",
+            "Latency distribution of filesystem operations.",
+        );
+        for s in stats {
+            let counts: Vec<u64> = s.read_latency_dist.iter().map(|v| v.count()).collect();
+            let sum: u64 = s
+                .fop_cumulative_latency_total
+                .iter()
+                .map(|v| v.count())
+                .sum();
+            self.histogram(
+                name,
+                &[("id", &s.id)],
+                &LATENCY_MICROS_BOUNDS,
+                &counts,
+                sum as f64 / 1_000_000.0,
+            );
+        }
+    }
+
+    fn encode_backend_metrics(&mut self, metrics: &[Arc<BackendMetrics>]) {
+        if metrics.is_empty() {
+            return;
+        }
+
+        let name = "nydusd_backend_read_requests_total";
+        self.family(
+            name,
+            "counter",
+            "Number of read requests to storage backend.",
+        );
+        for m in metrics {
+            let labels = [("id", m.id.as_str()), ("backend_type", &m.backend_type)];
+            self.sample(name, &labels, m.read_count.count());
+        }
+
+        let name = "nydusd_backend_read_errors_total";
+        self.family(
+            name,
+            "counter",
+            "Number of failed read requests to storage backend.",
+        );
+        for m in metrics {
+            let labels = [("id", m.id.as_str()), ("backend_type", &m.backend_type)];
+            self.sample(name, &labels, m.read_errors.count());
+        }
+
+        let name = "nydusd_backend_read_bytes_total";
+        self.family(name, "counter", "Total bytes read from storage backend.");
+        for m in metrics {
+            let labels = [("id", m.id.as_str()), ("backend_type", &m.backend_type)];
+            self.sample(name, &labels, m.read_amount_total.count());
+        }
+
+        let name = "nydusd_backend_read_requests_by_size_total";
+        self.family(
+            name,
+            "counter",
+            "Number of read requests to storage backend by request size.",
+        );
+        for m in metrics {
+            for (size, v) in BLOCK_SIZE_NAMES
+                .iter()
+                .zip(m.read_count_block_size_dist.iter())
+            {
+                let labels = [
+                    ("id", m.id.as_str()),
+                    ("backend_type", &m.backend_type),
+                    ("size", size),
+                ];
+                self.sample(name, &labels, v.count());
+            }
+        }
+
+        let name = "nydusd_backend_read_latency_seconds";
+        self.family(
+            name,
+            "histogram",
+            "Latency distribution of read requests to storage backend.",
+        );
+        for m in metrics {
+            let mut counts = [0u64; READ_LATENCY_RANGE_MAX];
+            for sizes in m.read_latency_sizes_dist.iter() {
+                for (idx, v) in sizes.iter().enumerate() {
+                    counts[idx] += v.count();
+                }
+            }
+            let labels = [("id", m.id.as_str()), ("backend_type", &m.backend_type)];
+            let sum = m.read_cumulative_latency_millis_total.count() as f64 / 1000.0;
+            self.histogram(name, &labels, &LATENCY_MILLIS_BOUNDS, &counts, sum);
+        }
+
+        let mut mirrors = Vec::new();
+        for m in metrics {
+            let mut entries: Vec<(String, Arc<MirrorMetrics>)> = m
+                .mirrors
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (host, v) in entries {
+                mirrors.push((m, host, v));
+            }
+        }
+        if mirrors.is_empty() {
+            return;
+        }
+
+        let name = "nydusd_backend_mirror_up";
+        self.family(name, "gauge", "Whether the registry mirror is healthy.");
+        for (m, host, v) in mirrors.iter() {
+            let labels = [
+                ("id", m.id.as_str()),
+                ("backend_type", &m.backend_type),
+                ("mirror", host),
+            ];
+            self.sample(name, &labels, v.healthy.load(Ordering::Relaxed) as u8);
+        }
+
+        let name = "nydusd_backend_mirror_requests_total";
+        self.family(
+            name,
+            "counter",
+            "Number of requests sent to registry mirror.",
+        );
+        for (m, host, v) in mirrors.iter() {
+            let labels = [
+                ("id", m.id.as_str()),
+                ("backend_type", &m.backend_type),
+                ("mirror", host),
+            ];
+            self.sample(name, &labels, v.requests.count());
+        }
+
+        let name = "nydusd_backend_mirror_errors_total";
+        self.family(
+            name,
+            "counter",
+            "Number of failed requests to registry mirror.",
+        );
+        for (m, host, v) in mirrors.iter() {
+            let labels = [
+                ("id", m.id.as_str()),
+                ("backend_type", &m.backend_type),
+                ("mirror", host),
+            ];
+            self.sample(name, &labels, v.errors.count());
+        }
+
+        let name = "nydusd_backend_mirror_disabled_total";
+        self.family(
+            name,
+            "counter",
+            "Number of times registry mirror has been marked unhealthy.",
+        );
+        for (m, host, v) in mirrors.iter() {
+            let labels = [
+                ("id", m.id.as_str()),
+                ("backend_type", &m.backend_type),
+                ("mirror", host),
+            ];
+            self.sample(name, &labels, v.disabled_count.count());
+        }
+    }
+
+    fn encode_blobcache_metrics(&mut self, metrics: &[Arc<BlobcacheMetrics>]) {
+        if metrics.is_empty() {
+            return;
+        }
+
+        let counters: [BlobcacheMetricDesc; 15] = [
+            (
+                "nydusd_blobcache_partial_hits_total",
+                "Number of read requests partially served by blob cache.",
+                |m| m.partial_hits.count(),
+            ),
+            (
+                "nydusd_blobcache_whole_hits_total",
+                "Number of read requests wholly served by blob cache.",
+                |m| m.whole_hits.count(),
+            ),
+            (
+                "nydusd_blobcache_reads_total",
+                "Number of read requests processed by blob cache.",
+                |m| m.total.count(),
+            ),
+            (
+                "nydusd_blobcache_prefetch_bytes_total",
+                "Total bytes of data prefetched from storage backend.",
+                |m| m.prefetch_data_amount.count(),
+            ),
+            (
+                "nydusd_blobcache_prefetch_requests_total",
+                "Number of prefetch requests issued to storage backend.",
+                |m| m.prefetch_requests_count.count(),
+            ),
+            (
+                "nydusd_blobcache_prefetch_unmerged_chunks_total",
+                "Number of prefetched chunks which can't be merged.",
+                |m| m.prefetch_unmerged_chunks.count(),
+            ),
+            (
+                "nydusd_blobcache_prefetch_latency_milliseconds_total",
+                "Cumulative latency of prefetch requests in milliseconds.",
+                |m| m.prefetch_cumulative_time_millis.count(),
+            ),
+            (
+                "nydusd_blobcache_evicted_blobs_total",
+                "Number of cached blobs evicted from the working directory.",
+                |m| m.evicted_blobs.count(),
+            ),
+            (
+                "nydusd_blobcache_reclaimed_bytes_total",
+                "Disk space reclaimed by evicting cached blobs.",
+                |m| m.reclaimed_bytes.count(),
+            ),
+            (
+                "nydusd_blobcache_scrubbed_chunks_total",
+                "Number of cached chunks verified by the background scrubber.",
+                |m| m.scrub.scrubbed_chunks.count(),
+            ),
+            (
+                "nydusd_blobcache_scrubbed_bytes_total",
+                "Total bytes of cached data verified by the background scrubber.",
+                |m| m.scrub.scrubbed_bytes.count(),
+            ),
+            (
+                "nydusd_blobcache_scrub_corrupted_chunks_total",
+                "Number of corrupted cached chunks found by the background scrubber.",
+                |m| m.scrub.corrupted_chunks.count(),
+            ),
+            (
+                "nydusd_blobcache_mem_cache_hits_total",
+                "Number of chunk reads served by the in-memory chunk cache.",
+                |m| m.mem_cache.hits.count(),
+            ),
+            (
+                "nydusd_blobcache_mem_cache_misses_total",
+                "Number of chunk reads missed in the in-memory chunk cache.",
+                |m| m.mem_cache.misses.count(),
+            ),
+            (
+                "nydusd_blobcache_mem_cache_evictions_total",
+                "Number of chunks evicted from the in-memory chunk cache.",
+                |m| m.mem_cache.evictions.count(),
+            ),
+        ];
+        for (name, help, value) in counters {
+            self.family(name, "counter", help);
+            for m in metrics {
+                self.sample(name, &[("id", &m.id)], value(m));
+            }
+        }
+
+        let gauges: [BlobcacheMetricDesc; 5] = [
+            (
+                "nydusd_blobcache_ready_chunks",
+                "Number of chunks in ready status.",
+                |m| m.entries_count.count(),
+            ),
+            (
+                "nydusd_blobcache_prefetch_workers",
+                "Number of active prefetch workers.",
+                |m| m.prefetch_workers.load(Ordering::Relaxed) as u64,
+            ),
+            (
+                "nydusd_blobcache_buffered_backend_bytes",
+                "Bytes of backend data buffered in memory.",
+                |m| m.buffered_backend_size.count(),
+            ),
+            (
+                "nydusd_blobcache_mem_cache_bytes",
+                "Bytes of chunk data in the in-memory chunk cache.",
+                |m| m.mem_cache.cached_bytes.count(),
+            ),
+            (
+                "nydusd_blobcache_mem_cache_hit_rate_percent",
+                "Hit percentage of the in-memory chunk cache.",
+                |m| m.mem_cache.hit_rate_percent.count(),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            self.family(name, "gauge", help);
+            for m in metrics {
+                self.sample(name, &[("id", &m.id)], value(m));
+            }
+        }
+
+        let name = "nydusd_blobcache_blob_info";
+        self.family(name, "gauge", "Blobs managed by blob cache.");
+        for m in metrics {
+            let mut files: Vec<String> =
+                m.underlying_files.lock().unwrap().iter().cloned().collect();
+            files.sort();
+            for file in files {
+                let blob_id = file.split_once('.').map(|(id, _)| id).unwrap_or(&file);
+                let labels = [
+                    ("id", m.id.as_str()),
+                    ("blob_id", blob_id),
+                    ("store_path", &m.store_path),
+                ];
+                self.sample(name, &labels, 1);
+            }
+        }
+    }
+}
+
+fn sorted_metrics<T>(metrics: &RwLock<HashMap<String, Arc<T>>>) -> Vec<Arc<T>> {
+    let guard = metrics.read().unwrap();
+    let mut keys: Vec<&String> = guard.keys().collect();
+    keys.sort();
+    keys.iter().map(|k| guard[*k].clone()).collect()
+}
+
+/// Export all registered metrics in the Prometheus text exposition format.
+///
+/// `inflight_ops` is the number of filesystem requests being processed, if available.
+pub fn export_prometheus_metrics(inflight_ops: Option<usize>) -> IoStatsResult<String> {
+    let mut encoder = PrometheusEncoder::default();
+
+    encoder.encode_fs_stats(&sorted_metrics(&FS_METRICS));
+    if let Some(ops) = inflight_ops {
+        let name = "nydusd_fs_inflight_operations";
+        encoder.family(
+            name,
+            "gauge",
+            "Number of filesystem requests being processed.",
+        );
+        encoder.sample(name, &[], ops);
+    }
+    encoder.encode_backend_metrics(&sorted_metrics(&BACKEND_METRICS));
+    encoder.encode_blobcache_metrics(&sorted_metrics(&BLOBCACHE_METRICS));
+
+    Ok(encoder.buf)
+}
+
 /// Trait to manipulate metric counters.
 pub trait Metric {
     /// Adds `value` to the current counter.
@@ -611,6 +1123,9 @@ impl Metric for BasicMetric {
 pub struct BackendMetrics {
     #[serde(skip_serializing, skip_deserializing)]
     id: String,
+    // Number of storage backend objects sharing the registered metrics object.
+    #[serde(skip_serializing, skip_deserializing)]
+    refs: AtomicU32,
     // type of storage backend.
     backend_type: String,
     // Cumulative count of read request to backend
@@ -626,33 +1141,47 @@ pub struct BackendMetrics {
     read_count_block_size_dist: [BasicMetric; BLOCK_READ_SIZES_MAX],
     // Categorize metrics as per their latency and request size
     read_latency_sizes_dist: [[BasicMetric; READ_LATENCY_RANGE_MAX]; BLOCK_READ_SIZES_MAX],
+    // Per-mirror statistics, indexed by mirror host.
+    mirrors: RwLock<HashMap<String, Arc<MirrorMetrics>>>,
 }
 
 impl BackendMetrics {
     /// Create a [`BackendMetrics`] object for a storage backend.
+    ///
+    /// Storage backend objects with the same id share the registered object, so counters survive
+    /// replacing a storage backend object at runtime.
     pub fn new(id: &str, backend_type: &str) -> Arc<Self> {
+        let mut metrics = BACKEND_METRICS.write().unwrap();
+        if let Some(v) = metrics.get(id) {
+            v.refs.fetch_add(1, Ordering::Relaxed);
+            return v.clone();
+        }
+
         let backend_metrics = Arc::new(Self {
             id: id.to_string(),
+            refs: AtomicU32::new(1),
             backend_type: backend_type.to_string(),
             ..Default::default()
         });
-
-        BACKEND_METRICS
-            .write()
-            .unwrap()
-            .insert(id.to_string(), backend_metrics.clone());
+        metrics.insert(id.to_string(), backend_metrics.clone());
 
         backend_metrics
     }
 
     /// Release a [`BackendMetrics`] object for a storage backend.
+    ///
+    /// The registered object is removed when released by the last storage backend object.
     pub fn release(&self) -> IoStatsResult<()> {
-        BACKEND_METRICS
-            .write()
-            .unwrap()
-            .remove(&self.id)
-            .map(|_| ())
-            .ok_or(MetricsError::NoCounter)
+        let mut metrics = BACKEND_METRICS.write().unwrap();
+        match metrics.get(&self.id) {
+            Some(v) if std::ptr::eq(v.as_ref(), self) => {
+                if self.refs.fetch_sub(1, Ordering::Relaxed) == 1 {
+                    metrics.remove(&self.id);
+                }
+                Ok(())
+            }
+            _ => Err(MetricsError::NoCounter),
+        }
     }
 
     /// Mark starting of an IO operations.
@@ -680,11 +1209,43 @@ impl BackendMetrics {
         }
     }
 
+    /// Get metrics for a registry mirror, creating it on first use.
+    pub fn mirror(&self, host: &str) -> Arc<MirrorMetrics> {
+        if let Some(m) = self.mirrors.read().unwrap().get(host) {
+            return m.clone();
+        }
+
+        self.mirrors
+            .write()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| {
+                Arc::new(MirrorMetrics {
+                    healthy: AtomicBool::new(true),
+                    ..Default::default()
+                })
+            })
+            .clone()
+    }
+
     fn export_metrics(&self) -> IoStatsResult<String> {
         serde_json::to_string(self).map_err(MetricsError::Serialize)
     }
 }
 
+/// Metrics for a registry mirror used by a storage backend.
+#[derive(Default, Serialize, Debug)]
+pub struct MirrorMetrics {
+    // Whether requests are currently sent to the mirror.
+    pub healthy: AtomicBool,
+    // Cumulative count of requests sent to the mirror.
+    pub requests: BasicMetric,
+    // Cumulative count of failed requests, which fall through to the next mirror or the origin.
+    pub errors: BasicMetric,
+    // How many times the mirror has been marked unhealthy.
+    pub disabled_count: BasicMetric,
+}
+
 // This function assumes that the counted duration won't be too long.
 fn saturating_duration_millis(d: &Duration) -> u64 {
     let d_secs = d.as_secs();
@@ -708,6 +1269,54 @@ fn saturating_duration_micros(d: &Duration) -> u64 {
     }
 }
 
+/// Statistics of background scrubbing of cached data.
+#[derive(Debug, Default, Serialize)]
+pub struct ScrubMetrics {
+    // Number of completed scrub passes over all cached blobs.
+    pub passes: BasicMetric,
+    // Number of ready chunks verified by the scrubber.
+    pub scrubbed_chunks: BasicMetric,
+    // Amount of cached data verified by the scrubber, in unit of Bytes.
+    pub scrubbed_bytes: BasicMetric,
+    // Number of corrupted chunks found, which have been marked as not ready for refetching.
+    pub corrupted_chunks: BasicMetric,
+    // The time in seconds since epoch when the last scrub pass ends.
+    pub last_pass_end_time_secs: BasicMetric,
+}
+
+/// Statistics of the in-memory cache for decompressed chunk data.
+#[derive(Debug, Default, Serialize)]
+pub struct MemCacheMetrics {
+    // Number of user chunk reads served by the memory cache.
+    pub hits: BasicMetric,
+    // Number of user chunk reads not found in the memory cache.
+    pub misses: BasicMetric,
+    // Hit percentage = hits * 100 / (hits + misses)
+    pub hit_rate_percent: BasicMetric,
+    // Number of chunks evicted from the memory cache.
+    pub evictions: BasicMetric,
+    // Number of chunks in the memory cache.
+    pub entries: BasicMetric,
+    // Size of chunk data in the memory cache, in unit of Bytes.
+    pub cached_bytes: BasicMetric,
+}
+
+impl MemCacheMetrics {
+    /// Account a lookup of the memory cache.
+    pub fn record_lookup(&self, hit: bool) {
+        if hit {
+            self.hits.inc();
+        } else {
+            self.misses.inc();
+        }
+        let hits = self.hits.count();
+        let total = hits + self.misses.count();
+        if total > 0 {
+            self.hit_rate_percent.set(hits * 100 / total);
+        }
+    }
+}
+
 #[derive(Debug, Default, Serialize)]
 pub struct BlobcacheMetrics {
     #[serde(skip_serializing, skip_deserializing)]
@@ -724,9 +1333,12 @@ pub struct BlobcacheMetrics {
     // How many `read` requests are processed by the blobcache instance.
     // This metric will be helpful when comparing with cache hits times.
     pub total: BasicMetric,
-    // Scale of blobcache. Blobcache does not evict entries.
-    // Means the number of chunks in ready status.
+    // Scale of blobcache, means the number of chunks in ready status.
     pub entries_count: BasicMetric,
+    // Number of cached blobs evicted from the working directory.
+    pub evicted_blobs: BasicMetric,
+    // Disk space reclaimed by evicting cached blobs, in unit of Bytes.
+    pub reclaimed_bytes: BasicMetric,
     // Together with below two fields, we can figure out average merging size thus
     // to estimate the possibility to merge backend IOs.
     // In unit of Bytes
@@ -752,6 +1364,10 @@ pub struct BlobcacheMetrics {
     pub prefetch_end_time_millis: BasicMetric,
     pub buffered_backend_size: BasicMetric,
     pub data_all_ready: AtomicBool,
+    // Results of background scrubbing of cached data.
+    pub scrub: ScrubMetrics,
+    // Statistics of the in-memory chunk cache.
+    pub mem_cache: MemCacheMetrics,
 }
 
 impl BlobcacheMetrics {
@@ -901,6 +1517,29 @@ mod tests {
         assert_ne!(ap.first_access_time_nanos.load(Ordering::Relaxed), 0);
     }
 
+    #[test]
+    fn test_get_accessed_inodes() {
+        let f = FsIoStats::default();
+        f.toggle_access_pattern(true);
+        for ino in 1..=3 {
+            f.new_file_counter(ino);
+        }
+        f.file_stats_update(3, StatsFop::Read, 4096, true);
+        f.file_stats_update(1, StatsFop::Read, 4096, true);
+        f.file_stats_update(3, StatsFop::Read, 4096, true);
+        f.file_stats_update(2, StatsFop::Getattr, 0, true);
+
+        let records = f.access_patterns.read().unwrap();
+        records[&1]
+            .first_access_time_secs
+            .store(100, Ordering::Relaxed);
+        records[&3]
+            .first_access_time_secs
+            .store(50, Ordering::Relaxed);
+        drop(records);
+        assert_eq!(f.get_accessed_inodes(), vec![3, 1]);
+    }
+
     #[test]
     fn test_file_stats_update() {
         let f = FsIoStats::default();
@@ -1074,6 +1713,99 @@ mod tests {
         assert!(export_events().is_ok());
     }
 
+    #[test]
+    fn test_prometheus_encoder() {
+        let mut encoder = PrometheusEncoder::default();
+        encoder.family("test_metric", "counter", "Test metric.");
+        encoder.sample("test_metric", &[("id", "a\"b\\c")], 1);
+        encoder.sample("test_metric", &[], 2);
+        assert_eq!(
+            encoder.buf,
+            "# HELP test_metric Test metric.\n# TYPE test_metric counter\n\
+             test_metric{id=\"a\\\"b\\\\c\"} 1\ntest_metric 2\n"
+        );
+
+        let mut encoder = PrometheusEncoder::default();
+        encoder.histogram("h", &[("id", "x")], &["1", "+Inf"], &[2, 3], 1.5);
+        assert_eq!(
+            encoder.buf,
+            "h_bucket{id=\"x\",le=\"1\"} 2\nh_bucket{id=\"x\",le=\"+Inf\"} 5\n\
+             h_sum{id=\"x\"} 1.5\nh_count{id=\"x\"} 5\n"
+        );
+    }
+
+    #[test]
+    fn test_prometheus_export() {
+        let fs = Arc::new(FsIoStats {
+            id: "fs0".to_string(),
+            ..Default::default()
+        });
+        fs.init();
+        let start = Some(SystemTime::now() - Duration::from_millis(10));
+        fs.latency_end(&start, StatsFop::Read);
+        fs.fop_update(StatsFop::Read, 4096, true);
+        fs.fop_update(StatsFop::Lookup, 0, false);
+
+        let backend = Arc::new(BackendMetrics {
+            id: "backend0".to_string(),
+            backend_type: "registry".to_string(),
+            ..Default::default()
+        });
+        let begin = SystemTime::now() - Duration::from_millis(30);
+        backend.end(&begin, 0x10000, false);
+        let mirror = backend.mirror("http://127.0.0.1:65001");
+        mirror.requests.add(2);
+        mirror.errors.inc();
+        assert!(Arc::ptr_eq(
+            &mirror,
+            &backend.mirror("http://127.0.0.1:65001")
+        ));
+
+        let cache = Arc::new(BlobcacheMetrics {
+            id: "cache0".to_string(),
+            store_path: "/tmp".to_string(),
+            ..Default::default()
+        });
+        cache.whole_hits.add(3);
+        cache
+            .underlying_files
+            .lock()
+            .unwrap()
+            .insert("blob1.blob.data".to_string());
+
+        let mut encoder = PrometheusEncoder::default();
+        encoder.encode_fs_stats(&[fs]);
+        encoder.encode_backend_metrics(&[backend]);
+        encoder.encode_blobcache_metrics(&[cache]);
+        let output = encoder.buf;
+
+        assert!(output.contains("nydusd_fs_read_bytes_total{id=\"fs0\"} 4096\n"));
+        assert!(output.contains("nydusd_fs_operations_total{id=\"fs0\",fop=\"read\"} 1\n"));
+        assert!(output.contains("nydusd_fs_operation_errors_total{id=\"fs0\",fop=\"lookup\"} 1\n"));
+        assert!(output
+            .contains("nydusd_fs_operation_latency_seconds_bucket{id=\"fs0\",le=\"+Inf\"} 1\n"));
+        assert!(output.contains(
+            "nydusd_backend_read_bytes_total{id=\"backend0\",backend_type=\"registry\"} 65536\n"
+        ));
+        assert!(output.contains(
+            "nydusd_backend_read_latency_seconds_count{id=\"backend0\",backend_type=\"registry\"} 1\n"
+        ));
+        assert!(output.contains(
+            "nydusd_backend_mirror_up{id=\"backend0\",backend_type=\"registry\",mirror=\"http://127.0.0.1:65001\"} 1\n"
+        ));
+        assert!(output.contains(
+            "nydusd_backend_mirror_errors_total{id=\"backend0\",backend_type=\"registry\",mirror=\"http://127.0.0.1:65001\"} 1\n"
+        ));
+        assert!(output.contains("nydusd_blobcache_whole_hits_total{id=\"cache0\"} 3\n"));
+        assert!(output.contains(
+            "nydusd_blobcache_blob_info{id=\"cache0\",blob_id=\"blob1\",store_path=\"/tmp\"} 1\n"
+        ));
+        assert_eq!(
+            output.matches("# TYPE nydusd_fs_open_files gauge").count(),
+            1
+        );
+    }
+
     #[test]
     fn test_backend_metric() {
         let id0: Option<String> = Some("id-0".to_string());
@@ -1089,5 +1821,15 @@ mod tests {
         assert!(export_backend_metrics(&none).is_err());
         assert!(b0.release().is_ok());
         assert!(b1.release().is_ok());
+
+        // Metrics shared by backend objects with the same id survive release of the old one.
+        let b0 = BackendMetrics::new("id-0", "t0");
+        let b1 = BackendMetrics::new("id-0", "t0");
+        assert!(Arc::ptr_eq(&b0, &b1));
+        assert!(b0.release().is_ok());
+        assert!(export_backend_metrics(&id0).is_ok());
+        assert!(b1.release().is_ok());
+        assert!(export_backend_metrics(&id0).is_err());
+        assert!(b1.release().is_err());
     }
 }
@@ -0,0 +1,200 @@
+// Copyright 2024 Nydus Developers. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verify detached signatures with ed25519 or ECDSA public keys.
+//!
+//! Signatures are generated over a SHA-256 digest:
+//! - ed25519: the signature is over the raw 32-byte digest.
+//! - ECDSA: the DER encoded signature is over the prehashed digest, which is what
+//!   `openssl dgst -sha256 -sign` generates for the original data.
+
+use std::fmt::{self, Debug, Formatter};
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+
+use openssl::pkey::{Id, PKey, PKeyRef, Private, Public};
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::sign::{Signer, Verifier};
+
+/// Length of the SHA-256 digest to be signed.
+pub const SIGNED_DIGEST_LENGTH: usize = 32;
+
+/// Verifier to check detached signatures against a set of trusted public keys.
+#[derive(Default)]
+pub struct SignatureVerifier {
+    keys: Vec<PKey<Public>>,
+}
+
+impl SignatureVerifier {
+    /// Create a verifier from PEM encoded public keys.
+    pub fn from_pem(pems: &[&[u8]]) -> Result<Self> {
+        let mut keys = Vec::with_capacity(pems.len());
+        for pem in pems {
+            let key = PKey::public_key_from_pem(pem)
+                .map_err(|e| einval!(format!("invalid public key, {}", e)))?;
+            match key.id() {
+                Id::ED25519 | Id::EC => keys.push(key),
+                id => {
+                    return Err(einval!(format!(
+                        "unsupported public key type {:?}, only ed25519 and ECDSA are supported",
+                        id
+                    )))
+                }
+            }
+        }
+
+        Ok(SignatureVerifier { keys })
+    }
+
+    /// Create a verifier from files containing PEM encoded public keys.
+    pub fn from_pem_files<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut pems = Vec::with_capacity(paths.len());
+        for path in paths {
+            let pem = fs::read(path.as_ref()).map_err(|e| {
+                Error::new(
+                    e.kind(),
+                    format!(
+                        "failed to read public key {}, {}",
+                        path.as_ref().display(),
+                        e
+                    ),
+                )
+            })?;
+            pems.push(pem);
+        }
+        let pems: Vec<&[u8]> = pems.iter().map(|v| v.as_slice()).collect();
+
+        Self::from_pem(&pems)
+    }
+
+    /// Check whether there's no trusted public key.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Check whether `signature` over `digest` is generated by any of the trusted keys.
+    pub fn verify(&self, digest: &[u8], signature: &[u8]) -> bool {
+        if digest.len() != SIGNED_DIGEST_LENGTH {
+            return false;
+        }
+        self.keys
+            .iter()
+            .any(|key| verify_with_key(key, digest, signature).unwrap_or(false))
+    }
+}
+
+impl Debug for SignatureVerifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignatureVerifier")
+            .field("keys", &self.keys.len())
+            .finish()
+    }
+}
+
+fn verify_with_key(
+    key: &PKeyRef<Public>,
+    digest: &[u8],
+    signature: &[u8],
+) -> std::result::Result<bool, openssl::error::ErrorStack> {
+    match key.id() {
+        Id::ED25519 => {
+            let mut verifier = Verifier::new_without_digest(key)?;
+            verifier.verify_oneshot(signature, digest)
+        }
+        _ => {
+            let mut ctx = PkeyCtx::new(key)?;
+            ctx.verify_init()?;
+            ctx.verify(digest, signature)
+        }
+    }
+}
+
+/// Sign `digest` with a PEM encoded ed25519 or ECDSA private key.
+pub fn sign(private_key_pem: &[u8], digest: &[u8]) -> Result<Vec<u8>> {
+    if digest.len() != SIGNED_DIGEST_LENGTH {
+        return Err(einval!(format!(
+            "invalid digest length {}, expect {}",
+            digest.len(),
+            SIGNED_DIGEST_LENGTH
+        )));
+    }
+    let key: PKey<Private> = PKey::private_key_from_pem(private_key_pem)
+        .map_err(|e| einval!(format!("invalid private key, {}", e)))?;
+    let signature = match key.id() {
+        Id::ED25519 => {
+            Signer::new_without_digest(&key).and_then(|mut s| s.sign_oneshot_to_vec(digest))
+        }
+        Id::EC => PkeyCtx::new(&key).and_then(|mut ctx| {
+            let mut buf = Vec::new();
+            ctx.sign_init()?;
+            ctx.sign_to_vec(digest, &mut buf)?;
+            Ok(buf)
+        }),
+        id => {
+            return Err(einval!(format!(
+                "unsupported private key type {:?}, only ed25519 and ECDSA are supported",
+                id
+            )))
+        }
+    };
+
+    signature.map_err(|e| eother!(format!("failed to sign digest, {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::rsa::Rsa;
+
+    fn ed25519_key() -> PKey<Private> {
+        PKey::generate_ed25519().unwrap()
+    }
+
+    fn ecdsa_key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let digest = [0x5au8; SIGNED_DIGEST_LENGTH];
+        let ed_key = ed25519_key();
+        let ec_key = ecdsa_key();
+        let ed_pub = ed_key.public_key_to_pem().unwrap();
+        let ec_pub = ec_key.public_key_to_pem().unwrap();
+
+        let ed_sig = sign(&ed_key.private_key_to_pem_pkcs8().unwrap(), &digest).unwrap();
+        let ec_sig = sign(&ec_key.private_key_to_pem_pkcs8().unwrap(), &digest).unwrap();
+
+        let verifier = SignatureVerifier::from_pem(&[&ed_pub, &ec_pub]).unwrap();
+        assert!(!verifier.is_empty());
+        assert!(verifier.verify(&digest, &ed_sig));
+        assert!(verifier.verify(&digest, &ec_sig));
+        assert!(!verifier.verify(&[0u8; SIGNED_DIGEST_LENGTH], &ed_sig));
+        assert!(!verifier.verify(&[0u8; SIGNED_DIGEST_LENGTH], &ec_sig));
+        assert!(!verifier.verify(&digest, b"invalid"));
+        assert!(!verifier.verify(&digest[..16], &ed_sig));
+
+        let verifier = SignatureVerifier::from_pem(&[&ec_pub]).unwrap();
+        assert!(!verifier.verify(&digest, &ed_sig));
+        assert!(SignatureVerifier::default().is_empty());
+        assert!(!SignatureVerifier::default().verify(&digest, &ed_sig));
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(SignatureVerifier::from_pem(&[b"invalid"]).is_err());
+        let rsa = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let rsa_pub = rsa.public_key_to_pem().unwrap();
+        assert!(SignatureVerifier::from_pem(&[&rsa_pub]).is_err());
+        let rsa_priv = rsa.private_key_to_pem_pkcs8().unwrap();
+        assert!(sign(&rsa_priv, &[0u8; SIGNED_DIGEST_LENGTH]).is_err());
+        let ed_priv = ed25519_key().private_key_to_pem_pkcs8().unwrap();
+        assert!(sign(&ed_priv, &[0u8; 16]).is_err());
+        assert!(SignatureVerifier::from_pem_files(&["/nonexistent/key.pem"]).is_err());
+    }
+}
@@ -271,6 +271,244 @@ impl VerityGenerator {
     }
 }
 
+/// Block size of fs-verity compatible Merkle trees.
+pub const FSVERITY_BLOCK_SIZE: usize = 4096;
+/// fs-verity hash algorithm number for SHA-256.
+pub const FSVERITY_HASH_ALG_SHA256: u16 = 1;
+/// Size of the `fsverity_digest` structure returned by `FS_IOC_MEASURE_VERITY` for SHA-256.
+pub const FSVERITY_MEASUREMENT_SIZE: usize = 4 + size_of::<DigestData>();
+/// Maximum number of Merkle tree entries stored for a file by [FileVerityTree].
+pub const FSVERITY_TREE_MAX_ENTRIES: u64 = 1024;
+const FSVERITY_DIGESTS_PER_BLOCK: u64 = (FSVERITY_BLOCK_SIZE / size_of::<DigestData>()) as u64;
+
+/// Generator of fs-verity compatible file digests.
+///
+/// The Merkle tree is built over 4K data blocks with SHA-256 and no salt, so the generated file
+/// digest is the same as the one reported by `fsverity digest` and `FS_IOC_MEASURE_VERITY`.
+/// Digests of data blocks are kept to verify data blocks read later.
+#[derive(Default)]
+pub struct FileVerityHasher {
+    size: u64,
+    buf: Vec<u8>,
+    blocks: Vec<RafsDigest>,
+}
+
+impl FileVerityHasher {
+    /// Create a new instance of [FileVerityHasher].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed file data in order.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.size += data.len() as u64;
+        if !self.buf.is_empty() {
+            let len = std::cmp::min(FSVERITY_BLOCK_SIZE - self.buf.len(), data.len());
+            self.buf.extend_from_slice(&data[..len]);
+            data = &data[len..];
+            if self.buf.len() == FSVERITY_BLOCK_SIZE {
+                self.blocks.push(Self::block_digest(&self.buf));
+                self.buf.clear();
+            }
+        }
+        while data.len() >= FSVERITY_BLOCK_SIZE {
+            self.blocks
+                .push(Self::block_digest(&data[..FSVERITY_BLOCK_SIZE]));
+            data = &data[FSVERITY_BLOCK_SIZE..];
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Finish the Merkle tree, and return the file digest and digests of all data blocks.
+    pub fn finalize(mut self) -> (RafsDigest, Vec<RafsDigest>) {
+        if !self.buf.is_empty() {
+            self.blocks.push(Self::block_digest(&self.buf));
+        }
+        let digest = Self::file_digest(self.size, &self.blocks);
+        (digest, self.blocks)
+    }
+
+    /// Calculate digest of a data block, which is padded with zero if it's not a full block.
+    pub fn block_digest(data: &[u8]) -> RafsDigest {
+        assert!(data.len() <= FSVERITY_BLOCK_SIZE);
+        if data.len() == FSVERITY_BLOCK_SIZE {
+            RafsDigest::from_buf(data, Algorithm::Sha256)
+        } else {
+            let mut block = vec![0u8; FSVERITY_BLOCK_SIZE];
+            block[..data.len()].copy_from_slice(data);
+            RafsDigest::from_buf(&block, Algorithm::Sha256)
+        }
+    }
+
+    /// Calculate the fs-verity file digest from file size and digests of data blocks.
+    pub fn file_digest(size: u64, blocks: &[RafsDigest]) -> RafsDigest {
+        let mut level = blocks.to_vec();
+        while level.len() > 1 {
+            level = Self::next_level(&level);
+        }
+        Self::descriptor_digest(size, level.first())
+    }
+
+    // Calculate digests of the upper Merkle tree level, each of which covers a block of digests.
+    fn next_level(digests: &[RafsDigest]) -> Vec<RafsDigest> {
+        let digest_size = size_of::<DigestData>();
+        digests
+            .chunks(FSVERITY_DIGESTS_PER_BLOCK as usize)
+            .map(|digests| {
+                let mut block = vec![0u8; FSVERITY_BLOCK_SIZE];
+                for (idx, d) in digests.iter().enumerate() {
+                    block[idx * digest_size..(idx + 1) * digest_size].copy_from_slice(d.as_ref());
+                }
+                RafsDigest::from_buf(&block, Algorithm::Sha256)
+            })
+            .collect()
+    }
+
+    // Layout of `struct fsverity_descriptor`, the root hash is zero for empty files.
+    fn descriptor_digest(size: u64, root: Option<&RafsDigest>) -> RafsDigest {
+        let digest_size = size_of::<DigestData>();
+        let mut desc = vec![0u8; 256];
+        desc[0] = 1;
+        desc[1] = FSVERITY_HASH_ALG_SHA256 as u8;
+        desc[2] = FSVERITY_BLOCK_SIZE.trailing_zeros() as u8;
+        desc[8..16].copy_from_slice(&size.to_le_bytes());
+        if let Some(root) = root {
+            desc[16..16 + digest_size].copy_from_slice(root.as_ref());
+        }
+        RafsDigest::from_buf(&desc, Algorithm::Sha256)
+    }
+}
+
+/// A level of the fs-verity Merkle tree of a file, to verify file data without reading the whole
+/// file.
+///
+/// Entries at level 1 are digests of data blocks, and each entry at level `n + 1` is the digest of
+/// a block of up to 128 entries at level `n`. The lowest level with no more than
+/// [FSVERITY_TREE_MAX_ENTRIES] entries is kept, so each entry covers a group of data blocks which
+/// may be verified independently, and the file digest may be calculated from the entries.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileVerityTree {
+    level: u32,
+    entries: Vec<RafsDigest>,
+}
+
+impl FileVerityTree {
+    /// Create a tree with zeroed entries for a file of `size` bytes, to reserve space for it.
+    pub fn with_file_size(size: u64) -> Self {
+        let (level, count) = Self::level_for_size(size);
+        FileVerityTree {
+            level,
+            entries: vec![RafsDigest::default(); count as usize],
+        }
+    }
+
+    /// Create a tree for a file of `size` bytes from digests of all its data blocks.
+    pub fn from_blocks(size: u64, blocks: &[RafsDigest]) -> Self {
+        let (level, _) = Self::level_for_size(size);
+        let mut entries = blocks.to_vec();
+        for _ in 1..level {
+            entries = FileVerityHasher::next_level(&entries);
+        }
+        FileVerityTree { level, entries }
+    }
+
+    /// Get the Merkle tree level of the entries.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// Get the Merkle tree entries.
+    pub fn entries(&self) -> &[RafsDigest] {
+        &self.entries
+    }
+
+    /// Get number of data blocks covered by each entry.
+    pub fn group_blocks(&self) -> u64 {
+        FSVERITY_DIGESTS_PER_BLOCK.pow(self.level.saturating_sub(1))
+    }
+
+    /// Calculate the fs-verity file digest from the entries.
+    pub fn file_digest(&self, size: u64) -> RafsDigest {
+        let mut level = self.entries.clone();
+        while level.len() > 1 {
+            level = FileVerityHasher::next_level(&level);
+        }
+        FileVerityHasher::descriptor_digest(size, level.first())
+    }
+
+    /// Verify digests of all data blocks covered by entry `group`.
+    pub fn verify_group(&self, group: usize, blocks: &[RafsDigest]) -> bool {
+        let mut digests = blocks.to_vec();
+        for _ in 1..self.level {
+            digests = FileVerityHasher::next_level(&digests);
+        }
+        digests.len() == 1 && self.entries.get(group) == Some(&digests[0])
+    }
+
+    /// Encode the tree as the level number in little endian followed by all entries.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(4 + self.entries.len() * size_of::<DigestData>());
+        buf.extend_from_slice(&self.level.to_le_bytes());
+        for entry in self.entries.iter() {
+            buf.extend_from_slice(entry.as_ref());
+        }
+        buf
+    }
+
+    /// Decode a tree for a file of `size` bytes.
+    pub fn decode(buf: &[u8], size: u64) -> Result<Self> {
+        let (level, count) = Self::level_for_size(size);
+        let digest_size = size_of::<DigestData>();
+        if buf.len() != 4 + count as usize * digest_size
+            || u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) != level
+        {
+            return Err(einval!("invalid fs-verity Merkle tree data"));
+        }
+        let entries = buf[4..]
+            .chunks(digest_size)
+            .map(|v| {
+                let mut digest = RafsDigest::default();
+                digest.data.copy_from_slice(v);
+                digest
+            })
+            .collect();
+        Ok(FileVerityTree { level, entries })
+    }
+
+    // Get the lowest level with no more than `FSVERITY_TREE_MAX_ENTRIES` entries.
+    fn level_for_size(size: u64) -> (u32, u64) {
+        let mut level = 1;
+        let mut count = div_round_up(size, FSVERITY_BLOCK_SIZE as u64);
+        while count > FSVERITY_TREE_MAX_ENTRIES {
+            count = div_round_up(count, FSVERITY_DIGESTS_PER_BLOCK);
+            level += 1;
+        }
+        (level, count)
+    }
+}
+
+/// Encode a file digest as `struct fsverity_digest`, as returned by `FS_IOC_MEASURE_VERITY`.
+pub fn fsverity_measurement(digest: &RafsDigest) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(FSVERITY_MEASUREMENT_SIZE);
+    buf.extend_from_slice(&FSVERITY_HASH_ALG_SHA256.to_le_bytes());
+    buf.extend_from_slice(&(size_of::<DigestData>() as u16).to_le_bytes());
+    buf.extend_from_slice(digest.as_ref());
+    buf
+}
+
+/// Decode a file digest from `struct fsverity_digest`.
+pub fn parse_fsverity_measurement(buf: &[u8]) -> Result<RafsDigest> {
+    if buf.len() != FSVERITY_MEASUREMENT_SIZE
+        || u16::from_le_bytes([buf[0], buf[1]]) != FSVERITY_HASH_ALG_SHA256
+        || u16::from_le_bytes([buf[2], buf[3]]) as usize != size_of::<DigestData>()
+    {
+        return Err(einval!("invalid fs-verity measurement data"));
+    }
+    let mut digest = RafsDigest::default();
+    digest.data.copy_from_slice(&buf[4..]);
+    Ok(digest)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -454,4 +692,95 @@ mod tests {
         let mut generator = VerityGenerator::new(file.into_file(), 0, 4097).unwrap();
         assert!(generator.initialize().is_ok());
     }
+
+    #[test]
+    fn test_file_verity_hasher() {
+        let (digest, blocks) = FileVerityHasher::new().finalize();
+        assert!(blocks.is_empty());
+        assert_eq!(
+            digest.to_string(),
+            "3d248ca542a24fc62d1c43b916eae5016878e2533c88238480b26128a1f1af95"
+        );
+
+        let data: Vec<u8> = (0..300000u32).map(|v| (v % 251) as u8).collect();
+        let mut hasher = FileVerityHasher::new();
+        hasher.update(&data);
+        let (digest, blocks) = hasher.finalize();
+        assert_eq!(blocks.len(), 74);
+        assert_eq!(blocks[1], FileVerityHasher::block_digest(&data[4096..8192]));
+        assert_eq!(
+            blocks[73],
+            FileVerityHasher::block_digest(&data[73 * 4096..])
+        );
+        assert_eq!(
+            digest,
+            FileVerityHasher::file_digest(data.len() as u64, &blocks)
+        );
+        assert_eq!(
+            digest.to_string(),
+            "9ba81fdc4f8dfd3b0d13b883f7f73f07e6c0c006c6ef81a3bc024508c8a57e3f"
+        );
+
+        // Feeding data in pieces generates the same digest.
+        let mut hasher = FileVerityHasher::new();
+        for piece in data.chunks(1000) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finalize().0, digest);
+
+        let buf = fsverity_measurement(&digest);
+        assert_eq!(buf.len(), FSVERITY_MEASUREMENT_SIZE);
+        assert_eq!(parse_fsverity_measurement(&buf).unwrap(), digest);
+        assert!(parse_fsverity_measurement(&buf[1..]).is_err());
+    }
+
+    #[test]
+    fn test_file_verity_tree() {
+        let tree = FileVerityTree::with_file_size(0);
+        assert_eq!(tree.level(), 1);
+        assert!(tree.entries().is_empty());
+        assert_eq!(tree.file_digest(0), FileVerityHasher::new().finalize().0);
+
+        // Digests of data blocks are stored for small files.
+        let data: Vec<u8> = (0..300000u32).map(|v| (v % 251) as u8).collect();
+        let size = data.len() as u64;
+        let mut hasher = FileVerityHasher::new();
+        hasher.update(&data);
+        let (digest, blocks) = hasher.finalize();
+        let tree = FileVerityTree::from_blocks(size, &blocks);
+        assert_eq!(tree.level(), 1);
+        assert_eq!(tree.group_blocks(), 1);
+        assert_eq!(tree.entries(), blocks.as_slice());
+        assert_eq!(tree.file_digest(size), digest);
+        assert!(tree.verify_group(3, &blocks[3..4]));
+        assert!(!tree.verify_group(3, &blocks[4..5]));
+        let buf = tree.encode();
+        assert_eq!(FileVerityTree::decode(&buf, size).unwrap(), tree);
+        assert!(FileVerityTree::decode(&buf, size + 4096).is_err());
+        assert!(FileVerityTree::decode(&buf[1..], size).is_err());
+
+        // Upper levels are stored for big files.
+        let count = FSVERITY_TREE_MAX_ENTRIES * 2 + 5;
+        let size = count * FSVERITY_BLOCK_SIZE as u64 - 100;
+        let blocks: Vec<RafsDigest> = (0..count)
+            .map(|v| RafsDigest::from_buf(&v.to_le_bytes(), Algorithm::Sha256))
+            .collect();
+        let tree = FileVerityTree::from_blocks(size, &blocks);
+        assert_eq!(tree.level(), 2);
+        assert_eq!(tree.group_blocks(), 128);
+        assert_eq!(tree.entries().len(), 17);
+        assert_eq!(tree, FileVerityTree::decode(&tree.encode(), size).unwrap());
+        assert_eq!(
+            FileVerityTree::with_file_size(size).entries().len(),
+            tree.entries().len()
+        );
+        assert_eq!(
+            tree.file_digest(size),
+            FileVerityHasher::file_digest(size, &blocks)
+        );
+        assert!(tree.verify_group(0, &blocks[..128]));
+        assert!(tree.verify_group(16, &blocks[2048..]));
+        assert!(!tree.verify_group(16, &blocks[2047..]));
+        assert!(!tree.verify_group(1, &blocks[..128]));
+    }
 }